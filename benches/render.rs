@@ -0,0 +1,76 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::widgets::Widget;
+
+use packetloss::draw::LogList;
+use packetloss::partition::Partitioner;
+use packetloss::ping::{AddrFamily, ColorMode, PacketChunk, Reply, Scale};
+
+const CHUNKS: usize = 20_000;
+const CHUNK_SIZE: usize = 10;
+
+fn packet(i: usize) -> Option<Reply> {
+    /* every seventh probe lost, latency varying a little */
+    if i.is_multiple_of(7) {
+        return None;
+    }
+
+    Some(Reply {
+        hostname: String::from("example.com"),
+        address: String::from("93.184.216.34"),
+        family: AddrFamily::IPV4,
+        latency_ms: 10.0 + (i % 13) as f64,
+        dropped: 0,
+        seq: i as i32,
+        recv_ttl: 56,
+        recv_qos: 0,
+    })
+}
+
+fn chunk(n: usize) -> PacketChunk {
+    let mut chunk = PacketChunk::new(100.0);
+    for i in 0..CHUNK_SIZE {
+        chunk.push(packet(n * CHUNK_SIZE + i));
+    }
+    chunk
+}
+
+fn list() -> LogList {
+    let mut list = LogList::new(CHUNKS + 1);
+    for i in 0..CHUNKS {
+        list.insert(chunk(i));
+    }
+    list
+}
+
+fn partition(c: &mut Criterion) {
+    c.bench_function("partition 20k chunks", |b| {
+        b.iter(|| Partitioner::new(black_box(200), black_box(60), CHUNKS as u16).count())
+    });
+}
+
+fn stats(c: &mut Criterion) {
+    let chunk = chunk(1);
+
+    c.bench_function("chunk loss", |b| b.iter(|| black_box(&chunk).loss()));
+    c.bench_function("chunk latency", |b| b.iter(|| black_box(&chunk).latency()));
+    c.bench_function("chunk color", |b| b.iter(|| black_box(&chunk).color((10.0, 1000.0), false, ColorMode::Loss, Scale::Linear)));
+}
+
+fn render(c: &mut Criterion) {
+    let mut list = list();
+
+    for &(w, h) in [(80, 24), (200, 60)].iter() {
+        let area = Rect::new(0, 0, w, h);
+        let mut buf = Buffer::empty(area);
+
+        c.bench_function(&format!("render 20k chunks {}x{}", w, h), |b| {
+            b.iter(|| list.draw(area, &mut buf))
+        });
+    }
+}
+
+criterion_group!(benches, partition, stats, render);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+/*
+ * C API of the packetloss probe engine, see src/ffi.rs. Build the library
+ * with
+ *
+ *     cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib
+ *
+ * and link against target/release/libpacketloss.so (.dylib, .dll).
+ */
+
+#ifndef PACKETLOSS_H
+#define PACKETLOSS_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct packetloss_monitor packetloss_monitor;
+
+typedef struct {
+    /* unix time the chunk was started, in seconds */
+    int64_t time;
+    uint32_t sent;
+    uint32_t received;
+    /* 0.0 to 1.0 */
+    double loss;
+    /* per-packet mean in ms, lost packets counting as the timeout */
+    double latency_ms;
+    /* negative when fewer than two replies came back */
+    double jitter_ms;
+    double mos;
+} packetloss_chunk;
+
+/* start probing, NULL when target isn't valid UTF-8 */
+packetloss_monitor *packetloss_start(const char *target, uint32_t interval_ms,
+                                     uint32_t chunk_size, uint32_t timeout_ms);
+
+/* 1 with a chunk in out, 0 when none came within wait_ms, -1 when the probe can't be sent */
+int packetloss_poll(packetloss_monitor *monitor, packetloss_chunk *out, uint32_t wait_ms);
+
+/* stop and free the monitor */
+void packetloss_stop(packetloss_monitor *monitor);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
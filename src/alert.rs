@@ -0,0 +1,490 @@
+use std::process::Command;
+use std::time::{Duration, Instant};
+use std::thread;
+
+use chrono::prelude::*;
+use log::{info, warn};
+
+use crate::pcap::Pcap;
+use crate::ping::PacketChunk;
+
+/*
+ * alert rules engine
+ *
+ * a chunk is "lossy" when its loss reaches the rule threshold. escalation
+ * stages fire once a run of consecutive lossy chunks reaches their count,
+ * and are re-armed after a run of good chunks.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Notify,
+    Webhook(String),
+    Command(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub after: u32,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub threshold: f64,
+    pub stages: Vec<Stage>,
+    pub cooldown: Duration,
+    pub rearm: u32,
+    /* chunks with an estimated MOS below this are lossy too */
+    pub mos: Option<f64>,
+    /* and chunks that lost this many probes in a row */
+    pub burst: Option<usize>,
+}
+
+impl Policy {
+    pub fn new(threshold: f64, cooldown: Duration, rearm: u32) -> Self {
+        Policy {
+            threshold,
+            stages: vec![],
+            cooldown,
+            rearm,
+            mos: None,
+            burst: None,
+        }
+    }
+
+    /* add a stage from a `N:action` spec, merging stages with equal counts */
+    pub fn stage(&mut self, spec: &str) -> Result<(), String> {
+        let (after, action) = parse_stage(spec)?;
+
+        match self.stages.iter_mut().find(|x| x.after == after) {
+            Some(stage) => stage.actions.push(action),
+            None => {
+                self.stages.push(Stage { after, actions: vec![action] });
+                self.stages.sort_by_key(|x| x.after);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_lossy(&self, chunk: &PacketChunk) -> bool {
+        chunk.sent() > 0 && (chunk.loss() >= self.threshold
+            || self.mos.map(|x| chunk.mos() < x).unwrap_or(false)
+            || self.burst.map(|x| chunk.longest_run() >= x).unwrap_or(false))
+    }
+}
+
+/// Validator for `--escalate` values, in the same shape as `is_int`
+pub fn is_stage(v: String) -> Result<(), String> {
+    parse_stage(&v).map(|_| ())
+}
+
+fn parse_stage(spec: &str) -> Result<(u32, Action), String> {
+    let mut parts = spec.splitn(2, ':');
+
+    let after = parts.next()
+        .and_then(|x| x.trim().parse::<u32>().ok())
+        .filter(|x| *x > 0)
+        .ok_or_else(|| String::from("Stage must start with a positive chunk count, e.g. 2:notify"))?;
+
+    let action = parts.next()
+        .ok_or_else(|| String::from("Stage must name an action, e.g. 2:notify"))?;
+
+    Ok((after, parse_action(action)?))
+}
+
+/// Validator for a bare action, as `--shift-action` takes
+pub fn is_action(v: String) -> Result<(), String> {
+    parse_action(&v).map(|_| ())
+}
+
+pub fn parse_action(action: &str) -> Result<Action, String> {
+    let mut action = action.splitn(2, '=');
+    let kind = action.next().unwrap().trim();
+    let arg = action.next().map(|x| x.trim().to_string());
+
+    let action = match (kind, arg) {
+        ("notify", None) => Action::Notify,
+        ("webhook", Some(url)) => Action::Webhook(url),
+        ("command", Some(cmd)) => Action::Command(cmd),
+        ("webhook", None) | ("command", None) => {
+            return Err(format!("Action '{}' needs a value, e.g. {}=...", kind, kind));
+        },
+        _ => {
+            return Err(format!("Unknown action '{}' (expected notify, webhook=URL or command=CMD)", kind));
+        },
+    };
+
+    Ok(action)
+}
+
+/// A run of lossy chunks, from the first lossy chunk until escalation re-arms
+#[derive(Debug, Clone)]
+pub struct Incident {
+    pub target: String,
+    pub start: DateTime<Local>,
+    pub end: Option<DateTime<Local>>,
+    /* (time, loss, latency) of every chunk seen while open */
+    pub samples: Vec<(DateTime<Local>, f64, f64)>,
+    /* packet capture file taken during the incident */
+    pub capture: Option<String>,
+}
+
+impl Incident {
+    fn new(target: &str, chunk: &PacketChunk) -> Self {
+        Incident {
+            target: target.to_string(),
+            start: chunk.time(),
+            end: None,
+            samples: vec![],
+            capture: None,
+        }
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        self.end.unwrap_or_else(Local::now) - self.start
+    }
+
+    pub fn worst_loss(&self) -> f64 {
+        self.samples.iter()
+            .map(|x| x.1)
+            .fold(0.0, f64::max)
+    }
+
+    pub fn mean_loss(&self) -> f64 {
+        if self.samples.is_empty() {
+            0.0
+        } else {
+            self.samples.iter().map(|x| x.1).sum::<f64>() / self.samples.len() as f64
+        }
+    }
+
+    /* one character per chunk, denser glyphs for more loss */
+    pub fn chart(&self) -> String {
+        let ramp = [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+        self.samples.iter()
+            .map(|x| ramp[((x.1 * (ramp.len() - 1) as f64).round() as usize).min(ramp.len() - 1)])
+            .collect()
+    }
+
+    pub fn summary(&self) -> String {
+        let duration = self.duration();
+        let end = self.end
+            .map(|x| x.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| String::from("ongoing"));
+
+        format!("Incident on {}\n\n\
+                 start:      {}\n\
+                 end:        {}\n\
+                 duration:   {}m {}s\n\
+                 chunks:     {}\n\
+                 worst loss: {:.0}%\n\
+                 mean loss:  {:.0}%\n\n\
+                 loss per chunk (' ' = 0%, '@' = 100%):\n\
+                 [{}]\n{}",
+            self.target,
+            self.start.format("%Y-%m-%d %H:%M:%S"), end,
+            duration.num_minutes(), duration.num_seconds() % 60,
+            self.samples.len(),
+            self.worst_loss() * 100.0, self.mean_loss() * 100.0,
+            self.chart(),
+            self.capture.as_ref()
+                .map(|x| format!("\npacket capture: {}\n", x))
+                .unwrap_or_default())
+    }
+
+    /* substitute {target}, {start}, {end}, {duration}, {chunks}, {worst}, {mean}, {chart} and {capture} */
+    pub fn render(&self, template: &str) -> String {
+        let duration = self.duration();
+        let end = self.end
+            .map(|x| x.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| String::from("ongoing"));
+
+        template
+            .replace("{target}", &self.target)
+            .replace("{start}", &self.start.format("%Y-%m-%d %H:%M:%S").to_string())
+            .replace("{end}", &end)
+            .replace("{duration}", &format!("{}m {}s", duration.num_minutes(), duration.num_seconds() % 60))
+            .replace("{chunks}", &self.samples.len().to_string())
+            .replace("{worst}", &format!("{:.0}%", self.worst_loss() * 100.0))
+            .replace("{mean}", &format!("{:.0}%", self.mean_loss() * 100.0))
+            .replace("{chart}", &self.chart())
+            .replace("{capture}", self.capture.as_deref().unwrap_or("none"))
+    }
+}
+
+/// Receives incident lifecycle events
+pub trait Sink: Send {
+    fn open(&mut self, _incident: &Incident) {}
+    fn close(&mut self, _incident: &Incident) {}
+}
+
+/// Tracks a policy against the chunk stream of one target
+pub struct Escalator {
+    policy: Policy,
+    target: String,
+    lossy: u32,
+    good: u32,
+    armed: Vec<bool>,
+    fired: Vec<Option<Instant>>,
+    incident: Option<Incident>,
+    sinks: Vec<Box<dyn Sink>>,
+    pcap: Option<Pcap>,
+    /* the local network is down, which is no fault of the target's */
+    held: bool,
+}
+
+/// Everything an action needs to describe the event that triggered it
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub target: String,
+    pub stage: u32,
+    pub consecutive: u32,
+    pub loss: f64,
+    pub latency: f64,
+    pub time: String,
+    /* the latency (ms) the path had before, for a path shift rather than loss */
+    pub baseline: Option<f64>,
+}
+
+impl Escalator {
+    pub fn new(policy: Policy, target: &str) -> Self {
+        let n = policy.stages.len();
+
+        Escalator {
+            policy,
+            target: target.to_string(),
+            lossy: 0,
+            good: 0,
+            armed: vec![true; n],
+            fired: vec![None; n],
+            incident: None,
+            sinks: vec![],
+            pcap: None,
+            held: false,
+        }
+    }
+
+    /// Leave chunks out of alerting while `held`, as when the local network
+    /// is down and every target fails alike
+    pub fn hold(&mut self, held: bool) {
+        self.held = held;
+    }
+
+    pub fn is_lossy(&self, chunk: &PacketChunk) -> bool {
+        self.policy.is_lossy(chunk)
+    }
+
+    /// Swap in a new policy, keeping any open incident. Stages are re-armed
+    /// when they changed
+    pub fn set_policy(&mut self, policy: Policy) {
+        let same = policy.stages.len() == self.policy.stages.len()
+            && policy.stages.iter().zip(self.policy.stages.iter())
+                .all(|(a, b)| a.after == b.after && a.actions == b.actions);
+
+        if !same {
+            let n = policy.stages.len();
+            self.armed = vec![true; n];
+            self.fired = vec![None; n];
+        }
+
+        self.policy = policy;
+    }
+
+    pub fn sink(&mut self, sink: Box<dyn Sink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Capture packets for the length of each incident
+    pub fn pcap(&mut self, pcap: Pcap) {
+        self.pcap = Some(pcap);
+    }
+
+    /* feed one chunk, running the actions of every stage that fires */
+    pub fn update(&mut self, chunk: &PacketChunk) {
+        for (alert, action) in self.check(chunk) {
+            run(action, alert);
+        }
+    }
+
+    fn check(&mut self, chunk: &PacketChunk) -> Vec<(Alert, Action)> {
+        if self.held {
+            return vec![];
+        }
+
+        if let Some(ref mut incident) = self.incident {
+            incident.samples.push((chunk.time(), chunk.loss(), chunk.latency()));
+        }
+
+        if !self.policy.is_lossy(chunk) {
+            self.good += 1;
+
+            if self.good >= self.policy.rearm {
+                self.lossy = 0;
+                for armed in self.armed.iter_mut() {
+                    *armed = true;
+                }
+
+                if let Some(mut incident) = self.incident.take() {
+                    incident.end = Some(chunk.time());
+
+                    if let Some(ref mut pcap) = self.pcap {
+                        pcap.stop();
+                    }
+
+                    for sink in self.sinks.iter_mut() {
+                        sink.close(&incident);
+                    }
+                }
+            }
+
+            return vec![];
+        }
+
+        self.good = 0;
+        self.lossy += 1;
+
+        if self.incident.is_none() {
+            let mut incident = Incident::new(&self.target, chunk);
+            incident.samples.push((chunk.time(), chunk.loss(), chunk.latency()));
+
+            /* before the sinks, so they can point at the file */
+            if let Some(ref mut pcap) = self.pcap {
+                incident.capture = pcap.start(&incident);
+            }
+
+            for sink in self.sinks.iter_mut() {
+                sink.open(&incident);
+            }
+
+            self.incident = Some(incident);
+        }
+
+        let now = Instant::now();
+        let mut actions = vec![];
+
+        for (i, stage) in self.policy.stages.iter().enumerate() {
+
+            if !self.armed[i] || self.lossy < stage.after {
+                continue;
+            }
+
+            /* a re-armed stage still waits out its cooldown */
+            if let Some(last) = self.fired[i] {
+                if now.duration_since(last) < self.policy.cooldown {
+                    continue;
+                }
+            }
+
+            self.armed[i] = false;
+            self.fired[i] = Some(now);
+
+            let alert = Alert {
+                target: self.target.clone(),
+                stage: stage.after,
+                consecutive: self.lossy,
+                loss: chunk.loss(),
+                latency: chunk.latency(),
+                time: chunk.time().to_rfc3339(),
+                baseline: None,
+            };
+
+            for action in stage.actions.iter() {
+                actions.push((alert.clone(), action.clone()));
+            }
+        }
+
+        actions
+    }
+}
+
+impl Alert {
+    pub fn message(&self) -> String {
+        match self.baseline {
+            Some(baseline) => format!("{}: latency moved from {:.1}ms to {:.1}ms for {} chunks, the path may have changed",
+                self.target, baseline, self.latency, self.consecutive),
+            None => format!("{}: {} consecutive lossy chunks ({:.0}% loss, {:.01}ms)",
+                self.target, self.consecutive, self.loss * 100.0, self.latency),
+        }
+    }
+
+    pub fn json(&self) -> String {
+        let baseline = self.baseline
+            .map(|x| format!("{:.3}", x))
+            .unwrap_or_else(|| String::from("null"));
+
+        format!("{{\"target\":\"{}\",\"stage\":{},\"consecutive\":{},\"loss\":{:.4},\"latency\":{:.3},\"baseline\":{},\"time\":\"{}\",\"message\":\"{}\"}}",
+            json_escape(&self.target), self.stage, self.consecutive,
+            self.loss, self.latency, baseline, json_escape(&self.time),
+            json_escape(&self.message()))
+    }
+}
+
+pub fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/* actions run on their own thread so a slow hook never stalls the ui */
+pub fn run(action: Action, alert: Alert) {
+    info!("stage {}: {}", alert.stage, alert.message());
+
+    thread::spawn(move || {
+        match action {
+            Action::Notify => {
+                let status = Command::new("notify-send")
+                    .arg("packetloss")
+                    .arg(alert.message())
+                    .status();
+
+                if let Err(e) = status {
+                    warn!("{}: notify-send: {}", alert.target, e);
+                }
+            },
+            Action::Webhook(url) => {
+                let sent = ureq::post(&url)
+                    .set("Content-Type", "application/json")
+                    .timeout(Duration::from_secs(10))
+                    .send_string(&alert.json());
+
+                if let Err(e) = sent {
+                    warn!("{}: webhook: {}", alert.target, e);
+                }
+            },
+            Action::Command(cmd) => {
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(&cmd)
+                    .env("PACKETLOSS_TARGET", &alert.target)
+                    .env("PACKETLOSS_STAGE", alert.stage.to_string())
+                    .env("PACKETLOSS_CONSECUTIVE", alert.consecutive.to_string())
+                    .env("PACKETLOSS_LOSS", format!("{:.4}", alert.loss))
+                    .env("PACKETLOSS_LATENCY", format!("{:.3}", alert.latency))
+                    .env("PACKETLOSS_BASELINE", alert.baseline.map(|x| format!("{:.3}", x)).unwrap_or_default())
+                    .env("PACKETLOSS_MESSAGE", alert.message())
+                    .status();
+
+                match status {
+                    Ok(status) if !status.success() => warn!("{}: '{}' {}", alert.target, cmd, status),
+                    Err(e) => warn!("{}: '{}': {}", alert.target, cmd, e),
+                    _ => {},
+                }
+            },
+        }
+    });
+}
@@ -0,0 +1,130 @@
+use std::io::{self, Write};
+use std::panic;
+use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, ThreadId};
+
+use tui::Terminal;
+
+/* termion on unix by default, crossterm where termion can't run (windows
+ * consoles) or misbehaves. Raw mode ends when the `RawMode` guard drops */
+
+#[cfg(not(feature = "crossterm"))]
+pub type Backend = tui::backend::TermionBackend<termion::raw::RawTerminal<io::Stdout>>;
+
+#[cfg(feature = "crossterm")]
+pub type Backend = tui::backend::CrosstermBackend;
+
+/*
+ * whichever way the monitor ends, the shell gets its terminal back: the
+ * cursor shown, the screen cleared, the window title that was there
+ * before, and raw mode off. the guard does it on the way out of a return
+ * or a `?`, and a panic hook does it before the panic's message is
+ * printed, so that the message isn't smeared over a raw screen. a panic
+ * on another thread leaves the terminal to the thread that owns it.
+ */
+
+/* the terminal is raw, and which thread set it up */
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static OWNER: Mutex<Option<ThreadId>> = Mutex::new(None);
+static HOOK: Once = Once::new();
+
+/* the mode before raw mode, for the panic hook, which can't reach termion's copy */
+#[cfg(not(feature = "crossterm"))]
+static SAVED: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// Gives the terminal back when dropped
+pub struct RawMode {
+    _private: (),
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/* once only, whether from the guard or the hook */
+fn restore() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    let mut stdout = io::stdout();
+    /* cursor shown, screen cleared and homed, title popped */
+    let _ = write!(stdout, "\x1b[?25h\x1b[2J\x1b[H\x1b[23;0t");
+    let _ = stdout.flush();
+
+    leave_raw_mode();
+}
+
+#[cfg(not(feature = "crossterm"))]
+fn leave_raw_mode() {
+    if let Some(saved) = SAVED.lock().ok().and_then(|mut x| x.take()) {
+        unsafe {
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &saved);
+        }
+    }
+}
+
+#[cfg(feature = "crossterm")]
+fn leave_raw_mode() {
+    let _ = crossterm::terminal::disable_raw_mode();
+}
+
+fn install_hook() {
+    *OWNER.lock().unwrap_or_else(|x| x.into_inner()) = Some(thread::current().id());
+
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            let owner = *OWNER.lock().unwrap_or_else(|x| x.into_inner());
+
+            if owner == Some(thread::current().id()) {
+                restore();
+            }
+
+            previous(info);
+        }));
+    });
+}
+
+/* the title is kept before it is changed, to be put back by `restore` */
+fn begin() -> io::Result<RawMode> {
+    install_hook();
+    ACTIVE.store(true, Ordering::SeqCst);
+
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[22;0t")?;
+    stdout.flush()?;
+
+    Ok(RawMode { _private: () })
+}
+
+#[cfg(not(feature = "crossterm"))]
+pub fn terminal() -> io::Result<(Terminal<Backend>, RawMode)> {
+    use termion::raw::IntoRawMode;
+
+    unsafe {
+        let mut saved: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDOUT_FILENO, &mut saved) == 0 {
+            *SAVED.lock().unwrap_or_else(|x| x.into_inner()) = Some(saved);
+        }
+    }
+
+    let stdout = io::stdout().into_raw_mode()?;
+    let raw = begin()?;
+    let terminal = Terminal::new(tui::backend::TermionBackend::new(stdout))?;
+
+    Ok((terminal, raw))
+}
+
+#[cfg(feature = "crossterm")]
+pub fn terminal() -> io::Result<(Terminal<Backend>, RawMode)> {
+    crossterm::terminal::enable_raw_mode()?;
+    let raw = begin()?;
+    let terminal = Terminal::new(tui::backend::CrosstermBackend::new())?;
+
+    Ok((terminal, raw))
+}
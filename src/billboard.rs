@@ -0,0 +1,127 @@
+use tui::buffer::Buffer;
+use tui::widgets::Widget;
+use tui::layout::Rect;
+use tui::style::Color;
+
+use crate::ping::PacketChunk;
+use crate::report;
+
+/*
+ * billboard mode: the newest chunk's loss and latency in block digits,
+ * scaled up to fill the pane so they read from across the room.
+ */
+
+/* rows of every glyph, '#' is a filled cell */
+const ROWS: u16 = 5;
+
+fn glyph(c: char) -> [&'static str; ROWS as usize] {
+    match c {
+        '0' => ["###", "# #", "# #", "# #", "###"],
+        '1' => [" # ", "## ", " # ", " # ", "###"],
+        '2' => ["###", "  #", "###", "#  ", "###"],
+        '3' => ["###", "  #", "###", "  #", "###"],
+        '4' => ["# #", "# #", "###", "  #", "  #"],
+        '5' => ["###", "#  ", "###", "  #", "###"],
+        '6' => ["###", "#  ", "###", "# #", "###"],
+        '7' => ["###", "  #", "  #", "  #", "  #"],
+        '8' => ["###", "# #", "###", "# #", "###"],
+        '9' => ["###", "# #", "###", "  #", "###"],
+        '%' => ["# #", "  #", " # ", "#  ", "# #"],
+        'm' => ["     ", "#### ", "# # #", "# # #", "# # #"],
+        's' => ["   ", " ##", "#  ", "  #", "## "],
+        '-' => ["   ", "   ", "###", "   ", "   "],
+        _ => ["   ", "   ", "   ", "   ", "   "],
+    }
+}
+
+/* glyphs are a column apart */
+fn width(text: &str) -> u16 {
+    text.chars()
+        .map(|c| glyph(c)[0].chars().count() as u16 + 1)
+        .sum::<u16>()
+        .saturating_sub(1)
+}
+
+/// The newest chunk of a target, or dashes before the first one
+pub struct Billboard<'a> {
+    chunk: Option<&'a PacketChunk>,
+}
+
+impl<'a> Billboard<'a> {
+    pub fn new(chunk: Option<&'a PacketChunk>) -> Self {
+        Billboard { chunk }
+    }
+
+    /* a line of text at font scale (sx, sy), its top left at (x, y) */
+    fn line(&self, text: &str, x: u16, y: u16, scale: (u16, u16), color: Color, buf: &mut Buffer) {
+        let (sx, sy) = scale;
+        let mut left = x;
+
+        for c in text.chars() {
+            let rows = glyph(c);
+
+            for (gy, row) in rows.iter().enumerate() {
+                for (gx, cell) in row.chars().enumerate() {
+                    if cell != '#' {
+                        continue;
+                    }
+
+                    let cx = left + gx as u16 * sx;
+                    let cy = y + gy as u16 * sy;
+                    for dy in 0..sy {
+                        for dx in 0..sx {
+                            buf.get_mut(cx + dx, cy + dy).set_symbol("█").set_fg(color);
+                        }
+                    }
+                }
+            }
+
+            left += (rows[0].chars().count() as u16 + 1) * sx;
+        }
+    }
+}
+
+impl<'a> Widget for Billboard<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        let (loss, latency, color) = match self.chunk {
+            Some(chunk) if chunk.down() => (String::from("100%"), String::from("-ms"), chunk.loss_color()),
+            Some(chunk) => (
+                format!("{:.0}%", chunk.loss() * 100.0),
+                format!("{:.0}ms", report::mean_latency(chunk)),
+                chunk.loss_color(),
+            ),
+            None => (String::from("-%"), String::from("-ms"), (128, 128, 128)),
+        };
+        let color = Color::Rgb(color.0, color.1, color.2);
+
+        /* loss above latency, a blank glyph row between them */
+        let columns = width(&loss).max(width(&latency));
+        let rows = ROWS * 2 + 1;
+
+        /* cells are about twice as tall as wide, so columns scale double */
+        let sy = (area.height / rows).min(area.width / (columns * 2));
+        let scale = if sy > 0 {
+            (sy * 2, sy)
+        } else if area.width >= columns && area.height >= rows {
+            (1, 1)
+        } else {
+            /* too small for block digits at all */
+            let text = format!("{} {}", loss, latency);
+            let x = area.x + area.width.saturating_sub(text.len() as u16) / 2;
+            buf.set_stringn(x, area.y + area.height / 2, &text, area.width as usize,
+                tui::style::Style::default().fg(color));
+            return;
+        };
+
+        let top = area.y + (area.height - rows * scale.1) / 2;
+        for (i, text) in [&loss, &latency].iter().enumerate() {
+            let x = area.x + (area.width - width(text) * scale.0) / 2;
+            let y = top + i as u16 * (ROWS + 1) * scale.1;
+            self.line(text, x, y, scale, if i == 0 { color } else { Color::White }, buf);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use tui::buffer::Buffer;
+use tui::widgets::Widget;
+use tui::layout::Rect;
+use tui::style::{Color, Style};
+
+use crate::locale::{self, Text};
+use crate::ping;
+use crate::report::{self, Day};
+
+/*
+ * calendar view: a month of the history, one cell per day with its uptime
+ * and the length and start of its worst incident, for going over a month
+ * of service at a time. a day with no loss is green, one with loss but
+ * always some replies amber (the slow color), and one that was down for a
+ * while red, fully red at 95% uptime or less.
+ */
+
+/// The first day of the month `date` is in
+pub fn month_of(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap()
+}
+
+/// The first day of the month `by` months after the one `month` starts
+pub fn shift(month: NaiveDate, by: i32) -> NaiveDate {
+    let months = month.year() * 12 + month.month0() as i32 + by;
+    NaiveDate::from_ymd_opt(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1).unwrap_or(month)
+}
+
+fn mix(a: (u8, u8, u8), b: (u8, u8, u8), mix: f64) -> (u8, u8, u8) {
+    let channel = |a: u8, b: u8| (a as f64 * mix + b as f64 * (1.0 - mix)) as u8;
+    (channel(a.0, b.0), channel(a.1, b.1), channel(a.2, b.2))
+}
+
+fn color(day: &Day) -> Color {
+    let palette = ping::palette();
+
+    let (r, g, b) = if day.uptime < 1.0 {
+        mix(palette.slow, palette.bad, ((day.uptime - 0.95) / 0.05).max(0.0))
+    } else if day.worst.is_some() {
+        palette.slow
+    } else {
+        palette.good
+    };
+
+    Color::Rgb(r, g, b)
+}
+
+/// The days of one month, see `report::days`
+pub struct Calendar<'a> {
+    days: &'a [Day],
+    month: NaiveDate,
+}
+
+impl<'a> Calendar<'a> {
+    pub fn new(days: &'a [Day], month: NaiveDate) -> Self {
+        Calendar {
+            days,
+            month: month_of(month),
+        }
+    }
+}
+
+impl<'a> Widget for Calendar<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        if area.width < 7 || area.height < 3 {
+            return;
+        }
+
+        let title = format!("◂ {} ▸", self.month.format("%Y-%m"));
+        let x = area.x + area.width.saturating_sub(title.chars().count() as u16) / 2;
+        buf.set_stringn(x, area.y, &title, area.width as usize, Style::default().fg(Color::White));
+
+        /* weeks start on monday, the first row may begin in the month before */
+        let offset = self.month.weekday().num_days_from_monday();
+        let length = (shift(self.month, 1) - self.month).num_days() as u32;
+        let weeks = (offset + length).div_ceil(7) as u16;
+
+        let width = area.width / 7;
+        let height = ((area.height - 2) / weeks).max(1);
+
+        for (i, name) in locale::tr(Text::Weekdays).split(' ').enumerate() {
+            buf.set_stringn(area.x + i as u16 * width, area.y + 1, name,
+                width.saturating_sub(1) as usize, Style::default().fg(Color::Gray));
+        }
+
+        for n in 0..length {
+            let date = self.month + Duration::days(n as i64);
+            let cell = offset + n;
+            let x = area.x + (cell % 7) as u16 * width;
+            let y = area.y + 2 + (cell / 7) as u16 * height;
+
+            if y >= area.bottom() {
+                break;
+            }
+
+            let day = self.days.iter().find(|x| x.date == date);
+            let style = match day {
+                Some(day) => Style::default().fg(Color::Black).bg(color(day)),
+                None => Style::default().fg(Color::DarkGray),
+            };
+
+            /* a column between cells */
+            let inner = width.saturating_sub(1);
+            let rows = height.min(area.bottom() - y);
+            for dy in 0..rows {
+                for dx in 0..inner {
+                    buf.get_mut(x + dx, y + dy).set_style(style);
+                }
+            }
+
+            let mut lines = vec![format!("{:>2}", date.day())];
+            if let Some(day) = day {
+                lines.push(format!("{:.2}%", day.uptime * 100.0));
+                if let Some(ref worst) = day.worst {
+                    lines.push(format!("{} {}", report::span(worst.duration()), worst.start.format("%H:%M")));
+                }
+            }
+
+            /* with no room for lines of their own, the number and uptime share one if they fit */
+            if rows == 1 && lines.len() > 1 {
+                let shared = format!("{} {}", lines[0], lines[1]);
+                if shared.len() <= inner as usize {
+                    lines = vec![shared];
+                }
+            }
+
+            for (dy, line) in lines.iter().take(rows as usize).enumerate() {
+                buf.set_stringn(x, y + dy as u16, line, inner as usize, style);
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::ping::PacketChunk;
+
+/*
+ * `packetloss check`: one burst judged against warning and critical
+ * thresholds, the way monitoring plugins report. the exit code is the
+ * status, 0 ok, 1 warning, 2 critical and 3 unknown when no ping could be
+ * sent, and the line printed carries the figures as performance data
+ * after a `|` for wrappers that graph them. latency is the median rtt of
+ * the replies, a value counts against a threshold once it is above it,
+ * and a host that never answers is critical.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl Status {
+    /// The exit code monitoring plugins use for it
+    pub fn code(self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Critical => 2,
+            Status::Unknown => 3,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// Loss in percent and latency in ms past which a check warns or fails
+#[derive(Debug, Clone, Default)]
+pub struct Thresholds {
+    pub warn_loss: Option<f64>,
+    pub crit_loss: Option<f64>,
+    pub warn_latency: Option<f64>,
+    pub crit_latency: Option<f64>,
+}
+
+fn past(value: Option<f64>, limit: Option<f64>) -> bool {
+    match (value, limit) {
+        (Some(value), Some(limit)) => value > limit,
+        _ => false,
+    }
+}
+
+impl Thresholds {
+    pub fn judge(&self, chunk: &PacketChunk) -> Status {
+        let loss = Some(chunk.loss() * 100.0);
+        let latency = chunk.median_rtt();
+
+        if chunk.received() == 0 || past(loss, self.crit_loss) || past(latency, self.crit_latency) {
+            Status::Critical
+        } else if past(loss, self.warn_loss) || past(latency, self.warn_latency) {
+            Status::Warning
+        } else {
+            Status::Ok
+        }
+    }
+}
+
+/* a performance data value, `label=value;warn;crit;min;max` */
+fn perf(label: &str, value: String, warn: Option<f64>, crit: Option<f64>, max: &str) -> String {
+    let limit = |x: Option<f64>| x.map(|x| x.to_string()).unwrap_or_default();
+    format!("{}={};{};{};0;{}", label, value, limit(warn), limit(crit), max)
+        .trim_end_matches(';')
+        .to_string()
+}
+
+/// The status line for a finished check
+pub fn line(address: &str, chunk: &PacketChunk, thresholds: &Thresholds, status: Status) -> String {
+    let lost = chunk.sent() - chunk.received();
+    let loss = chunk.loss() * 100.0;
+
+    let mut out = format!("PING {} - {}: {} sent, {} lost ({:.1}%)", status.name(), address, chunk.sent(), lost, loss);
+
+    if let Some(rtt) = chunk.median_rtt() {
+        out.push_str(&format!(", rtt {:.1}ms", rtt));
+    }
+    if let Some(jitter) = chunk.jitter() {
+        out.push_str(&format!(", jitter {:.1}ms", jitter));
+    }
+
+    out.push_str(&format!("|{}", perf("loss", format!("{:.1}%", loss), thresholds.warn_loss, thresholds.crit_loss, "100")));
+
+    if let Some(rtt) = chunk.median_rtt() {
+        out.push_str(&format!(" {}", perf("rtt", format!("{:.3}ms", rtt), thresholds.warn_latency, thresholds.crit_latency, "")));
+    }
+
+    out
+}
+
+/// The status line when no ping could be sent
+pub fn unknown(address: &str, error: &dyn std::fmt::Display) -> String {
+    format!("PING {} - {}: {}", Status::Unknown.name(), address, error)
+}
@@ -0,0 +1,109 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::dirs;
+use crate::ping::PacketChunk;
+use crate::record;
+use crate::target::Target;
+
+/*
+ * checkpoints of the history, for when the monitor never gets to exit: a
+ * power cut, a kill -9, a dropped ssh session. every --checkpoint seconds
+ * each target's history is written as a recording to checkpoints/ in the
+ * state directory, by way of a temporary file so that a cut mid-write
+ * leaves the previous checkpoint whole. a clean exit removes them, so one
+ * found at start was left by a monitor that didn't exit and is offered
+ * back before the ui starts. one written within the last two periods
+ * belongs to a monitor still running and is left alone.
+ */
+
+fn dir() -> PathBuf {
+    dirs::state()
+        .map(|x| x.join("checkpoints"))
+        .unwrap_or_else(|| env::temp_dir().join("packetloss-checkpoints"))
+}
+
+/* a target's checkpoint, kept per session when there is one */
+fn path(address: &str, session: Option<&str>) -> PathBuf {
+    let name = match session {
+        Some(session) => format!("{}-{}.log", session, record::file_name(address)),
+        None => format!("{}.log", record::file_name(address)),
+    };
+
+    dir().join(name)
+}
+
+/// The history a monitor left behind, for one target
+pub struct Left {
+    pub target: usize,
+    pub chunks: Vec<PacketChunk>,
+}
+
+pub struct Checkpoints {
+    /* one per target, in order */
+    paths: Vec<PathBuf>,
+    every: Duration,
+    last: Instant,
+}
+
+impl Checkpoints {
+    pub fn new(addresses: &[&str], session: Option<&str>, every: Duration) -> Self {
+        Checkpoints {
+            paths: addresses.iter().map(|x| path(x, session)).collect(),
+            every,
+            last: Instant::now(),
+        }
+    }
+
+    /// Checkpoints of these targets left by a monitor that didn't exit
+    /// cleanly, with any chunks in them
+    pub fn left(&self) -> Vec<Left> {
+        let running = self.every * 2;
+
+        self.paths.iter()
+            .enumerate()
+            .filter(|(_, path)| {
+                fs::metadata(path)
+                    .and_then(|x| x.modified())
+                    .map(|x| x.elapsed().map(|x| x >= running).unwrap_or(true))
+                    .unwrap_or(false)
+            })
+            .filter_map(|(i, path)| {
+                let chunks = record::read_chunks(&path.to_string_lossy()).ok()?;
+                Some(Left { target: i, chunks }).filter(|x| !x.chunks.is_empty())
+            })
+            .collect()
+    }
+
+    pub fn due(&self) -> bool {
+        self.last.elapsed() >= self.every
+    }
+
+    /// Write the history of every target, whether or not it was due
+    pub fn save(&mut self, targets: &[Target]) -> io::Result<()> {
+        self.last = Instant::now();
+        fs::create_dir_all(dir())?;
+
+        for (path, target) in self.paths.iter().zip(targets.iter()) {
+            let partial = path.with_extension("log.tmp");
+
+            /* the list is newest first, a recording oldest first */
+            let chunks: Vec<_> = target.list.chunks().collect();
+            record::write(chunks.into_iter().rev(), Some(&target.address), &partial.to_string_lossy())?;
+
+            fs::rename(&partial, path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Forget the checkpoints on a clean exit
+    pub fn remove(&self) {
+        for path in self.paths.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
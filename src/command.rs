@@ -0,0 +1,162 @@
+use crate::event::Key;
+
+/*
+ * what the keys do, by name
+ *
+ * every key in the monitor maps to one of these, and a macro binds a key to
+ * several of them in a row. a macro's commands all run before the next
+ * redraw, so it acts as one step.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    SelectNext,
+    SelectPrev,
+    SelectFirst,
+    SelectLast,
+    Clear,
+    ToggleDebug,
+    ToggleMos,
+    ToggleLog,
+    ToggleOrder,
+    ToggleView,
+    /* all targets side by side, or only the one in view */
+    ToggleSplit,
+    ToggleBillboard,
+    ToggleCalendar,
+    /* the month shown in the calendar */
+    PrevMonth,
+    NextMonth,
+    NextTarget,
+    PrevTarget,
+    /* with many targets, the grid of them all or the chosen one's history */
+    ToggleOverview,
+    Probe,
+    /* read the config file again */
+    Reload,
+    /* open the label prompt */
+    Marker,
+    /* place a marker with this label */
+    Mark(String),
+    Quit,
+}
+
+impl Command {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let mut parts = s.splitn(2, ' ');
+        let name = parts.next().unwrap();
+        let arg = parts.next().map(|x| x.trim().to_string());
+
+        let command = match (name, arg) {
+            ("select-next", None) => Command::SelectNext,
+            ("select-prev", None) => Command::SelectPrev,
+            ("select-first", None) => Command::SelectFirst,
+            ("select-last", None) => Command::SelectLast,
+            ("clear", None) => Command::Clear,
+            ("toggle-debug", None) => Command::ToggleDebug,
+            ("toggle-mos", None) => Command::ToggleMos,
+            ("toggle-log", None) => Command::ToggleLog,
+            ("toggle-order", None) => Command::ToggleOrder,
+            ("toggle-view", None) => Command::ToggleView,
+            ("toggle-split", None) => Command::ToggleSplit,
+            ("toggle-billboard", None) => Command::ToggleBillboard,
+            ("toggle-calendar", None) => Command::ToggleCalendar,
+            ("prev-month", None) => Command::PrevMonth,
+            ("next-month", None) => Command::NextMonth,
+            ("next-target", None) => Command::NextTarget,
+            ("prev-target", None) => Command::PrevTarget,
+            ("toggle-overview", None) => Command::ToggleOverview,
+            ("probe", None) => Command::Probe,
+            ("reload", None) => Command::Reload,
+            ("marker", None) => Command::Marker,
+            ("mark", label) => Command::Mark(label.unwrap_or_default()),
+            ("quit", None) => Command::Quit,
+            (_, Some(_)) if NAMES.contains(&name) => {
+                return Err(format!("Command '{}' takes no argument", name));
+            },
+            _ => {
+                return Err(format!("Unknown command '{}' (expected one of {})", name, NAMES.join(", ")));
+            },
+        };
+
+        Ok(command)
+    }
+}
+
+const NAMES: &[&str] = &[
+    "select-next", "select-prev", "select-first", "select-last", "clear",
+    "toggle-debug", "toggle-mos", "toggle-log", "toggle-order", "toggle-view",
+    "toggle-split", "toggle-billboard", "toggle-calendar", "prev-month", "next-month",
+    "next-target", "prev-target", "toggle-overview", "probe", "reload", "marker", "mark", "quit",
+];
+
+/// The command behind a key when no macro is bound to it
+pub fn builtin(key: Key) -> Option<Command> {
+    let command = match key {
+        Key::Char('q') => Command::Quit,
+        Key::Char('i') => Command::Marker,
+        Key::Char('j') | Key::Down | Key::Right => Command::SelectNext,
+        Key::Char('k') | Key::Up | Key::Left => Command::SelectPrev,
+        Key::Char('g') | Key::Home => Command::SelectFirst,
+        Key::Char('G') | Key::End => Command::SelectLast,
+        Key::Esc => Command::Clear,
+        Key::Char('D') => Command::ToggleDebug,
+        Key::Char('m') => Command::ToggleMos,
+        Key::Char('r') => Command::Probe,
+        Key::Char('R') => Command::Reload,
+        Key::Char('o') => Command::ToggleOrder,
+        Key::Char('v') => Command::ToggleView,
+        Key::Char('s') => Command::ToggleSplit,
+        Key::Char('b') => Command::ToggleBillboard,
+        Key::Char('c') => Command::ToggleCalendar,
+        Key::Char('[') => Command::PrevMonth,
+        Key::Char(']') => Command::NextMonth,
+        Key::Char('\t') => Command::NextTarget,
+        Key::BackTab => Command::PrevTarget,
+        Key::Char('\n') => Command::ToggleOverview,
+        Key::Char('l') => Command::ToggleLog,
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+/* F1 to F12, or a single character */
+fn parse_key(s: &str) -> Option<Key> {
+    let mut chars = s.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_control() => return Some(Key::Char(c)),
+        _ => {},
+    }
+
+    s.strip_prefix('F')
+        .and_then(|x| x.parse::<u8>().ok())
+        .filter(|x| (1..=12).contains(x))
+        .map(Key::F)
+}
+
+/* `KEY=COMMAND;COMMAND...`, e.g. `F5=select-first;probe` */
+pub fn parse_macro(spec: &str) -> Result<(Key, Vec<Command>), String> {
+    let mut parts = spec.splitn(2, '=');
+
+    let key = parts.next()
+        .and_then(|x| parse_key(x.trim()))
+        .ok_or_else(|| String::from("Macro must start with F1-F12 or a single character, e.g. F5=probe"))?;
+
+    let commands = parts.next()
+        .filter(|x| !x.trim().is_empty())
+        .ok_or_else(|| String::from("Macro must name at least one command, e.g. F5=probe"))?;
+
+    let commands = commands.split(';')
+        .map(Command::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((key, commands))
+}
+
+/// Validator for `--bind` values, in the same shape as `is_int`
+pub fn is_macro(v: String) -> Result<(), String> {
+    parse_macro(&v).map(|_| ())
+}
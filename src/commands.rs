@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use clap::ArgMatches;
+
+use crate::check;
+use crate::config::{self, Config, Settings};
+use crate::discover;
+use crate::error::{Context, Error};
+use crate::icmp::Ping;
+use crate::import;
+use crate::liveness;
+use crate::netns;
+use crate::output;
+use crate::ping::PacketChunk;
+use crate::query;
+use crate::record;
+use crate::report;
+use crate::setup::labels;
+
+/*
+ * the subcommands that run once and exit rather than monitor: reading
+ * and writing recordings, one-off checks, the config file and `serve`.
+ */
+
+/* the config file at `path` if there is one, with `profile` picked in it */
+pub fn load_config(path: Option<&Path>, profile: Option<&str>) -> Result<Option<Config>, Error> {
+    let mut config = match path {
+        Some(path) => Config::load(path).map_err(Error::Config)?,
+        None => None,
+    };
+
+    if let Some(name) = profile {
+        match config {
+            Some(ref mut config) => config.select(name).map_err(Error::Config)?,
+            None => return Err(Error::Config(format!("no config file to take profile '{}' from", name))),
+        }
+    }
+
+    Ok(config)
+}
+
+pub fn config_command(matches: &ArgMatches, sub: &ArgMatches, path: Option<PathBuf>, profile: Option<String>,
+                  env: &BTreeMap<&'static str, Vec<String>>) -> Result<(), Error> {
+
+    let path = |sub: &ArgMatches| sub.value_of("path")
+        .map(PathBuf::from)
+        .or_else(|| path.clone())
+        .ok_or_else(|| Error::Config(String::from("no config path, pass one or use --config")));
+
+    match sub.subcommand() {
+        ("init", Some(init)) => {
+            let path = path(init)?;
+
+            if path.exists() && !init.is_present("force") {
+                return Err(Error::Config(format!("{} already exists, use --force to overwrite", path.display())));
+            }
+
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            fs::write(&path, config::template(&Settings::new(matches, None))).file(&path)?;
+            println!("wrote {}", path.display());
+        },
+        ("validate", Some(validate)) => {
+            let path = path(validate)?;
+            let errors = config::validate(&path).file(&path)?;
+
+            if errors.is_empty() {
+                println!("{}: ok", path.display());
+            } else {
+                for e in errors.iter() {
+                    eprintln!("{}", e);
+                }
+                process::exit(1);
+            }
+        },
+        ("show", _) => {
+            let config = load_config(path(sub).ok().as_deref(), profile.as_deref())?;
+
+            print!("{}", config::show(&Settings::new(matches, config.as_ref()).environment(env)));
+        },
+        _ => {
+            println!("{}", sub.usage());
+        },
+    }
+
+    Ok(())
+}
+
+pub fn discover_command(matches: &ArgMatches) -> Result<(), Error> {
+    let count = matches.value_of("count").unwrap()
+        .parse::<u64>().unwrap();
+    let timeout = matches.value_of("timeout").unwrap()
+        .parse::<u64>().unwrap();
+
+    let neighbors = match matches.value_of("sweep") {
+        /* already checked by the validator */
+        Some(subnet) => discover::sweep(subnet).unwrap(),
+        None => discover::neighbors()?,
+    };
+
+    let mut results = discover::probe(neighbors, count, Duration::from_millis(timeout));
+
+    /* a sweep hits every address, only the ones that answered are hosts */
+    if matches.is_present("sweep") {
+        results.retain(|x| !x.1.down());
+    }
+
+    print!("{}", discover::table(results));
+
+    Ok(())
+}
+
+pub fn import_command(matches: &ArgMatches) -> Result<(), Error> {
+    let file = matches.value_of("file").unwrap();
+    let output = matches.value_of("output").unwrap();
+
+    /* the last line of a log without timestamps was written as the file was */
+    let end = match file {
+        "-" => Local::now(),
+        _ => fs::metadata(file).and_then(|x| x.modified()).file(file)?.into(),
+    };
+    let options = import::Options {
+        chunk_size: matches.value_of("chunk-size").unwrap().parse::<u64>().unwrap(),
+        interval: matches.value_of("interval").unwrap().parse::<f64>().unwrap(),
+        start: matches.value_of("start").and_then(query::parse_time),
+        end,
+        timeout: matches.value_of("timeout").unwrap().parse::<f64>().unwrap(),
+    };
+
+    let (target, chunks) = match file {
+        "-" => import::parse(io::stdin().lock(), &options).during("reading stdin")?,
+        _ => import::parse(io::BufReader::new(fs::File::open(file).file(file)?), &options).file(file)?,
+    };
+
+    record::write(chunks.iter(), target.as_deref(), output).file(output)?;
+    println!("{} chunks{} written to {}", chunks.len(),
+        target.map(|x| format!(" of {}", x)).unwrap_or_default(), output);
+
+    Ok(())
+}
+
+pub fn merge_command(matches: &ArgMatches) -> Result<(), Error> {
+    let files: Vec<&str> = matches.values_of("file").unwrap().collect();
+    let output = matches.value_of("output").unwrap();
+    let merged = record::merge(&files, output).during("merging recordings")?;
+
+    println!("{} chunks from {} recordings written to {}, {} duplicates dropped",
+        merged.chunks, files.len(), output, merged.duplicates);
+
+    Ok(())
+}
+
+pub fn export_command(matches: &ArgMatches) -> Result<(), Error> {
+    let file = matches.value_of("file").unwrap();
+    let (target, chunks) = record::read(file).file(file)?;
+
+    let tags: Vec<String> = matches.values_of("tag").into_iter().flatten().map(String::from).collect();
+
+    match export(target.as_deref().unwrap_or(file), &tags, &chunks, matches.value_of("format").unwrap()) {
+        /* piped into head */
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(Error::from),
+    }
+}
+
+/* a recording's chunks, oldest first, for other tools */
+fn export(target: &str, tags: &[String], chunks: &[PacketChunk], format: &str) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    /* a spreadsheet gets the columns of query, the rest the lines of --no-tui */
+    let format = match output::Format::parse(format) {
+        Some(format) => format,
+        None => {
+            let all = query::Query { since: None, until: None, conditions: vec![] };
+            return all.run(chunks, query::Format::Csv, &mut stdout).map(|_| ());
+        },
+    };
+
+    let mut out = output::Output::new(stdout, format, target);
+    out.set_tags(tags);
+    for chunk in chunks.iter() {
+        out.chunk(chunk)?;
+    }
+
+    Ok(())
+}
+
+pub fn query_command(matches: &ArgMatches) -> Result<(), Error> {
+    let query = query::Query {
+        since: matches.value_of("since").and_then(query::parse_time),
+        until: matches.value_of("until").and_then(query::parse_time),
+        conditions: matches.values_of("where").into_iter().flatten()
+            .map(|x| query::Condition::parse(x).unwrap())
+            .collect(),
+    };
+    let format = matches.value_of("format").and_then(query::Format::parse).unwrap();
+
+    let file = matches.value_of("file").unwrap();
+    let chunks = record::read_chunks(file).file(file)?;
+    match query.run(&chunks, format, &mut io::stdout().lock()) {
+        /* piped into head */
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map(|_| ()).map_err(Error::from),
+    }
+}
+
+pub fn report_command(matches: &ArgMatches) -> Result<(), Error> {
+    match matches.values_of("compare") {
+        Some(files) => {
+            let files: Vec<&str> = files.collect();
+            print!("{}", report::compare(&record::read_chunks(files[0]).file(files[0])?,
+                &record::read_chunks(files[1]).file(files[1])?));
+        },
+        None => {
+            let file = matches.value_of("file").unwrap();
+            print!("{}", report::summary(&record::read_chunks(file).file(file)?));
+        },
+    }
+
+    Ok(())
+}
+
+pub fn check_command(matches: &ArgMatches) -> ! {
+    let address = matches.value_of("address").unwrap();
+    let count = matches.value_of("count").unwrap()
+        .parse::<u64>().unwrap()
+        .max(1);
+    let timeout = matches.value_of("timeout").unwrap()
+        .parse::<u64>().unwrap();
+    let limit = |name: &str| matches.value_of(name).map(|x| x.parse::<f64>().unwrap());
+
+    let thresholds = check::Thresholds {
+        warn_loss: limit("warn-loss"),
+        crit_loss: limit("crit-loss"),
+        warn_latency: limit("warn-latency"),
+        crit_latency: limit("crit-latency"),
+    };
+
+    let ping = Ping::new(address, Duration::from_millis(timeout));
+
+    match ping.mode().and_then(|_| ping.ping(count)) {
+        Ok(chunk) => {
+            let status = thresholds.judge(&chunk);
+            println!("{}", check::line(address, &chunk, &thresholds, status));
+            process::exit(status.code());
+        },
+        Err(e) => {
+            println!("{}", check::unknown(address, &e));
+            process::exit(check::Status::Unknown.code());
+        },
+    }
+}
+
+/*
+ * one burst and an exit code, for orchestrators that only look at that:
+ * 0 healthy, 1 unhealthy, 2 when no ping can be sent. a name that doesn't
+ * resolve is unhealthy, a container's dns is part of its network, and so
+ * is a host that never answers, whatever --max-loss allows.
+ */
+pub fn healthcheck_command(matches: &ArgMatches) -> ! {
+    let address = matches.value_of("address").unwrap();
+    let samples = matches.value_of("samples").unwrap()
+        .parse::<u64>().unwrap()
+        .max(1);
+    let max_loss = matches.value_of("max-loss").unwrap()
+        .parse::<f64>().unwrap() / 100.0;
+    let max_latency = matches.value_of("max-latency")
+        .map(|x| x.parse::<f64>().unwrap());
+    let timeout = matches.value_of("timeout").unwrap()
+        .parse::<u64>().unwrap();
+    let quiet = matches.is_present("quiet");
+
+    let ping = Ping::new(address, Duration::from_millis(timeout));
+
+    let chunk = match ping.mode().and_then(|_| ping.ping(samples)) {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            eprintln!("{}: {}", address, e);
+            process::exit(2);
+        },
+    };
+
+    let latency = report::mean_latency(&chunk);
+    let healthy = chunk.received() > 0
+        && chunk.loss() <= max_loss
+        && max_latency.is_none_or(|x| latency <= x);
+
+    if !quiet {
+        let mut line = format!("{}: {}, {}/{} lost", address, if healthy { "healthy" } else { "unhealthy" },
+            chunk.sent() - chunk.received(), chunk.sent());
+        if chunk.received() > 0 {
+            line.push_str(&format!(", {:.1}ms", latency));
+        }
+        println!("{}", line);
+    }
+
+    process::exit(if healthy { 0 } else { 1 });
+}
+
+/* probe forever, writing chunks to stdout until nobody is reading */
+pub fn serve(settings: &Settings) -> Result<(), Error> {
+    let address = match settings.value_of("address") {
+        Some(address) => address,
+        None => {
+            clap::Error::with_description(
+                "The following required arguments were not provided:\n    <address>",
+                clap::ErrorKind::MissingRequiredArgument).exit();
+        },
+    };
+    let chunk_size = settings.value_of("chunk-size").unwrap()
+        .parse::<u64>().unwrap();
+    let interval = settings.value_of("interval").unwrap()
+        .parse::<u64>().unwrap();
+    let timeout = settings.value_of("timeout").unwrap()
+        .parse::<u64>().unwrap();
+
+    if let Some(name) = settings.value_of("netns") {
+        netns::enter(name).during(&format!("entering network namespace {}", name))?;
+    }
+
+    let format = settings.value_of("stdout-format")
+        .and_then(output::Format::parse)
+        .unwrap_or(output::Format::Stream);
+    let every = settings.value_of("heartbeat-every")
+        .map(|x| Duration::from_secs(x.parse::<u64>().unwrap().max(1)));
+
+    /* a chunk takes up to a timeout per ping on top of the interval */
+    let stale = settings.value_of("stale-after")
+        .map(|x| Duration::from_secs(x.parse::<u64>().unwrap()))
+        .unwrap_or_else(|| (Duration::from_secs(interval) + Duration::from_millis(timeout * chunk_size)) * 3);
+    let liveness = liveness::Liveness::new(stale);
+
+    if let Some(addr) = settings.value_of("health-listen") {
+        liveness::listen(addr, liveness.clone()).during(&format!("serving health checks on {}", addr))?;
+    }
+
+    let ping = Ping::new(address, Duration::from_millis(timeout));
+    ping.mode().target(address)?;
+    let stdout = io::stdout();
+    let mut out = output::Output::new(stdout.lock(), format, address);
+    if let Some((tags, _)) = labels(settings, &[address]).map_err(Error::Config)?.pop() {
+        out.set_tags(&tags);
+    }
+
+    let mut beat = every.map(|x| Instant::now() + x);
+
+    loop {
+        let chunk = ping.ping(chunk_size).target(address)?;
+        liveness.chunk();
+
+        match out.chunk(&chunk) {
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+
+        /* sleep out the interval, waking for any heartbeats that fall inside it */
+        let wake = Instant::now() + Duration::from_secs(interval);
+
+        while let Some(next) = beat.filter(|x| *x < wake) {
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+
+            match out.heartbeat() {
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+
+            beat = every.map(|x| next + x);
+        }
+
+        thread::sleep(wake.saturating_duration_since(Instant::now()));
+    }
+}
@@ -0,0 +1,896 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use crate::alert;
+use crate::command;
+use crate::dirs;
+use crate::draw;
+use crate::http;
+use crate::locale;
+use crate::logfile;
+use crate::ping::{self, Scale};
+use crate::output;
+use crate::reflect;
+use crate::session;
+use crate::shift;
+use crate::term;
+
+/*
+ * configuration file support. every key mirrors a long command line
+ * option, and can also be set as PACKETLOSS_<KEY> in the environment;
+ * values are resolved as command line > environment > config file > default.
+ * a [profile.NAME] table holds the same keys, and when picked with
+ * --profile sits over the rest of the file.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Int,
+    Str,
+    List,
+    Bool,
+    Float,
+}
+
+/// Every option that may be set from the config file, with a short description
+pub const KEYS: &[(&str, Kind, &str)] = &[
+    ("address", Kind::Str, "host to ping"),
+    ("host", Kind::List, "more hosts to ping alongside address, on the same schedule"),
+    ("discover-lan", Kind::Bool, "also ping every live host of the neighbor table"),
+    ("targets", Kind::Str, "toml file of targets to ping, each a [[target]] with its own tags, accent and rearm"),
+    ("tag", Kind::List, "labels for the targets the targets file gives none, e.g. [\"WAN\"]"),
+    ("session", Kind::Str, "session name, its layout is saved on quit and restored next time"),
+    ("accent", Kind::Str, "border color of the panes of the targets the targets file gives none, a name or #rrggbb"),
+    ("color-good", Kind::Str, "tile color with no loss at the lowest latency, #rrggbb"),
+    ("color-bad", Kind::Str, "tile color with all packets lost or at the top of the latency scale, #rrggbb"),
+    ("color-down", Kind::Str, "tile color when nothing came back at all, #rrggbb"),
+    ("color-slow", Kind::Str, "color slow replies pull a tile toward, #rrggbb"),
+    ("chunk-size", Kind::Int, "number of pings per chunk"),
+    ("interval", Kind::Int, "interval between pings (s)"),
+    ("timeout", Kind::Int, "ping timeout duration (ms)"),
+    ("stdout-format", Kind::Str, "output of serve, daemon and --no-tui, \"stream\", \"logfmt\", \"json\" or \"plain\""),
+    ("heartbeat-every", Kind::Int, "in serve, also write a heartbeat line this often (s)"),
+    ("health-listen", Kind::Str, "in serve, address ([ADDRESS]:PORT) for the /healthz and /readyz endpoints"),
+    ("stale-after", Kind::Int, "in serve, age of the last chunk at which /healthz fails (s)"),
+    ("via", Kind::Str, "probe from a remote machine, ssh://[user@]host[:port][/path/to/packetloss]"),
+    ("netns", Kind::Str, "network namespace to probe from, a name or path"),
+    ("reflector", Kind::Str, "udp reflector (HOST:PORT) to probe instead of pinging"),
+    ("twamp", Kind::Str, "TWAMP-light reflector (HOST[:PORT]) to probe instead of pinging"),
+    ("tcp", Kind::Str, "HOST:PORT to time tcp connects to instead of pinging"),
+    ("http", Kind::Str, "URL to time http requests to instead of pinging"),
+    ("http-method", Kind::Str, "request method for http, \"HEAD\" or \"GET\""),
+    ("probe-cmd", Kind::Str, "shell command to run for each probe instead of pinging"),
+    ("proto", Kind::Str, "what to probe the hosts with, \"icmp\" or \"udp\""),
+    ("port", Kind::Int, "port for proto udp"),
+    ("slow", Kind::Int, "soft timeout, slower replies are shown as slow (ms)"),
+    ("max", Kind::Int, "maximum number of packets to be stored"),
+    ("fps", Kind::Int, "maximum redraws per second"),
+    ("no-blink", Kind::Bool, "never flash the newest tile when loss arrives"),
+    ("no-tui", Kind::Bool, "probe and alert without a ui, writing each chunk to stdout"),
+    ("capture", Kind::Int, "pings in the burst sent right after a lossy chunk"),
+    ("capture-spacing", Kind::Int, "time between capture pings (ms)"),
+    ("alert-loss", Kind::Int, "packet loss (%) at which a chunk counts as lossy"),
+    ("game", Kind::Int, "game preset, the tick rate (Hz) pings are sent at"),
+    ("frame-budget", Kind::Int, "in game mode, replies slower than this are late (ms)"),
+    ("mos", Kind::Bool, "color tiles by estimated VoIP call quality (MOS)"),
+    ("alert-mos", Kind::Float, "estimated MOS below which a chunk counts as lossy"),
+    ("weight-bursts", Kind::Bool, "color tiles by loss weighted for drops one after another"),
+    ("alert-burst", Kind::Int, "probes lost in a row at which a chunk counts as lossy"),
+    ("latency-color", Kind::Str, "how latency maps to color, \"linear\" or \"log\""),
+    ("order", Kind::Str, "where the tile flow starts, \"newest-first\" or \"oldest-first\""),
+    ("view", Kind::Str, "how the history is laid out, \"tiles\" or \"timeline\""),
+    ("lang", Kind::Str, "language of the labels and reports, \"en\", \"de\" or \"es\""),
+    ("bind", Kind::List, "keys bound to commands, e.g. [\"F5=select-first;probe\"]"),
+    ("wrap", Kind::Bool, "moving the selection past either end of the history wraps around"),
+    ("billboard", Kind::Bool, "start with the newest loss and latency in huge digits"),
+    ("latency-scale", Kind::Str, "latency (ms) drawn fully green and fully red, e.g. \"10:200\""),
+    ("escalate", Kind::List, "escalation stages, e.g. [\"2:notify\", \"10:webhook=https://...\"]"),
+    ("alert-cooldown", Kind::Int, "minimum time before a stage may fire again (s)"),
+    ("alert-rearm", Kind::Int, "number of good chunks needed to re-arm escalation"),
+    ("alert-shift", Kind::Float, "factor the baseline latency must move by to count as a path shift"),
+    ("alert-shift-for", Kind::Int, "how long the latency must stay moved to count as a path shift (s)"),
+    ("shift-action", Kind::List, "actions run on a path shift up, e.g. [\"notify\"]"),
+    ("smtp", Kind::Str, "smtp relay for incident emails"),
+    ("smtp-from", Kind::Str, "sender address for incident emails"),
+    ("smtp-to", Kind::List, "recipient addresses for incident emails"),
+    ("slack", Kind::Str, "slack incoming webhook url"),
+    ("discord", Kind::Str, "discord webhook url"),
+    ("telegram-token", Kind::Str, "telegram bot token"),
+    ("telegram-chat", Kind::Str, "telegram chat id"),
+    ("open-template", Kind::Str, "chat message sent when an incident opens"),
+    ("close-template", Kind::Str, "chat message sent when an incident closes"),
+    ("pagerduty", Kind::Str, "pagerduty routing key"),
+    ("opsgenie", Kind::Str, "opsgenie api key"),
+    ("pcap-dir", Kind::Str, "directory for packet captures taken while an incident is open"),
+    ("pcap-command", Kind::Str, "capture command, {file}, {target} and {max} are substituted"),
+    ("pcap-linger", Kind::Int, "keep capturing this long after an incident closes (s)"),
+    ("pcap-max", Kind::Int, "packets at which a capture stops on its own"),
+    ("heartbeat-url", Kind::Str, "url requested after every completed chunk"),
+    ("throughput-cmd", Kind::Str, "throughput test command, printing Mbit/s"),
+    ("throughput-url", Kind::Str, "url downloaded from as a throughput test"),
+    ("throughput-every", Kind::Int, "chunks between throughput tests"),
+    ("retransmits", Kind::Str, "show tcp retransmits of the host (\"all\") or of a peer"),
+    ("record-events", Kind::Str, "record the session to this file, for replay"),
+    ("checkpoint", Kind::Int, "how often the history is checkpointed for a restore after a crash (s), 0 for never"),
+    ("log-file", Kind::Str, "append probe errors, alert failures and scheduling to this file"),
+    ("log-level", Kind::Str, "least severe lines written to log-file, \"error\", \"warn\", \"info\", \"debug\" or \"trace\""),
+    ("listen", Kind::Str, "in reflect, address ([ADDRESS]:PORT) to echo probes on"),
+];
+
+/* options that are useless without a partner */
+const REQUIRES: &[(&str, &str)] = &[
+    ("smtp", "smtp-to"),
+    ("telegram-token", "telegram-chat"),
+    ("telegram-chat", "telegram-token"),
+    ("shift-action", "alert-shift"),
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    address: Option<String>,
+    host: Option<Vec<String>>,
+    discover_lan: Option<bool>,
+    targets: Option<String>,
+    tag: Option<Vec<String>>,
+    session: Option<String>,
+    accent: Option<String>,
+    color_good: Option<String>,
+    color_bad: Option<String>,
+    color_down: Option<String>,
+    color_slow: Option<String>,
+    chunk_size: Option<u64>,
+    interval: Option<u64>,
+    timeout: Option<u64>,
+    stdout_format: Option<String>,
+    heartbeat_every: Option<u64>,
+    health_listen: Option<String>,
+    stale_after: Option<u64>,
+    via: Option<String>,
+    netns: Option<String>,
+    reflector: Option<String>,
+    twamp: Option<String>,
+    tcp: Option<String>,
+    http: Option<String>,
+    http_method: Option<String>,
+    probe_cmd: Option<String>,
+    proto: Option<String>,
+    port: Option<u64>,
+    slow: Option<u64>,
+    max: Option<u64>,
+    fps: Option<u64>,
+    no_blink: Option<bool>,
+    no_tui: Option<bool>,
+    capture: Option<u64>,
+    capture_spacing: Option<u64>,
+    alert_loss: Option<u64>,
+    game: Option<u64>,
+    frame_budget: Option<u64>,
+    mos: Option<bool>,
+    alert_mos: Option<f64>,
+    weight_bursts: Option<bool>,
+    alert_burst: Option<u64>,
+    latency_color: Option<String>,
+    order: Option<String>,
+    view: Option<String>,
+    lang: Option<String>,
+    bind: Option<Vec<String>>,
+    wrap: Option<bool>,
+    billboard: Option<bool>,
+    latency_scale: Option<String>,
+    escalate: Option<Vec<String>>,
+    alert_cooldown: Option<u64>,
+    alert_rearm: Option<u64>,
+    alert_shift: Option<f64>,
+    alert_shift_for: Option<u64>,
+    shift_action: Option<Vec<String>>,
+    smtp: Option<String>,
+    smtp_from: Option<String>,
+    smtp_to: Option<Vec<String>>,
+    slack: Option<String>,
+    discord: Option<String>,
+    telegram_token: Option<String>,
+    telegram_chat: Option<String>,
+    open_template: Option<String>,
+    close_template: Option<String>,
+    pagerduty: Option<String>,
+    opsgenie: Option<String>,
+    pcap_dir: Option<String>,
+    pcap_command: Option<String>,
+    pcap_linger: Option<u64>,
+    pcap_max: Option<u64>,
+    heartbeat_url: Option<String>,
+    throughput_cmd: Option<String>,
+    throughput_url: Option<String>,
+    throughput_every: Option<u64>,
+    retransmits: Option<String>,
+    record_events: Option<String>,
+    checkpoint: Option<u64>,
+    log_file: Option<String>,
+    log_level: Option<String>,
+    listen: Option<String>,
+    /* [profile.NAME] tables, each a set of the keys above */
+    profile: BTreeMap<String, Config>,
+    /* the profile picked with --profile, if any */
+    #[serde(skip)]
+    selected: Option<String>,
+}
+
+fn int(x: &Option<u64>) -> Option<Vec<String>> {
+    x.map(|x| vec![x.to_string()])
+}
+
+fn boolean(x: &Option<bool>) -> Option<Vec<String>> {
+    x.map(|x| vec![x.to_string()])
+}
+
+fn string(x: &Option<String>) -> Option<Vec<String>> {
+    x.clone().map(|x| vec![x])
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Read a config file, `Ok(None)` when it does not exist
+    pub fn load(path: &Path) -> Result<Option<Config>, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+        let config = Config::parse(&text)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        if let Some((key, e)) = config.check().into_iter().next() {
+            return Err(format!("{}:{}: {}: {}", path.display(), line_of(&text, key), key, e));
+        }
+
+        Ok(Some(config))
+    }
+
+    /* the file's values keyed by option name */
+    pub fn values(&self) -> BTreeMap<&'static str, Vec<String>> {
+        let values = vec![
+            ("address", string(&self.address)),
+            ("host", self.host.clone()),
+            ("discover-lan", boolean(&self.discover_lan)),
+            ("targets", string(&self.targets)),
+            ("tag", self.tag.clone()),
+            ("session", string(&self.session)),
+            ("accent", string(&self.accent)),
+            ("color-good", string(&self.color_good)),
+            ("color-bad", string(&self.color_bad)),
+            ("color-down", string(&self.color_down)),
+            ("color-slow", string(&self.color_slow)),
+            ("chunk-size", int(&self.chunk_size)),
+            ("interval", int(&self.interval)),
+            ("timeout", int(&self.timeout)),
+            ("stdout-format", string(&self.stdout_format)),
+            ("heartbeat-every", int(&self.heartbeat_every)),
+            ("health-listen", string(&self.health_listen)),
+            ("stale-after", int(&self.stale_after)),
+            ("via", string(&self.via)),
+            ("netns", string(&self.netns)),
+            ("reflector", string(&self.reflector)),
+            ("twamp", string(&self.twamp)),
+            ("tcp", string(&self.tcp)),
+            ("http", string(&self.http)),
+            ("http-method", string(&self.http_method)),
+            ("probe-cmd", string(&self.probe_cmd)),
+            ("proto", string(&self.proto)),
+            ("port", int(&self.port)),
+            ("slow", int(&self.slow)),
+            ("max", int(&self.max)),
+            ("fps", int(&self.fps)),
+            ("no-blink", boolean(&self.no_blink)),
+            ("no-tui", boolean(&self.no_tui)),
+            ("capture", int(&self.capture)),
+            ("capture-spacing", int(&self.capture_spacing)),
+            ("alert-loss", int(&self.alert_loss)),
+            ("game", int(&self.game)),
+            ("frame-budget", int(&self.frame_budget)),
+            ("mos", boolean(&self.mos)),
+            ("alert-mos", self.alert_mos.map(|x| vec![x.to_string()])),
+            ("weight-bursts", boolean(&self.weight_bursts)),
+            ("alert-burst", int(&self.alert_burst)),
+            ("latency-color", string(&self.latency_color)),
+            ("order", string(&self.order)),
+            ("view", string(&self.view)),
+            ("lang", string(&self.lang)),
+            ("bind", self.bind.clone()),
+            ("wrap", boolean(&self.wrap)),
+            ("billboard", boolean(&self.billboard)),
+            ("latency-scale", string(&self.latency_scale)),
+            ("escalate", self.escalate.clone()),
+            ("alert-cooldown", int(&self.alert_cooldown)),
+            ("alert-rearm", int(&self.alert_rearm)),
+            ("alert-shift", self.alert_shift.map(|x| vec![x.to_string()])),
+            ("alert-shift-for", int(&self.alert_shift_for)),
+            ("shift-action", self.shift_action.clone()),
+            ("smtp", string(&self.smtp)),
+            ("smtp-from", string(&self.smtp_from)),
+            ("smtp-to", self.smtp_to.clone()),
+            ("slack", string(&self.slack)),
+            ("discord", string(&self.discord)),
+            ("telegram-token", string(&self.telegram_token)),
+            ("telegram-chat", string(&self.telegram_chat)),
+            ("open-template", string(&self.open_template)),
+            ("close-template", string(&self.close_template)),
+            ("pagerduty", string(&self.pagerduty)),
+            ("opsgenie", string(&self.opsgenie)),
+            ("pcap-dir", string(&self.pcap_dir)),
+            ("pcap-command", string(&self.pcap_command)),
+            ("pcap-linger", int(&self.pcap_linger)),
+            ("pcap-max", int(&self.pcap_max)),
+            ("heartbeat-url", string(&self.heartbeat_url)),
+            ("throughput-cmd", string(&self.throughput_cmd)),
+            ("throughput-url", string(&self.throughput_url)),
+            ("throughput-every", int(&self.throughput_every)),
+            ("retransmits", string(&self.retransmits)),
+            ("record-events", string(&self.record_events)),
+            ("checkpoint", int(&self.checkpoint)),
+            ("log-file", string(&self.log_file)),
+            ("log-level", string(&self.log_level)),
+            ("listen", string(&self.listen)),
+        ];
+
+        values.into_iter()
+            .filter_map(|(k, v)| v.map(|v| (k, v)))
+            .collect()
+    }
+
+    /// Semantic checks the toml types can't express, as (key, message)
+    pub fn check(&self) -> Vec<(&'static str, String)> {
+        let values = self.values();
+        let base = check(&values);
+        let mut errors = base.clone();
+
+        /* a profile is checked over the file, reporting only what it adds */
+        for (name, profile) in self.profile.iter() {
+            if !profile.profile.is_empty() {
+                errors.push(("profile", format!("'{}': a profile can't hold profiles", name)));
+            }
+
+            let mut merged = values.clone();
+            merged.extend(profile.values());
+
+            for (key, e) in check(&merged).into_iter().filter(|x| !base.contains(x)) {
+                errors.push(("profile", format!("'{}': {}: {}", name, key, e)));
+            }
+        }
+
+        errors
+    }
+
+    /// Names of the profiles in the file
+    pub fn profiles(&self) -> Vec<&str> {
+        self.profile.keys().map(|x| x.as_str()).collect()
+    }
+
+    /// Pick the profile whose values go over the file's
+    pub fn select(&mut self, name: &str) -> Result<(), String> {
+        if !self.profile.contains_key(name) {
+            return Err(match self.profiles().as_slice() {
+                [] => format!("no profile '{}', the config file has no [profile.NAME] tables", name),
+                names => format!("no profile '{}', expected one of {}", name, names.join(", ")),
+            });
+        }
+
+        self.selected = Some(name.to_string());
+        Ok(())
+    }
+
+    /* the selected profile's values, empty with none selected */
+    fn selected_values(&self) -> BTreeMap<&'static str, Vec<String>> {
+        self.selected.as_ref()
+            .and_then(|x| self.profile.get(x))
+            .map(|x| x.values())
+            .unwrap_or_default()
+    }
+}
+
+fn check(values: &BTreeMap<&'static str, Vec<String>>) -> Vec<(&'static str, String)> {
+    let mut errors = vec![];
+
+    for stage in values.get("escalate").into_iter().flatten() {
+        if let Err(e) = alert::is_stage(stage.clone()) {
+            errors.push(("escalate", format!("'{}': {}", stage, e)));
+        }
+    }
+
+    for action in values.get("shift-action").into_iter().flatten() {
+        if let Err(e) = alert::is_action(action.clone()) {
+            errors.push(("shift-action", format!("'{}': {}", action, e)));
+        }
+    }
+
+    if let Some(factor) = values.get("alert-shift").and_then(|x| x.first()) {
+        if let Err(e) = shift::is_factor(factor.clone()) {
+            errors.push(("alert-shift", format!("'{}': {}", factor, e)));
+        }
+    }
+
+    for bind in values.get("bind").into_iter().flatten() {
+        if let Err(e) = command::is_macro(bind.clone()) {
+            errors.push(("bind", format!("'{}': {}", bind, e)));
+        }
+    }
+
+    if let Some(name) = values.get("session").and_then(|x| x.first()) {
+        if let Err(e) = session::is_name(name.clone()) {
+            errors.push(("session", format!("'{}': {}", name, e)));
+        }
+    }
+
+    if let Some(format) = values.get("stdout-format").and_then(|x| x.first()) {
+        if output::Format::parse(format).is_none() {
+            errors.push(("stdout-format", format!("'{}': expected stream, logfmt, json or plain", format)));
+        }
+    }
+
+    if let Some(url) = values.get("http").and_then(|x| x.first()) {
+        if let Err(e) = http::is_url(url.clone()) {
+            errors.push(("http", format!("'{}': {}", url, e)));
+        }
+    }
+
+    if let Some(method) = values.get("http-method").and_then(|x| x.first()) {
+        if http::Method::parse(method).is_none() {
+            errors.push(("http-method", format!("'{}': expected HEAD or GET", method)));
+        }
+    }
+
+    if let Some(proto) = values.get("proto").and_then(|x| x.first()) {
+        if proto != "icmp" && proto != "udp" {
+            errors.push(("proto", format!("'{}': expected icmp or udp", proto)));
+        }
+    }
+
+    if let Some(port) = values.get("port").and_then(|x| x.first()) {
+        if port.parse::<u16>().is_err() {
+            errors.push(("port", format!("'{}': expected a port number", port)));
+        }
+    }
+
+    for key in ["health-listen", "listen"].iter() {
+        if let Some(addr) = values.get(key).and_then(|x| x.first()) {
+            if let Err(e) = reflect::is_listen_addr(addr.clone()) {
+                errors.push((key, format!("'{}': {}", addr, e)));
+            }
+        }
+    }
+
+    if let Some(accent) = values.get("accent").and_then(|x| x.first()) {
+        if let Err(e) = term::is_color(accent.clone()) {
+            errors.push(("accent", format!("'{}': {}", accent, e)));
+        }
+    }
+
+    if let Some(level) = values.get("log-level").and_then(|x| x.first()) {
+        if let Err(e) = logfile::is_level(level.clone()) {
+            errors.push(("log-level", format!("'{}': {}", level, e)));
+        }
+    }
+
+    if let Some(scale) = values.get("latency-color").and_then(|x| x.first()) {
+        if Scale::parse(scale).is_none() {
+            errors.push(("latency-color", format!("'{}': expected linear or log", scale)));
+        }
+    }
+
+    for key in ["color-good", "color-bad", "color-down", "color-slow"].iter() {
+        if let Some(color) = values.get(key).and_then(|x| x.first()) {
+            if let Err(e) = term::is_rgb(color.clone()) {
+                errors.push((key, format!("'{}': {}", color, e)));
+            }
+        }
+    }
+
+    if let Some(order) = values.get("order").and_then(|x| x.first()) {
+        if ping::Order::parse(order).is_none() {
+            errors.push(("order", format!("'{}': expected newest-first or oldest-first", order)));
+        }
+    }
+
+    if let Some(view) = values.get("view").and_then(|x| x.first()) {
+        if draw::View::parse(view).is_none() {
+            errors.push(("view", format!("'{}': expected tiles or timeline", view)));
+        }
+    }
+
+    if let Some(lang) = values.get("lang").and_then(|x| x.first()) {
+        if locale::Lang::parse(lang).is_none() {
+            errors.push(("lang", format!("'{}': expected one of {}", lang, locale::CODES.join(", "))));
+        }
+    }
+
+    if let Some(range) = values.get("latency-scale").and_then(|x| x.first()) {
+        if ping::parse_range(range).is_none() {
+            errors.push(("latency-scale", format!("'{}': expected MIN:MAX in ms", range)));
+        }
+    }
+
+    for &(key, other) in REQUIRES.iter() {
+        if values.contains_key(key) && !values.contains_key(other) {
+            errors.push((key, format!("requires '{}' to be set as well", other)));
+        }
+    }
+
+    errors
+}
+
+/// `PACKETLOSS_CHUNK_SIZE` for `chunk-size`
+pub fn env_name(key: &str) -> String {
+    format!("PACKETLOSS_{}", key.to_uppercase().replace('-', "_"))
+}
+
+/*
+ * the options set in the environment, for containers where mounting a
+ * config file is a chore. lists are comma separated, and an empty
+ * variable counts as unset.
+ */
+pub fn environment() -> Result<BTreeMap<&'static str, Vec<String>>, String> {
+    let mut values = BTreeMap::new();
+
+    let list = |x: &str| -> Vec<String> {
+        x.split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect()
+    };
+
+    for &(key, kind, _) in KEYS.iter() {
+        let name = env_name(key);
+
+        let value = match env::var(&name) {
+            Ok(value) => value,
+            Err(env::VarError::NotPresent) => continue,
+            Err(e) => return Err(format!("{}: {}", name, e)),
+        };
+
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+
+        let parsed = match kind {
+            Kind::Int => value.parse::<u64>()
+                .map(|x| vec![x.to_string()])
+                .map_err(|_| "expected an integer"),
+            Kind::Float => value.parse::<f64>()
+                .map(|x| vec![x.to_string()])
+                .map_err(|_| "expected a number"),
+            Kind::Bool => match value {
+                "1" | "true" | "yes" => Ok(vec![String::from("true")]),
+                "0" | "false" | "no" => Ok(vec![String::from("false")]),
+                _ => Err("expected true or false"),
+            },
+            Kind::Str => Ok(vec![value.to_string()]),
+            Kind::List => Ok(list(value)),
+        };
+
+        values.insert(key, parsed.map_err(|e| format!("{}: '{}': {}", name, value, e))?);
+    }
+
+    /* the name deployment manifests tend to reach for */
+    if let Ok(targets) = env::var("PACKETLOSS_TARGETS") {
+        let mut targets = list(&targets).into_iter();
+
+        if let Some(target) = targets.next() {
+            if values.contains_key("address") || values.contains_key("host") {
+                return Err(String::from("PACKETLOSS_TARGETS: conflicts with PACKETLOSS_ADDRESS and PACKETLOSS_HOST"));
+            }
+            values.insert("address", vec![target]);
+
+            let rest: Vec<String> = targets.collect();
+            if !rest.is_empty() {
+                values.insert("host", rest);
+            }
+        }
+    }
+
+    if let Some((key, e)) = check(&values).into_iter().next() {
+        return Err(format!("{}: {}", env_name(key), e));
+    }
+
+    Ok(values)
+}
+
+/* line of the first `key = ...` assignment or `[key.NAME]` table, for error locations */
+fn line_of(text: &str, key: &str) -> usize {
+    text.lines()
+        .position(|x| {
+            let x = x.trim_start();
+            (x.starts_with(key) && x[key.len()..].trim_start().starts_with('='))
+                || x.starts_with(&format!("[{}.", key))
+        })
+        .map(|x| x + 1)
+        .unwrap_or(1)
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::config()?.join("config.toml"))
+}
+
+/// `PACKETLOSS_CONFIG`, a config file to read in place of the default one
+pub fn env_path() -> Option<PathBuf> {
+    env::var_os(env_name("config"))
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+}
+
+/// `PACKETLOSS_PROFILE`, the profile to use when none is given on the command line
+pub fn env_profile() -> Option<String> {
+    env::var(env_name("profile"))
+        .ok()
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| x.trim().to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Cli,
+    Env,
+    Profile,
+    File,
+    Default,
+    Unset,
+}
+
+impl Source {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Cli => "command line",
+            Source::Env => "environment",
+            Source::Profile => "profile",
+            Source::File => "config file",
+            Source::Default => "default",
+            Source::Unset => "unset",
+        }
+    }
+}
+
+/// Command line options layered over the environment and a config file
+pub struct Settings<'a> {
+    /* a subcommand's matches first, then its parents' */
+    matches: Vec<&'a ArgMatches<'a>>,
+    env: BTreeMap<&'static str, Vec<String>>,
+    file: BTreeMap<&'static str, Vec<String>>,
+    /* the selected profile's values, over the file's */
+    profile: BTreeMap<&'static str, Vec<String>>,
+    selected: Option<String>,
+    /* where the file's values were read from, for `reload` */
+    path: Option<PathBuf>,
+}
+
+impl<'a> Settings<'a> {
+    pub fn new(matches: &'a ArgMatches<'a>, config: Option<&Config>) -> Self {
+        Settings {
+            matches: vec![matches],
+            env: BTreeMap::new(),
+            file: config.map(|x| x.values()).unwrap_or_default(),
+            profile: config.map(|x| x.selected_values()).unwrap_or_default(),
+            selected: config.and_then(|x| x.selected.clone()),
+            path: None,
+        }
+    }
+
+    /// Remember the config file the values came from, so it can be read again
+    pub fn path(mut self, path: Option<&Path>) -> Self {
+        self.path = path.map(Path::to_path_buf);
+        self
+    }
+
+    pub fn config_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The name of the profile in use
+    pub fn profile(&self) -> Option<&str> {
+        self.selected.as_deref()
+    }
+
+    /// The same options over the config file as it is now, and the same
+    /// profile in it. A file that is gone leaves only the command line,
+    /// environment and defaults
+    pub fn reload(&self) -> Result<Settings<'a>, String> {
+        let mut config = match self.path {
+            Some(ref path) => Config::load(path)?,
+            None => None,
+        };
+
+        if let (Some(config), Some(name)) = (config.as_mut(), self.selected.as_ref()) {
+            config.select(name)?;
+        }
+
+        Ok(Settings {
+            matches: self.matches.clone(),
+            env: self.env.clone(),
+            file: config.as_ref().map(|x| x.values()).unwrap_or_default(),
+            profile: config.as_ref().map(|x| x.selected_values()).unwrap_or_default(),
+            selected: self.selected.clone(),
+            path: self.path.clone(),
+        })
+    }
+
+    /// Layer values from `environment()` between the command line and the file
+    pub fn environment(mut self, env: &BTreeMap<&'static str, Vec<String>>) -> Self {
+        self.env = env.clone();
+        self
+    }
+
+    /// Fall back to the matches of an enclosing command
+    pub fn under(mut self, parent: &'a ArgMatches<'a>) -> Self {
+        self.matches.push(parent);
+        self
+    }
+
+    /* the matches an option was given in, or failing that has a default in */
+    fn matches_of(&self, name: &str) -> Option<&'a ArgMatches<'a>> {
+        self.matches.iter()
+            .find(|x| x.occurrences_of(name) > 0)
+            .or_else(|| self.matches.iter().find(|x| x.value_of(name).is_some()))
+            .cloned()
+    }
+
+    pub fn source(&self, name: &str) -> Source {
+        match self.matches_of(name) {
+            Some(m) if m.occurrences_of(name) > 0 => Source::Cli,
+            _ if self.env.contains_key(name) => Source::Env,
+            _ if self.profile.contains_key(name) => Source::Profile,
+            _ if self.file.contains_key(name) => Source::File,
+            Some(_) => Source::Default,
+            None => Source::Unset,
+        }
+    }
+
+    /* the values of an option set in the environment or the file */
+    fn layered(&self, name: &str) -> Option<&Vec<String>> {
+        match self.source(name) {
+            Source::Env => self.env.get(name),
+            Source::Profile => self.profile.get(name),
+            Source::File => self.file.get(name),
+            _ => None,
+        }
+    }
+
+    pub fn value_of(&self, name: &str) -> Option<&str> {
+        match self.layered(name) {
+            Some(values) => values.first().map(|x| x.as_str()),
+            None => self.matches_of(name).and_then(|x| x.value_of(name)),
+        }
+    }
+
+    /// Whether a flag is set on the command line or turned on in the environment or file
+    pub fn flag(&self, name: &str) -> bool {
+        match self.layered(name) {
+            Some(values) => values.first().map(|x| x == "true").unwrap_or(false),
+            None => self.matches_of(name).is_some(),
+        }
+    }
+
+    pub fn values_of(&self, name: &str) -> Vec<&str> {
+        match self.layered(name) {
+            Some(values) => values.iter().map(|x| x.as_str()).collect(),
+            None => self.matches_of(name)
+                .and_then(|x| x.values_of(name))
+                .into_iter()
+                .flatten()
+                .collect(),
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+fn render(kind: Kind, values: &[&str]) -> String {
+    match kind {
+        Kind::Int | Kind::Bool | Kind::Float => values.first().map(|x| x.to_string()).unwrap_or_default(),
+        Kind::Str => values.first().map(|x| quote(x)).unwrap_or_default(),
+        Kind::List => {
+            let items: Vec<String> = values.iter().map(|x| quote(x)).collect();
+            format!("[{}]", items.join(", "))
+        },
+    }
+}
+
+/// A fully commented config file holding the built-in defaults
+pub fn template(settings: &Settings) -> String {
+    let mut out = String::from("# packetloss configuration\n#\n\
+        # every key mirrors a command line option of the same name, and can\n\
+        # also be set in the environment as PACKETLOSS_<KEY>, e.g. PACKETLOSS_CHUNK_SIZE.\n\
+        # options given on the command line take precedence, then the environment.\n\
+        # a running monitor reads the file again on R or SIGHUP, picking up the interval,\n\
+        # timeout, alert thresholds and colors. the chunk size and targets take a restart.\n");
+
+    for &(key, kind, help) in KEYS.iter() {
+        let values = settings.matches_of(key)
+            .and_then(|x| x.values_of(key))
+            .map(|x| x.collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        out.push_str(&format!("\n# {}\n", help));
+
+        if values.is_empty() {
+            let example = match kind {
+                Kind::Int => "0",
+                Kind::Bool => "false",
+                Kind::Float => "0.0",
+                Kind::Str => "\"\"",
+                Kind::List => "[]",
+            };
+            out.push_str(&format!("# {} = {}\n", key, example));
+        } else {
+            out.push_str(&format!("# {} = {}\n", key, render(kind, &values)));
+        }
+    }
+
+    out.push_str("\n# named profiles hold any of the keys above, and are picked with --profile\n\
+        # or PACKETLOSS_PROFILE. a profile's keys take precedence over the rest of the file.\n\
+        # [profile.gaming]\n\
+        # interval = 1\n\
+        # chunk-size = 20\n\
+        #\n\
+        # [profile.wan]\n\
+        # interval = 30\n\
+        # timeout = 2000\n");
+
+    out
+}
+
+/// The effective value of every key, annotated with where it came from
+pub fn show(settings: &Settings) -> String {
+    let mut out = String::new();
+
+    if let Some(name) = settings.profile() {
+        out.push_str(&format!("# profile {}\n", name));
+    }
+
+    for &(key, kind, _) in KEYS.iter() {
+        let source = settings.source(key);
+
+        if kind == Kind::Bool {
+            out.push_str(&format!("{} = {}  # {}\n", key, settings.flag(key), source.name()));
+            continue;
+        }
+
+        if source == Source::Unset {
+            out.push_str(&format!("# {} is unset\n", key));
+            continue;
+        }
+
+        let values = settings.values_of(key);
+        out.push_str(&format!("{} = {}  # {}\n", key, render(kind, &values), source.name()));
+    }
+
+    out
+}
+
+/// Check a config file, returning every problem as `path:line:col: message`
+pub fn validate(path: &Path) -> Result<Vec<String>, io::Error> {
+    let text = fs::read_to_string(path)?;
+
+    let config = match Config::parse(&text) {
+        Ok(config) => config,
+        Err(e) => {
+            let (line, col) = e.line_col().map(|(l, c)| (l + 1, c + 1)).unwrap_or((1, 1));
+            return Ok(vec![format!("{}:{}:{}: {}", path.display(), line, col, e)]);
+        },
+    };
+
+    Ok(config.check().into_iter()
+        .map(|(key, e)| format!("{}:{}:1: {}: {}", path.display(), line_of(&text, key), key, e))
+        .collect())
+}
@@ -0,0 +1,143 @@
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Once;
+
+use chrono::Local;
+use tui::layout::Rect;
+
+use crate::dirs;
+use crate::record;
+use crate::target::Target;
+
+/*
+ * a bug in the widgets must not cost a night of history. drawing runs
+ * under `guard`, which catches a panic instead of letting it unwind out
+ * of the monitor with the terminal still raw. the panic's message is
+ * taken by the hook rather than printed over the screen, the caller puts
+ * the terminal back, `save` writes every target's history as a recording
+ * that `replay` and `report` read, and the `Report` is what is printed
+ * once the terminal is the shell's again. panics anywhere else, in other
+ * threads or outside a guard, go to the hook that was there before.
+ */
+
+thread_local! {
+    static GUARDED: Cell<bool> = const { Cell::new(false) };
+    static CAUGHT: RefCell<Option<Panic>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+/// What a caught panic said, and where
+#[derive(Debug)]
+pub struct Panic {
+    message: String,
+    location: Option<String>,
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(ref location) => write!(f, "{} at {}", self.message, location),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+fn install() {
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            if !GUARDED.with(|x| x.get()) {
+                return previous(info);
+            }
+
+            let payload = info.payload();
+            let message = payload.downcast_ref::<&str>().map(|x| x.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown panic"));
+
+            CAUGHT.with(|x| *x.borrow_mut() = Some(Panic {
+                message,
+                location: info.location().map(|x| x.to_string()),
+            }));
+        }));
+    });
+}
+
+/// Run `f`, returning the panic it raised instead of unwinding further
+pub fn guard<R>(f: impl FnOnce() -> R) -> Result<R, Panic> {
+    install();
+
+    GUARDED.with(|x| x.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARDED.with(|x| x.set(false));
+
+    result.map_err(|_| CAUGHT.with(|x| x.borrow_mut().take()).unwrap_or(Panic {
+        message: String::from("unknown panic"),
+        location: None,
+    }))
+}
+
+/* crashes/ in the state directory, next to sessions/, or the temp directory without one */
+fn dir() -> PathBuf {
+    dirs::state()
+        .map(|x| x.join("crashes"))
+        .unwrap_or_else(env::temp_dir)
+}
+
+/// Write the history of each target with any to a recording of its own,
+/// returning the paths written
+pub fn save(targets: &[Target]) -> io::Result<Vec<PathBuf>> {
+    let dir = dir();
+    fs::create_dir_all(&dir)?;
+
+    let stamp = Local::now().format("%Y%m%d-%H%M%S");
+    let mut saved = vec![];
+
+    for target in targets.iter().filter(|x| x.list.newest().is_some()) {
+        let path = dir.join(format!("{}-{}.log", stamp, record::file_name(&target.address)));
+
+        /* the list is newest first, a recording oldest first */
+        let chunks: Vec<_> = target.list.chunks().collect();
+        record::write(chunks.into_iter().rev(), Some(&target.address), &path.to_string_lossy())?;
+
+        saved.push(path);
+    }
+
+    Ok(saved)
+}
+
+/// What is printed after a crash, once the terminal is restored
+#[derive(Debug)]
+pub struct Report {
+    pub panic: Panic,
+    /// The size of the screen that was being drawn
+    pub size: Rect,
+    pub saved: io::Result<Vec<PathBuf>>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "drawing the screen failed: {}", self.panic)?;
+        write!(f, "  the terminal was {}x{}", self.size.width, self.size.height)?;
+
+        match self.saved {
+            Ok(ref paths) if paths.is_empty() => write!(f, "\n  there was no history to save")?,
+            Ok(ref paths) => {
+                for path in paths {
+                    write!(f, "\n  history saved to {}", path.display())?;
+                }
+                write!(f, "\n  look at it again with `packetloss replay FILE` or `packetloss report FILE`")?;
+            },
+            Err(ref e) => write!(f, "\n  the history could not be saved: {}", e)?,
+        }
+
+        write!(f, "\n  this is a bug, please report it with the lines above")
+    }
+}
@@ -0,0 +1,267 @@
+use std::io;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::setup::{alert_policy, escalator, has_subnet, labels, lock_instance, policies, probers_for, reprobe, shift, spawn, targets_of};
+use crate::error::{Context, Error};
+use crate::config::Settings;
+use crate::event::{self, Event, Events};
+use crate::health::Tracker;
+use crate::heartbeat::Heartbeat;
+use crate::locale::{self, Text};
+use crate::netns;
+use crate::output::{self, Output};
+use crate::probe::{self, Prober, Streams};
+use crate::record::Recorder;
+use crate::route::Watcher;
+use crate::systemd::Notifier;
+use crate::target::{Label, Target};
+use crate::term::SelectableLogList;
+
+/*
+ * headless monitoring, for a service manager on a machine nobody watches.
+ * the targets are probed on the same schedule and alerted on as in the
+ * ui, but the terminal and keyboard are never touched: each chunk goes to
+ * stdout as a line of --stdout-format, and problems to stderr. with one
+ * target and the stream format the log can be watched from time to time
+ * with `tail -f LOG | packetloss attach`, and a recording with `replay`.
+ * SIGHUP reads the config file again, as R does in the ui, SIGUSR1
+ * writes each target's totals so far as a stats line, and SIGTERM writes
+ * them once more and stops. under systemd it is ready once the first
+ * chunk is in, and feeds the watchdog with every chunk after.
+ */
+
+/* the history is in the log and the recording, a target only needs its newest chunk */
+const HISTORY: usize = 1;
+
+pub async fn run(settings: &Settings<'_>) -> Result<(), Error> {
+    if settings.value_of("via").is_some() {
+        clap::Error::with_description(
+            "daemon and --no-tui probe from here, run them on the remote machine instead of using --via",
+            clap::ErrorKind::ArgumentConflict).exit();
+    }
+
+    let addresses = targets_of(settings, None, false);
+    let addresses: Vec<&str> = addresses.iter().map(String::as_str).collect();
+
+    let chunk_size = settings.value_of("chunk-size").unwrap()
+        .parse::<u64>().unwrap();
+    let interval = settings.value_of("interval").unwrap()
+        .parse::<u64>().unwrap();
+    let timeout = settings.value_of("timeout").unwrap()
+        .parse::<u64>().unwrap();
+    let mut slow = settings.value_of("slow")
+        .map(|x| x.parse::<u64>().unwrap());
+    let capture = settings.value_of("capture")
+        .map(|x| x.parse::<u64>().unwrap())
+        .filter(|x| *x > 0);
+    let capture_spacing = settings.value_of("capture-spacing").unwrap()
+        .parse::<u64>().unwrap();
+
+    let policy = alert_policy(settings, true);
+
+    if let Some(name) = settings.value_of("netns") {
+        netns::enter(name).during(&format!("entering network namespace {}", name))?;
+    }
+
+    /* nobody to ask, a second monitor of a target is only warned of */
+    let _locks = lock_instance(settings, &addresses, false)?;
+
+    let streams = Streams::new(&addresses);
+    let probers = probers_for(settings, &addresses, Duration::from_millis(timeout), true)?;
+
+    /* fail at startup, where the service manager shows it, rather than with every chunk */
+    if let Prober::Icmp(ref ping) = probers[0] {
+        ping.mode().target(addresses[0])?;
+    }
+
+    let mut recorder = settings.value_of("record-events").map(|x| Recorder::create(x, Some(addresses[0])).file(x)).transpose()?;
+    let mut heartbeat = settings.value_of("heartbeat-url").map(Heartbeat::new);
+
+    let format = settings.value_of("stdout-format")
+        .and_then(output::Format::parse)
+        .unwrap_or(output::Format::Stream);
+    let mut outputs: Vec<Output<io::Stdout>> = addresses.iter()
+        .map(|address| Output::new(io::stdout(), format, address))
+        .collect();
+
+    let mut targets: Vec<Target> = addresses.iter()
+        .zip(policies(settings, &policy, &addresses).map_err(Error::Config)?)
+        .map(|(address, policy)| Target::new(address, SelectableLogList::new(HISTORY),
+            Tracker::new(policy.threshold, policy.rearm),
+            escalator(settings, &policy, address, true),
+            shift(settings, address, true)))
+        .collect();
+    label(&mut targets, &mut outputs, labels(settings, &addresses).map_err(Error::Config)?);
+
+    let mut events = Events::with_config(event::Config {
+        keyboard: false,
+        ..event::Config::default()
+    });
+
+    let mut schedule = probe::Schedule {
+        chunk_size,
+        spacing: Duration::default(),
+        interval: Duration::from_secs(interval),
+        capture: capture.map(|x| (x, Duration::from_millis(capture_spacing))),
+        policy,
+        turns: has_subnet(settings),
+    };
+
+    let mut workers = spawn(streams.leaders(probers), &addresses, &schedule, &events);
+
+    let mut notifier = Notifier::from_env();
+    let mut watcher = Watcher::new(streams.count());
+
+    /* a chunk takes up to a timeout per ping on top of the interval */
+    let cycle = Duration::from_secs(interval) + Duration::from_millis(timeout * chunk_size);
+    if let Some(watchdog) = notifier.watchdog().filter(|x| *x < cycle) {
+        warn!("the watchdog expects a chunk every {:.1}s, one can take up to {:.1}s", watchdog.as_secs_f64(), cycle.as_secs_f64());
+        eprintln!("WatchdogSec={:.1}s is shorter than a chunk can take, {:.1}s", watchdog.as_secs_f64(), cycle.as_secs_f64());
+    }
+
+    loop {
+        let event = events.next().await?;
+
+        match event {
+            Event::Chunk(probed) => {
+                let probed = probed?;
+                let stream = probed.target;
+                let mut chunk = probed.chunk;
+
+                if let Some(slow) = slow {
+                    chunk.slow_after(slow as f64);
+                }
+
+                if let Some(error) = chunk.error() {
+                    eprintln!("{}: {}", addresses[streams.targets(stream)[0]], error);
+                }
+
+                /* the local network going down fails every target alike, and isn't theirs to alert on */
+                if watcher.update(stream, &chunk) {
+                    for target in targets.iter_mut() {
+                        target.escalator.hold(watcher.offline());
+                    }
+
+                    let text = locale::tr(if watcher.offline() { Text::NetworkDown } else { Text::NetworkBack });
+                    warn!("{}", text);
+                    eprintln!("{}", text);
+                }
+
+                if stream == 0 {
+                    if let Some(ref mut recorder) = recorder {
+                        recorder.chunk(&chunk)?;
+                    }
+                }
+
+                for &i in streams.targets(stream) {
+                    let shifted = targets[i].shift.as_mut().and_then(|x| x.update(&chunk));
+
+                    match outputs[i].chunk(&chunk) {
+                        /* whoever read the log is gone */
+                        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                        result => result?,
+                    }
+
+                    if let Some(label) = shifted {
+                        info!("{}: {}", targets[i].address, label);
+                        eprintln!("{}: {}", targets[i].address, label);
+
+                        if i == 0 {
+                            if let Some(ref mut recorder) = recorder {
+                                recorder.marker(chunk.time(), &label)?;
+                            }
+                        }
+                    }
+
+                    targets[i].insert(chunk.clone(), false);
+                }
+
+                if let Some(ref mut heartbeat) = heartbeat {
+                    heartbeat.beat(&chunk, schedule.interval);
+                }
+
+                notifier.chunk();
+            },
+            /* the file's values in place of the ones it had, as in the ui */
+            Event::Reload => {
+                let fresh = match settings.reload() {
+                    Ok(fresh) => fresh,
+                    Err(e) => {
+                        warn!("reloading: {}", e);
+                        eprintln!("{}", e);
+                        continue;
+                    },
+                };
+
+                let policy = alert_policy(&fresh, true);
+
+                /* the targets file is read again too, for its tags and recovery counts; the targets stay */
+                let listed = labels(&fresh, &addresses)
+                    .and_then(|labels| Ok((labels, policies(&fresh, &policy, &addresses)?)));
+                let policies = match listed {
+                    Ok((labels, policies)) => {
+                        label(&mut targets, &mut outputs, labels);
+                        policies
+                    },
+                    Err(e) => {
+                        warn!("reloading: {}", e);
+                        eprintln!("{}", e);
+                        continue;
+                    },
+                };
+
+                slow = fresh.value_of("slow").map(|x| x.parse::<u64>().unwrap());
+
+                for (target, policy) in targets.iter_mut().zip(policies) {
+                    let shift = shift(&fresh, &target.address, true);
+                    target.configure(&policy, shift);
+                }
+
+                schedule.interval = Duration::from_secs(fresh.value_of("interval").unwrap().parse::<u64>().unwrap());
+                schedule.policy = policy;
+
+                match reprobe(&fresh, &addresses, true) {
+                    Ok(probers) => {
+                        workers.clear();
+                        workers = spawn(streams.leaders(probers), &addresses, &schedule, &events);
+                    },
+                    Err(e) => {
+                        warn!("reloading: {}", e);
+                        eprintln!("{}", e);
+                        continue;
+                    },
+                }
+
+                let path = settings.config_path().map(|x| x.display().to_string()).unwrap_or_default();
+                info!("reloaded {}", path);
+                eprintln!("{}", locale::format(Text::Reloaded, &[&path]));
+            },
+            Event::Stats | Event::Stop => {
+                let stop = matches!(event, Event::Stop);
+
+                for output in outputs.iter_mut() {
+                    match output.stats() {
+                        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                        result => result?,
+                    }
+                }
+
+                if stop {
+                    notifier.stopping();
+                    return Ok(());
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/* each target's tags, and its accent though nothing is drawn, onto the target and its output */
+fn label<W: io::Write>(targets: &mut [Target], outputs: &mut [Output<W>], labels: Vec<Label>) {
+    for ((target, output), (tags, accent)) in targets.iter_mut().zip(outputs.iter_mut()).zip(labels) {
+        output.set_tags(&tags);
+        target.label(tags, accent);
+    }
+}
@@ -0,0 +1,67 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/*
+ * where packetloss keeps its files. the config directory holds what
+ * people write, config.toml, and the state directory what the monitor
+ * writes for itself and reads back later: session layouts, checkpoints,
+ * crash files and instance locks. on linux and the other unixes both
+ * follow the XDG base directory spec, $XDG_CONFIG_HOME or ~/.config and
+ * $XDG_STATE_HOME or ~/.local/state. macOS keeps state in ~/Library/
+ * Application Support, and windows uses %APPDATA% for config and
+ * %LOCALAPPDATA% for state. --state-dir, or PACKETLOSS_STATE_DIR, puts
+ * the state somewhere else, e.g. on a volume that outlives a container.
+ */
+
+static STATE: OnceLock<PathBuf> = OnceLock::new();
+
+fn var(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+}
+
+fn home(rest: &[&str]) -> Option<PathBuf> {
+    let mut path = var("HOME")?;
+    path.extend(rest);
+    Some(path)
+}
+
+/// The directory config.toml is looked for in
+pub fn config() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        var("APPDATA")
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| home(&[".config"]))
+    };
+
+    Some(base?.join("packetloss"))
+}
+
+/// The directory the monitor keeps its own files in, `--state-dir` when given
+pub fn state() -> Option<PathBuf> {
+    if let Some(dir) = STATE.get() {
+        return Some(dir.clone());
+    }
+
+    let base = if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("XDG_STATE_HOME").or_else(|| home(&["Library", "Application Support"]))
+    } else {
+        var("XDG_STATE_HOME").or_else(|| home(&[".local", "state"]))
+    };
+
+    Some(base?.join("packetloss"))
+}
+
+/// Keep state in `dir` from here on, in place of the default
+pub fn set_state(dir: PathBuf) {
+    let _ = STATE.set(dir);
+}
+
+/// `PACKETLOSS_STATE_DIR`, when `--state-dir` isn't given
+pub fn env_state() -> Option<PathBuf> {
+    var("PACKETLOSS_STATE_DIR")
+}
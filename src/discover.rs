@@ -0,0 +1,198 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::icmp::Ping;
+use crate::ping::PacketChunk;
+use crate::report;
+
+/*
+ * lan discovery. hosts come from the kernel's neighbor table, which only
+ * knows about devices we have talked to recently, or from pinging every
+ * address of a subnet.
+ */
+
+/* largest subnet a sweep will walk, a /20 */
+const MAX_SWEEP: u32 = 4096;
+
+/* probes in flight at once */
+const PARALLEL: usize = 32;
+
+pub struct Neighbor {
+    pub address: String,
+    pub mac: Option<String>,
+    pub device: Option<String>,
+}
+
+impl Neighbor {
+    fn new(address: &str) -> Self {
+        Neighbor {
+            address: address.to_string(),
+            mac: None,
+            device: None,
+        }
+    }
+}
+
+/// Live entries of the neighbor table, ipv6 included when `ip` is available
+pub fn neighbors() -> io::Result<Vec<Neighbor>> {
+    match Command::new("ip").arg("neigh").arg("show").output() {
+        Ok(ref out) if out.status.success() => Ok(parse_ip(&String::from_utf8_lossy(&out.stdout))),
+        _ => Ok(parse_arp(&fs::read_to_string("/proc/net/arp")?)),
+    }
+}
+
+/* "10.0.0.1 dev eth0 lladdr 00:11:22:33:44:55 REACHABLE" */
+fn parse_ip(text: &str) -> Vec<Neighbor> {
+    let mut neighbors = vec![];
+
+    for line in text.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let state = match words.last() {
+            Some(state) => *state,
+            None => continue,
+        };
+
+        if state == "FAILED" || state == "INCOMPLETE" {
+            continue;
+        }
+
+        let after = |key: &str| words.iter()
+            .position(|x| *x == key)
+            .and_then(|i| words.get(i + 1))
+            .map(|x| x.to_string());
+
+        let mut neighbor = Neighbor::new(words[0]);
+        neighbor.device = after("dev");
+        neighbor.mac = after("lladdr");
+        neighbors.push(neighbor);
+    }
+
+    neighbors
+}
+
+/* "IP address  HW type  Flags  HW address  Mask  Device", after a header */
+fn parse_arp(text: &str) -> Vec<Neighbor> {
+    let mut neighbors = vec![];
+
+    for line in text.lines().skip(1) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        /* flags 0x0 is an incomplete entry */
+        if words.len() < 6 || words[2] == "0x0" {
+            continue;
+        }
+
+        let mut neighbor = Neighbor::new(words[0]);
+        neighbor.mac = Some(words[3].to_string());
+        neighbor.device = Some(words[5].to_string());
+        neighbors.push(neighbor);
+    }
+
+    neighbors
+}
+
+/// Every host address of an ipv4 subnet such as `192.168.1.0/24`
+pub fn sweep(cidr: &str) -> Result<Vec<Neighbor>, String> {
+    let mut parts = cidr.splitn(2, '/');
+
+    let base = parts.next()
+        .and_then(|x| x.parse::<Ipv4Addr>().ok())
+        .ok_or_else(|| format!("'{}' is not an ipv4 subnet", cidr))?;
+    let prefix = parts.next()
+        .unwrap_or("32")
+        .parse::<u32>()
+        .ok()
+        .filter(|x| *x <= 32)
+        .ok_or_else(|| format!("'{}' has an invalid prefix length", cidr))?;
+
+    let size = 1u64 << (32 - prefix);
+    if size > MAX_SWEEP as u64 {
+        return Err(format!("'{}' has {} addresses, sweeps are limited to {}", cidr, size, MAX_SWEEP));
+    }
+
+    let mask = if prefix == 0 { 0 } else { !0u32 << (32 - prefix) };
+    let network = u32::from(base) & mask;
+
+    /* skip the network and broadcast addresses where they exist */
+    let hosts = if size > 2 {
+        (network + 1)..(network + size as u32 - 1)
+    } else {
+        network..(network + size as u32)
+    };
+
+    Ok(hosts.map(|x| Neighbor::new(&Ipv4Addr::from(x).to_string())).collect())
+}
+
+pub fn is_subnet(v: String) -> Result<(), String> {
+    sweep(&v).map(|_| ())
+}
+
+/// Ping every neighbor, a few at a time, returning them with their chunk
+pub fn probe(neighbors: Vec<Neighbor>, count: u64, timeout: Duration) -> Vec<(Neighbor, PacketChunk)> {
+    let mut results = vec![];
+    let mut neighbors = neighbors.into_iter();
+
+    loop {
+        let batch: Vec<Neighbor> = neighbors.by_ref().take(PARALLEL).collect();
+        if batch.is_empty() {
+            break;
+        }
+
+        let (tx, rx) = mpsc::channel();
+
+        for (i, neighbor) in batch.iter().enumerate() {
+            let tx = tx.clone();
+            let ping = Ping::new(&neighbor.address, timeout);
+
+            thread::spawn(move || {
+                let _ = tx.send((i, ping.ping(count).ok()));
+            });
+        }
+
+        drop(tx);
+
+        let mut chunks: Vec<(usize, Option<PacketChunk>)> = rx.iter().collect();
+        chunks.sort_by_key(|x| x.0);
+
+        for (neighbor, (_, chunk)) in batch.into_iter().zip(chunks) {
+            if let Some(chunk) = chunk {
+                results.push((neighbor, chunk));
+            }
+        }
+    }
+
+    results
+}
+
+/// One line per host, worst loss first
+pub fn table(mut results: Vec<(Neighbor, PacketChunk)>) -> String {
+    let mut out = String::new();
+
+    if results.is_empty() {
+        out.push_str("no hosts found\n");
+        return out;
+    }
+
+    results.sort_by(|a, b| b.1.loss().partial_cmp(&a.1.loss()).unwrap());
+
+    let width = results.iter().map(|x| x.0.address.len()).max().unwrap_or(0);
+
+    for (neighbor, chunk) in results.iter() {
+        let _ = writeln!(out, "{:width$}  {:>4.0}% loss  {:>7.01}ms  {:17}  {}",
+            neighbor.address,
+            chunk.loss() * 100.0,
+            report::mean_latency(chunk),
+            neighbor.mac.as_deref().unwrap_or("-"),
+            neighbor.device.as_deref().unwrap_or("-"),
+            width = width);
+    }
+
+    out
+}
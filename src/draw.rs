@@ -0,0 +1,684 @@
+use std::fmt::{self, Write};
+
+use chrono::{Datelike, Timelike};
+
+use tui::buffer::Buffer;
+use tui::widgets::Widget;
+use tui::layout::Rect;
+use tui::style::{Color, Style};
+
+use crate::locale::{self, Text};
+use crate::partition::Partitioner;
+use crate::ping::{self, mix_colors, ColorMode, IcmpError, Order, PacketChunk, Scale};
+use crate::store::ChunkStore;
+
+/* ` text ` in the current language, appended to a label buffer */
+fn label(info: &mut String, text: Text, args: &[&dyn fmt::Display]) {
+    info.push(' ');
+    locale::fill(info, locale::tr(text), args);
+    info.push(' ');
+}
+
+/* the cross-hatch over chunks that got nothing back */
+fn hatch() -> Color {
+    let (r, g, b) = ping::palette().bad;
+    Color::Rgb(r, g, b)
+}
+
+/*
+ * seperate struct for drawing - need min response time dynamically.
+ * the label is written into a caller-owned buffer so drawing thousands
+ * of tiles per frame does not allocate.
+ */
+pub struct DrawablePacket<'a> {
+    packet: &'a PacketChunk,
+    min_latency: f64,
+    label: &'a mut String,
+    mode: ColorMode,
+    scale: Scale,
+    range: Option<(f64, f64)>,
+    order: Order,
+}
+
+impl<'a> DrawablePacket<'a> {
+    pub fn new(packet: &'a PacketChunk, min: f64, label: &'a mut String) -> Self {
+        DrawablePacket {
+            packet,
+            min_latency: min,
+            label,
+            mode: ColorMode::Loss,
+            scale: Scale::Linear,
+            range: None,
+            order: Order::NewestFirst,
+        }
+    }
+
+    pub fn mode(mut self, mode: ColorMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /* pinned per-packet latency range, instead of the minimum seen and the timeout */
+    pub fn range(mut self, range: Option<(f64, f64)>) -> Self {
+        self.range = range;
+        self
+    }
+
+    /* decides which edge faces newer tiles, where markers go */
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+impl<'a> Widget for DrawablePacket<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        /* chunk latency is the sum over its packets */
+        let sent = self.packet.sent() as f64;
+        let range = match self.range {
+            Some((min, max)) => (min * sent, max * sent),
+            None => (self.min_latency, self.packet.timeout * sent),
+        };
+
+        let (r,g,b) = self.packet.color(range, self.range.is_some(), self.mode, self.scale);
+        let color = Color::Rgb(r,g,b);
+
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        self.background(&area, buf, color);
+
+        let down = self.packet.down();
+
+        /* a long chunk gets a column per minute, so that one bad minute shows */
+        let minutes = self.packet.minutes().len() as u16;
+        if !down && minutes > 1 && area.width >= minutes {
+            for (i, (r, g, b)) in self.packet.minute_colors(range, self.range.is_some(), self.mode, self.scale).enumerate() {
+                /* the later minutes face the newer tiles */
+                let slot = match self.order {
+                    Order::NewestFirst => minutes - 1 - i as u16,
+                    Order::OldestFirst => i as u16,
+                };
+                let left = area.left() + slot * area.width / minutes;
+                let right = area.left() + (slot + 1) * area.width / minutes;
+
+                for y in area.top()..area.bottom() {
+                    for x in left..right {
+                        buf.get_mut(x, y).set_bg(Color::Rgb(r, g, b));
+                    }
+                }
+            }
+        }
+
+        /* cross-hatch dead chunks so they read as different even without color */
+        if down {
+            for y in area.top()..area.bottom() {
+                for x in area.left()..area.right() {
+                    if (x + y) % 4 == 0 {
+                        let cell = buf.get_mut(x, y);
+                        cell.set_symbol("✖");
+                        cell.set_fg(hatch());
+                    }
+                }
+            }
+        }
+
+        /* markers come after this chunk, on the edge facing newer tiles */
+        if let Some((_, label)) = self.packet.markers().last() {
+            let (x, symbol) = match self.order {
+                Order::NewestFirst => (area.left(), "▎"),
+                Order::OldestFirst => (area.right() - 1, "▕"),
+            };
+
+            for y in area.top()..area.bottom() {
+                buf.get_mut(x, y).set_symbol(symbol).set_fg(Color::Cyan);
+            }
+
+            if !label.is_empty() && area.height >= 3 && area.width > 2 {
+                let marker = tui::style::Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Cyan);
+
+                let info = &mut *self.label;
+                info.clear();
+                let _ = write!(info, " {} ", label);
+                buf.set_stringn(area.left() + 1, area.top(), info.as_str(), area.width as usize - 1, marker);
+            }
+        }
+
+        let pct = (self.packet.loss()*100f64) as u32;
+        let latency = self.packet.latency();
+
+        /* the short label never fits where the long one doesn't, so try it second */
+        let time = self.packet.time().format("%b %d %H:%M:%S");
+        let info = &mut *self.label;
+
+        info.clear();
+        let slow = self.packet.slow();
+
+        if down {
+            let _ = write!(info, " {}: {} ", time, locale::tr(Text::Down));
+        } else if let ColorMode::Game { hz } = self.mode {
+            let late = self.packet.late();
+            label(info, Text::ChunkLate, &[&time,
+                &format_args!("{:.1}", late as f64 / self.packet.sent() as f64 * hz), &late, &self.packet.sent()]);
+        } else if self.mode == ColorMode::Mos {
+            label(info, Text::ChunkMos, &[&time,
+                &format_args!("{:.2}", self.packet.mos()), &pct, &format_args!("{:.01}", latency)]);
+        } else if let Some((up, down)) = self.packet.oneway().filter(|_| pct > 0) {
+            label(info, Text::ChunkOneway, &[&time, &pct, &up, &down, &format_args!("{:.01}", latency)]);
+        } else if slow > 0 {
+            label(info, Text::ChunkSlow, &[&time, &pct, &slow, &format_args!("{:.01}", latency)]);
+        } else {
+            label(info, Text::Chunk, &[&time, &pct, &format_args!("{:.01}", latency)]);
+        }
+
+        if area.width < info.len() as u16 {
+            info.clear();
+            if down {
+                label(info, Text::Down, &[]);
+            } else if let ColorMode::Game { hz } = self.mode {
+                label(info, Text::LateShort,
+                    &[&format_args!("{:.1}", self.packet.late() as f64 / self.packet.sent() as f64 * hz)]);
+            } else if self.mode == ColorMode::Mos {
+                let _ = write!(info, " MOS {:.2} ", self.packet.mos());
+            } else {
+                let _ = write!(info, " {}% [{:.0}ms] ", pct, latency);
+            }
+
+            if area.width < info.len() as u16 {
+                return;
+            }
+        }
+
+        if self.packet.manual() && area.width >= info.len() as u16 + 2 {
+            info.insert_str(1, "↻ ");
+        }
+
+        let x = area.x + (area.width / 2).saturating_sub(info.len() as u16 / 2);
+        let y = area.y + (area.height / 2);
+
+        let style = tui::style::Style::default()
+            .bg(color);
+
+        buf.set_stringn(x, y, info.as_str(), area.width as usize, style);
+
+        /* throughput, retransmits, the most common icmp error and bursts of loss go on the line below */
+        let throughput = self.packet.throughput();
+        let retrans = self.packet.retrans_rate();
+        let icmp = self.packet.dominant_error();
+        let error = self.packet.error();
+        /* a single drop isn't a burst */
+        let run = self.packet.longest_run();
+        let burst = self.packet.burst_ratio().filter(|_| run >= 2);
+
+        if area.height >= 3 && (throughput.is_some() || retrans.is_some() || icmp.is_some() || error.is_some() || burst.is_some()) {
+            info.clear();
+
+            if let Some(mbps) = throughput {
+                let _ = write!(info, " {:.1} Mbit/s ", mbps);
+            }
+            if let Some(rate) = retrans {
+                label(info, Text::Retransmits, &[&format_args!("{:.2}", rate * 100.0)]);
+            }
+            if let Some((error, count)) = icmp {
+                label(info, Text::IcmpErrors, &[&count, &locale::tr(icmp_text(error))]);
+            }
+            if let Some(ratio) = burst {
+                label(info, Text::Burst, &[&run, &format_args!("{:.1}", ratio)]);
+            }
+            if let Some(error) = error {
+                let _ = write!(info, " {} ", error);
+            }
+
+            if area.width >= info.len() as u16 {
+                let x = area.x + (area.width / 2).saturating_sub(info.len() as u16 / 2);
+                buf.set_stringn(x, y + 1, info.as_str(), area.width as usize, style);
+            }
+        }
+    }
+}
+
+fn icmp_text(error: IcmpError) -> Text {
+    match error {
+        IcmpError::NetUnreachable => Text::IcmpNet,
+        IcmpError::HostUnreachable => Text::IcmpHost,
+        IcmpError::Prohibited => Text::IcmpProhibited,
+        IcmpError::FragNeeded => Text::IcmpFrag,
+        IcmpError::TimeExceeded => Text::IcmpTtl,
+        IcmpError::Other => Text::IcmpOther,
+    }
+}
+
+/*
+ * a capture drawn as a strip, one cell per packet or, when there are more
+ * packets than cells, the worst packet of each group
+ */
+pub struct DrawableCapture<'a> {
+    capture: &'a PacketChunk,
+    min_latency: f64,
+    label: &'a mut String,
+    scale: Scale,
+    range: Option<(f64, f64)>,
+}
+
+impl<'a> DrawableCapture<'a> {
+    pub fn new(capture: &'a PacketChunk, min: f64, label: &'a mut String) -> Self {
+        DrawableCapture {
+            capture,
+            min_latency: min,
+            label,
+            scale: Scale::Linear,
+            range: None,
+        }
+    }
+
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn range(mut self, range: Option<(f64, f64)>) -> Self {
+        self.range = range;
+        self
+    }
+}
+
+impl<'a> Widget for DrawableCapture<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        let packets = &self.capture.packets;
+
+        if area.width == 0 || area.height == 0 || packets.is_empty() {
+            return;
+        }
+
+        let palette = ping::palette();
+
+        let cells = area.width as usize;
+        let (min, max) = self.range.unwrap_or((self.min_latency, self.capture.timeout));
+
+        for i in 0..cells {
+            let start = i * packets.len() / cells;
+            let end = ((i + 1) * packets.len() / cells).max(start + 1);
+            let group = &packets[start..end];
+
+            let worst = group.iter()
+                .map(|x| match x {
+                    Some(ref x) if x.dropped == 0 => Some(x.latency_ms),
+                    _ => None,
+                })
+                .try_fold(0.0, |acc: f64, x| x.map(|x| acc.max(x)));
+
+            let (r, g, b) = match worst {
+                Some(latency) => {
+                    let lat = self.scale.position(latency, min, max, self.range.is_some());
+                    mix_colors(lat, palette.good, palette.bad)
+                },
+                None => palette.down,
+            };
+
+            for y in area.top()..area.bottom() {
+                let cell = buf.get_mut(area.x + i as u16, y);
+                cell.set_symbol(" ");
+                cell.set_bg(Color::Rgb(r, g, b));
+            }
+        }
+
+        let received = self.capture.received();
+        let worst = packets.iter()
+            .filter_map(|x| x.as_ref())
+            .filter(|x| x.dropped == 0)
+            .map(|x| x.latency_ms)
+            .fold(0.0, f64::max);
+
+        let info = &mut *self.label;
+        info.clear();
+        label(info, Text::Capture, &[&packets.len(),
+            &format_args!("{:.0}", self.capture.loss() * 100.0), &format_args!("{:.01}", worst)]);
+
+        if received == 0 {
+            info.clear();
+            label(info, Text::CaptureLost, &[&packets.len()]);
+        }
+
+        if area.width < info.len() as u16 {
+            return;
+        }
+
+        let x = area.x + (area.width / 2).saturating_sub(info.len() as u16 / 2);
+        let style = tui::style::Style::default()
+            .fg(Color::White)
+            .bg(Color::Black);
+
+        buf.set_stringn(x, area.y, info.as_str(), area.width as usize, style);
+    }
+}
+
+/* partial cells for the top of a column, in eighths */
+const BARS: [&str; 8] = ["▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+
+/*
+ * a chunk as one column of the timeline, as tall as its latency and colored
+ * by its loss, or by MOS or late ticks when those are what tiles show
+ */
+pub struct DrawableColumn<'a> {
+    packet: &'a PacketChunk,
+    min_latency: f64,
+    mode: ColorMode,
+    scale: Scale,
+    range: Option<(f64, f64)>,
+}
+
+impl<'a> DrawableColumn<'a> {
+    pub fn new(packet: &'a PacketChunk, min: f64) -> Self {
+        DrawableColumn {
+            packet,
+            min_latency: min,
+            mode: ColorMode::Loss,
+            scale: Scale::Linear,
+            range: None,
+        }
+    }
+
+    pub fn mode(mut self, mode: ColorMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn range(mut self, range: Option<(f64, f64)>) -> Self {
+        self.range = range;
+        self
+    }
+}
+
+impl<'a> Widget for DrawableColumn<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        let sent = self.packet.sent() as f64;
+        let range = match self.range {
+            Some((min, max)) => (min * sent, max * sent),
+            None => (self.min_latency, self.packet.timeout * sent),
+        };
+
+        let (r, g, b) = match self.mode {
+            ColorMode::Loss => self.packet.loss_color(),
+            ColorMode::Burst => self.packet.burst_color(),
+            mode => self.packet.color(range, self.range.is_some(), mode, self.scale),
+        };
+        let color = Color::Rgb(r, g, b);
+
+        /* nothing came back, so the whole column is hatched like a dead tile */
+        if self.packet.down() {
+            for y in area.top()..area.bottom() {
+                for x in area.left()..area.right() {
+                    let cell = buf.get_mut(x, y);
+                    cell.set_symbol(if (x + y) % 4 == 0 { "✖" } else { " " });
+                    cell.set_fg(hatch());
+                    cell.set_bg(color);
+                }
+            }
+            return;
+        }
+
+        let height = 1.0 - self.scale.position(self.packet.latency(), range.0, range.1, self.range.is_some());
+
+        /* at least a sliver, so the fastest chunks still show */
+        let eighths = ((height * area.height as f64 * 8.0).round() as u16).max(1);
+
+        for (row, y) in (area.top()..area.bottom()).rev().enumerate() {
+            let filled = eighths.saturating_sub(row as u16 * 8).min(8);
+            if filled == 0 {
+                break;
+            }
+
+            for x in area.left()..area.right() {
+                let cell = buf.get_mut(x, y);
+                cell.set_symbol(BARS[filled as usize - 1]);
+                cell.set_fg(color);
+            }
+        }
+
+        if !self.packet.markers().is_empty() {
+            let cell = buf.get_mut(area.left(), area.top());
+            cell.set_symbol("▾");
+            cell.set_fg(Color::Cyan);
+        }
+    }
+}
+
+/* cells across each chunk of the timeline */
+const COLUMN_WIDTH: u16 = 2;
+
+/// How the history is laid out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Tiles,
+    /* one column per chunk, time running left to right */
+    Timeline,
+}
+
+impl View {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "tiles" => Some(View::Tiles),
+            "timeline" => Some(View::Timeline),
+            _ => None,
+        }
+    }
+}
+
+/// The history as a grid of tiles or a timeline, drawn with the list's colors
+pub struct LogList {
+    items: ChunkStore,
+    /* reused tile label, see DrawablePacket */
+    label: String,
+    mode: ColorMode,
+    scale: Scale,
+    range: Option<(f64, f64)>,
+    order: Order,
+    view: View,
+}
+
+
+impl LogList {
+    pub fn new(max: usize) -> Self {
+        LogList {
+            items: ChunkStore::new(max),
+            label: String::with_capacity(64),
+            mode: ColorMode::Loss,
+            scale: Scale::Linear,
+            range: None,
+            order: Order::NewestFirst,
+            view: View::Tiles,
+        }
+    }
+}
+
+
+impl LogList {
+    pub fn insert(&mut self, item: PacketChunk) {
+        self.items.insert(item);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> &ChunkStore {
+        &self.items
+    }
+
+    pub fn items_mut(&mut self) -> &mut ChunkStore {
+        &mut self.items
+    }
+
+    pub fn order(&self) -> Order {
+        self.order
+    }
+
+    pub fn set_mode(&mut self, mode: ColorMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    pub fn set_range(&mut self, range: Option<(f64, f64)>) {
+        self.range = range;
+    }
+
+    pub fn set_order(&mut self, order: Order) {
+        self.order = order;
+    }
+
+    pub fn set_view(&mut self, view: View) {
+        self.view = view;
+    }
+
+    pub fn memory(&self) -> usize {
+        self.items.memory()
+    }
+
+    pub fn partition(&mut self, size: Rect) -> impl Iterator<Item = Rect> {
+        Partitioner::new(size.width, size.height, self.len() as u16)
+            .map(move |x| Rect::new(x.x + size.x, x.y + size.y, x.width, x.height))
+    }
+}
+
+impl Widget for LogList {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        if self.view == View::Timeline {
+            self.timeline(area, buf);
+            return;
+        }
+
+        let len = self.items.len();
+        let partitions = self.partition(area);
+        for (k, area) in partitions.enumerate() {
+            let i = match self.order {
+                Order::NewestFirst => k,
+                Order::OldestFirst => len - 1 - k,
+            };
+
+            let item = &self.items[i];
+            let mut drawable = DrawablePacket::new(item, self.items.min_latency(), &mut self.label)
+                .mode(self.mode)
+                .scale(self.scale)
+                .range(self.range)
+                .order(self.order);
+            drawable.draw(area, buf);
+
+            if let Some(older) = self.items.get(i + 1) {
+                divider(item, older, area, buf, self.order, &mut self.label);
+            }
+        }
+
+    }
+}
+
+impl LogList {
+    /// Draws chunk `i` alone over `area`, its capture along the bottom row when it has one
+    pub fn inspect(&mut self, i: usize, mut area: Rect, buf: &mut Buffer) {
+        let item = &self.items[i];
+
+        if let Some(capture) = item.capture().filter(|_| area.height >= 3) {
+            area.height -= 1;
+
+            let strip = Rect::new(area.x, area.y + area.height, area.width, 1);
+            DrawableCapture::new(capture, self.items.min_latency(), &mut self.label)
+                .scale(self.scale)
+                .range(self.range)
+                .draw(strip, buf);
+        }
+
+        DrawablePacket::new(item, self.items.min_latency(), &mut self.label)
+            .mode(self.mode)
+            .scale(self.scale)
+            .range(self.range)
+            .order(self.order)
+            .draw(area, buf);
+    }
+
+    /* the newest chunk on the right edge, as many older ones as fit to its left */
+    fn timeline(&mut self, area: Rect, buf: &mut Buffer) {
+        let columns = (area.width / COLUMN_WIDTH) as usize;
+
+        for (k, item) in self.items.iter().take(columns).enumerate() {
+            let x = area.right() - (k as u16 + 1) * COLUMN_WIDTH;
+
+            DrawableColumn::new(item, self.items.min_latency())
+                .mode(self.mode)
+                .scale(self.scale)
+                .range(self.range)
+                .draw(Rect::new(x, area.y, COLUMN_WIDTH, area.height), buf);
+        }
+    }
+}
+
+/*
+ * a line down the edge of the first tile of each hour that faces the
+ * previous hour, heavier for a new day, with the hour or date along the
+ * bottom when it fits.
+ */
+fn divider(item: &PacketChunk, older: &PacketChunk, area: Rect, buf: &mut Buffer, order: Order, label: &mut String) {
+    let (time, before) = (item.time(), older.time());
+
+    let day = (time.year(), time.ordinal()) != (before.year(), before.ordinal());
+
+    if area.width < 3 || (!day && time.hour() == before.hour()) {
+        return;
+    }
+
+    let x = match order {
+        Order::NewestFirst => area.right() - 1,
+        Order::OldestFirst => area.left(),
+    };
+
+    for y in area.top()..area.bottom() {
+        let cell = buf.get_mut(x, y);
+        cell.set_symbol(if day { "┃" } else { "┊" });
+        cell.set_fg(Color::Gray);
+    }
+
+    label.clear();
+    if day {
+        let _ = write!(label, " {} ", time.format("%b %d"));
+    } else {
+        let _ = write!(label, " {} ", time.format("%H:00"));
+    }
+
+    if area.height >= 3 && (area.width as usize) > label.len() + 1 {
+        let style = Style::default()
+            .fg(Color::Black)
+            .bg(Color::Gray);
+        let start = match order {
+            Order::NewestFirst => x - label.len() as u16,
+            Order::OldestFirst => x + 1,
+        };
+        buf.set_stringn(start, area.bottom() - 1, label.as_str(), label.len(), style);
+    }
+}
@@ -0,0 +1,81 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::RecvError;
+
+use thiserror::Error;
+
+use crate::crash::Report;
+use crate::ping::PingError;
+
+/*
+ * the one error type of the binary. the plain variants say what failed,
+ * the context variants wrap them with where: which target was being
+ * probed, which file was being read or written, or what was being set up
+ * at the time. context is added where the error crosses into code that
+ * knows it, with the `Context` methods, and reads outermost first, e.g.
+ * "recording.log: permission denied".
+ */
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    IO(#[from] io::Error),
+    #[error("{0}")]
+    Ping(#[from] PingError),
+    #[error("the event loop stopped: {0}")]
+    Event(#[from] RecvError),
+    #[error("config: {0}")]
+    Config(String),
+    #[error("{0}")]
+    Crash(Report),
+    #[error("{target}: {source}")]
+    Target {
+        target: String,
+        source: Box<Error>,
+    },
+    #[error("{}: {source}", path.display())]
+    File {
+        path: PathBuf,
+        source: Box<Error>,
+    },
+    #[error("{phase}: {source}")]
+    Phase {
+        phase: String,
+        source: Box<Error>,
+    },
+}
+
+/// Where an error happened, added on its way up
+pub trait Context<T> {
+    /// While probing or setting up `target`
+    fn target(self, target: &str) -> Result<T, Error>;
+
+    /// While reading or writing `path`
+    fn file<P: AsRef<Path>>(self, path: P) -> Result<T, Error>;
+
+    /// While doing `phase`, e.g. "entering network namespace lab"
+    fn during(self, phase: &str) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn target(self, target: &str) -> Result<T, Error> {
+        self.map_err(|e| Error::Target {
+            target: target.to_string(),
+            source: Box::new(e.into()),
+        })
+    }
+
+    fn file<P: AsRef<Path>>(self, path: P) -> Result<T, Error> {
+        self.map_err(|e| Error::File {
+            path: path.as_ref().to_path_buf(),
+            source: Box::new(e.into()),
+        })
+    }
+
+    fn during(self, phase: &str) -> Result<T, Error> {
+        self.map_err(|e| Error::Phase {
+            phase: phase.to_string(),
+            source: Box::new(e.into()),
+        })
+    }
+}
@@ -1,33 +1,153 @@
-use std::io;
-use std::sync::mpsc;
-use std::thread;
+use std::sync::mpsc::RecvError;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
-use termion::event::Key;
-use termion::input::TermRead;
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio::time;
+
+use crate::error::Error;
+use crate::probe::Probed;
+use crate::signal;
+
+/// A key press, whichever terminal library read it. Mirrors termion's keys so
+/// recordings and bindings stay the same across backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    BackTab,
+    Delete,
+    Insert,
+    F(u8),
+    Char(char),
+    Alt(char),
+    Ctrl(char),
+    Null,
+    Esc,
+}
+
+#[cfg(not(feature = "crossterm"))]
+impl From<termion::event::Key> for Key {
+    fn from(key: termion::event::Key) -> Key {
+        use termion::event::Key as K;
+
+        match key {
+            K::Backspace => Key::Backspace,
+            K::Left => Key::Left,
+            K::Right => Key::Right,
+            K::Up => Key::Up,
+            K::Down => Key::Down,
+            K::Home => Key::Home,
+            K::End => Key::End,
+            K::PageUp => Key::PageUp,
+            K::PageDown => Key::PageDown,
+            K::BackTab => Key::BackTab,
+            K::Delete => Key::Delete,
+            K::Insert => Key::Insert,
+            K::F(n) => Key::F(n),
+            K::Char(c) => Key::Char(c),
+            K::Alt(c) => Key::Alt(c),
+            K::Ctrl(c) => Key::Ctrl(c),
+            K::Esc => Key::Esc,
+            _ => Key::Null,
+        }
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl Key {
+    fn from_crossterm(event: crossterm::event::KeyEvent) -> Option<Key> {
+        use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+
+        /* windows reports releases too, termion never did */
+        if event.kind == KeyEventKind::Release {
+            return None;
+        }
+
+        let key = match event.code {
+            KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::CONTROL) => Key::Ctrl(c),
+            KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::ALT) => Key::Alt(c),
+            KeyCode::Char(c) => Key::Char(c),
+            KeyCode::Enter => Key::Char('\n'),
+            KeyCode::Tab => Key::Char('\t'),
+            KeyCode::BackTab => Key::BackTab,
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::Left => Key::Left,
+            KeyCode::Right => Key::Right,
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            KeyCode::Delete => Key::Delete,
+            KeyCode::Insert => Key::Insert,
+            KeyCode::F(n) => Key::F(n),
+            KeyCode::Null => Key::Null,
+            KeyCode::Esc => Key::Esc,
+            _ => return None,
+        };
+
+        Some(key)
+    }
+}
 
 pub enum Event<I> {
     Input(I),
     Tick,
+    /* from the probe worker, which stops after an error */
+    Chunk(Result<Box<Probed>, Error>),
+    /* SIGHUP, read the config file again */
+    Reload,
+    /* SIGUSR1, write the statistics so far */
+    Stats,
+    /* SIGTERM, stop as if quit from the keyboard */
+    Stop,
 }
 
-/// An small event handler that wrap termion input and tick events. Each event
-/// type is handled in its own thread and returned to a common `Receiver`
+/// An small event handler that wrap termion (or crossterm) input and tick events. Each event
+/// type is handled in its own task and returned to a common receiver. Must be
+/// created inside a tokio runtime
 pub struct Events {
-    rx: mpsc::Receiver<Event<Key>>,
+    tx: mpsc::UnboundedSender<Event<Key>>,
+    rx: mpsc::UnboundedReceiver<Event<Key>>,
+    depth: Arc<AtomicUsize>,
+}
+
+/// Sends events from another task into `Events`
+pub struct EventSender {
+    tx: mpsc::UnboundedSender<Event<Key>>,
+    depth: Arc<AtomicUsize>,
+}
+
+impl EventSender {
+    pub fn send(&self, event: Event<Key>) -> Result<(), mpsc::error::SendError<Event<Key>>> {
+        self.depth.fetch_add(1, Ordering::Relaxed);
+        self.tx.send(event)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Config {
-    pub exit_key: Key,
     pub tick_rate: Duration,
+    /* off when nothing is at the terminal, the keyboard is never read */
+    pub keyboard: bool,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
-            exit_key: Key::Char('q'),
             tick_rate: Duration::from_millis(250),
+            keyboard: true,
         }
     }
 }
@@ -38,42 +158,119 @@ impl Events {
     }
 
     pub fn with_config(config: Config) -> Events {
-        let (tx, rx) = mpsc::channel();
-        {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let depth = Arc::new(AtomicUsize::new(0));
+        if config.keyboard {
             let tx = tx.clone();
-            thread::spawn(move || {
-                let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    match evt {
-                        Ok(key) => {
-                            if let Err(_) = tx.send(Event::Input(key)) {
-                                return;
-                            }
-                            if key == config.exit_key {
-                                return;
-                            }
-                        }
-                        Err(_) => {}
-                    }
-                }
-            })
-        };
+            let depth = depth.clone();
+            /*
+             * both terminal libraries read blocking, so the keyboard gets a
+             * blocking task. it reads until the monitor is gone, a q can be
+             * part of a label or bound to something else, only the main loop
+             * knows which; the runtime is shut down without waiting on it.
+             */
+            task::spawn_blocking(move || {
+                read_keys(|key| {
+                    depth.fetch_add(1, Ordering::Relaxed);
+                    tx.send(Event::Input(key)).is_ok()
+                })
+            });
+        }
         {
             let tx = tx.clone();
-            thread::spawn(move || {
-                let tx = tx.clone();
+            let depth = depth.clone();
+            tokio::spawn(async move {
+                let mut ticks = time::interval(config.tick_rate);
                 loop {
-                    tx.send(Event::Tick).unwrap();
-                    thread::sleep(config.tick_rate);
+                    ticks.tick().await;
+                    depth.fetch_add(1, Ordering::Relaxed);
+                    if tx.send(Event::Tick).is_err() {
+                        return;
+                    }
                 }
             })
         };
+        signal::listen(EventSender {
+            tx: tx.clone(),
+            depth: depth.clone(),
+        });
         Events {
+            tx,
             rx,
+            depth,
         }
     }
 
-    pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
-        self.rx.recv()
+    pub fn sender(&self) -> EventSender {
+        EventSender {
+            tx: self.tx.clone(),
+            depth: self.depth.clone(),
+        }
+    }
+
+    pub async fn next(&mut self) -> Result<Event<Key>, RecvError> {
+        let event = self.rx.recv().await.ok_or(RecvError)?;
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        Ok(event)
+    }
+
+    /// Like `next`, but gives up after `timeout` and returns `None`
+    pub async fn next_timeout(&mut self, timeout: Duration) -> Result<Option<Event<Key>>, RecvError> {
+        match time::timeout(timeout, self.rx.recv()).await {
+            Ok(Some(event)) => {
+                self.depth.fetch_sub(1, Ordering::Relaxed);
+                Ok(Some(event))
+            },
+            Ok(None) => Err(RecvError),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Number of events sent but not yet received
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+/* feeds keys to `handle` until it returns false or input ends */
+#[cfg(not(feature = "crossterm"))]
+fn read_keys<F: FnMut(Key) -> bool>(mut handle: F) {
+    use std::io::{self, IsTerminal, Read};
+    use termion::input::TermRead;
+
+    /* with chunks piped into stdin, keys come from the terminal itself */
+    let input: Box<dyn Read + Send> = if io::stdin().is_terminal() {
+        Box::new(io::stdin())
+    } else {
+        match termion::get_tty() {
+            Ok(tty) => Box::new(tty),
+            Err(_) => return,
+        }
+    };
+
+    for key in input.keys().flatten() {
+        if !handle(Key::from(key)) {
+            return;
+        }
+    }
+}
+
+/* crossterm reads the console directly, so piped stdin is no concern here */
+#[cfg(feature = "crossterm")]
+fn read_keys<F: FnMut(Key) -> bool>(mut handle: F) {
+    use crossterm::event::{self, Event as Input};
+
+    loop {
+        match event::read() {
+            Ok(Input::Key(key)) => {
+                if let Some(key) = Key::from_crossterm(key) {
+                    if !handle(key) {
+                        return;
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(_) => return,
+        }
     }
 }
@@ -0,0 +1,127 @@
+use std::io::{self, Read};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+
+use crate::ping::{ms, AddrFamily, Probe, ProbeResult, Reply};
+
+/*
+ * command probes, for anything there is no native probe for: a vpn's
+ * status, a serial link, a service's own health check. each probe runs the
+ * command once with sh. exiting 0 is a reply, any other status a drop, as
+ * is still running at the timeout, when the command and everything it
+ * started is killed. a number at the start of the first line of output is
+ * taken as the latency in ms, otherwise it's how long the command ran.
+ *
+ * the command sees PACKETLOSS_SEQ, counting probes from 0, and
+ * PACKETLOSS_TIMEOUT in ms.
+ */
+
+/* output past this is read and thrown away, so the command never blocks on a full pipe */
+const OUTPUT: u64 = 4096;
+
+pub struct Exec {
+    command: String,
+    timeout: Duration,
+    seq: AtomicU16,
+}
+
+impl Exec {
+    pub fn new(command: &str, timeout: Duration) -> Self {
+        Exec {
+            command: command.to_string(),
+            timeout,
+            seq: AtomicU16::new(0),
+        }
+    }
+}
+
+/* "12.5", "12.5ms" or "12.5 ms up", the rest of the line is the command's own business */
+fn latency(output: &str) -> Option<f64> {
+    let word = output.lines().next()?.split_whitespace().next()?;
+    let latency = word.trim_end_matches("ms").parse::<f64>().ok()?;
+
+    Some(latency).filter(|x| x.is_finite() && *x >= 0.0)
+}
+
+impl Probe for Exec {
+    fn probe(&self) -> ProbeResult {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+
+        let mut reply = Reply {
+            hostname: self.command.clone(),
+            address: String::new(),
+            family: AddrFamily::IPV4,
+            latency_ms: -1.0,
+            dropped: 1,
+            seq: seq as i32,
+            recv_ttl: -1,
+            recv_qos: 0,
+        };
+
+        let mut command = Command::new("sh");
+        command.arg("-c")
+            .arg(&self.command)
+            .env("PACKETLOSS_SEQ", seq.to_string())
+            .env("PACKETLOSS_TIMEOUT", self.timeout.as_millis().to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+
+        /* a group of its own, so a timeout kills whatever the shell started too */
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let start = Instant::now();
+        let mut child = command.spawn()?;
+
+        let mut stdout = child.stdout.take().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = vec![];
+            let _ = (&mut stdout).take(OUTPUT).read_to_end(&mut output);
+            let _ = io::copy(&mut stdout, &mut io::sink());
+            let _ = tx.send(String::from_utf8_lossy(&output).into_owned());
+        });
+
+        /* the output ends once the command and anything holding on to its stdout has exited */
+        let output = match rx.recv_timeout(self.timeout) {
+            Ok(output) => output,
+            Err(_) => {
+                #[cfg(unix)]
+                unsafe {
+                    libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+                }
+                #[cfg(not(unix))]
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(reply);
+            },
+        };
+
+        let status = child.wait()?;
+        let took = ms(start.elapsed());
+
+        match status.code() {
+            Some(0) => (),
+            /* sh couldn't find or run it, and won't next time either */
+            Some(code @ 126) | Some(code @ 127) => {
+                return Err(io::Error::new(io::ErrorKind::NotFound,
+                    format!("'{}' could not be run (exit status {})", self.command, code)).into());
+            },
+            _ => return Ok(reply),
+        }
+
+        reply.latency_ms = latency(&output).unwrap_or(took);
+        reply.dropped = 0;
+        Ok(reply)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
@@ -0,0 +1,147 @@
+//! A C API over [`Monitor`], for tooling that isn't written in Rust
+//!
+//! Built into a shared library with the `ffi` feature:
+//!
+//! ```text
+//! cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib
+//! ```
+//!
+//! and declared for C in `include/packetloss.h`. A monitor is started with
+//! `packetloss_start`, its chunks are taken one at a time with
+//! `packetloss_poll`, and `packetloss_stop` ends it and frees the handle.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::monitor::Monitor;
+use crate::report::ChunkReport;
+
+/// The figures of one chunk, `packetloss_chunk` in C
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Chunk {
+    /// Unix time the chunk was started, in seconds
+    pub time: i64,
+    pub sent: u32,
+    pub received: u32,
+    /// From 0.0 to 1.0
+    pub loss: f64,
+    /// Per-packet mean in ms, lost packets counting as the timeout
+    pub latency_ms: f64,
+    /// Negative when fewer than two replies came back
+    pub jitter_ms: f64,
+    pub mos: f64,
+}
+
+impl From<&ChunkReport> for Chunk {
+    fn from(report: &ChunkReport) -> Self {
+        Chunk {
+            time: report.time.timestamp(),
+            sent: report.sent as u32,
+            received: report.received as u32,
+            loss: report.loss,
+            latency_ms: report.latency,
+            jitter_ms: report.jitter.unwrap_or(-1.0),
+            mos: report.mos,
+        }
+    }
+}
+
+/* a chunk, or a round the probe couldn't be sent at all */
+type Round = Result<ChunkReport, ()>;
+
+/// A running monitor, opaque to C
+pub struct Handle {
+    monitor: Monitor,
+    rx: Receiver<Round>,
+}
+
+/// Start probing `target` every `interval_ms`, `chunk_size` ICMP echoes a
+/// chunk, counting replies slower than `timeout_ms` as lost. Null when the
+/// target isn't valid UTF-8
+///
+/// # Safety
+///
+/// `target` must be a valid nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn packetloss_start(target: *const c_char, interval_ms: u32, chunk_size: u32, timeout_ms: u32) -> *mut Handle {
+    if target.is_null() {
+        return ptr::null_mut();
+    }
+
+    let target = match CStr::from_ptr(target).to_str() {
+        Ok(target) => target,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let errors = tx.clone();
+    let name = target.to_string();
+
+    let monitor = Monitor::builder()
+        .target(target)
+        .interval(Duration::from_millis(interval_ms as u64))
+        .chunk_size(chunk_size as u64)
+        .timeout(Duration::from_millis(timeout_ms as u64))
+        .on_chunk(move |chunk| {
+            let _ = tx.send(Ok(ChunkReport::new(&name, chunk)));
+        })
+        .on_error(move |_| {
+            let _ = errors.send(Err(()));
+        })
+        .build();
+
+    match monitor {
+        Ok(mut monitor) => {
+            monitor.start();
+            Box::into_raw(Box::new(Handle { monitor, rx }))
+        },
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Wait up to `wait_ms` for the next chunk. 1 when it was written to `out`,
+/// 0 when none was done in time, and -1 when the probe couldn't be sent
+/// at all, e.g. for lack of permission. The monitor keeps going either way
+///
+/// # Safety
+///
+/// `handle` must come from `packetloss_start` and not be stopped yet, `out`
+/// must point to a writable `packetloss_chunk`
+#[no_mangle]
+pub unsafe extern "C" fn packetloss_poll(handle: *mut Handle, out: *mut Chunk, wait_ms: u32) -> c_int {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return -1,
+    };
+
+    match handle.rx.recv_timeout(Duration::from_millis(wait_ms as u64)) {
+        Ok(Ok(report)) => {
+            if let Some(out) = out.as_mut() {
+                *out = Chunk::from(&report);
+            }
+            1
+        },
+        Ok(Err(())) | Err(RecvTimeoutError::Disconnected) => -1,
+        Err(RecvTimeoutError::Timeout) => 0,
+    }
+}
+
+/// Stop the monitor, waiting for the chunk in flight, and free it
+///
+/// # Safety
+///
+/// `handle` must come from `packetloss_start`, or be null. It can't be
+/// used again afterwards
+#[no_mangle]
+pub unsafe extern "C" fn packetloss_stop(handle: *mut Handle) {
+    if handle.is_null() {
+        return;
+    }
+
+    let mut handle = Box::from_raw(handle);
+    handle.monitor.stop();
+}
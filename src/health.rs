@@ -0,0 +1,169 @@
+use tui::style::Color;
+
+use crate::ping::PacketChunk;
+
+/*
+ * at-a-glance state of a target, from its most recent chunk. shown as a
+ * glyph so a wall of panes can be triaged by shape as well as color.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Health {
+    Unknown,
+    Up,
+    Degraded,
+    Down,
+}
+
+impl Health {
+    /// `threshold` is the loss ratio at which a chunk counts as lossy
+    pub fn of(chunk: &PacketChunk, threshold: f64) -> Health {
+        if chunk.sent() == 0 {
+            Health::Unknown
+        } else if chunk.down() {
+            Health::Down
+        } else if chunk.loss() >= threshold {
+            Health::Degraded
+        } else {
+            Health::Up
+        }
+    }
+
+    pub fn glyph(&self) -> &'static str {
+        match self {
+            Health::Unknown => "·",
+            Health::Up => "●",
+            Health::Degraded => "◐",
+            Health::Down => "○",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Health::Unknown => Color::Gray,
+            Health::Up => Color::Rgb(15, 224, 71),
+            Health::Degraded => Color::Rgb(224, 190, 15),
+            Health::Down => Color::Rgb(224, 15, 71),
+        }
+    }
+}
+
+/* worse states compare greater */
+fn severity(health: Health) -> u8 {
+    match health {
+        Health::Unknown => 0,
+        Health::Up => 1,
+        Health::Degraded => 2,
+        Health::Down => 3,
+    }
+}
+
+/// Health with hysteresis: it worsens at once but only recovers after
+/// `recover` consecutive better chunks, so borderline links don't flap
+pub struct Tracker {
+    health: Health,
+    threshold: f64,
+    recover: u32,
+    better: u32,
+}
+
+impl Tracker {
+    pub fn new(threshold: f64, recover: u32) -> Self {
+        Tracker {
+            health: Health::Unknown,
+            threshold,
+            recover: recover.max(1),
+            better: 0,
+        }
+    }
+
+    /// Change the lossy threshold and recovery count, keeping the current state
+    pub fn configure(&mut self, threshold: f64, recover: u32) {
+        self.threshold = threshold;
+        self.recover = recover.max(1);
+    }
+
+    pub fn health(&self) -> Health {
+        self.health
+    }
+
+    /// Feed a chunk, returning whether the state changed
+    pub fn update(&mut self, chunk: &PacketChunk) -> bool {
+        let next = Health::of(chunk, self.threshold);
+
+        if next == self.health {
+            self.better = 0;
+            return false;
+        }
+
+        if self.health != Health::Unknown && severity(next) < severity(self.health) {
+            self.better += 1;
+
+            if self.better < self.recover {
+                return false;
+            }
+        }
+
+        self.health = next;
+        self.better = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ping;
+
+    /* 10 probes, `lost` of them lost */
+    fn chunk(lost: usize) -> PacketChunk {
+        let mut chunk = PacketChunk::new(1000.0);
+        for i in 0..10 {
+            chunk.push(if i < lost { None } else { Some(ping::reply(10.0, 0)) });
+        }
+        chunk
+    }
+
+    #[test]
+    fn worsens_at_once() {
+        let mut tracker = Tracker::new(0.2, 3);
+
+        /* the first chunk is taken as it is, better than unknown or not */
+        assert!(tracker.update(&chunk(0)));
+        assert_eq!(tracker.health(), Health::Up);
+
+        assert!(tracker.update(&chunk(5)));
+        assert_eq!(tracker.health(), Health::Degraded);
+        assert!(tracker.update(&chunk(10)));
+        assert_eq!(tracker.health(), Health::Down);
+    }
+
+    #[test]
+    fn recovers_after_a_streak() {
+        let mut tracker = Tracker::new(0.2, 3);
+        tracker.update(&chunk(10));
+
+        assert!(!tracker.update(&chunk(0)));
+        assert!(!tracker.update(&chunk(0)));
+        assert_eq!(tracker.health(), Health::Down);
+        assert!(tracker.update(&chunk(0)));
+        assert_eq!(tracker.health(), Health::Up);
+    }
+
+    #[test]
+    fn relapse_restarts_the_streak() {
+        let mut tracker = Tracker::new(0.2, 3);
+        tracker.update(&chunk(5));
+
+        tracker.update(&chunk(0));
+        tracker.update(&chunk(0));
+        /* as bad as it already was, so the two good ones no longer count */
+        assert!(!tracker.update(&chunk(5)));
+
+        assert!(!tracker.update(&chunk(0)));
+        assert!(!tracker.update(&chunk(0)));
+        assert_eq!(tracker.health(), Health::Degraded);
+        assert!(tracker.update(&chunk(0)));
+        assert_eq!(tracker.health(), Health::Up);
+    }
+}
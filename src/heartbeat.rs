@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+use std::thread;
+
+use log::warn;
+
+use crate::ping::PacketChunk;
+
+/*
+ * dead-man's-switch heartbeat. a beat is sent after a completed chunk,
+ * whatever its loss, so the remote end only goes quiet when the monitor
+ * itself stops working. a chunk whose burst failed outright, the socket
+ * or the name gone, is no sign of a working monitor and sends none. with
+ * several targets the beats are at most one per interval, not one per
+ * target.
+ */
+
+pub struct Heartbeat {
+    url: String,
+    last: Option<Instant>,
+}
+
+impl Heartbeat {
+    pub fn new(url: &str) -> Self {
+        Heartbeat {
+            url: url.to_string(),
+            last: None,
+        }
+    }
+
+    /// Beat for `chunk`, unless it failed or another beat went out within `interval`
+    pub fn beat(&mut self, chunk: &PacketChunk, interval: Duration) {
+        if chunk.error().is_some() || self.last.is_some_and(|x| x.elapsed() < interval) {
+            return;
+        }
+        self.last = Some(Instant::now());
+
+        let url = self.url.clone();
+        thread::spawn(move || {
+            let sent = ureq::get(&url)
+                .timeout(Duration::from_secs(10))
+                .call();
+
+            if let Err(e) = sent {
+                warn!("heartbeat: {}", e);
+            }
+        });
+    }
+}
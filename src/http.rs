@@ -0,0 +1,121 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use ureq::{Agent, AgentBuilder, ErrorKind};
+
+use crate::ping::{ms, AddrFamily, PingError, Probe, ProbeResult, Reply};
+
+/*
+ * http probes, for reachability of the application rather than the host.
+ * each probe is one request on a kept-alive connection, timed up to the
+ * response headers. a 2xx, after any redirects, is a reply. an error
+ * status, a refused connection or a failed tls handshake count as lost,
+ * the same as no response within the timeout.
+ */
+
+/* a GET reads at most this much of the body, so the connection can be reused */
+const BODY: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Head,
+    Get,
+}
+
+impl Method {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "HEAD" => Some(Method::Head),
+            "GET" => Some(Method::Get),
+            _ => None,
+        }
+    }
+}
+
+pub struct Http {
+    url: String,
+    method: Method,
+    agent: Agent,
+    seq: AtomicU16,
+    timeout: Duration,
+}
+
+impl Http {
+    pub fn new(url: &str, method: Method, timeout: Duration) -> Self {
+        let agent = AgentBuilder::new()
+            .timeout(timeout)
+            .user_agent(concat!("packetloss/", env!("CARGO_PKG_VERSION")))
+            .build();
+
+        Http {
+            url: url.to_string(),
+            method,
+            agent,
+            seq: AtomicU16::new(0),
+            timeout,
+        }
+    }
+}
+
+impl Probe for Http {
+    fn probe(&self) -> ProbeResult {
+        let mut reply = Reply {
+            hostname: self.url.clone(),
+            address: String::new(),
+            family: AddrFamily::IPV4,
+            latency_ms: -1.0,
+            dropped: 1,
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) as i32,
+            recv_ttl: -1,
+            recv_qos: 0,
+        };
+
+        let request = match self.method {
+            Method::Head => self.agent.head(&self.url),
+            Method::Get => self.agent.get(&self.url),
+        };
+
+        let start = Instant::now();
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Transport(ref e)) if e.kind() == ErrorKind::Dns => {
+                return Err(PingError::Resolve(self.url.clone()));
+            },
+            /* every probe would fail the same way */
+            Err(ureq::Error::Transport(ref e)) if e.kind() == ErrorKind::InvalidUrl
+                || e.kind() == ErrorKind::UnknownScheme => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, e.to_string()).into());
+            },
+            Err(_) => return Ok(reply),
+        };
+        let latency = ms(start.elapsed());
+
+        let peer = response.remote_addr();
+        reply.address = peer.ip().to_string();
+        reply.family = if peer.is_ipv6() { AddrFamily::IPV6 } else { AddrFamily::IPV4 };
+
+        /* 4xx and 5xx are errors already, this leaves the odd 1xx or unfollowed 3xx */
+        let ok = (200..300).contains(&response.status());
+        let _ = io::copy(&mut response.into_reader().take(BODY), &mut io::sink());
+
+        if ok {
+            reply.latency_ms = latency;
+            reply.dropped = 0;
+        }
+
+        Ok(reply)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+pub fn is_url(v: String) -> Result<(), String> {
+    if v.starts_with("http://") || v.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(String::from("Value must be an http:// or https:// URL"))
+    }
+}
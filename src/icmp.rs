@@ -0,0 +1,386 @@
+use chrono::Local;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::ping::{burst, ms, AddrFamily, IcmpError, PacketChunk, PingError, Probe, ProbeResult, Reply};
+
+/* icmp types, for v4 and v6 */
+const ECHO_REQUEST: (u8, u8) = (8, 128);
+const ECHO_REPLY: (u8, u8) = (0, 129);
+
+/* bytes after the 8 byte icmp header, like ping(8) */
+const PAYLOAD: usize = 56;
+
+/* the last resort when no icmp socket may be opened, usually setuid or with file capabilities */
+const SYSTEM_PING: &str = "/bin/ping";
+
+/// The kind of ICMP socket a `Ping` sends through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Needs root or CAP_NET_RAW, sees the reply's TTL
+    Raw,
+    /// Unprivileged, as Linux allows for groups in `net.ipv4.ping_group_range`
+    /// and macOS for everyone
+    Datagram,
+    /// The system's ping(8), one process per probe, when no socket is permitted
+    System,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Raw => write!(f, "raw socket"),
+            Mode::Datagram => write!(f, "datagram socket (unprivileged)"),
+            Mode::System => write!(f, "{}", SYSTEM_PING),
+        }
+    }
+}
+
+/// ICMP echo probes to one host
+///
+/// A raw socket is used where permitted, otherwise an unprivileged datagram
+/// socket, and as a last resort the system's ping, see `mode`.
+pub struct Ping {
+    addr: String,
+    timeout: Duration,
+    seq: AtomicU16,
+    mode: Option<Mode>,
+}
+
+impl Ping {
+    /// Probes to `addr`, a host name or address, counting replies slower than `timeout` as lost
+    pub fn new(addr: &str, timeout: Duration) -> Self {
+
+        Ping {
+            addr: addr.to_string(),
+            timeout,
+            seq: AtomicU16::new(0),
+            mode: probe(),
+        }
+    }
+
+    /// How probes are sent, chosen when this was created. An error when no
+    /// socket is permitted and there is no system ping either
+    pub fn mode(&self) -> Result<Mode, PingError> {
+        self.mode.ok_or_else(denied)
+    }
+
+    /// `count` pings sent back to back
+    pub fn ping(&self, count: u64) -> Result<PacketChunk, PingError> {
+        self.burst(count, Duration::from_secs(0))
+    }
+
+    /// `count` pings, each starting `spacing` after the previous one
+    pub fn burst(&self, count: u64, spacing: Duration) -> Result<PacketChunk, PingError> {
+        Ok(burst(self, count, spacing))
+    }
+
+    fn echo(&self) -> ProbeResult {
+
+        let target = (self.addr.as_str(), 0).to_socket_addrs()
+            .map_err(|_| PingError::Resolve(self.addr.clone()))?
+            .next()
+            .ok_or_else(|| PingError::Resolve(self.addr.clone()))?;
+
+        let raw = self.mode()? == Mode::Raw;
+        let socket = open(&target, raw)?;
+        socket.connect(&SockAddr::from(target))?;
+
+        let v6 = target.is_ipv6();
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        /* a datagram socket gets its identifier from the kernel, the payload tells replies apart */
+        let token = Local::now().timestamp_nanos_opt().unwrap_or(0) as u64 ^ seq as u64;
+
+        let mut packet = [0u8; 8 + PAYLOAD];
+        packet[0] = if v6 { ECHO_REQUEST.1 } else { ECHO_REQUEST.0 };
+        packet[4..6].copy_from_slice(&(process::id() as u16).to_be_bytes());
+        packet[6..8].copy_from_slice(&seq.to_be_bytes());
+        packet[8..16].copy_from_slice(&token.to_be_bytes());
+        /* the kernel fills in the icmpv6 checksum, it covers a pseudo header */
+        if !v6 {
+            let sum = checksum(&packet);
+            packet[2..4].copy_from_slice(&sum.to_be_bytes());
+        }
+
+        let mut item = Reply {
+            hostname: self.addr.clone(),
+            address: target.ip().to_string(),
+            family: if v6 { AddrFamily::IPV6 } else { AddrFamily::IPV4 },
+            latency_ms: -1.0,
+            dropped: 1,
+            seq: seq as i32,
+            recv_ttl: -1,
+            recv_qos: 0,
+        };
+
+        let start = Instant::now();
+        let deadline = start + self.timeout;
+        socket.send(&packet)?;
+
+        let mut buf = [0u8; 1500];
+        loop {
+            let left = match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if left > Duration::from_millis(0) => left,
+                _ => return Ok(item),
+            };
+            socket.set_read_timeout(Some(left))?;
+
+            let n = match (&socket).read(&mut buf) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => return Ok(item),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                /* a datagram socket hears of icmp errors only as the errno the kernel made of them */
+                Err(ref e) if e.kind() == io::ErrorKind::HostUnreachable => return Err(PingError::Icmp(IcmpError::HostUnreachable)),
+                Err(ref e) if e.kind() == io::ErrorKind::NetworkUnreachable => return Err(PingError::Icmp(IcmpError::NetUnreachable)),
+                Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => return Err(PingError::Icmp(IcmpError::Prohibited)),
+                Err(e) => return Err(e.into()),
+            };
+
+            /* raw v4 sockets, and datagram ones on macOS, see the ip header too */
+            let mut reply = &buf[..n];
+            let mut ttl = -1;
+            if !v6 && n >= 20 && reply[0] >> 4 == 4 {
+                let header = (reply[0] & 0x0f) as usize * 4;
+                ttl = reply[8] as i32;
+                reply = &reply[header.min(n)..];
+            }
+
+            /* raw sockets see errors about our echo, which quote its icmp header */
+            if raw {
+                if let Some((error, quoted)) = classify(reply, v6) {
+                    if quoted[6..8] == packet[6..8] && quoted[4..6] == packet[4..6] {
+                        return Err(PingError::Icmp(error));
+                    }
+                    continue;
+                }
+            }
+
+            let kind = if v6 { ECHO_REPLY.1 } else { ECHO_REPLY.0 };
+            if reply.len() < 16 || reply[0] != kind || reply[6..8] != packet[6..8] || reply[8..16] != packet[8..16] {
+                continue;
+            }
+            /* other processes' replies reach raw sockets too */
+            if raw && reply[4..6] != packet[4..6] {
+                continue;
+            }
+
+            item.latency_ms = ms(start.elapsed());
+            item.dropped = 0;
+            item.recv_ttl = ttl;
+            return Ok(item);
+        }
+    }
+
+    /* one probe through ping(8), reading the rtt and ttl off its output */
+    fn system_ping(&self) -> ProbeResult {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+
+        /* the reply wait is in ms on the bsds and macOS, in seconds elsewhere */
+        let wait = if cfg!(any(target_os = "macos", target_os = "freebsd")) {
+            self.timeout.as_millis().max(1).to_string()
+        } else {
+            format!("{:.3}", self.timeout.as_secs_f64())
+        };
+
+        let output = Command::new(SYSTEM_PING)
+            .args(["-n", "-c", "1", "-W", &wait, &self.addr])
+            .stdin(Stdio::null())
+            .output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let latency = match field(&stdout, "time=") {
+            Some(latency) => latency,
+            /* ping(8) tells of icmp errors on stdout, and exits as if lost */
+            None if stderr.trim().is_empty() => match reported(&stdout) {
+                Some(error) => return Err(PingError::Icmp(error)),
+                None => -1.0,
+            },
+            /* no reply and a complaint, not just a lost packet */
+            None => {
+                let msg = stderr.trim().to_string();
+                let lower = msg.to_lowercase();
+
+                return Err(if lower.contains("unknown host")
+                    || lower.contains("cannot resolve")
+                    || lower.contains("name or service not known")
+                    || lower.contains("temporary failure in name resolution") {
+                    PingError::Resolve(self.addr.clone())
+                } else if let Some(error) = reported(&stdout) {
+                    PingError::Icmp(error)
+                } else if lower.contains("network is unreachable") {
+                    PingError::IO(io::Error::new(io::ErrorKind::NetworkUnreachable, msg))
+                } else {
+                    PingError::IO(io::Error::other(msg))
+                });
+            },
+        };
+
+        /* "PING host (address) ..." */
+        let address = stdout.split(['(', ')']).nth(1).unwrap_or("").to_string();
+
+        Ok(Reply {
+            hostname: self.addr.clone(),
+            family: if address.contains(':') { AddrFamily::IPV6 } else { AddrFamily::IPV4 },
+            address,
+            latency_ms: latency,
+            dropped: if latency < 0.0 { 1 } else { 0 },
+            seq: seq as i32,
+            recv_ttl: field(&stdout, "ttl=").map(|x| x as i32).unwrap_or(-1),
+            recv_qos: 0,
+        })
+    }
+}
+
+impl Probe for Ping {
+    fn probe(&self) -> ProbeResult {
+        match self.mode()? {
+            Mode::System => self.system_ping(),
+            Mode::Raw | Mode::Datagram => self.echo(),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn ready(&self) -> Result<(), PingError> {
+        self.mode().map(|_| ())
+    }
+}
+
+/* the number after `key` in ping(8) output, e.g. time=0.045 */
+fn field(output: &str, key: &str) -> Option<f64> {
+    let start = output.find(key)? + key.len();
+
+    output[start..]
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .next()?
+        .parse()
+        .ok()
+}
+
+/* the icmp error ping(8) printed in place of a reply, if any */
+fn reported(output: &str) -> Option<IcmpError> {
+    let lower = output.to_lowercase();
+
+    if lower.contains("destination host unreachable") || lower.contains("address unreachable") {
+        Some(IcmpError::HostUnreachable)
+    } else if lower.contains("destination net unreachable") {
+        Some(IcmpError::NetUnreachable)
+    } else if lower.contains("prohibited") || lower.contains("packet filtered") {
+        Some(IcmpError::Prohibited)
+    } else if lower.contains("frag needed") || lower.contains("packet too big") {
+        Some(IcmpError::FragNeeded)
+    } else if lower.contains("time to live exceeded") || lower.contains("time exceeded") {
+        Some(IcmpError::TimeExceeded)
+    } else if lower.contains("unreachable") {
+        Some(IcmpError::Other)
+    } else {
+        None
+    }
+}
+
+/*
+ * an icmp error message, with the icmp header of the packet it is about.
+ * v4 errors quote the original ip header first, v6 ones always a 40 byte
+ * header since raw v6 sockets see no header of their own
+ */
+fn classify(reply: &[u8], v6: bool) -> Option<(IcmpError, &[u8])> {
+    if reply.len() < 8 {
+        return None;
+    }
+
+    let (kind, code) = (reply[0], reply[1]);
+
+    /* types 1 and 3 are unreachable and time exceeded in v6, 3 and 11 in v4 */
+    let error = if v6 {
+        match (kind, code) {
+            (1, 0) => IcmpError::NetUnreachable,
+            (1, 3) => IcmpError::HostUnreachable,
+            (1, 1) | (1, 5) | (1, 6) => IcmpError::Prohibited,
+            (1, _) => IcmpError::Other,
+            /* packet too big, what v4 calls fragmentation needed */
+            (2, _) => IcmpError::FragNeeded,
+            (3, _) => IcmpError::TimeExceeded,
+            _ => return None,
+        }
+    } else {
+        match (kind, code) {
+            (3, 0) | (3, 6) | (3, 11) => IcmpError::NetUnreachable,
+            (3, 1) | (3, 7) | (3, 12) => IcmpError::HostUnreachable,
+            (3, 4) => IcmpError::FragNeeded,
+            (3, 9) | (3, 10) | (3, 13) => IcmpError::Prohibited,
+            (3, _) => IcmpError::Other,
+            (11, _) => IcmpError::TimeExceeded,
+            _ => return None,
+        }
+    };
+
+    let quoted = &reply[8..];
+    let header = if v6 {
+        40
+    } else if !quoted.is_empty() {
+        (quoted[0] & 0x0f) as usize * 4
+    } else {
+        return None;
+    };
+
+    quoted.get(header..header + 8).map(|x| (error, x))
+}
+
+/* the best socket this process may open, the same rules cover v4 and v6 */
+fn probe() -> Option<Mode> {
+    if Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).is_ok() {
+        Some(Mode::Raw)
+    } else if Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4)).is_ok() {
+        Some(Mode::Datagram)
+    } else if Path::new(SYSTEM_PING).exists() {
+        Some(Mode::System)
+    } else {
+        None
+    }
+}
+
+fn denied() -> PingError {
+    PingError::IO(io::Error::new(io::ErrorKind::PermissionDenied,
+        format!("icmp sockets need root, CAP_NET_RAW, or a group in net.ipv4.ping_group_range, \
+                 and there is no {} to fall back on", SYSTEM_PING)))
+}
+
+fn open(target: &SocketAddr, raw: bool) -> Result<Socket, PingError> {
+    let (domain, protocol) = match target.ip() {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+
+    Ok(Socket::new(domain, if raw { Type::RAW } else { Type::DGRAM }, Some(protocol))?)
+}
+
+/* the internet checksum of rfc 1071 */
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+
+    for pair in data.chunks(2) {
+        let word = match pair {
+            [a, b] => u16::from_be_bytes([*a, *b]),
+            [a] => u16::from_be_bytes([*a, 0]),
+            _ => 0,
+        };
+        sum += word as u32;
+    }
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
@@ -0,0 +1,182 @@
+use std::io::{self, BufRead};
+
+use chrono::prelude::*;
+
+use crate::ping::{self, PacketChunk, Params};
+
+/*
+ * the output of the system ping, made into chunks: iputils on linux, and
+ * the bsd one macos has. a reply line gives its sequence number and round
+ * trip time; a line about the sequence number without one, a timeout or
+ * an unreachable, and any number skipped over are lost probes. with
+ * ping -D every line starts with its unix time, otherwise the probes are
+ * taken to be `interval` apart, ending at `end` unless a start is given.
+ */
+
+/* sequence numbers are 16 bits, and wrap in a long enough log */
+const SEQUENCES: i64 = 1 << 16;
+
+/// How the probes of a log are timed and cut into chunks
+pub struct Options {
+    pub chunk_size: u64,
+    /* seconds between probes, as given to ping -i */
+    pub interval: f64,
+    pub start: Option<DateTime<Local>>,
+    /* when the log ended, for one without timestamps and no start */
+    pub end: DateTime<Local>,
+    /* ms, what a lost probe is drawn with */
+    pub timeout: f64,
+}
+
+/* one probe: its time if the line had one, and its round trip unless lost */
+struct Probe {
+    time: Option<DateTime<Local>>,
+    latency: Option<f64>,
+}
+
+/// The host a ping log was of, if it says, and its probes as chunks
+pub fn parse<R: BufRead>(input: R, options: &Options) -> io::Result<(Option<String>, Vec<PacketChunk>)> {
+    let mut target = None;
+    let mut probes: Vec<Probe> = vec![];
+    /* the sequence numbers of the first probe and the one after the newest, counting past wraps */
+    let mut first: Option<i64> = None;
+    let mut next: Option<i64> = None;
+
+    for line in input.lines() {
+        let line = line?;
+        let (time, line) = stamp(&line);
+
+        /* "PING example.com (93.184.216.34) 56(84) bytes of data." */
+        if let Some(rest) = line.strip_prefix("PING ") {
+            target = target.or_else(|| rest.split_whitespace().next().map(String::from));
+            continue;
+        }
+
+        /* "(DUP!)" is a second reply to a probe already counted */
+        let seq = match sequence(line) {
+            Some(seq) if !line.contains("DUP!") => seq,
+            _ => continue,
+        };
+
+        let seq = match next {
+            Some(next) => {
+                /* the nearest number to the one expected that has these 16 bits */
+                let mut seq = next - next.rem_euclid(SEQUENCES) + seq;
+                if seq < next - SEQUENCES / 2 {
+                    seq += SEQUENCES;
+                }
+                seq
+            },
+            None => seq,
+        };
+
+        let start = *first.get_or_insert(seq);
+
+        /* a reply that came in late, after a later probe or its timeout, fills in its own */
+        if next.is_some_and(|x| seq < x) {
+            if let (Some(probe), Some(latency)) = (probes.get_mut((seq - start).max(0) as usize), latency(line)) {
+                probe.latency = Some(latency);
+            }
+            continue;
+        }
+
+        for _ in next.unwrap_or(seq)..seq {
+            probes.push(Probe { time: None, latency: None });
+        }
+        next = Some(seq + 1);
+
+        probes.push(Probe { time, latency: latency(line) });
+    }
+
+    Ok((target, chunks(probes, options)))
+}
+
+/* the unix time ping -D puts in front, and the rest of the line */
+fn stamp(line: &str) -> (Option<DateTime<Local>>, &str) {
+    let parsed = line.strip_prefix('[')
+        .and_then(|x| x.split_once(']'))
+        .and_then(|(stamp, rest)| Some((unix_time(stamp)?, rest.trim_start())));
+
+    match parsed {
+        Some((time, rest)) => (Some(time), rest),
+        None => (None, line),
+    }
+}
+
+/* "1697040000.123456", the fraction read as digits so no float rounds it */
+fn unix_time(stamp: &str) -> Option<DateTime<Local>> {
+    let (secs, fraction) = stamp.split_once('.').unwrap_or((stamp, ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = format!("{:0<9}", fraction).parse::<u32>().ok()?;
+
+    Local.timestamp_opt(secs.parse::<i64>().ok()?, nanos).single()
+}
+
+/* "icmp_seq=3", or "icmp_seq 3" in a macos timeout */
+fn sequence(line: &str) -> Option<i64> {
+    let rest = &line[line.find("icmp_seq")? + "icmp_seq".len()..];
+    let digits: String = rest.trim_start_matches(['=', ' ']).chars().take_while(|x| x.is_ascii_digit()).collect();
+
+    digits.parse::<i64>().ok()
+}
+
+/* "time=12.3 ms", or "time<1 ms" on some systems */
+fn latency(line: &str) -> Option<f64> {
+    let rest = &line[line.find(" time")? + " time".len()..];
+    let rest = rest.strip_prefix('=').or_else(|| rest.strip_prefix('<'))?;
+
+    rest.split_whitespace().next()?.parse::<f64>().ok()
+}
+
+/* every probe a time, from the nearest one that has one, then cut into chunks */
+fn chunks(probes: Vec<Probe>, options: &Options) -> Vec<PacketChunk> {
+    let step = |n: i64| chrono::Duration::milliseconds((options.interval * 1000.0) as i64 * n);
+
+    let anchor = probes.iter().position(|x| x.time.is_some());
+    let (at, time) = match anchor {
+        Some(i) => (i, probes[i].time.unwrap()),
+        None => match options.start {
+            Some(start) => (0, start),
+            None => (probes.len().saturating_sub(1), options.end),
+        },
+    };
+
+    let mut times = vec![time; probes.len()];
+    let mut last = time;
+    for i in (0..at).rev() {
+        times[i] = times[i + 1] - step(1);
+    }
+    for (i, probe) in probes.iter().enumerate().skip(at) {
+        last = probe.time.unwrap_or(last + step((i > at) as i64));
+        times[i] = last;
+    }
+
+    let params = Params {
+        interval: 0.0,
+        chunk_size: options.chunk_size,
+        spacing: options.interval * 1000.0,
+    };
+
+    let size = options.chunk_size.max(1) as usize;
+    let mut chunks = vec![];
+
+    for (probes, times) in probes.chunks(size).zip(times.chunks(size)) {
+        let mut chunk = PacketChunk::new(options.timeout);
+        chunk.set_time(times[0]);
+        chunk.set_params(params);
+
+        for (probe, time) in probes.iter().zip(times) {
+            chunk.push(Some(match probe.latency {
+                Some(latency) => ping::reply(latency, 0),
+                None => ping::reply(options.timeout, 1),
+            }));
+            chunk.stamp((*time - times[0]).num_microseconds().unwrap_or(0) as f64 / 1000.0);
+        }
+
+        chunks.push(chunk);
+    }
+
+    chunks
+}
@@ -0,0 +1,61 @@
+//! Packet loss and latency tracking, as used by the `packetloss` monitor
+//!
+//! A [`icmp::Ping`] sends a burst of ICMP echoes to one host and returns the
+//! replies as a [`ping::PacketChunk`], which carries loss, latency, jitter
+//! and an estimated MOS. Other kinds of probe implement [`ping::Probe`] and
+//! get their chunks from [`ping::burst`], as [`tcp::Tcp`] and [`udp::Udp`]
+//! do for TCP connects and UDP datagrams. A [`store::ChunkStore`] keeps a
+//! bounded history of chunks, and [`report`] summarizes a run of them.
+//! [`monitor::Monitor`] probes on a schedule from its own thread, for
+//! programs that only want the chunks.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use packetloss::icmp::Ping;
+//! use packetloss::store::ChunkStore;
+//!
+//! let ping = Ping::new("example.com", Duration::from_secs(1));
+//! let mut history = ChunkStore::new(1000);
+//!
+//! let chunk = ping.ping(10).unwrap();
+//! println!("{:.0}% loss", chunk.loss() * 100.0);
+//! history.insert(chunk);
+//! ```
+//!
+//! The tui widgets in `draw`, down to `LogList` with the whole history as
+//! tiles or a timeline, need the default `tui` feature, build with
+//! `default-features = false` to leave termion and tui out. The binary also
+//! wants a terminal library: `termion` by default, or `crossterm` for
+//! windows consoles (`--no-default-features --features crossterm`).
+//!
+//! Probing (`icmp`, `tcp`, `udp` and `monitor`) needs the `probe` feature,
+//! which the binary's features turn on. Without it no sockets or threads
+//! are left, only the statistics, colors and tile layout of [`ping`],
+//! [`report`], [`store`] and [`partition`], and those build for wasm32, e.g.
+//! for a browser viewer of recorded sessions:
+//! `cargo build --lib --no-default-features --target wasm32-unknown-unknown`.
+//!
+//! [`strip`] draws a history as a single row of colored cells, for other
+//! terminal UIs to embed, with tui or (feature `ratatui`) ratatui.
+//!
+//! The `ffi` feature adds the `ffi` module, a C API over the monitor for a
+//! shared library, declared in `include/packetloss.h`.
+
+pub mod locale;
+pub mod partition;
+pub mod ping;
+pub mod report;
+pub mod store;
+pub mod strip;
+#[cfg(feature = "probe")]
+pub mod icmp;
+#[cfg(feature = "probe")]
+pub mod monitor;
+#[cfg(feature = "probe")]
+pub mod tcp;
+#[cfg(feature = "probe")]
+pub mod udp;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "tui")]
+pub mod draw;
@@ -0,0 +1,92 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::reflect;
+
+/*
+ * http health endpoints for orchestrators, served on a thread of their own:
+ *
+ *   /healthz  200 while chunks keep coming, 503 once the last one is older
+ *             than the stale limit, so a wedged probe loop gets restarted
+ *   /readyz   200 once the first chunk is out, 503 before
+ */
+
+pub struct Liveness {
+    start: Instant,
+    last: Mutex<Option<Instant>>,
+    stale: Duration,
+}
+
+impl Liveness {
+    pub fn new(stale: Duration) -> Arc<Self> {
+        Arc::new(Liveness {
+            start: Instant::now(),
+            last: Mutex::new(None),
+            stale,
+        })
+    }
+
+    /// Note that a chunk was just produced
+    pub fn chunk(&self) {
+        *self.last.lock().unwrap() = Some(Instant::now());
+    }
+
+    fn status(&self, path: &str) -> (u16, String) {
+        let last = *self.last.lock().unwrap();
+
+        match (path, last) {
+            ("/healthz", Some(last)) if last.elapsed() > self.stale => {
+                (503, format!("stale: last chunk {}s ago\n", last.elapsed().as_secs()))
+            },
+            ("/healthz", Some(last)) => (200, format!("ok: last chunk {}s ago\n", last.elapsed().as_secs())),
+            /* still working on the first chunk, which is only a problem if it takes too long */
+            ("/healthz", None) if self.start.elapsed() > self.stale => {
+                (503, format!("stale: no chunk in {}s\n", self.start.elapsed().as_secs()))
+            },
+            ("/healthz", None) => (200, String::from("ok: starting\n")),
+            ("/readyz", Some(_)) => (200, String::from("ready\n")),
+            ("/readyz", None) => (503, String::from("not ready: no chunk yet\n")),
+            _ => (404, String::from("not found\n")),
+        }
+    }
+
+    fn respond(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+
+        /* GET /healthz HTTP/1.1 */
+        let path = request.split_whitespace().nth(1).unwrap_or("");
+        let path = path.split('?').next().unwrap_or("");
+
+        let (code, body) = self.status(path);
+        let reason = match code {
+            200 => "OK",
+            503 => "Service Unavailable",
+            _ => "Not Found",
+        };
+
+        let mut stream = stream;
+        write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            code, reason, body.len(), body)?;
+        stream.flush()
+    }
+}
+
+/// Serve the endpoints on `[ADDRESS]:PORT` until the process exits
+pub fn listen(addr: &str, liveness: Arc<Liveness>) -> io::Result<()> {
+    /* bound here so a taken port fails the command rather than the thread */
+    let listener = TcpListener::bind(reflect::listen_addr(addr))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = liveness.respond(stream);
+        }
+    });
+
+    Ok(())
+}
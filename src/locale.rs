@@ -0,0 +1,362 @@
+use std::env;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/*
+ * translated ui and report text. every language is a plain table from
+ * `Text` to a template, where each {} is filled in order by `fill`, so a
+ * translation may move words around the numbers but not reorder them.
+ * the language is picked once at startup, english when nothing matches.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lang {
+    En,
+    De,
+    Es,
+}
+
+/// Language codes accepted by `--lang`
+pub const CODES: &[&str] = &["en", "de", "es"];
+
+impl Lang {
+    /// A language code, or a locale such as `de_DE.UTF-8`
+    pub fn parse(s: &str) -> Option<Lang> {
+        let code = s.split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+
+        match code.as_str() {
+            "en" | "c" | "posix" => Some(Lang::En),
+            "de" => Some(Lang::De),
+            "es" => Some(Lang::Es),
+            _ => None,
+        }
+    }
+
+    /// The system locale from LC_ALL, LC_MESSAGES or LANG, in that order
+    pub fn from_env() -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|x| env::var(x).ok())
+            .find(|x| !x.is_empty())
+            .and_then(|x| Lang::parse(&x))
+            .unwrap_or(Lang::En)
+    }
+}
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+/// Switch the language of everything drawn or reported from now on
+pub fn set(lang: Lang) {
+    CURRENT.store(lang as usize, Ordering::Relaxed);
+}
+
+pub fn current() -> Lang {
+    match CURRENT.load(Ordering::Relaxed) {
+        1 => Lang::De,
+        2 => Lang::Es,
+        _ => Lang::En,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Text {
+    PacketList,
+    InspectPacket,
+    InspectParams,
+    MarkerPrompt,
+    Down,
+    Chunk,
+    ChunkSlow,
+    ChunkOneway,
+    ChunkMos,
+    ChunkLate,
+    LateShort,
+    Retransmits,
+    Capture,
+    CaptureLost,
+    NoChunks,
+    From,
+    To,
+    Chunks,
+    ChunksLine,
+    Packets,
+    PacketsLine,
+    Latency,
+    LatencyLine,
+    Jitter,
+    JitterLine,
+    Oneway,
+    OnewayLine,
+    Speed,
+    SpeedLine,
+    Tcp,
+    TcpLine,
+    Marker,
+    Mos,
+    MosLine,
+    Worst,
+    Runtime,
+    WorstLine,
+    Before,
+    After,
+    Change,
+    Better,
+    Worse,
+    Loss,
+    Incidents,
+    Day,
+    DayLine,
+    DayClean,
+    Weekdays,
+    PathShift,
+    Reloaded,
+    StatsLogged,
+    StatsNoLog,
+    Backoff,
+    NetworkDown,
+    NetworkBack,
+    IcmpErrors,
+    Burst,
+    IcmpNet,
+    IcmpHost,
+    IcmpProhibited,
+    IcmpFrag,
+    IcmpTtl,
+    IcmpOther,
+}
+
+/// The current language's template for `text`
+pub fn tr(text: Text) -> &'static str {
+    match current() {
+        Lang::En => en(text),
+        Lang::De => de(text),
+        Lang::Es => es(text),
+    }
+}
+
+/// Append `template` to `out` with each {} replaced by the next of `args`
+pub fn fill(out: &mut String, template: &str, args: &[&dyn fmt::Display]) {
+    let mut args = args.iter();
+    let mut parts = template.split("{}");
+
+    out.push_str(parts.next().unwrap_or(""));
+    for part in parts {
+        if let Some(arg) = args.next() {
+            let _ = write!(out, "{}", arg);
+        }
+        out.push_str(part);
+    }
+}
+
+/// `fill` into a new string
+pub fn format(text: Text, args: &[&dyn fmt::Display]) -> String {
+    let mut out = String::new();
+    fill(&mut out, tr(text), args);
+    out
+}
+
+fn en(text: Text) -> &'static str {
+    match text {
+        Text::PacketList => "Packet list",
+        Text::InspectPacket => "Inspect packet",
+        Text::InspectParams => "{} pings every {}s, {}ms apart, {}ms timeout",
+        Text::MarkerPrompt => "marker: {}▏ (enter to place, esc to cancel)",
+        Text::Down => "DOWN",
+        Text::Chunk => "{}: {}% packet loss, time {}ms",
+        Text::ChunkSlow => "{}: {}% packet loss, {} slow, time {}ms",
+        Text::ChunkOneway => "{}: {}% packet loss (↑{} ↓{}), time {}ms",
+        Text::ChunkMos => "{}: MOS {}, {}% packet loss, time {}ms",
+        Text::ChunkLate => "{}: {} late/s, {} of {} probes late",
+        Text::LateShort => "{} late/s",
+        Text::Retransmits => "{}% tcp retransmits",
+        Text::Capture => "capture: {} pings, {}% loss, max {}ms",
+        Text::CaptureLost => "capture: {} pings, all lost",
+        Text::NoChunks => "no chunks recorded",
+        Text::From => "from",
+        Text::To => "to",
+        Text::Chunks => "chunks",
+        Text::ChunksLine => "{} ({} with loss)",
+        Text::Packets => "packets",
+        Text::PacketsLine => "{} sent, {} received, {}% loss",
+        Text::Latency => "latency",
+        Text::LatencyLine => "min {}ms, avg {}ms, max {}ms",
+        Text::Jitter => "jitter",
+        Text::JitterLine => "avg {}ms, max {}ms",
+        Text::Oneway => "one-way",
+        Text::OnewayLine => "{} lost outbound, {} lost on return",
+        Text::Speed => "speed",
+        Text::SpeedLine => "avg {} Mbit/s, min {}, max {} over {} tests, {}% loss meanwhile",
+        Text::Tcp => "tcp",
+        Text::TcpLine => "avg {}% retransmits, max {}%",
+        Text::Marker => "marker",
+        Text::Mos => "mos",
+        Text::MosLine => "avg {}, min {}",
+        Text::Worst => "worst",
+        Text::Runtime => "running",
+        Text::WorstLine => "{} with {}% loss",
+        Text::Before => "before",
+        Text::After => "after",
+        Text::Change => "change",
+        Text::Better => "better",
+        Text::Worse => "worse",
+        Text::Loss => "loss",
+        Text::Incidents => "incidents",
+        Text::Day => "day",
+        Text::DayLine => "{} {}% up, worst {} for {} with {}% loss",
+        Text::DayClean => "{} {}% up, no loss",
+        Text::Weekdays => "Mon Tue Wed Thu Fri Sat Sun",
+        Text::PathShift => "path {}→{}ms",
+        Text::Reloaded => "reloaded {}",
+        Text::StatsLogged => "statistics written to {}",
+        Text::StatsNoLog => "no --log-file to write statistics to",
+        Text::Backoff => "{}: {} failed bursts in a row, next try in {}s",
+        Text::NetworkDown => "local network down: no default route, alerts held until it is back",
+        Text::NetworkBack => "local network back, alerting again",
+        Text::IcmpErrors => "{}× {}",
+        Text::Burst => "{} lost in a row, burst ratio {}",
+        Text::IcmpNet => "net unreachable",
+        Text::IcmpHost => "host unreachable",
+        Text::IcmpProhibited => "prohibited",
+        Text::IcmpFrag => "frag needed",
+        Text::IcmpTtl => "ttl exceeded",
+        Text::IcmpOther => "icmp error",
+    }
+}
+
+fn de(text: Text) -> &'static str {
+    match text {
+        Text::PacketList => "Paketliste",
+        Text::InspectPacket => "Paket prüfen",
+        Text::InspectParams => "{} Pings alle {}s, {}ms Abstand, {}ms Timeout",
+        Text::MarkerPrompt => "Markierung: {}▏ (Enter setzt, Esc bricht ab)",
+        Text::Down => "AUSFALL",
+        Text::Chunk => "{}: {}% Paketverlust, Zeit {}ms",
+        Text::ChunkSlow => "{}: {}% Paketverlust, {} langsam, Zeit {}ms",
+        Text::ChunkOneway => "{}: {}% Paketverlust (↑{} ↓{}), Zeit {}ms",
+        Text::ChunkMos => "{}: MOS {}, {}% Paketverlust, Zeit {}ms",
+        Text::ChunkLate => "{}: {} verspätet/s, {} von {} Proben verspätet",
+        Text::LateShort => "{} verspätet/s",
+        Text::Retransmits => "{}% TCP-Neuübertragungen",
+        Text::Capture => "Mitschnitt: {} Pings, {}% Verlust, max {}ms",
+        Text::CaptureLost => "Mitschnitt: {} Pings, alle verloren",
+        Text::NoChunks => "keine Blöcke aufgezeichnet",
+        Text::From => "von",
+        Text::To => "bis",
+        Text::Chunks => "Blöcke",
+        Text::ChunksLine => "{} ({} mit Verlust)",
+        Text::Packets => "Pakete",
+        Text::PacketsLine => "{} gesendet, {} empfangen, {}% Verlust",
+        Text::Latency => "Latenz",
+        Text::LatencyLine => "min {}ms, Mittel {}ms, max {}ms",
+        Text::Jitter => "Jitter",
+        Text::JitterLine => "Mittel {}ms, max {}ms",
+        Text::Oneway => "Richtung",
+        Text::OnewayLine => "{} auf dem Hinweg verloren, {} auf dem Rückweg",
+        Text::Speed => "Tempo",
+        Text::SpeedLine => "Mittel {} Mbit/s, min {}, max {} in {} Tests, {}% Verlust währenddessen",
+        Text::Tcp => "TCP",
+        Text::TcpLine => "Mittel {}% Neuübertragungen, max {}%",
+        Text::Marker => "Markierung",
+        Text::Mos => "MOS",
+        Text::MosLine => "Mittel {}, min {}",
+        Text::Worst => "schlimmster",
+        Text::Runtime => "Laufzeit",
+        Text::WorstLine => "{} mit {}% Verlust",
+        Text::Before => "vorher",
+        Text::After => "nachher",
+        Text::Change => "Änderung",
+        Text::Better => "besser",
+        Text::Worse => "schlechter",
+        Text::Loss => "Verlust",
+        Text::Incidents => "Störungen",
+        Text::Day => "Tag",
+        Text::DayLine => "{} {}% erreichbar, schlimmste {} für {} mit {}% Verlust",
+        Text::DayClean => "{} {}% erreichbar, kein Verlust",
+        Text::Weekdays => "Mo Di Mi Do Fr Sa So",
+        Text::PathShift => "Pfad {}→{}ms",
+        Text::Reloaded => "{} neu geladen",
+        Text::StatsLogged => "Statistik nach {} geschrieben",
+        Text::StatsNoLog => "keine --log-file für die Statistik",
+        Text::Backoff => "{}: {} Bursts in Folge fehlgeschlagen, nächster Versuch in {}s",
+        Text::NetworkDown => "lokales Netz ausgefallen: keine Standardroute, Alarme ruhen bis es zurück ist",
+        Text::NetworkBack => "lokales Netz wieder da, Alarme wieder aktiv",
+        Text::IcmpErrors => "{}× {}",
+        Text::Burst => "{} in Folge verloren, Burst-Verhältnis {}",
+        Text::IcmpNet => "Netz unerreichbar",
+        Text::IcmpHost => "Host unerreichbar",
+        Text::IcmpProhibited => "verboten",
+        Text::IcmpFrag => "Fragmentierung nötig",
+        Text::IcmpTtl => "TTL abgelaufen",
+        Text::IcmpOther => "ICMP-Fehler",
+    }
+}
+
+fn es(text: Text) -> &'static str {
+    match text {
+        Text::PacketList => "Lista de paquetes",
+        Text::InspectPacket => "Inspeccionar paquete",
+        Text::InspectParams => "{} pings cada {}s, separados {}ms, {}ms de timeout",
+        Text::MarkerPrompt => "marcador: {}▏ (enter para colocar, esc para cancelar)",
+        Text::Down => "CAÍDO",
+        Text::Chunk => "{}: {}% de pérdida, tiempo {}ms",
+        Text::ChunkSlow => "{}: {}% de pérdida, {} lentos, tiempo {}ms",
+        Text::ChunkOneway => "{}: {}% de pérdida (↑{} ↓{}), tiempo {}ms",
+        Text::ChunkMos => "{}: MOS {}, {}% de pérdida, tiempo {}ms",
+        Text::ChunkLate => "{}: {} tardías/s, {} de {} sondas tardías",
+        Text::LateShort => "{} tardías/s",
+        Text::Retransmits => "{}% de retransmisiones tcp",
+        Text::Capture => "captura: {} pings, {}% de pérdida, máx {}ms",
+        Text::CaptureLost => "captura: {} pings, todos perdidos",
+        Text::NoChunks => "no hay bloques registrados",
+        Text::From => "desde",
+        Text::To => "hasta",
+        Text::Chunks => "bloques",
+        Text::ChunksLine => "{} ({} con pérdida)",
+        Text::Packets => "paquetes",
+        Text::PacketsLine => "{} enviados, {} recibidos, {}% de pérdida",
+        Text::Latency => "latencia",
+        Text::LatencyLine => "mín {}ms, media {}ms, máx {}ms",
+        Text::Jitter => "jitter",
+        Text::JitterLine => "media {}ms, máx {}ms",
+        Text::Oneway => "sentido",
+        Text::OnewayLine => "{} perdidos a la ida, {} a la vuelta",
+        Text::Speed => "velocidad",
+        Text::SpeedLine => "media {} Mbit/s, mín {}, máx {} en {} pruebas, {}% de pérdida mientras tanto",
+        Text::Tcp => "tcp",
+        Text::TcpLine => "media {}% de retransmisiones, máx {}%",
+        Text::Marker => "marcador",
+        Text::Mos => "mos",
+        Text::MosLine => "media {}, mín {}",
+        Text::Worst => "peor",
+        Text::Runtime => "duración",
+        Text::WorstLine => "{} con {}% de pérdida",
+        Text::Before => "antes",
+        Text::After => "después",
+        Text::Change => "cambio",
+        Text::Better => "mejor",
+        Text::Worse => "peor",
+        Text::Loss => "pérdida",
+        Text::Incidents => "incidentes",
+        Text::Day => "día",
+        Text::DayLine => "{} {}% activo, peor {} durante {} con {}% de pérdida",
+        Text::DayClean => "{} {}% activo, sin pérdida",
+        Text::Weekdays => "lun mar mié jue vie sáb dom",
+        Text::PathShift => "ruta {}→{}ms",
+        Text::Reloaded => "{} recargado",
+        Text::StatsLogged => "estadísticas escritas en {}",
+        Text::StatsNoLog => "no hay --log-file para las estadísticas",
+        Text::Backoff => "{}: {} ráfagas fallidas seguidas, próximo intento en {}s",
+        Text::NetworkDown => "red local caída: sin ruta por defecto, alertas en pausa hasta que vuelva",
+        Text::NetworkBack => "red local de vuelta, alertas activas de nuevo",
+        Text::IcmpErrors => "{}× {}",
+        Text::Burst => "{} perdidos seguidos, ratio de ráfaga {}",
+        Text::IcmpNet => "red inalcanzable",
+        Text::IcmpHost => "host inalcanzable",
+        Text::IcmpProhibited => "prohibido",
+        Text::IcmpFrag => "fragmentación necesaria",
+        Text::IcmpTtl => "ttl excedido",
+        Text::IcmpOther => "error icmp",
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use crate::dirs;
+use crate::record;
+
+/*
+ * one monitor per target and one writer per recording. a monitor takes
+ * an advisory lock on a file in locks/ of the state directory for each
+ * target it probes and for the recording it writes, and holds them until
+ * it exits; the system lets go of them however it exits, so a crashed
+ * monitor never leaves a stale one. the pid of the holder is written in
+ * the file, for the message the next one prints. where there is no
+ * flock(2) nothing is detected.
+ */
+
+fn dir() -> Option<PathBuf> {
+    Some(dirs::state()?.join("locks"))
+}
+
+/// Held until dropped
+pub struct Lock {
+    _file: Option<File>,
+}
+
+/// Another process holding a lock, with its pid if it wrote one
+pub struct Held {
+    pub pid: Option<u32>,
+}
+
+/// Lock `name` of `kind` ("target", "recording"), or find who holds it.
+/// Without a state directory nothing can be locked, and nothing is
+pub fn take(kind: &str, name: &str) -> io::Result<Result<Lock, Held>> {
+    let dir = match dir() {
+        Some(dir) => dir,
+        None => return Ok(Ok(Lock { _file: None })),
+    };
+    fs::create_dir_all(&dir)?;
+
+    let path = dir.join(format!("{}-{}.lock", kind, record::file_name(name)));
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+
+    if !exclusive(&file)? {
+        let mut pid = String::new();
+        file.read_to_string(&mut pid)?;
+
+        return Ok(Err(Held { pid: pid.trim().parse().ok() }));
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", std::process::id())?;
+
+    Ok(Ok(Lock { _file: Some(file) }))
+}
+
+/* false when another process has it */
+#[cfg(unix)]
+fn exclusive(file: &File) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+
+    match io::Error::last_os_error() {
+        ref e if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        e => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+fn exclusive(_file: &File) -> io::Result<bool> {
+    Ok(true)
+}
+
+impl Held {
+    /// "another packetloss (pid 1234)"
+    pub fn holder(&self) -> String {
+        match self.pid {
+            Some(pid) => format!("another packetloss (pid {})", pid),
+            None => String::from("another packetloss"),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use chrono::{Local, SecondsFormat};
+use log::{LevelFilter, Log, Metadata, Record};
+
+/*
+ * --log-file. the ui owns the terminal and the alert sinks run in threads
+ * of their own, so what goes wrong in them (a probe that fails, a webhook
+ * that times out) and what the monitor decides (a capture burst, a late
+ * chunk, a resize) is written with the `log` macros to a file instead,
+ * one line each:
+ *
+ *   <time> <LEVEL> <module>: <message>
+ *
+ * the file is appended to, so a restarted monitor carries on the same
+ * log, and every line is flushed as it is written.
+ */
+
+struct Logger {
+    out: Mutex<File>,
+    level: LevelFilter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let module = record.target();
+        let module = module.strip_prefix("packetloss::").unwrap_or(module);
+
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(out, "{} {:5} {}: {}",
+                Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+                record.level(), module, record.args());
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut out) = self.out.lock() {
+            let _ = out.flush();
+        }
+    }
+}
+
+/// Validator for `--log-level`
+pub fn is_level(v: String) -> Result<(), String> {
+    match LevelFilter::from_str(&v) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(String::from("Value must be one of off, error, warn, info, debug or trace")),
+    }
+}
+
+/// Log to `path` from here on, lines above `level` left out
+pub fn init(path: &str, level: &str) -> io::Result<()> {
+    let out = OpenOptions::new().create(true).append(true).open(path)?;
+    let level = LevelFilter::from_str(level).unwrap_or(LevelFilter::Info);
+
+    let logger = Logger {
+        out: Mutex::new(out),
+        level,
+    };
+
+    /* only ever set once, at startup */
+    log::set_boxed_logger(Box::new(logger))
+        .map_err(|e| io::Error::new(io::ErrorKind::AlreadyExists, e.to_string()))?;
+    log::set_max_level(level);
+
+    Ok(())
+}
@@ -1,80 +1,157 @@
-use tui::Terminal;
-use tui::widgets::Widget;
-use tui::backend::TermionBackend;
-use termion::raw::IntoRawMode;
-use termion::event::Key;
-use clap::{App, Arg};
-
-use std::io;
-use std::time::{Duration};
-use std::thread;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::fmt;
-
-mod ping;
+use clap::{App, Arg, SubCommand};
+
+use std::path::PathBuf;
+use std::process;
+use std::panic::{self, AssertUnwindSafe};
+
+#[cfg(not(any(feature = "termion", feature = "crossterm")))]
+compile_error!("the terminal needs either the termion or the crossterm feature");
+
+mod backend;
+mod billboard;
+mod calendar;
 mod term;
 mod event;
-
-use crate::ping::Ping;
-use crate::term::SelectableLogList;
-use crate::event::{Event, Events};
+mod alert;
+mod smtp;
+mod notify;
+mod pager;
+mod heartbeat;
+mod metrics;
+mod record;
+mod config;
+mod man;
+mod import;
+mod health;
+mod discover;
+mod reflect;
+mod twamp;
+mod throughput;
+mod retrans;
+mod netns;
+mod stream;
+mod session;
+mod output;
+mod liveness;
+mod pcap;
+mod probe;
+mod command;
+mod target;
+mod http;
+mod exec;
+mod query;
+mod shift;
+mod daemon;
+mod check;
+mod error;
+mod crash;
+mod logfile;
+mod checkpoint;
+mod lock;
+mod dirs;
+mod signal;
+mod systemd;
+mod route;
+mod setup;
+mod commands;
+mod ui;
+
+use packetloss::{draw, icmp, locale, ping, report, tcp, udp};
+
+use crate::locale::Lang;
+use crate::config::Settings;
+use crate::error::{Context, Error};
+use crate::commands::{check_command, config_command, discover_command, export_command, healthcheck_command,
+                      import_command, load_config, merge_command, query_command, report_command, serve};
 
 /*
  * TODO:
  * redraw flag in LogList
  */
 
-#[derive(Debug)]
-enum Error {
-    IO(io::Error),
-    Ping(oping::PingError),
-    Event(std::sync::mpsc::RecvError),
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Error::IO(e) => write!(f, "IO Error: {}", e),
-            Error::Ping(e) => write!(f, "Ping error: {}", e),
-            Error::Event(e) => write!(f, "Event error: {}", e),
-        }
-    }
-}
-
-impl From<io::Error> for Error {
-    fn from(e: io::Error) -> Error {
-        Error::IO(e)
-    }
+fn is_int(v: String) -> Result<(), String> {
+    v.parse::<u64>()
+        .map(|_| ())
+        .map_err(|_| String::from("Value must be an integer"))
 }
 
-impl From<oping::PingError> for Error {
-    fn from(e: oping::PingError) -> Error {
-        Error::Ping(e)
-    }
+fn is_port(v: String) -> Result<(), String> {
+    v.parse::<u16>()
+        .map(|_| ())
+        .map_err(|_| String::from("Value must be a port number"))
 }
 
-impl From<std::sync::mpsc::RecvError> for Error {
-    fn from(e: std::sync::mpsc::RecvError) -> Error {
-        Error::Event(e)
-    }
+fn is_float(v: String) -> Result<(), String> {
+    v.parse::<f64>()
+        .map(|_| ())
+        .map_err(|_| String::from("Value must be a number"))
 }
 
-fn is_int(v: String) -> Result<(), String> {
-    v.parse::<u64>()
+fn is_range(v: String) -> Result<(), String> {
+    ping::parse_range(&v)
         .map(|_| ())
-        .map_err(|_| String::from("Value must be an integer"))
+        .ok_or_else(|| String::from("Value must be MIN:MAX in ms, e.g. 10:200"))
 }
 
-fn main() -> Result<(), Error> {
-
-    let matches = App::new("packetloss")
-        .version("0.1")
-        .author("Spencer Powell")
-        .about("Show a colored graph of packet loss over time")
+/* options of the monitor, which is also what runs without a subcommand */
+fn monitor_args(app: App<'static, 'static>) -> App<'static, 'static> {
+    app
         .arg(Arg::with_name("address")
-            .help("Host to ping")
-            .required(true))
+            .help("Host to ping, or several to ping on the same schedule. a subnet up to a /24, \
+                   e.g. 192.168.1.0/28, is every host in it that answers, pinged in turns")
+            .multiple(true))
+        .arg(Arg::with_name("host")
+            .long("host")
+            .value_name("HOST")
+            .help("another host to ping alongside the address, shown in its own list \
+                   (tab to switch, s for side by side)")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("discover-lan")
+            .long("discover-lan")
+            .help("also ping every live host of the neighbor table, each in its own list, \
+                   to find the device on the lan that drops packets"))
+        .arg(Arg::with_name("targets")
+            .long("targets")
+            .value_name("FILE")
+            .help("toml file of targets to ping alongside any given, each a [[target]] with an address \
+                   and its own tags = [..], accent = COLOR and rearm = N in place of --alert-rearm"))
+        .arg(Arg::with_name("tag")
+            .long("tag")
+            .help("label for the targets the targets file gives none, shown in the window title \
+                   and the output (e.g. WAN, VPN)")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("session")
+            .long("session")
+            .value_name("NAME")
+            .help("name the session; its layout is saved on quit and restored next time")
+            .validator(session::is_name))
+        .arg(Arg::with_name("accent")
+            .long("accent")
+            .value_name("COLOR")
+            .help("border color of the panes of the targets the targets file gives none, a name or #rrggbb")
+            .validator(term::is_color))
+        .arg(Arg::with_name("color-good")
+            .long("color-good")
+            .value_name("#RRGGBB")
+            .help("tile color with no loss at the lowest latency")
+            .validator(term::is_rgb))
+        .arg(Arg::with_name("color-bad")
+            .long("color-bad")
+            .value_name("#RRGGBB")
+            .help("tile color with all packets lost, or at the top of the latency scale")
+            .validator(term::is_rgb))
+        .arg(Arg::with_name("color-down")
+            .long("color-down")
+            .value_name("#RRGGBB")
+            .help("tile color when nothing came back at all")
+            .validator(term::is_rgb))
+        .arg(Arg::with_name("color-slow")
+            .long("color-slow")
+            .value_name("#RRGGBB")
+            .help("color slow replies pull a tile toward, see --slow")
+            .validator(term::is_rgb))
         .arg(Arg::with_name("chunk-size")
             .long("chunk-size")
             .short("n")
@@ -93,131 +170,779 @@ fn main() -> Result<(), Error> {
             .help("ping timeout duration (ms)")
             .validator(is_int)
             .default_value("100"))
+        .arg(Arg::with_name("via")
+            .long("via")
+            .value_name("ssh://[user@]host[:port][/path/to/packetloss]")
+            .help("probe from a remote machine by running 'packetloss serve' there over ssh, \
+                   showing the results here"))
+        .arg(Arg::with_name("netns")
+            .long("netns")
+            .value_name("NAME")
+            .help("probe from inside a network namespace, by 'ip netns' name or path \
+                   such as /proc/PID/ns/net (needs CAP_SYS_ADMIN)"))
+        .arg(Arg::with_name("reflector")
+            .long("reflector")
+            .value_name("HOST:PORT")
+            .help("probe a 'packetloss reflect' peer over udp instead of pinging, \
+                   telling outbound from return loss"))
+        .arg(Arg::with_name("twamp")
+            .long("twamp")
+            .value_name("HOST[:PORT]")
+            .help("probe a TWAMP-light reflector (port 862 by default) instead of pinging")
+            .conflicts_with("reflector"))
+        .arg(Arg::with_name("tcp")
+            .long("tcp")
+            .value_name("HOST:PORT")
+            .help("time tcp connects instead of pinging, for networks that drop icmp. \
+                   connects that time out count as lost")
+            .validator(tcp::is_host_port)
+            .conflicts_with_all(&["reflector", "twamp"]))
+        .arg(Arg::with_name("http")
+            .long("http")
+            .value_name("URL")
+            .help("time http requests instead of pinging, counting error statuses as lost. \
+                   raise --timeout to suit the server")
+            .validator(http::is_url)
+            .conflicts_with_all(&["reflector", "twamp", "tcp"]))
+        .arg(Arg::with_name("http-method")
+            .long("http-method")
+            .value_name("METHOD")
+            .help("request method for --http, HEAD or GET (reading up to 64KiB of the body)")
+            .possible_values(&["HEAD", "GET"]))
+        .arg(Arg::with_name("probe-cmd")
+            .long("probe-cmd")
+            .value_name("COMMAND")
+            .help("run a shell command for each probe instead of pinging. exit status 0 is a reply, \
+                   anything else or still running at --timeout is lost. a number starting the \
+                   output is taken as the latency in ms")
+            .conflicts_with_all(&["reflector", "twamp", "tcp", "http"]))
+        .arg(Arg::with_name("proto")
+            .long("proto")
+            .value_name("PROTO")
+            .help("what to probe the hosts with: icmp echoes, the default, or udp datagrams \
+                   answered by a port unreachable or echoed by a responder")
+            .possible_values(&["icmp", "udp"])
+            .conflicts_with_all(&["reflector", "twamp", "tcp", "http", "probe-cmd", "via"]))
+        .arg(Arg::with_name("port")
+            .long("port")
+            .value_name("PORT")
+            .help("port for --proto udp, 33434 by default")
+            .validator(is_port))
+        .arg(Arg::with_name("slow")
+            .long("slow")
+            .value_name("MS")
+            .help("soft timeout: replies slower than this are shown as slow, \
+                   only --timeout counts as loss")
+            .validator(is_int))
+        .arg(Arg::with_name("no-blink")
+            .long("no-blink")
+            .help("don't flash the newest tile when a lossy chunk arrives"))
+        .arg(Arg::with_name("no-tui")
+            .long("no-tui")
+            .help("run headless, e.g. under systemd: probe and alert as usual without touching the \
+                   terminal, writing each chunk to stdout. with the stream format the log can be \
+                   watched later with 'tail -f LOG | packetloss attach'"))
+        .arg(Arg::with_name("stdout-format")
+            .long("stdout-format")
+            .value_name("FORMAT")
+            .help("with --no-tui or daemon, how chunks are written. stream is read back by attach, \
+                   the others suit log collectors")
+            .possible_values(&["stream", "logfmt", "json", "plain"])
+            .default_value("stream"))
+        .arg(Arg::with_name("capture")
+            .long("capture")
+            .value_name("COUNT")
+            .help("right after a lossy chunk, send COUNT closely spaced pings and attach them \
+                   to it, viewable when inspecting the chunk")
+            .validator(is_int))
+        .arg(Arg::with_name("capture-spacing")
+            .long("capture-spacing")
+            .help("time between capture pings (ms)")
+            .validator(is_int)
+            .default_value("20"))
+        .arg(Arg::with_name("alert-loss")
+            .long("alert-loss")
+            .help("packet loss (%) at which a chunk counts as lossy for alerting")
+            .validator(is_int)
+            .default_value("10"))
+        .arg(Arg::with_name("game")
+            .long("game")
+            .value_name("HZ")
+            .help("game preset: send each chunk's pings at a server tick rate such as 64 \
+                   and show late probes per second")
+            .validator(is_int))
+        .arg(Arg::with_name("frame-budget")
+            .long("frame-budget")
+            .value_name("MS")
+            .help("in game mode, replies slower than this are late (default: one tick)")
+            .validator(is_int))
+        .arg(Arg::with_name("mos")
+            .long("mos")
+            .help("color tiles by estimated VoIP call quality (MOS) instead of loss and latency"))
+        .arg(Arg::with_name("alert-mos")
+            .long("alert-mos")
+            .value_name("SCORE")
+            .help("also count chunks with an estimated MOS below SCORE as lossy, e.g. 3.6")
+            .validator(is_float))
+        .arg(Arg::with_name("weight-bursts")
+            .long("weight-bursts")
+            .help("color tiles by loss weighted by its burst ratio, so that drops one after another \
+                   show worse than as many scattered ones"))
+        .arg(Arg::with_name("alert-burst")
+            .long("alert-burst")
+            .value_name("N")
+            .help("also count chunks that lost N or more probes in a row as lossy")
+            .validator(is_int))
+        .arg(Arg::with_name("latency-color")
+            .long("latency-color")
+            .value_name("SCALE")
+            .help("how latency maps to color: linear, or log to spread out differences near the baseline")
+            .possible_values(&["linear", "log"])
+            .default_value("linear"))
+        .arg(Arg::with_name("order")
+            .long("order")
+            .value_name("ORDER")
+            .help("where the tile flow starts: newest-first, or oldest-first for a timeline \
+                   ending in the newest tile")
+            .possible_values(&["newest-first", "oldest-first"])
+            .default_value("newest-first"))
+        .arg(Arg::with_name("view")
+            .long("view")
+            .value_name("VIEW")
+            .help("how the history is laid out: tiles, or timeline for one column per chunk \
+                   running left to right, as tall as its latency and colored by its loss")
+            .possible_values(&["tiles", "timeline"])
+            .default_value("tiles"))
+        .arg(Arg::with_name("bind")
+            .long("bind")
+            .value_name("KEY=COMMANDS")
+            .help("bind F1-F12 or a character to commands run in one step, separated by ';', \
+                   e.g. F5=select-first;probe;mark checked. commands are select-next, select-prev, \
+                   select-first, select-last, clear, toggle-debug, toggle-mos, toggle-log, \
+                   toggle-order, toggle-view, toggle-split, toggle-billboard, toggle-calendar, prev-month, \
+                   next-month, next-target, prev-target, toggle-overview, probe, reload, marker, mark LABEL and quit")
+            .validator(command::is_macro)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("wrap")
+            .long("wrap")
+            .help("moving the selection past the first or last chunk wraps around to the other end"))
+        .arg(Arg::with_name("billboard")
+            .long("billboard")
+            .help("start with the newest loss and latency in huge digits filling the screen, \
+                   for wall-mounted monitors ('b' toggles)"))
+        .arg(Arg::with_name("latency-scale")
+            .long("latency-scale")
+            .value_name("MIN:MAX")
+            .help("pin the latency (ms) drawn fully green and fully red, instead of \
+                   the lowest seen and the timeout, e.g. 10:200")
+            .validator(is_range))
+        .arg(Arg::with_name("escalate")
+            .long("escalate")
+            .help("escalation stage as N:ACTION, fired after N consecutive lossy chunks \
+                   (ACTION is notify, webhook=URL or command=CMD)")
+            .validator(alert::is_stage)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("alert-cooldown")
+            .long("alert-cooldown")
+            .help("minimum time before a stage may fire again (s)")
+            .validator(is_int)
+            .default_value("300"))
+        .arg(Arg::with_name("alert-rearm")
+            .long("alert-rearm")
+            .help("number of good chunks needed to declare recovery and re-arm escalation")
+            .validator(is_int)
+            .default_value("1"))
+        .arg(Arg::with_name("alert-shift")
+            .long("alert-shift")
+            .value_name("FACTOR")
+            .help("mark the history when the baseline latency moves by FACTOR or more and stays \
+                   there, e.g. 1.5, as when the path is rerouted; a move up also runs the shift actions")
+            .validator(shift::is_factor))
+        .arg(Arg::with_name("alert-shift-for")
+            .long("alert-shift-for")
+            .value_name("SECS")
+            .help("how long the latency must stay moved before it counts as a path shift")
+            .validator(is_int)
+            .default_value("300"))
+        .arg(Arg::with_name("shift-action")
+            .long("shift-action")
+            .value_name("ACTION")
+            .help("action run when the path shifts to a higher latency \
+                   (notify, webhook=URL or command=CMD)")
+            .validator(alert::is_action)
+            .requires("alert-shift")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("smtp")
+            .long("smtp")
+            .value_name("SERVER[:PORT]")
+            .help("email an incident summary through this smtp relay when an incident closes")
+            .requires("smtp-to"))
+        .arg(Arg::with_name("smtp-from")
+            .long("smtp-from")
+            .help("sender address for incident emails")
+            .default_value("packetloss@localhost"))
+        .arg(Arg::with_name("smtp-to")
+            .long("smtp-to")
+            .help("recipient address for incident emails")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("slack")
+            .long("slack")
+            .value_name("URL")
+            .help("post incidents to a slack incoming webhook"))
+        .arg(Arg::with_name("discord")
+            .long("discord")
+            .value_name("URL")
+            .help("post incidents to a discord webhook"))
+        .arg(Arg::with_name("telegram-token")
+            .long("telegram-token")
+            .value_name("TOKEN")
+            .help("post incidents through this telegram bot")
+            .requires("telegram-chat"))
+        .arg(Arg::with_name("telegram-chat")
+            .long("telegram-chat")
+            .value_name("CHAT_ID")
+            .help("telegram chat to post incidents to")
+            .requires("telegram-token"))
+        .arg(Arg::with_name("open-template")
+            .long("open-template")
+            .help("chat message sent when an incident opens \
+                   ({target}, {start}, {worst}, ...)")
+            .default_value(notify::OPEN_TEMPLATE))
+        .arg(Arg::with_name("close-template")
+            .long("close-template")
+            .help("chat message sent when an incident closes \
+                   ({target}, {duration}, {chunks}, {worst}, {mean}, {chart}, ...)")
+            .default_value(notify::CLOSE_TEMPLATE))
+        .arg(Arg::with_name("pagerduty")
+            .long("pagerduty")
+            .value_name("ROUTING_KEY")
+            .help("trigger and resolve pagerduty events for incidents"))
+        .arg(Arg::with_name("opsgenie")
+            .long("opsgenie")
+            .value_name("API_KEY")
+            .help("open and close opsgenie alerts for incidents"))
+        .arg(Arg::with_name("pcap-dir")
+            .long("pcap-dir")
+            .value_name("DIR")
+            .help("capture packets to DIR while an incident is open, linking the file in the incident"))
+        .arg(Arg::with_name("pcap-command")
+            .long("pcap-command")
+            .value_name("CMD")
+            .help("capture command ({file}, {target} and {max} are substituted), stopped with SIGTERM")
+            .default_value(pcap::DEFAULT_COMMAND))
+        .arg(Arg::with_name("pcap-linger")
+            .long("pcap-linger")
+            .value_name("SECS")
+            .help("keep capturing this long after an incident closes")
+            .validator(is_int)
+            .default_value("10"))
+        .arg(Arg::with_name("pcap-max")
+            .long("pcap-max")
+            .value_name("PACKETS")
+            .help("packets at which a capture stops on its own")
+            .validator(is_int)
+            .default_value("100000"))
+        .arg(Arg::with_name("heartbeat-url")
+            .long("heartbeat-url")
+            .value_name("URL")
+            .help("url requested after every completed chunk, for dead-man's-switch services"))
+        .arg(Arg::with_name("throughput-cmd")
+            .long("throughput-cmd")
+            .value_name("CMD")
+            .help("throughput test run now and then beside probing, the first number \
+                   it prints is taken as Mbit/s and noted on the chunk")
+            .conflicts_with("throughput-url"))
+        .arg(Arg::with_name("throughput-url")
+            .long("throughput-url")
+            .value_name("URL")
+            .help("throughput test by downloading from URL for a few seconds"))
+        .arg(Arg::with_name("throughput-every")
+            .long("throughput-every")
+            .value_name("CHUNKS")
+            .help("chunks between throughput tests")
+            .validator(is_int)
+            .default_value("10"))
+        .arg(Arg::with_name("retransmits")
+            .long("retransmits")
+            .value_name("all|PEER")
+            .help("show the tcp retransmit rate of the whole host, or of connections to PEER \
+                   (an ss filter such as 192.0.2.1:443), beside each chunk (linux)"))
+        .arg(Arg::with_name("record-events")
+            .long("record-events")
+            .value_name("FILE")
+            .help("record keys, ticks, resizes and chunks to FILE for later replay")
+            .conflicts_with("replay-events"))
+        .arg(Arg::with_name("replay-events")
+            .long("replay-events")
+            .value_name("FILE")
+            .help("replay a recorded session instead of pinging"))
+        .arg(Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .value_name("SECONDS")
+            .help("write the history to the state directory this often, and offer it back on the next \
+                   start if the monitor never got to exit; 0 turns it off")
+            .validator(is_int)
+            .default_value("60"))
+        .arg(Arg::with_name("log-file")
+            .long("log-file")
+            .value_name("FILE")
+            .help("append probe errors, alert failures, scheduling decisions and resizes to FILE, \
+                   which the ui would otherwise hide"))
+        .arg(Arg::with_name("log-level")
+            .long("log-level")
+            .value_name("LEVEL")
+            .help("least severe lines written to --log-file: off, error, warn, info, debug or trace")
+            .validator(logfile::is_level)
+            .default_value("info"))
+}
+
+fn app() -> App<'static, 'static> {
+    let app = App::new("packetloss")
+        .version("0.1")
+        .author("Spencer Powell")
+        .about("Show a colored graph of packet loss over time");
+
+    monitor_args(app)
+        .arg(Arg::with_name("config")
+            .long("config")
+            .short("c")
+            .value_name("FILE")
+            .help("config file (default: $PACKETLOSS_CONFIG, or $XDG_CONFIG_HOME/packetloss/config.toml). \
+                   every option it can hold may also be set as PACKETLOSS_<OPTION>, e.g. PACKETLOSS_INTERVAL=5")
+            .global(true))
+        .arg(Arg::with_name("profile")
+            .long("profile")
+            .value_name("NAME")
+            .help("use the options of a [profile.NAME] table in the config file over the rest of it, \
+                   e.g. --profile gaming (default: $PACKETLOSS_PROFILE)")
+            .global(true))
+        .arg(Arg::with_name("state-dir")
+            .long("state-dir")
+            .value_name("DIR")
+            .help("keep session layouts, checkpoints, crash files and locks in DIR (default: \
+                   $PACKETLOSS_STATE_DIR, or $XDG_STATE_HOME/packetloss)")
+            .global(true))
         .arg(Arg::with_name("max")
             .long("max")
             .short("m")
             .help("maximum number of packets to be stored")
             .validator(is_int)
-            .default_value("20475"))
-        .get_matches();
-
-    let address = matches.value_of("address").unwrap();
-    let chunk_size = matches.value_of("chunk-size").unwrap()
-        .parse::<u64>().unwrap();
-    let interval = matches.value_of("interval").unwrap()
-        .parse::<u64>().unwrap();
-    let timeout = matches.value_of("timeout").unwrap()
-        .parse::<u64>().unwrap();
-    let max = matches.value_of("max").unwrap()
-        .parse::<usize>().unwrap();
-
-    let ping = Ping::new(address, Duration::from_millis(timeout));
-
-    let stdout = io::stdout().into_raw_mode()?;
-    let backend = TermionBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-    terminal.hide_cursor()?;
-
-    let events = Events::new();
-
-    let mut list = SelectableLogList::new(max);
-    let mut sleep = Sleep::new();
-    let mut internal_size = terminal.size()?;
-
-    let mut redraw = true;
-
-    loop {
-
-        let size = terminal.size()?;
-
-
-        if size != internal_size {
-
-            terminal.resize(size)?;
-            internal_size = size;
-            terminal.clear()?;
-
-            redraw = true;
-
-            continue;
-
-        }
-
-        /* only redraw after size is verified */
-        if redraw {
-            redraw = false;
-            terminal.draw(|mut f| {
-                list.render(&mut f, size);
-            })?;
-        }
-
-        if sleep.done() {
-
-            let chunk = ping.ping(chunk_size)?;
-            list.insert(chunk);
-            sleep = Sleep::sleep(Duration::from_secs(interval));
-
-            redraw = true;
-        }
-
-        match events.next()? {
-            Event::Input(input) => match input {
-                Key::Char('q') => { break; }
-                Key::Char('j') => {
-                    list.select_next();
-                    redraw = true;
-                },
-                Key::Char('k') => {
-                    list.select_prev();
-                    redraw = true;
-                },
-                Key::Char('g') => {
-                    list.select_first();
-                    redraw = true;
-                },
-                Key::Char('G') => {
-                    list.select_last();
-                    redraw = true;
-                },
-                Key::Esc => {
-                    list.clear();
-                    redraw = true;
-                },
-                _ => {},
-            },
-            _ => {},
-        }
+            .default_value("20475")
+            .global(true))
+        .arg(Arg::with_name("lang")
+            .long("lang")
+            .value_name("LANG")
+            .help("language of the labels and reports (default: from LC_ALL, LC_MESSAGES or LANG)")
+            .possible_values(locale::CODES)
+            .global(true))
+        .arg(Arg::with_name("fps")
+            .long("fps")
+            .help("maximum redraws per second, bursts of input are coalesced")
+            .validator(is_int)
+            .default_value("30")
+            .global(true))
+        .subcommand(monitor_args(SubCommand::with_name("tui")
+            .alias("monitor")
+            .about("Ping a host and show the loss graph (the default)")))
+        .subcommand(monitor_args(SubCommand::with_name("daemon")
+            .about("Probe and alert without a ui, e.g. under systemd, writing each chunk to stdout \
+                    (the same as --no-tui)")))
+        .subcommand(SubCommand::with_name("replay")
+            .about("Replay a session recorded with --record-events")
+            .arg(Arg::with_name("file")
+                .help("recording to replay")
+                .required(true)))
+        .subcommand(SubCommand::with_name("report")
+            .about("Summarize the chunks of a recorded session")
+            .arg(Arg::with_name("file")
+                .help("recording to summarize")
+                .required_unless("compare"))
+            .arg(Arg::with_name("compare")
+                .long("compare")
+                .number_of_values(2)
+                .value_names(&["BEFORE", "AFTER"])
+                .conflicts_with("file")
+                .help("compare two recordings instead, e.g. from before and after a change to the line")))
+        .subcommand(SubCommand::with_name("query")
+            .about("Print the chunks of a recorded session that match, as a table or csv")
+            .arg(Arg::with_name("file")
+                .help("recording to search")
+                .required(true))
+            .arg(Arg::with_name("since")
+                .long("since")
+                .value_name("TIME")
+                .help("only chunks from this local time on, e.g. \"2024-01-03 20:00\"")
+                .validator(query::is_time))
+            .arg(Arg::with_name("until")
+                .long("until")
+                .value_name("TIME")
+                .help("only chunks before this local time")
+                .validator(query::is_time))
+            .arg(Arg::with_name("where")
+                .long("where")
+                .value_name("CONDITION")
+                .help("only chunks meeting FIELD OP NUMBER, e.g. \"loss>5\" (percent) or \"latency>=80\" (ms). \
+                       fields: loss, latency, jitter, mos, sent, received. repeat to require several")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(query::is_condition))
+            .arg(Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("how to print the chunks")
+                .possible_values(&["table", "csv"])
+                .default_value("table")))
+        .subcommand(SubCommand::with_name("export")
+            .about("Write every chunk of a recorded session in a format other tools read")
+            .arg(Arg::with_name("file")
+                .help("recording to export")
+                .required(true))
+            .arg(Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("csv for spreadsheets, a line per chunk for the others, with the fields of --stdout-format")
+                .possible_values(&["csv", "json", "logfmt", "plain"])
+                .default_value("csv"))
+            .arg(Arg::with_name("tag")
+                .long("tag")
+                .help("label every line with a tag of the target, not for csv (e.g. WAN, VPN)")
+                .multiple(true)
+                .number_of_values(1)))
+        .subcommand(SubCommand::with_name("import")
+            .about("Make the output of the system ping, e.g. ping -D HOST > FILE, into a recording")
+            .arg(Arg::with_name("file")
+                .help("ping output to read, - for stdin")
+                .required(true))
+            .arg(Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .value_name("FILE")
+                .help("recording to write")
+                .required(true))
+            .arg(Arg::with_name("chunk-size")
+                .long("chunk-size")
+                .short("n")
+                .help("number of pings per chunk")
+                .validator(is_int)
+                .default_value("10"))
+            .arg(Arg::with_name("interval")
+                .long("interval")
+                .short("i")
+                .help("seconds between pings, as given to ping -i, for output without ping -D timestamps")
+                .validator(is_float)
+                .default_value("1"))
+            .arg(Arg::with_name("start")
+                .long("start")
+                .value_name("TIME")
+                .help("when the first ping was sent, for output without timestamps. by default the last \
+                       is taken to be when the file was last written")
+                .validator(query::is_time))
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .short("t")
+                .help("ping timeout duration (ms), what a lost ping is drawn with")
+                .validator(is_int)
+                .default_value("1000")))
+        .subcommand(SubCommand::with_name("merge")
+            .about("Join recordings of the same host into one, dropping chunks found in more than one")
+            .arg(Arg::with_name("file")
+                .help("recordings to merge, in any order")
+                .multiple(true)
+                .required(true))
+            .arg(Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .value_name("FILE")
+                .help("recording to write, which may be one of the inputs")
+                .required(true)))
+        .subcommand(SubCommand::with_name("serve")
+            .about("Probe without a ui, writing one line per chunk to stdout (used by --via)")
+            .arg(Arg::with_name("address")
+                .help("Host to ping"))
+            .arg(Arg::with_name("chunk-size")
+                .long("chunk-size")
+                .short("n")
+                .help("number of pings per chunk")
+                .validator(is_int)
+                .default_value("10"))
+            .arg(Arg::with_name("interval")
+                .long("interval")
+                .short("i")
+                .help("interval between pings (s)")
+                .validator(is_int)
+                .default_value("60"))
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .short("t")
+                .help("ping timeout duration (ms)")
+                .validator(is_int)
+                .default_value("100"))
+            .arg(Arg::with_name("netns")
+                .long("netns")
+                .value_name("NAME")
+                .help("probe from inside a network namespace"))
+            .arg(Arg::with_name("stdout-format")
+                .long("stdout-format")
+                .value_name("FORMAT")
+                .help("stream is read back by --via and attach, the others suit log collectors")
+                .possible_values(&["stream", "logfmt", "json", "plain"])
+                .default_value("stream"))
+            .arg(Arg::with_name("heartbeat-every")
+                .long("heartbeat-every")
+                .value_name("SECS")
+                .help("also write a heartbeat line this often, whether or not anything changed")
+                .validator(is_int))
+            .arg(Arg::with_name("health-listen")
+                .long("health-listen")
+                .value_name("[ADDRESS]:PORT")
+                .help("serve /healthz and /readyz over http for orchestrators")
+                .validator(reflect::is_listen_addr))
+            .arg(Arg::with_name("stale-after")
+                .long("stale-after")
+                .value_name("SECS")
+                .help("/healthz fails once the last chunk is older than this \
+                       (default: three intervals and chunks)")
+                .validator(is_int)))
+        .subcommand(monitor_args(SubCommand::with_name("attach")
+            .about("Show chunks piped in on stdin from 'packetloss serve', e.g. over socat")))
+        .subcommand(SubCommand::with_name("reflect")
+            .about("Echo udp probes for clients using --reflector")
+            .arg(Arg::with_name("listen")
+                .long("listen")
+                .short("l")
+                .value_name("[ADDRESS]:PORT")
+                .help("address to listen on")
+                .validator(reflect::is_listen_addr)
+                .default_value(":9000")))
+        .subcommand(SubCommand::with_name("discover")
+            .about("Find LAN hosts in the neighbor table and show their packet loss")
+            .arg(Arg::with_name("sweep")
+                .long("sweep")
+                .value_name("SUBNET")
+                .help("ping every address of an ipv4 subnet instead, e.g. 192.168.1.0/24")
+                .validator(discover::is_subnet))
+            .arg(Arg::with_name("count")
+                .long("count")
+                .short("n")
+                .help("number of pings per host")
+                .validator(is_int)
+                .default_value("5"))
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .short("t")
+                .help("ping timeout duration (ms)")
+                .validator(is_int)
+                .default_value("100")))
+        .subcommand(SubCommand::with_name("check")
+            .about("Ping a host N times, print a summary and exit 0 (ok), 1 (warning), 2 (critical) \
+                    or 3 (unknown) by the thresholds, like a monitoring plugin")
+            .arg(Arg::with_name("address")
+                .help("Host to ping")
+                .required(true))
+            .arg(Arg::with_name("count")
+                .long("count")
+                .short("n")
+                .help("number of pings")
+                .validator(is_int)
+                .default_value("10"))
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .short("t")
+                .help("ping timeout duration (ms)")
+                .validator(is_int)
+                .default_value("1000"))
+            .arg(Arg::with_name("warn-loss")
+                .long("warn-loss")
+                .value_name("PERCENT")
+                .help("warn above this much packet loss")
+                .validator(is_float)
+                .default_value("5"))
+            .arg(Arg::with_name("crit-loss")
+                .long("crit-loss")
+                .value_name("PERCENT")
+                .help("critical above this much packet loss")
+                .validator(is_float)
+                .default_value("20"))
+            .arg(Arg::with_name("warn-latency")
+                .long("warn-latency")
+                .value_name("MS")
+                .help("warn when the median rtt is above this")
+                .validator(is_float))
+            .arg(Arg::with_name("crit-latency")
+                .long("crit-latency")
+                .value_name("MS")
+                .help("critical when the median rtt is above this")
+                .validator(is_float)))
+        .subcommand(SubCommand::with_name("healthcheck")
+            .about("Ping a host a few times and exit 0 when healthy, 1 when not and 2 when it can't \
+                    ping at all, for container HEALTHCHECK lines")
+            .arg(Arg::with_name("address")
+                .help("Host to ping")
+                .required(true))
+            .arg(Arg::with_name("samples")
+                .long("samples")
+                .short("n")
+                .value_name("COUNT")
+                .help("number of pings")
+                .validator(is_int)
+                .default_value("3"))
+            .arg(Arg::with_name("max-loss")
+                .long("max-loss")
+                .value_name("PERCENT")
+                .help("most packet loss that is still healthy")
+                .validator(is_int)
+                .default_value("0"))
+            .arg(Arg::with_name("max-latency")
+                .long("max-latency")
+                .value_name("MS")
+                .help("also unhealthy when the mean latency of the replies is above this")
+                .validator(is_int))
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .short("t")
+                .help("ping timeout duration (ms), the check takes at most this times the samples")
+                .validator(is_int)
+                .default_value("1000"))
+            .arg(Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .help("print nothing but errors, leave it to the exit code")))
+        .subcommand(SubCommand::with_name("config")
+            .about("Create, check or print the configuration")
+            .subcommand(SubCommand::with_name("init")
+                .about("Write a commented default config file")
+                .arg(Arg::with_name("path")
+                    .help("where to write it (default: the config path)"))
+                .arg(Arg::with_name("force")
+                    .long("force")
+                    .help("overwrite an existing file")))
+            .subcommand(SubCommand::with_name("validate")
+                .about("Check a config file for errors")
+                .arg(Arg::with_name("path")
+                    .help("file to check (default: the config path)")))
+            .subcommand(SubCommand::with_name("show")
+                .about("Print the effective configuration and where each value comes from")))
+        .subcommand(SubCommand::with_name("man")
+            .about("Print a roff man page generated from these options"))
+}
+
+fn main() {
+    if let Err(e) = start() {
+        eprintln!("packetloss: {}", e);
+        process::exit(1);
     }
+}
 
-    terminal.clear()?;
+fn start() -> Result<(), Error> {
 
-    Ok(())
-}
+    let matches = app().get_matches();
 
-struct Sleep {
-    ready: Arc<AtomicBool>,
-}
+    let config_path = matches.value_of("config")
+        .map(PathBuf::from)
+        .or_else(config::env_path)
+        .or_else(config::default_path);
 
-impl Sleep {
-    fn new() -> Self {
-        let ready = Arc::new(AtomicBool::new(true));
-        Sleep { ready }
+    if let Some(dir) = matches.value_of("state-dir").map(PathBuf::from).or_else(dirs::env_state) {
+        dirs::set_state(dir);
     }
 
-    fn sleep(time: Duration) -> Self {
+    let env = config::environment().map_err(Error::Config)?;
+
+    let profile = matches.value_of("profile")
+        .map(String::from)
+        .or_else(config::env_profile);
+
+    match matches.subcommand() {
+        ("config", Some(sub)) => return config_command(&matches, sub, config_path, profile, &env),
+        ("man", _) => {
+            print!("{}", man::render(app())?);
+            return Ok(());
+        },
+        ("discover", Some(sub)) => return discover_command(sub),
+        ("import", Some(sub)) => return import_command(sub),
+        ("healthcheck", Some(sub)) => healthcheck_command(sub),
+        ("check", Some(sub)) => check_command(sub),
+        ("merge", Some(sub)) => return merge_command(sub),
+        ("export", Some(sub)) => return export_command(sub),
+        ("query", Some(sub)) => return query_command(sub),
+        _ => {},
+    }
 
-        let ready = Arc::new(AtomicBool::new(false));
-        let notify = ready.clone();
+    let config = load_config(config_path.as_deref(), profile.as_deref())?;
+
+    let lang = {
+        let sub = matches.subcommand().1.unwrap_or(&matches);
+        let settings = Settings::new(sub, config.as_ref()).environment(&env).under(&matches);
+        settings.value_of("lang").and_then(Lang::parse).unwrap_or_else(Lang::from_env)
+    };
+    locale::set(lang);
+
+    match matches.subcommand() {
+        ("report", Some(sub)) => report_command(sub),
+        ("tui", Some(sub)) => {
+            let settings = Settings::new(sub, config.as_ref()).environment(&env).under(&matches)
+                .path(config_path.as_deref());
+            run(&settings, sub.value_of("replay-events"), false, settings.flag("no-tui"))
+        },
+        ("daemon", Some(sub)) => {
+            let settings = Settings::new(sub, config.as_ref()).environment(&env).under(&matches)
+                .path(config_path.as_deref());
+            run(&settings, None, false, true)
+        },
+        ("replay", Some(sub)) => {
+            let settings = Settings::new(sub, config.as_ref()).environment(&env).under(&matches)
+                .path(config_path.as_deref());
+            run(&settings, sub.value_of("file"), false, settings.flag("no-tui"))
+        },
+        ("attach", Some(sub)) => {
+            let settings = Settings::new(sub, config.as_ref()).environment(&env).under(&matches)
+                .path(config_path.as_deref());
+            run(&settings, None, true, settings.flag("no-tui"))
+        },
+        ("serve", Some(sub)) => {
+            serve(&Settings::new(sub, config.as_ref()).environment(&env).under(&matches))
+        },
+        ("reflect", Some(sub)) => {
+            let settings = Settings::new(sub, config.as_ref()).environment(&env).under(&matches);
+            let listen = settings.value_of("listen").unwrap();
+            reflect::serve(listen).during(&format!("reflecting on {}", listen))
+        },
+        _ => {
+            let settings = Settings::new(&matches, config.as_ref()).environment(&env)
+                .path(config_path.as_deref());
+            run(&settings, matches.value_of("replay-events"), false, settings.flag("no-tui"))
+        },
+    }
+}
 
-        thread::spawn(move || {
-            thread::sleep(time);
-            notify.store(true, Ordering::Relaxed);
-        });
+/* the monitor's tasks all share one runtime, `headless` for daemon or --no-tui */
+fn run(settings: &Settings, replay: Option<&str>, attach: bool, headless: bool) -> Result<(), Error> {
+    if headless && (replay.is_some() || attach) {
+        clap::Error::with_description(
+            "--no-tui probes, it can't replay or attach",
+            clap::ErrorKind::ArgumentConflict).exit();
+    }
 
-        Sleep { ready }
+    if let Some(path) = settings.value_of("log-file") {
+        logfile::init(path, settings.value_of("log-level").unwrap()).file(path)?;
     }
 
-    fn done(&self) -> bool {
-        self.ready.load(Ordering::Relaxed)
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| if headless {
+        runtime.block_on(daemon::run(settings))
+    } else {
+        runtime.block_on(ui::run(settings, replay, attach))
+    }));
+
+    /* the keyboard task can still be waiting on a read, even after a panic */
+    runtime.shutdown_background();
+
+    match result {
+        Ok(result) => result,
+        Err(panic) => panic::resume_unwind(panic),
     }
 }
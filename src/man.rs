@@ -0,0 +1,113 @@
+use std::io;
+
+use clap::App;
+
+use crate::config;
+
+/*
+ * man page generation. clap 2 has no public view of its arguments, so
+ * the page is built from the unwrapped help text - one line per entry,
+ * name and description separated by a run of spaces - which keeps it in
+ * step with the real definitions.
+ */
+
+fn escape(s: &str) -> String {
+    let s = s.replace('\\', "\\e").replace('-', "\\-");
+
+    if s.starts_with('.') || s.starts_with('\'') {
+        format!("\\&{}", s)
+    } else {
+        s
+    }
+}
+
+pub fn render(app: App) -> io::Result<String> {
+    let app = app.set_term_width(0);
+
+    let mut help = vec![];
+    app.write_help(&mut help)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    let help = String::from_utf8_lossy(&help);
+
+    let mut lines = help.lines();
+
+    /* "name version", author, about, then the sections */
+    let title = lines.next().unwrap_or("");
+    let mut words = title.split_whitespace();
+    let name = words.next().unwrap_or("packetloss");
+    let version = words.next().unwrap_or("");
+
+    let mut header = vec![];
+    let mut sections: Vec<(String, Vec<String>)> = vec![];
+
+    for line in lines {
+        if !line.starts_with(' ') && line.ends_with(':') {
+            sections.push((line.trim_end_matches(':').to_string(), vec![]));
+        } else if let Some(section) = sections.last_mut() {
+            section.1.push(line.to_string());
+        } else if !line.trim().is_empty() {
+            header.push(line.trim().to_string());
+        }
+    }
+
+    let about = header.last().cloned().unwrap_or_default();
+
+    let mut out = String::new();
+
+    out.push_str(&format!(".TH {} 1 \"\" \"{} {}\" \"User Commands\"\n",
+        name.to_uppercase(), name, version));
+    out.push_str(&format!(".SH NAME\n{} \\- {}\n", escape(name), escape(&about)));
+
+    for (title, body) in sections {
+        if title == "USAGE" {
+            out.push_str(".SH SYNOPSIS\n");
+            for line in body.iter().filter(|x| !x.trim().is_empty()) {
+                out.push_str(&format!("{}\n", escape(line.trim())));
+            }
+            continue;
+        }
+
+        out.push_str(&format!(".SH {}\n", title));
+
+        for line in body.iter().filter(|x| !x.trim().is_empty()) {
+            /* entries sit at 4 or 8 columns, deeper lines continue a description */
+            let indent = line.len() - line.trim_start().len();
+            let line = line.trim();
+
+            if indent > 8 {
+                out.push_str(&format!(".br\n{}\n", escape(line)));
+                continue;
+            }
+
+            let (entry, help) = match line.find("  ") {
+                Some(i) => (&line[..i], line[i..].trim()),
+                None => (line, ""),
+            };
+
+            out.push_str(&format!(".TP\n.B {}\n", escape(entry)));
+            if !help.is_empty() {
+                out.push_str(&format!("{}\n", escape(help)));
+            }
+        }
+    }
+
+    /* the environment mirrors the config file, which the help text can't show */
+    out.push_str(".SH ENVIRONMENT\n");
+    out.push_str(&format!(".TP\n.B {}\n{}\n", escape(&config::env_name("config")),
+        escape("config file to read in place of the default one")));
+    out.push_str(&format!(".TP\n.B {}\n{}\n", escape(&config::env_name("profile")),
+        escape("profile of the config file to use when --profile isn't given")));
+    out.push_str(&format!(".TP\n.B {}\n{}\n", escape(&config::env_name("state-dir")),
+        escape("directory for session layouts, checkpoints, crash files and locks when --state-dir isn't given")));
+    for &(key, _, help) in config::KEYS.iter() {
+        out.push_str(&format!(".TP\n.B {}\n{}\n", escape(&config::env_name(key)), escape(help)));
+    }
+    out.push_str(&format!(".PP\n{}\n", escape("Lists are separated by commas. Options given on the \
+        command line take precedence over the environment, and the environment over the config file.")));
+
+    if header.len() > 1 {
+        out.push_str(&format!(".SH AUTHOR\n{}\n", escape(&header[0])));
+    }
+
+    Ok(out)
+}
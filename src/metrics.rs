@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use tui::buffer::Buffer;
+use tui::widgets::{Block, Widget, Borders};
+use tui::layout::Rect;
+use tui::style::{Style, Color};
+
+use crate::icmp::Mode;
+
+/// The tool's own health, shown in the debug pane
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /* time spent inside the last ping call */
+    pub probe: Duration,
+    /* how late the last chunk started relative to the interval, in ms */
+    pub drift: i64,
+    pub queue: usize,
+    pub memory: usize,
+    pub chunks: usize,
+    pub frames: u64,
+    /* draws that took longer than a tick */
+    pub dropped: u64,
+    pub draw: Duration,
+    /* how icmp probes are sent, when pinging locally */
+    pub socket: Option<Mode>,
+}
+
+pub const HEIGHT: u16 = 9;
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
+
+pub fn bytes(n: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut n = n as f64;
+    let mut unit = 0;
+
+    while n >= 1024.0 && unit < units.len() - 1 {
+        n /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", n, units[unit])
+}
+
+pub struct MetricsPane<'a> {
+    metrics: &'a Metrics,
+}
+
+impl<'a> MetricsPane<'a> {
+    pub fn new(metrics: &'a Metrics) -> Self {
+        MetricsPane { metrics }
+    }
+}
+
+impl<'a> Widget for MetricsPane<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default()
+                .fg(Color::White))
+            .style(Style::default()
+                .bg(Color::Black))
+            .title(" Debug ");
+
+        block.draw(area, buf);
+        let inner = block.inner(area);
+
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+
+        let m = self.metrics;
+        let lines = [
+            format!("probe time    {:.01}ms", ms(m.probe)),
+            format!("sched drift   {:+}ms", m.drift),
+            format!("event queue   {}", m.queue),
+            format!("history       {} chunks, {}", m.chunks, bytes(m.memory)),
+            format!("last draw     {:.01}ms", ms(m.draw)),
+            format!("frames        {} ({} dropped)", m.frames, m.dropped),
+            format!("icmp via      {}", m.socket.map(|x| x.to_string()).unwrap_or_else(|| String::from("-"))),
+        ];
+
+        let style = Style::default()
+            .fg(Color::White)
+            .bg(Color::Black);
+
+        for (i, line) in lines.iter().enumerate().take(inner.height as usize) {
+            buf.set_stringn(inner.x + 1, inner.y + i as u16, line,
+                inner.width.saturating_sub(1) as usize, style);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! A probe engine to embed, without the monitor's own main loop
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use packetloss::monitor::Monitor;
+//!
+//! let mut monitor = Monitor::builder()
+//!     .target("example.com")
+//!     .interval(Duration::from_secs(10))
+//!     .timeout(Duration::from_millis(200))
+//!     .on_chunk(|chunk| println!("{:.0}% loss", chunk.loss() * 100.0))
+//!     .build()
+//!     .unwrap();
+//!
+//! monitor.start();
+//! /* ... */
+//! monitor.stop();
+//! ```
+//!
+//! Probes are ICMP echoes unless another `ping::Probe` is given to `probe`.
+//! `stream` hands the chunks out as a `futures_core::Stream` instead, and
+//! `iter` as a blocking iterator:
+//!
+//! ```no_run
+//! use packetloss::monitor::Monitor;
+//!
+//! for report in Monitor::builder().target("example.com").iter().unwrap() {
+//!     println!("{:.0}% loss, {:.1}ms", report.loss * 100.0, report.latency);
+//! }
+//! ```
+
+use std::pin::Pin;
+use std::sync::{mpsc as sync, Arc, Condvar, Mutex};
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use crate::icmp::Ping;
+use crate::ping::{self, PacketChunk, PingError, Probe};
+use crate::report::ChunkReport;
+
+type OnChunk = Box<dyn FnMut(PacketChunk) + Send>;
+type OnError = Box<dyn FnMut(PingError) + Send>;
+
+/// Settings for a `Monitor`, from `Monitor::builder`
+pub struct Builder {
+    target: Option<String>,
+    interval: Duration,
+    chunk_size: u64,
+    timeout: Duration,
+    probe: Option<Box<dyn Probe>>,
+    on_chunk: Option<OnChunk>,
+    on_error: Option<OnError>,
+}
+
+impl Builder {
+    /// Host name or address to probe, required. It also names the chunks
+    /// of a probe given to `probe`
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// Time from the start of one chunk to the start of the next, 60s by default
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Probes per chunk, 10 by default
+    pub fn chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Replies slower than this count as lost, 100ms by default. Only for
+    /// the default ICMP probe, others bring their own
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How each probe is sent, ICMP echoes to `target` unless set
+    pub fn probe<P: Probe + 'static>(mut self, probe: P) -> Self {
+        self.probe = Some(Box::new(probe));
+        self
+    }
+
+    /// Called on the engine's thread with every chunk
+    pub fn on_chunk<F: FnMut(PacketChunk) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_chunk = Some(Box::new(f));
+        self
+    }
+
+    /// Called instead of `on_chunk` when the probe can't be sent at all,
+    /// the engine keeps trying
+    pub fn on_error<F: FnMut(PingError) + Send + 'static>(mut self, f: F) -> Self {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// A stopped monitor, see `Monitor::start`
+    pub fn build(self) -> Result<Monitor, String> {
+        let target = self.target.ok_or_else(|| String::from("a target is required"))?;
+
+        let engine = Engine {
+            target,
+            interval: self.interval,
+            chunk_size: self.chunk_size,
+            /* made on the engine's thread, it checks which socket it may open */
+            probe: self.probe,
+            timeout: self.timeout,
+            on_chunk: self.on_chunk.unwrap_or_else(|| Box::new(|_| {})),
+            on_error: self.on_error.unwrap_or_else(|| Box::new(|_| {})),
+        };
+
+        Ok(Monitor {
+            shared: Arc::new((Mutex::new(State::Paused), Condvar::new())),
+            engine: Some(engine),
+            thread: None,
+        })
+    }
+
+    /// A started monitor whose chunks come out of the returned stream, in
+    /// place of `on_chunk`. Any executor may poll it
+    pub fn stream(self) -> Result<ChunkStream, String> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let target = self.target.clone().unwrap_or_default();
+
+        let mut monitor = self
+            .on_chunk(move |chunk| {
+                let _ = tx.send(ChunkReport::new(&target, chunk));
+            })
+            .build()?;
+        monitor.start();
+
+        Ok(ChunkStream { monitor, rx })
+    }
+
+    /// Like `stream`, for synchronous code. Each `next` blocks until a chunk is done
+    pub fn iter(self) -> Result<ChunkIter, String> {
+        let (tx, rx) = sync::channel();
+        let target = self.target.clone().unwrap_or_default();
+
+        let mut monitor = self
+            .on_chunk(move |chunk| {
+                let _ = tx.send(ChunkReport::new(&target, chunk));
+            })
+            .build()?;
+        monitor.start();
+
+        Ok(ChunkIter { monitor, rx })
+    }
+}
+
+/// Chunks of a running monitor as a `Stream`, ending once it is stopped
+pub struct ChunkStream {
+    monitor: Monitor,
+    rx: mpsc::UnboundedReceiver<ChunkReport>,
+}
+
+impl ChunkStream {
+    /// To pause or stop the engine behind the stream
+    pub fn monitor(&mut self) -> &mut Monitor {
+        &mut self.monitor
+    }
+}
+
+impl Stream for ChunkStream {
+    type Item = ChunkReport;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkReport>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// Chunks of a running monitor as a blocking iterator, ending once it is stopped
+pub struct ChunkIter {
+    monitor: Monitor,
+    rx: sync::Receiver<ChunkReport>,
+}
+
+impl ChunkIter {
+    /// To pause or stop the engine behind the iterator
+    pub fn monitor(&mut self) -> &mut Monitor {
+        &mut self.monitor
+    }
+}
+
+impl Iterator for ChunkIter {
+    type Item = ChunkReport;
+
+    fn next(&mut self) -> Option<ChunkReport> {
+        self.rx.recv().ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Running,
+    Paused,
+    Stopped,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+struct Engine {
+    target: String,
+    interval: Duration,
+    chunk_size: u64,
+    probe: Option<Box<dyn Probe>>,
+    timeout: Duration,
+    on_chunk: OnChunk,
+    on_error: OnError,
+}
+
+impl Engine {
+    fn run(mut self, shared: Shared) {
+        let (ref state, ref wake) = *shared;
+
+        let (target, timeout) = (&self.target, self.timeout);
+        let probe = self.probe.get_or_insert_with(|| Box::new(Ping::new(target, timeout)));
+
+        loop {
+            {
+                let mut state = state.lock().unwrap();
+                while *state == State::Paused {
+                    state = wake.wait(state).unwrap();
+                }
+                if *state == State::Stopped {
+                    return;
+                }
+            }
+
+            let start = Instant::now();
+            match probe.ready() {
+                Ok(()) => (self.on_chunk)(ping::burst(&**probe, self.chunk_size, Duration::from_secs(0))),
+                Err(e) => (self.on_error)(e),
+            }
+
+            /* out the interval, unless paused or stopped meanwhile */
+            let next = start + self.interval;
+            let mut state = state.lock().unwrap();
+            while *state == State::Running {
+                let left = match next.checked_duration_since(Instant::now()) {
+                    Some(left) if left > Duration::from_millis(0) => left,
+                    _ => break,
+                };
+                state = wake.wait_timeout(state, left).unwrap().0;
+            }
+        }
+    }
+}
+
+/// A handle on the probe engine, which runs on its own thread once started.
+/// Dropping it stops the engine
+pub struct Monitor {
+    shared: Shared,
+    engine: Option<Engine>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    pub fn builder() -> Builder {
+        Builder {
+            target: None,
+            interval: Duration::from_secs(60),
+            chunk_size: 10,
+            timeout: Duration::from_millis(100),
+            probe: None,
+            on_chunk: None,
+            on_error: None,
+        }
+    }
+
+    /// Start probing, or carry on after `pause`, with a chunk right away
+    pub fn start(&mut self) {
+        self.set(State::Running);
+
+        if let Some(engine) = self.engine.take() {
+            let shared = self.shared.clone();
+            self.thread = Some(thread::spawn(move || engine.run(shared)));
+        }
+    }
+
+    /// Stop probing after the chunk in flight, until `start`
+    pub fn pause(&mut self) {
+        self.set(State::Paused);
+    }
+
+    /// End the engine, waiting for the chunk in flight. It can't be started again
+    pub fn stop(&mut self) {
+        self.set(State::Stopped);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.engine = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        *self.shared.0.lock().unwrap() == State::Running
+    }
+
+    fn set(&self, next: State) {
+        let mut state = self.shared.0.lock().unwrap();
+        /* stopping is final */
+        if *state != State::Stopped {
+            *state = next;
+        }
+        self.shared.1.notify_all();
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,39 @@
+use std::io;
+
+/*
+ * network namespaces. setns only moves the calling thread, but threads
+ * started afterwards inherit it, so entering before anything is spawned
+ * puts every probe - and every webhook - inside the namespace.
+ */
+
+/// `NAME` as made by `ip netns add`, or a path such as `/proc/PID/ns/net`
+pub fn path(name: &str) -> String {
+    if name.contains('/') {
+        name.to_string()
+    } else {
+        format!("/var/run/netns/{}", name)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn enter(name: &str) -> io::Result<()> {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    let path = path(name);
+    let file = File::open(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+
+    if unsafe { libc::setns(file.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        let e = io::Error::last_os_error();
+        return Err(io::Error::new(e.kind(), format!("entering {}: {}", path, e)));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn enter(name: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other,
+        format!("{}: network namespaces are linux only", path(name))))
+}
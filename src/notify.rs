@@ -0,0 +1,87 @@
+use std::time::Duration;
+use std::thread;
+
+use log::warn;
+
+use crate::alert::{json_escape, Incident, Sink};
+
+pub const OPEN_TEMPLATE: &str =
+    ":warning: {target} is losing packets since {start} ({worst} loss)";
+pub const CLOSE_TEMPLATE: &str =
+    ":white_check_mark: {target} recovered after {duration}, {chunks} chunks, worst {worst}, mean {mean}\n`[{chart}]`";
+
+/// Chat services that accept a json message over https
+#[derive(Debug, Clone)]
+pub enum Chat {
+    Slack(String),
+    Discord(String),
+    Telegram { token: String, chat: String },
+}
+
+impl Chat {
+    fn url(&self) -> String {
+        match self {
+            Chat::Slack(url) | Chat::Discord(url) => url.clone(),
+            Chat::Telegram { token, .. } => {
+                format!("https://api.telegram.org/bot{}/sendMessage", token)
+            },
+        }
+    }
+
+    fn payload(&self, text: &str) -> String {
+        let text = json_escape(text);
+
+        match self {
+            Chat::Slack(_) => format!("{{\"text\":\"{}\"}}", text),
+            Chat::Discord(_) => format!("{{\"content\":\"{}\"}}", text),
+            Chat::Telegram { chat, .. } => {
+                format!("{{\"chat_id\":\"{}\",\"text\":\"{}\"}}", json_escape(chat), text)
+            },
+        }
+    }
+
+    pub fn send(&self, text: &str) -> Result<(), Box<ureq::Error>> {
+        ureq::post(&self.url())
+            .set("Content-Type", "application/json")
+            .timeout(Duration::from_secs(10))
+            .send_string(&self.payload(text))
+            .map(|_| ())
+            .map_err(Box::new)
+    }
+}
+
+/// Posts one message when an incident opens and one when it closes
+pub struct ChatSink {
+    chat: Chat,
+    open: String,
+    close: String,
+}
+
+impl ChatSink {
+    pub fn new(chat: Chat, open: &str, close: &str) -> Self {
+        ChatSink {
+            chat,
+            open: open.to_string(),
+            close: close.to_string(),
+        }
+    }
+
+    fn post(&self, text: String) {
+        let chat = self.chat.clone();
+        thread::spawn(move || {
+            if let Err(e) = chat.send(&text) {
+                warn!("chat message: {}", e);
+            }
+        });
+    }
+}
+
+impl Sink for ChatSink {
+    fn open(&mut self, incident: &Incident) {
+        self.post(incident.render(&self.open));
+    }
+
+    fn close(&mut self, incident: &Incident) {
+        self.post(incident.render(&self.close));
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use std::time::Instant;
+
+use chrono::Local;
+
+use crate::alert::json_escape;
+use crate::ping::PacketChunk;
+use crate::report;
+use crate::stream;
+
+/*
+ * what `serve` writes to stdout. the stream format is read back by
+ * --via and attach; the others are for log collectors, one record per
+ * line with the same fields in each format.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Stream,
+    Logfmt,
+    Json,
+    Plain,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "stream" => Some(Format::Stream),
+            "logfmt" => Some(Format::Logfmt),
+            "json" => Some(Format::Json),
+            "plain" => Some(Format::Plain),
+            _ => None,
+        }
+    }
+}
+
+enum Value {
+    Str(String),
+    Int(u64),
+    Num(f64),
+}
+
+fn render(format: Format, fields: &[(&str, Value)]) -> String {
+    let mut out = String::new();
+
+    match format {
+        Format::Json => {
+            out.push('{');
+            for (i, (key, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let _ = match value {
+                    Value::Str(s) => write!(out, "\"{}\":\"{}\"", key, json_escape(s)),
+                    Value::Int(n) => write!(out, "\"{}\":{}", key, n),
+                    Value::Num(n) => write!(out, "\"{}\":{:.3}", key, n),
+                };
+            }
+            out.push('}');
+        },
+        Format::Logfmt => {
+            for (i, (key, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                let _ = match value {
+                    Value::Str(s) if s.is_empty() || s.contains([' ', '"', '=']) => {
+                        write!(out, "{}=\"{}\"", key, s.replace('\\', "\\\\").replace('"', "\\\""))
+                    },
+                    Value::Str(s) => write!(out, "{}={}", key, s),
+                    Value::Int(n) => write!(out, "{}={}", key, n),
+                    Value::Num(n) => write!(out, "{}={:.3}", key, n),
+                };
+            }
+        },
+        /* time, event and target bare, then the rest */
+        Format::Plain | Format::Stream => {
+            for (i, (key, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                let _ = match value {
+                    Value::Str(s) if i < 3 => write!(out, "{}", s),
+                    Value::Str(s) => write!(out, "{}={}", key, s),
+                    Value::Int(n) => write!(out, "{}={}", key, n),
+                    Value::Num(n) => write!(out, "{}={:.2}", key, n),
+                };
+            }
+        },
+    }
+
+    out
+}
+
+pub struct Output<W: Write> {
+    out: W,
+    format: Format,
+    target: String,
+    /* the target's tags, joined with commas */
+    tags: String,
+    start: Instant,
+    chunks: u64,
+    last: Option<Instant>,
+    /* running totals for `stats`, the chunks themselves aren't kept */
+    sent: u64,
+    received: u64,
+    /* min, sum and max of the chunks' mean latency */
+    latency: (f64, f64, f64),
+    /* the time and loss of the chunk with the most */
+    worst: Option<(String, f64)>,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(out: W, format: Format, target: &str) -> Self {
+        Output {
+            out,
+            format,
+            target: target.to_string(),
+            tags: String::new(),
+            start: Instant::now(),
+            chunks: 0,
+            last: None,
+            sent: 0,
+            received: 0,
+            latency: (f64::INFINITY, 0.0, 0.0),
+            worst: None,
+        }
+    }
+
+    /// Label every record with the target's tags
+    pub fn set_tags(&mut self, tags: &[String]) {
+        self.tags = tags.join(",");
+    }
+
+    /* the fields every record starts with */
+    fn head(&self, time: String, event: &str) -> Vec<(&'static str, Value)> {
+        let mut fields = vec![
+            ("time", Value::Str(time)),
+            ("event", Value::Str(event.to_string())),
+            ("target", Value::Str(self.target.clone())),
+        ];
+
+        if !self.tags.is_empty() {
+            fields.push(("tags", Value::Str(self.tags.clone())));
+        }
+
+        fields
+    }
+
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.out, "{}", line)?;
+        /* collectors and --via read line by line, never sit on one */
+        self.out.flush()
+    }
+
+    pub fn chunk(&mut self, chunk: &PacketChunk) -> io::Result<()> {
+        self.chunks += 1;
+        self.last = Some(Instant::now());
+
+        let latency = report::mean_latency(chunk);
+        self.sent += chunk.sent() as u64;
+        self.received += chunk.received() as u64;
+        self.latency = (self.latency.0.min(latency), self.latency.1 + latency, self.latency.2.max(latency));
+
+        if self.worst.as_ref().is_none_or(|x| chunk.loss() > x.1) {
+            self.worst = Some((chunk.time().to_rfc3339(), chunk.loss()));
+        }
+
+        if self.format == Format::Stream {
+            return stream::write(&mut self.out, chunk);
+        }
+
+        let mut fields = self.head(chunk.time().to_rfc3339(), "chunk");
+        fields.push(("sent", Value::Int(chunk.sent() as u64)));
+        fields.push(("received", Value::Int(chunk.received() as u64)));
+        fields.push(("loss", Value::Num(chunk.loss())));
+        fields.push(("latency_ms", Value::Num(report::mean_latency(chunk))));
+
+        if let Some(jitter) = chunk.jitter() {
+            fields.push(("jitter_ms", Value::Num(jitter)));
+        }
+
+        fields.push(("mos", Value::Num(chunk.mos())));
+        fields.push(("lost_in_a_row", Value::Int(chunk.longest_run() as u64)));
+
+        if let Some(ratio) = chunk.burst_ratio() {
+            fields.push(("burst_ratio", Value::Num(ratio)));
+        }
+        fields.push(("timeout_ms", Value::Num(chunk.timeout())));
+
+        /* the schedule it was taken on, which may have changed since the last */
+        if let Some(params) = chunk.params() {
+            fields.push(("interval_s", Value::Num(params.interval)));
+            fields.push(("chunk_size", Value::Int(params.chunk_size)));
+            fields.push(("spacing_ms", Value::Num(params.spacing)));
+        }
+
+        let line = render(self.format, &fields);
+        self.line(&line)
+    }
+
+    /// A liveness line, written whether or not anything changed
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        if self.format == Format::Stream {
+            let line = format!("heartbeat {}", self.chunks);
+            return self.line(&line);
+        }
+
+        let mut fields = self.head(Local::now().to_rfc3339(), "heartbeat");
+        fields.push(("uptime_s", Value::Int(self.start.elapsed().as_secs())));
+        fields.push(("chunks", Value::Int(self.chunks)));
+
+        if let Some(last) = self.last {
+            fields.push(("last_chunk_s", Value::Int(last.elapsed().as_secs())));
+        }
+
+        let line = render(self.format, &fields);
+        self.line(&line)
+    }
+
+    /// The totals of every chunk written so far, on SIGUSR1 and on the way out
+    pub fn stats(&mut self) -> io::Result<()> {
+        let mut fields = self.head(Local::now().to_rfc3339(), "stats");
+        let head = fields.len();
+        fields.push(("uptime_s", Value::Int(self.start.elapsed().as_secs())));
+        fields.push(("chunks", Value::Int(self.chunks)));
+        fields.push(("sent", Value::Int(self.sent)));
+        fields.push(("received", Value::Int(self.received)));
+
+        if self.sent > 0 {
+            fields.push(("loss", Value::Num(1.0 - self.received as f64 / self.sent as f64)));
+        }
+
+        if self.chunks > 0 {
+            let (min, sum, max) = self.latency;
+            fields.push(("latency_min_ms", Value::Num(min)));
+            fields.push(("latency_avg_ms", Value::Num(sum / self.chunks as f64)));
+            fields.push(("latency_max_ms", Value::Num(max)));
+        }
+
+        if let Some((ref time, loss)) = self.worst {
+            fields.push(("worst_time", Value::Str(time.clone())));
+            fields.push(("worst_loss", Value::Num(loss)));
+        }
+
+        /* attach skips the line, as it does heartbeats */
+        let line = if self.format == Format::Stream {
+            format!("stats {}", render(self.format, &fields[head..]))
+        } else {
+            render(self.format, &fields)
+        };
+        self.line(&line)
+    }
+}
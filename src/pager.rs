@@ -0,0 +1,138 @@
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+use std::thread;
+
+use log::warn;
+
+use crate::alert::{json_escape, Incident, Sink};
+
+const PAGERDUTY_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+const OPSGENIE_URL: &str = "https://api.opsgenie.com/v2/alerts";
+
+/// On-call services with an explicit trigger/resolve lifecycle
+#[derive(Debug, Clone)]
+pub enum Pager {
+    PagerDuty(String),
+    Opsgenie(String),
+}
+
+impl Pager {
+    /* one open event per target, so repeated incidents never stack up */
+    fn dedup(target: &str) -> String {
+        format!("packetloss-{}", target)
+    }
+
+    fn trigger(&self, incident: &Incident) -> Result<(), Box<ureq::Error>> {
+        let dedup = json_escape(&Pager::dedup(&incident.target));
+        let summary = json_escape(&format!("packet loss to {} ({:.0}% loss)",
+            incident.target, incident.worst_loss() * 100.0));
+
+        match self {
+            Pager::PagerDuty(key) => {
+                post(PAGERDUTY_URL, None, &format!(
+                    "{{\"routing_key\":\"{}\",\"event_action\":\"trigger\",\"dedup_key\":\"{}\",\
+                     \"payload\":{{\"summary\":\"{}\",\"source\":\"{}\",\"severity\":\"error\",\
+                     \"timestamp\":\"{}\"}}}}",
+                    json_escape(key), dedup, summary, json_escape(&incident.target),
+                    incident.start.to_rfc3339()))
+            },
+            Pager::Opsgenie(key) => {
+                post(OPSGENIE_URL, Some(key), &format!(
+                    "{{\"message\":\"{}\",\"alias\":\"{}\",\"source\":\"packetloss\",\
+                     \"description\":\"{}\"}}",
+                    summary, dedup, json_escape(&incident.summary())))
+            },
+        }
+    }
+
+    fn resolve(&self, incident: &Incident) -> Result<(), Box<ureq::Error>> {
+        let dedup = Pager::dedup(&incident.target);
+
+        match self {
+            Pager::PagerDuty(key) => {
+                post(PAGERDUTY_URL, None, &format!(
+                    "{{\"routing_key\":\"{}\",\"event_action\":\"resolve\",\"dedup_key\":\"{}\"}}",
+                    json_escape(key), json_escape(&dedup)))
+            },
+            Pager::Opsgenie(key) => {
+                let url = format!("{}/{}/close?identifierType=alias", OPSGENIE_URL, encode(&dedup));
+                post(&url, Some(key), &format!(
+                    "{{\"source\":\"packetloss\",\"note\":\"{}\"}}",
+                    json_escape(&incident.summary())))
+            },
+        }
+    }
+}
+
+fn post(url: &str, genie: Option<&str>, body: &str) -> Result<(), Box<ureq::Error>> {
+    let mut request = ureq::post(url)
+        .set("Content-Type", "application/json")
+        .timeout(Duration::from_secs(10));
+
+    if let Some(key) = genie {
+        request = request.set("Authorization", &format!("GenieKey {}", key));
+    }
+
+    request.send_string(body).map(|_| ()).map_err(Box::new)
+}
+
+/* percent-encode a path segment */
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/* what the worker sends, in the order the incidents opened and closed */
+enum Page {
+    Trigger(Incident),
+    Resolve(Incident),
+}
+
+/// Triggers an on-call event when an incident opens and resolves it on recovery
+pub struct PagerSink {
+    tx: Sender<Page>,
+}
+
+impl PagerSink {
+    /*
+     * one worker sends every event in turn, so a resolve never overtakes
+     * the trigger it answers and leaves the page open. it stops when the
+     * sink is dropped.
+     */
+    pub fn new(pager: Pager) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for page in rx {
+                match page {
+                    Page::Trigger(incident) => {
+                        if let Err(e) = pager.trigger(&incident) {
+                            warn!("{}: triggering the page: {}", incident.target, e);
+                        }
+                    },
+                    Page::Resolve(incident) => {
+                        if let Err(e) = pager.resolve(&incident) {
+                            warn!("{}: resolving the page: {}", incident.target, e);
+                        }
+                    },
+                }
+            }
+        });
+
+        PagerSink { tx }
+    }
+}
+
+impl Sink for PagerSink {
+    fn open(&mut self, incident: &Incident) {
+        let _ = self.tx.send(Page::Trigger(incident.clone()));
+    }
+
+    fn close(&mut self, incident: &Incident) {
+        let _ = self.tx.send(Page::Resolve(incident.clone()));
+    }
+}
@@ -0,0 +1,100 @@
+use std::cmp::min;
+
+/*
+ * tile layout. `length` tiles are spread over a width x height grid of
+ * cells, using all of it without going past it: the first tiles are the
+ * largest, each row taking what's left of its width.
+ */
+
+/// Where one tile goes, in cells from the top left of the grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// The tiles of a grid, in order, see `Partitioner::new`
+#[derive(Debug)]
+pub struct Partitioner {
+    x: u16,
+    y: u16,
+    width: u16,
+    max_width: u16,
+    height: u16,
+    length: u16,
+}
+
+impl Partitioner {
+    /// `length` tiles over `width` x `height` cells
+    pub fn new(width: u16, height: u16, length: u16) -> Self {
+        Partitioner {
+            x: 0,
+            y: 0,
+            width,
+            max_width: width,
+            height,
+            length,
+        }
+    }
+}
+
+fn ceil(a: u16, b: u16) -> u16 {
+    if a == 0 {
+        0
+    } else {
+        1 + ((a - 1) / b)
+    }
+}
+
+impl Iterator for Partitioner {
+    type Item = Tile;
+
+    /*
+     * we want to use up all of size
+     * dont exceed size
+     */
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.height == 0 || self.length == 0 {
+            return None;
+        }
+
+        let x = self.x;
+        let y = self.y;
+
+        let after = min(self.length, (self.height - 1) * self.max_width);
+
+        let mut wdiv = (self.length - after) + 1;
+        let hdiv = min(self.height, self.length);
+
+        if self.height == 1 && wdiv > 0 {
+            wdiv -= 1;
+        }
+
+        let width = ceil(self.width, wdiv);
+        let height = ceil(self.height, hdiv);
+
+        self.width -= width;
+        self.height -= height - 1;
+
+        /* if the line's width was consumed consume one more line and reset width */
+        if self.width == 0 && self.height > 1 {
+            self.width = self.max_width;
+            self.height -= 1;
+            self.y += 1;
+        }
+
+        self.x += width;
+        self.y += height - 1;
+
+        if self.x == self.max_width {
+            self.x = 0;
+        }
+
+        self.length -= 1;
+
+        Some(Tile { x, y, width, height })
+    }
+}
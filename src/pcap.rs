@@ -0,0 +1,113 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::alert::Incident;
+
+/*
+ * packet captures around incidents. a capture command is started when an
+ * incident opens and stopped a little while after it closes, writing to
+ * a file named after the target and start time. the path goes into the
+ * incident record, so it shows up in emails and chat messages.
+ */
+
+/* {file}, {target} and {max} are substituted */
+pub const DEFAULT_COMMAND: &str = "tcpdump -i any -U -n -c {max} -w {file} host {target}";
+
+pub struct Pcap {
+    dir: PathBuf,
+    command: String,
+    linger: Duration,
+    max: u64,
+    running: Option<Child>,
+}
+
+impl Pcap {
+    pub fn new(dir: &str, command: &str, linger: Duration, max: u64) -> Self {
+        Pcap {
+            dir: PathBuf::from(dir),
+            command: command.to_string(),
+            linger,
+            max,
+            running: None,
+        }
+    }
+
+    /// Start capturing for a new incident, the capture file path on success
+    pub fn start(&mut self, incident: &Incident) -> Option<String> {
+        /* a capture still lingering after the last incident carries on alone */
+        self.stop();
+
+        let host = host(&incident.target);
+
+        let name = format!("packetloss-{}-{}.pcap",
+            host.replace(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-', "_"),
+            incident.start.format("%Y%m%d-%H%M%S"));
+        let path = self.dir.join(name).to_string_lossy().into_owned();
+
+        fs::create_dir_all(&self.dir).ok()?;
+
+        let command = self.command
+            .replace("{file}", &quote(&path))
+            .replace("{target}", &quote(&host))
+            .replace("{max}", &self.max.to_string());
+
+        /* the ui owns the terminal, so the command gets none of it */
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("exec {}", command))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+
+        self.running = Some(child);
+        Some(path)
+    }
+
+    /// Stop the running capture once the linger time has passed
+    pub fn stop(&mut self) {
+        let mut child = match self.running.take() {
+            Some(child) => child,
+            None => return,
+        };
+
+        let linger = self.linger;
+
+        thread::spawn(move || {
+            thread::sleep(linger);
+
+            /* SIGTERM rather than kill(), so tcpdump flushes the file */
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+            }
+            #[cfg(not(unix))]
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+    }
+}
+
+/* the host of a target, which may be an http url or carry a port like the reflector's */
+fn host(target: &str) -> String {
+    let target = target.splitn(2, "://").last().unwrap_or(target);
+    let target = target.split('/').next().unwrap_or(target);
+
+    if let Ok(addr) = target.parse::<SocketAddr>() {
+        return addr.ip().to_string();
+    }
+
+    match target.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => host.to_string(),
+        _ => target.to_string(),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
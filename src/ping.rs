@@ -1,71 +1,710 @@
-use oping::{PingItem, PingError};
-
-use tui::buffer::Buffer;
-use tui::widgets::Widget;
-use tui::layout::Rect;
-use tui::style::Color;
-
 use chrono::prelude::*;
 
+use std::error;
+use std::fmt;
+use std::io;
+use std::mem::size_of;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Duration;
+#[cfg(feature = "probe")]
+use std::time::Instant;
+#[cfg(feature = "probe")]
+use std::thread;
+use std::fmt::Write;
 
-pub struct Ping {
-    addr: String,
-    timeout: Duration,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddrFamily {
+    IPV4,
+    IPV6,
 }
 
-impl Ping {
-    pub fn new(addr: &str, timeout: Duration) -> Self {
+/// One probe's reply, or the lack of one, whatever kind of probe it was
+#[derive(Debug, Clone)]
+pub struct Reply {
+    /// The name the probe was sent to
+    pub hostname: String,
+    /// The address it resolved to
+    pub address: String,
+    pub family: AddrFamily,
+    /// Round trip time, negative when dropped
+    pub latency_ms: f64,
+    /// 1 when no reply came back within the timeout
+    pub dropped: u32,
+    pub seq: i32,
+    /// TTL of the reply, -1 where the probe doesn't show the ip header
+    pub recv_ttl: i32,
+    pub recv_qos: u8,
+}
+
+/// Why a probe was answered with an ICMP error rather than a reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IcmpError {
+    /// No route to the network
+    NetUnreachable,
+    /// The last router couldn't reach the host
+    HostUnreachable,
+    /// A filter along the way refused the probe
+    Prohibited,
+    /// The probe was too big for a link and not allowed to be fragmented
+    FragNeeded,
+    /// The TTL ran out on the way, e.g. in a routing loop
+    TimeExceeded,
+    Other,
+}
 
-        Ping {
-            addr: addr.to_string(),
-            timeout: timeout,
+impl IcmpError {
+    /// The short name used in recordings
+    pub fn code(self) -> &'static str {
+        match self {
+            IcmpError::NetUnreachable => "net",
+            IcmpError::HostUnreachable => "host",
+            IcmpError::Prohibited => "prohibited",
+            IcmpError::FragNeeded => "frag",
+            IcmpError::TimeExceeded => "ttl",
+            IcmpError::Other => "other",
         }
     }
 
-    pub fn ping(&self, count: u64) -> Result<PacketChunk, PingError> {
-        let mut chunk = PacketChunk::new((self.timeout.as_secs() * 1000 + self.timeout.subsec_millis() as u64) as f64);
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "net" => Some(IcmpError::NetUnreachable),
+            "host" => Some(IcmpError::HostUnreachable),
+            "prohibited" => Some(IcmpError::Prohibited),
+            "frag" => Some(IcmpError::FragNeeded),
+            "ttl" => Some(IcmpError::TimeExceeded),
+            "other" => Some(IcmpError::Other),
+            _ => None,
+        }
+    }
+}
 
-        chunk.packets = (0..count)
-            .map(|_| self.do_ping().ok())
-            .collect();
+impl fmt::Display for IcmpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IcmpError::NetUnreachable => write!(f, "destination net unreachable"),
+            IcmpError::HostUnreachable => write!(f, "destination host unreachable"),
+            IcmpError::Prohibited => write!(f, "administratively prohibited"),
+            IcmpError::FragNeeded => write!(f, "fragmentation needed"),
+            IcmpError::TimeExceeded => write!(f, "time to live exceeded"),
+            IcmpError::Other => write!(f, "icmp error"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PingError {
+    /// The host name didn't resolve to any address
+    Resolve(String),
+    IO(io::Error),
+    /// The probe got an ICMP error back, and counts as lost
+    Icmp(IcmpError),
+}
+
+impl fmt::Display for PingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PingError::Resolve(host) => write!(f, "{}: name or service not known", host),
+            PingError::IO(e) => write!(f, "{}", e),
+            PingError::Icmp(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for PingError {}
 
-        Ok(chunk)
+impl From<io::Error> for PingError {
+    fn from(e: io::Error) -> PingError {
+        PingError::IO(e)
     }
+}
 
-    fn do_ping(&self) -> Result<PingItem, PingError> {
-        let mut ping = oping::Ping::new();
+/// A reply or a drop, or an error when the probe couldn't be sent at all
+pub type ProbeResult = Result<Reply, PingError>;
 
-        let ms = self.timeout.subsec_millis();
-        let timeout = self.timeout.as_secs() as f64 + (ms as f64 / 1000_f64);
+/// One kind of probe to a fixed target, such as an ICMP echo
+///
+/// Chunks are built on top of it by `burst`, so a new kind of probe shows
+/// up everywhere chunks do without further changes.
+pub trait Probe: Send + Sync {
+    /// Send one probe and wait up to `timeout` for its reply
+    fn probe(&self) -> ProbeResult;
 
-        ping.set_timeout(timeout)?;
-        ping.add_host(self.addr.as_str())?;
+    /// Replies slower than this count as lost
+    fn timeout(&self) -> Duration;
 
-        Ok(ping.send()?.next().unwrap())
+    /// An error when no probe can be sent at all, e.g. for lack of permission
+    fn ready(&self) -> Result<(), PingError> {
+        Ok(())
     }
 }
 
+/// `count` probes, each starting `spacing` after the previous one
+#[cfg(feature = "probe")]
+pub fn burst<P: Probe + ?Sized>(probe: &P, count: u64, spacing: Duration) -> PacketChunk {
+    let timeout = probe.timeout();
+    let mut chunk = PacketChunk::new((timeout.as_secs() * 1000 + timeout.subsec_millis() as u64) as f64);
+    let origin = Instant::now();
+
+    for _ in 0..count {
+        let start = Instant::now();
+
+        match probe.probe() {
+            /* the reply is timestamped on return, the send is that minus the rtt */
+            Ok(reply) => {
+                let send = ms(origin.elapsed()) - reply.latency_ms;
+                chunk.push(Some(reply));
+                chunk.stamp(send);
+            },
+            /* an answer all the same, the next probe may get a reply */
+            Err(PingError::Icmp(error)) => {
+                chunk.push(Some(reply(-1.0, 1)));
+                chunk.stamp(ms(start.duration_since(origin)));
+                chunk.icmp.push(error);
+            },
+            Err(ref e) if unreachable(e) => {
+                /* the rest would fail the same way, after waiting on the resolver */
+                while (chunk.sent() as u64) < count {
+                    chunk.push(None);
+                }
+                /* the chunk is shown with its target, the name needn't be said twice */
+                chunk.error = Some(match e {
+                    PingError::Resolve(_) => String::from("name or service not known"),
+                    e => e.to_string(),
+                });
+                break;
+            },
+            Err(_) => {
+                chunk.push(None);
+                chunk.stamp(ms(start.duration_since(origin)));
+            },
+        }
+
+        if let Some(rest) = spacing.checked_sub(start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+
+    chunk
+}
+
+/* text with no space or `|` in it, for the error field of `encode` */
+fn escape(s: &str) -> String {
+    s.replace('%', "%25").replace(' ', "%20").replace('|', "%7C").replace('\n', "%0A")
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut rest = s;
+
+    while let Some(i) = rest.find('%') {
+        out.push_str(&rest[..i]);
+        let code = u8::from_str_radix(rest.get(i + 1..i + 3)?, 16).ok()?;
+        out.push(code as char);
+        rest = &rest[i + 3..];
+    }
+    out.push_str(rest);
+
+    Some(out)
+}
+
+/// A duration in fractional milliseconds
+pub fn ms(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
+
+/* errors meaning the local network can't reach anything right now */
+#[cfg(feature = "probe")]
+fn unreachable(e: &PingError) -> bool {
+    match e {
+        PingError::Resolve(_) => true,
+        PingError::IO(e) => matches!(e.kind(), io::ErrorKind::NetworkUnreachable | io::ErrorKind::HostUnreachable),
+        PingError::Icmp(_) => false,
+    }
+}
+
+/* long chunks are drawn a minute of probes at a time, see `PacketChunk::minutes` */
+const MINUTE: f64 = 60_000.0;
+
+/// The schedule a chunk was probed on, as it stood when the chunk was taken
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Params {
+    /* seconds from the end of one chunk to the start of the next */
+    pub interval: f64,
+    pub chunk_size: u64,
+    /* ms between the probes of a chunk */
+    pub spacing: f64,
+}
+
+/// The replies to one burst of probes, and what was measured alongside it
 #[derive(Clone)]
 pub struct PacketChunk {
-    packets: Vec<Option<PingItem>>,
+    pub(crate) packets: Vec<Option<Reply>>,
     time: DateTime<Local>,
-    timeout: f64,
+    pub(crate) timeout: f64,
     tint: (u8,u8,u8),
     tint_weight: f64,
+    /* high resolution burst taken right after this chunk looked wrong */
+    capture: Option<Box<PacketChunk>>,
+    /* soft timeout in ms, replies slower than this count as slow rather than lost */
+    slow: Option<f64>,
+    /* monotonic send time of each packet in ms from the start of the chunk, if known */
+    stamps: Vec<Option<f64>>,
+    /* packets lost on the way out and on the way back, from a reflector */
+    oneway: Option<(usize, usize)>,
+    /* Mbit/s measured by a throughput test running during this chunk */
+    throughput: Option<f64>,
+    /* tcp segments (retransmitted, sent) by the host during this chunk */
+    retrans: Option<(u64, u64)>,
+    /* fired from the keyboard rather than on schedule */
+    manual: bool,
+    /* user markers placed after this chunk, as (time, label) */
+    markers: Vec<(DateTime<Local>, String)>,
+    /* the icmp errors that came back in place of replies, one per probe */
+    icmp: Vec<IcmpError>,
+    /* why the burst failed as a whole, e.g. a name that didn't resolve */
+    error: Option<String>,
+    /* the schedule it was taken on, unknown for chunks recorded before it was kept */
+    params: Option<Params>,
+    /* cut by minute the first time it is drawn, and again after a change */
+    minutes: OnceLock<Vec<PacketChunk>>,
 }
 
 impl PacketChunk {
+    /// An empty chunk stamped with the current time, `timeout` in ms
     pub fn new(timeout: f64) -> Self {
         PacketChunk {
             packets: vec![],
             time: Local::now(),
-            timeout: timeout,
+            timeout,
             tint: (0, 0, 0),
             tint_weight: 0.0,
+            capture: None,
+            slow: None,
+            stamps: vec![],
+            oneway: None,
+            throughput: None,
+            retrans: None,
+            manual: false,
+            markers: vec![],
+            icmp: vec![],
+            error: None,
+            params: None,
+            minutes: OnceLock::new(),
+        }
+    }
+
+    /// A burst of `count` probes that all failed for `error`, `timeout` in ms
+    pub fn failed(timeout: f64, count: u64, error: &str) -> Self {
+        let mut chunk = PacketChunk::new(timeout);
+
+        for _ in 0..count {
+            chunk.push(None);
+        }
+        chunk.error = Some(error.to_string());
+
+        chunk
+    }
+
+    /* record one probe, None when it failed outright */
+    pub fn push(&mut self, packet: Option<Reply>) {
+        self.packets.push(packet);
+        self.stamps.push(None);
+        self.minutes = OnceLock::new();
+    }
+
+    /* send time of the last pushed packet */
+    pub fn stamp(&mut self, send: f64) {
+        if let Some(last) = self.stamps.last_mut() {
+            *last = Some(send);
+        }
+        self.minutes = OnceLock::new();
+    }
+
+    /// Interarrival jitter in ms as in RFC 3550, over the received packets
+    pub fn jitter(&self) -> Option<f64> {
+        let mut jitter = 0.0;
+        let mut last: Option<(f64, f64)> = None;
+        let mut pairs = 0;
+
+        for (packet, stamp) in self.packets.iter().zip(self.stamps.iter()) {
+            let (packet, send) = match (packet, stamp) {
+                (Some(ref packet), Some(send)) if packet.dropped == 0 => (packet, *send),
+                _ => continue,
+            };
+
+            let receive = send + packet.latency_ms;
+
+            if let Some((s, r)) = last {
+                let d = ((receive - r) - (send - s)).abs();
+                jitter += (d - jitter) / 16.0;
+                pairs += 1;
+            }
+
+            last = Some((send, receive));
+        }
+
+        if pairs == 0 {
+            None
+        } else {
+            Some(jitter)
+        }
+    }
+
+    pub fn attach(&mut self, capture: PacketChunk) {
+        self.capture = Some(Box::new(capture));
+    }
+
+    pub fn slow_after(&mut self, ms: f64) {
+        self.slow = Some(ms);
+        self.minutes = OnceLock::new();
+    }
+
+    /// Replies that made it, but later than the soft timeout
+    pub fn slow(&self) -> usize {
+        let threshold = match self.slow {
+            Some(threshold) => threshold,
+            None => return 0,
+        };
+
+        self.packets.iter()
+            .filter_map(|x| x.as_ref())
+            .filter(|x| x.dropped == 0 && x.latency_ms > threshold)
+            .count()
+    }
+
+    pub fn set_oneway(&mut self, up: usize, down: usize) {
+        self.oneway = Some((up, down));
+    }
+
+    /// Losses split into (outbound, return), when measured against a reflector
+    pub fn oneway(&self) -> Option<(usize, usize)> {
+        self.oneway
+    }
+
+    pub fn annotate(&mut self, mbps: f64) {
+        self.throughput = Some(mbps);
+    }
+
+    pub fn throughput(&self) -> Option<f64> {
+        self.throughput
+    }
+
+    pub fn set_retrans(&mut self, retransmitted: u64, sent: u64) {
+        self.retrans = Some((retransmitted, sent));
+    }
+
+    /// Share of tcp segments retransmitted during this chunk
+    pub fn retrans_rate(&self) -> Option<f64> {
+        match self.retrans {
+            Some((_, 0)) | None => None,
+            Some((retransmitted, sent)) => Some(retransmitted as f64 / sent as f64),
+        }
+    }
+
+    /// Probes that were lost or slower than the soft timeout
+    pub fn late(&self) -> usize {
+        self.slow() + self.sent() - self.received()
+    }
+
+    pub fn capture(&self) -> Option<&PacketChunk> {
+        self.capture.as_deref()
+    }
+
+    /// The ICMP errors probes got back, one per probe
+    pub fn icmp_errors(&self) -> &[IcmpError] {
+        &self.icmp
+    }
+
+    /// The most common ICMP error and how many probes got it, the first
+    /// seen of those tied
+    pub fn dominant_error(&self) -> Option<(IcmpError, usize)> {
+        let mut best: Option<(IcmpError, usize)> = None;
+
+        for &error in self.icmp.iter() {
+            let count = self.icmp.iter().filter(|x| **x == error).count();
+            if best.is_none_or(|x| count > x.1) {
+                best = Some((error, count));
+            }
+        }
+
+        best
+    }
+
+    /// Why the burst failed as a whole, if it did
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Stamp the chunk with the schedule it was taken on
+    pub fn set_params(&mut self, params: Params) {
+        self.params = Some(params);
+        self.minutes = OnceLock::new();
+    }
+
+    pub fn params(&self) -> Option<Params> {
+        self.params
+    }
+
+    /// The timeout in ms the chunk was probed with
+    pub fn timeout(&self) -> f64 {
+        self.timeout
+    }
+
+    /*
+     * one-line text form: time, timeout, then one field per packet -
+     * `-` failed, `d` dropped, otherwise the latency in ms - each followed
+     * by `@send` when the send time is known. one-way losses are given
+     * as `^up,down`, throughput as `~mbps`, tcp retransmits as
+     * `r<retransmitted>/<sent>`, icmp errors as `!<kind>:<count>` per kind,
+     * a manual chunk is marked with `m`, the schedule it was taken on is
+     * `p<interval s>/<chunk size>/<spacing ms>`, the error of a failed
+     * burst is `?<error>` with spaces, `%` and `|` escaped as in urls, and
+     * a capture follows after a `|` in the same form.
+     */
+    pub fn encode(&self) -> String {
+        let mut line = format!("{} {}", self.time.to_rfc3339(), self.timeout);
+
+        for (packet, stamp) in self.packets.iter().zip(self.stamps.iter()) {
+            match packet {
+                None => line.push_str(" -"),
+                Some(ref packet) if packet.dropped != 0 => line.push_str(" d"),
+                Some(ref packet) => {
+                    let _ = write!(line, " {}", packet.latency_ms);
+                },
+            }
+
+            if let Some(send) = stamp {
+                let _ = write!(line, "@{:.3}", send);
+            }
+        }
+
+        if let Some((up, down)) = self.oneway {
+            let _ = write!(line, " ^{},{}", up, down);
+        }
+
+        if let Some(mbps) = self.throughput {
+            let _ = write!(line, " ~{}", mbps);
+        }
+
+        if let Some((retransmitted, sent)) = self.retrans {
+            let _ = write!(line, " r{}/{}", retransmitted, sent);
+        }
+
+        let mut kinds = self.icmp.clone();
+        kinds.sort();
+        kinds.dedup();
+        for kind in kinds {
+            let _ = write!(line, " !{}:{}", kind.code(), self.icmp.iter().filter(|x| **x == kind).count());
+        }
+
+        if self.manual {
+            line.push_str(" m");
+        }
+
+        if let Some(params) = self.params {
+            let _ = write!(line, " p{}/{}/{}", params.interval, params.chunk_size, params.spacing);
+        }
+
+        if let Some(ref error) = self.error {
+            let _ = write!(line, " ?{}", escape(error));
+        }
+
+        if let Some(ref capture) = self.capture {
+            let _ = write!(line, " | {}", capture.encode());
+        }
+
+        line
+    }
+
+    /// Parse the form written by `encode`
+    pub fn decode(line: &str) -> Option<PacketChunk> {
+        let mut parts = line.splitn(2, " | ");
+        let line = parts.next()?;
+        let capture = match parts.next() {
+            Some(capture) => Some(PacketChunk::decode(capture)?),
+            None => None,
+        };
+
+        let mut fields = line.split_whitespace();
+
+        let time = DateTime::parse_from_rfc3339(fields.next()?).ok()?;
+        let timeout = fields.next()?.parse::<f64>().ok()?;
+
+        let mut chunk = PacketChunk::new(timeout);
+        chunk.time = time.with_timezone(&Local);
+
+        for field in fields {
+            if let Some(rest) = field.strip_prefix('^') {
+                let mut counts = rest.splitn(2, ',').map(|x| x.parse::<usize>().ok());
+                chunk.oneway = Some((counts.next()??, counts.next()??));
+                continue;
+            }
+
+            if let Some(rest) = field.strip_prefix('~') {
+                chunk.throughput = Some(rest.parse::<f64>().ok()?);
+                continue;
+            }
+
+            if field == "m" {
+                chunk.manual = true;
+                continue;
+            }
+
+            if let Some(rest) = field.strip_prefix('p') {
+                let mut parts = rest.splitn(3, '/');
+                chunk.params = Some(Params {
+                    interval: parts.next()?.parse::<f64>().ok()?,
+                    chunk_size: parts.next()?.parse::<u64>().ok()?,
+                    spacing: parts.next()?.parse::<f64>().ok()?,
+                });
+                continue;
+            }
+
+            if let Some(rest) = field.strip_prefix('?') {
+                chunk.error = Some(unescape(rest)?);
+                continue;
+            }
+
+            if let Some(rest) = field.strip_prefix('!') {
+                let mut parts = rest.splitn(2, ':');
+                let kind = IcmpError::parse(parts.next()?)?;
+                let count = parts.next()?.parse::<usize>().ok()?;
+                chunk.icmp.extend(std::iter::repeat_n(kind, count));
+                continue;
+            }
+
+            if let Some(rest) = field.strip_prefix('r') {
+                let mut counts = rest.splitn(2, '/').map(|x| x.parse::<u64>().ok());
+                chunk.retrans = Some((counts.next()??, counts.next()??));
+                continue;
+            }
+
+            let mut field = field.splitn(2, '@');
+
+            let packet = match field.next()? {
+                "-" => None,
+                "d" => Some(reply(timeout, 1)),
+                x => Some(reply(x.parse::<f64>().ok()?, 0)),
+            };
+            chunk.push(packet);
+
+            if let Some(send) = field.next() {
+                chunk.stamp(send.parse::<f64>().ok()?);
+            }
         }
+
+        chunk.capture = capture.map(Box::new);
+
+        Some(chunk)
+    }
+
+    /*
+     * a chunk of hundreds of probes spread over many minutes averages a
+     * bad minute into the rest, so it is drawn cut into the probes sent in
+     * each minute from its start, by their send times or else the spacing
+     * it was taken with. nothing when it spans under two minutes, or when
+     * neither is known. the chunk itself stays whole everywhere else.
+     */
+    pub fn minutes(&self) -> &[PacketChunk] {
+        self.minutes.get_or_init(|| {
+            let spacing = self.params.map(|x| x.spacing).filter(|x| *x > 0.0);
+            let mut minutes: Vec<PacketChunk> = vec![];
+
+            for (i, (packet, stamp)) in self.packets.iter().zip(self.stamps.iter()).enumerate() {
+                let send = match stamp.or_else(|| spacing.map(|x| i as f64 * x)) {
+                    Some(send) => send,
+                    None => return vec![],
+                };
+
+                let minute = (send / MINUTE) as usize;
+                while minutes.len() <= minute {
+                    let mut chunk = PacketChunk::new(self.timeout);
+                    chunk.time = self.time + chrono::Duration::minutes(minutes.len() as i64);
+                    chunk.slow = self.slow;
+                    minutes.push(chunk);
+                }
+
+                /* the host details are the chunk's, a minute only needs the figures */
+                minutes[minute].push(packet.as_ref().map(|x| reply(x.latency_ms, x.dropped)));
+            }
+
+            minutes.retain(|x| x.sent() > 0);
+            if minutes.len() < 2 {
+                minutes.clear();
+            }
+            minutes
+        })
+    }
+
+    /// The color of each of `minutes`, as `color` gives the whole chunk's
+    pub fn minute_colors(&self, range: (f64, f64), pinned: bool, mode: ColorMode, scale: Scale) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+        let sent = self.sent().max(1) as f64;
+
+        /* `range` is for the whole chunk's latency, a minute gets its share */
+        self.minutes().iter().map(move |x| {
+            let share = x.sent() as f64 / sent;
+            mix_colors(self.tint_weight, self.tint, x.color((range.0 * share, range.1 * share), pinned, mode, scale))
+        })
+    }
+
+    /// When the chunk was started
+    pub fn time(&self) -> DateTime<Local> {
+        self.time
+    }
+
+    /// Stamp the chunk with when it started, for chunks taken elsewhere
+    pub fn set_time(&mut self, time: DateTime<Local>) {
+        self.time = time;
+        self.minutes = OnceLock::new();
+    }
+
+    /// Mark the chunk as fired on request, out of schedule
+    pub fn mark_manual(&mut self) {
+        self.manual = true;
+    }
+
+    pub fn manual(&self) -> bool {
+        self.manual
+    }
+
+    /// Place a marker after this chunk, the label may be empty
+    pub fn mark(&mut self, time: DateTime<Local>, label: &str) {
+        self.markers.push((time, label.to_string()));
+    }
+
+    pub fn markers(&self) -> &[(DateTime<Local>, String)] {
+        &self.markers
     }
 
+    /* approximate heap + inline bytes held by this chunk */
+    pub fn memory(&self) -> usize {
+        let strings: usize = self.packets.iter()
+            .filter_map(|x| x.as_ref())
+            .map(|x| x.hostname.capacity() + x.address.capacity())
+            .sum();
+
+        size_of::<PacketChunk>()
+            + self.packets.capacity() * size_of::<Option<Reply>>()
+            + self.stamps.capacity() * size_of::<Option<f64>>()
+            + strings
+            + self.markers.iter().map(|x| size_of::<(DateTime<Local>, String)>() + x.1.capacity()).sum::<usize>()
+            + self.icmp.capacity() * size_of::<IcmpError>()
+            + self.error.as_ref().map(|x| x.capacity()).unwrap_or(0)
+            + self.capture.as_ref().map(|x| x.memory()).unwrap_or(0)
+            + self.minutes.get().map(|x| x.iter().map(|x| x.memory()).sum()).unwrap_or(0)
+    }
+
+    /* expected memory of a chunk of `count` pings to `addr`, before it exists */
+    pub fn estimate(addr: &str, count: u64) -> usize {
+        /* each reply keeps both the name and the resolved address */
+        let strings = addr.len() + 46;
+
+        size_of::<PacketChunk>()
+            + count as usize * (size_of::<Option<Reply>>() + size_of::<Option<f64>>() + strings)
+    }
+
+    /// Probes sent, including those that failed outright
     pub fn sent(&self) -> usize {
         self.packets.len()
     }
@@ -74,9 +713,10 @@ impl PacketChunk {
         self.packets.iter()
             .filter(|x| x.is_some())
             .filter(|x| x.as_ref().unwrap().dropped == 0)
-            .collect::<Vec<_>>().len()
+            .count()
     }
 
+    /// Share of probes without a reply, 0 to 1
     pub fn loss(&self) -> f64 {
         let sent = self.sent();
         if sent == 0 {
@@ -86,6 +726,60 @@ impl PacketChunk {
         }
     }
 
+    /// The most probes lost one after another
+    pub fn longest_run(&self) -> usize {
+        let mut longest = 0;
+        let mut run = 0;
+
+        for packet in self.packets.iter() {
+            if packet.as_ref().is_some_and(|x| x.dropped == 0) {
+                run = 0;
+            } else {
+                run += 1;
+                longest = longest.max(run);
+            }
+        }
+
+        longest
+    }
+
+    /*
+     * the burst ratio of RFC 3611, after the Gilbert-Elliott loss model:
+     * the mean length of the runs of lost probes over the mean a loss rate
+     * as high would give if the probes were lost independently, 1 / (1 - p).
+     * 1 is loss at random, above 1 it comes bunched, below 1 spread out.
+     * none without loss, or without a reply to measure it against
+     */
+    pub fn burst_ratio(&self) -> Option<f64> {
+        let lost = self.sent() - self.received();
+        if lost == 0 || self.received() == 0 {
+            return None;
+        }
+
+        /* a run starts at every lost probe that follows a received one, or starts the chunk */
+        let mut runs = 0;
+        let mut previous = true;
+        for packet in self.packets.iter() {
+            let received = packet.as_ref().is_some_and(|x| x.dropped == 0);
+            if !received && previous {
+                runs += 1;
+            }
+            previous = received;
+        }
+
+        Some(lost as f64 / runs as f64 * (1.0 - self.loss()))
+    }
+
+    /// Loss scaled by the burst ratio, so that drops one after another
+    /// weigh more than as many scattered ones, 0 to 1
+    pub fn weighted_loss(&self) -> f64 {
+        if self.down() {
+            return 1.0;
+        }
+
+        (self.loss() * self.burst_ratio().unwrap_or(1.0)).min(1.0)
+    }
+
     pub fn tint(&mut self, color: (u8,u8,u8)) {
         self.tint = color;
     }
@@ -100,6 +794,23 @@ impl PacketChunk {
         }
     }
 
+    /// Median latency of the replies in ms, None when nothing came back
+    pub fn median_rtt(&self) -> Option<f64> {
+        let mut received: Vec<f64> = self.packets.iter()
+            .filter_map(|x| x.as_ref())
+            .filter(|x| x.dropped == 0)
+            .map(|x| x.latency_ms)
+            .collect();
+
+        if received.is_empty() {
+            return None;
+        }
+
+        received.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        Some(received[received.len() / 2])
+    }
+
+    /// Sum of each probe's latency in ms, lost probes counting as the timeout
     pub fn latency(&self) -> f64 {
 
         let mut acc = 0.0;
@@ -121,27 +832,271 @@ impl PacketChunk {
         acc
     }
 
-    pub fn color(&self, min: f64) -> (u8, u8, u8) {
+    /*
+     * R-factor from the simplified ITU-T G.107 E-model, taking the mean rtt
+     * of the replies, twice the jitter and 10ms of codec delay as the
+     * effective latency, and 2.5 points per percent of loss
+     */
+    pub fn r_factor(&self) -> f64 {
+        let received: Vec<f64> = self.packets.iter()
+            .filter_map(|x| x.as_ref())
+            .filter(|x| x.dropped == 0)
+            .map(|x| x.latency_ms)
+            .collect();
+
+        if received.is_empty() {
+            return 0.0;
+        }
 
-        let loss = self.loss();
-        let mut lat = min / self.latency();
+        let latency = received.iter().sum::<f64>() / received.len() as f64;
+        let effective = latency + 2.0 * self.jitter().unwrap_or(0.0) + 10.0;
 
-        if lat > 1.0 {
-            lat = 1.0;
+        let r = if effective < 160.0 {
+            93.2 - effective / 40.0
+        } else {
+            93.2 - (effective - 120.0) / 10.0
+        };
+
+        (r - 2.5 * self.loss() * 100.0).clamp(0.0, 100.0)
+    }
+
+    /// Estimated mean opinion score, 1 (unusable) to 4.5 (excellent)
+    pub fn mos(&self) -> f64 {
+        let r = self.r_factor();
+        1.0 + 0.035 * r + 0.000007 * r * (r - 60.0) * (100.0 - r)
+    }
+
+    /* nothing at all came back */
+    pub fn down(&self) -> bool {
+        self.sent() > 0 && self.received() == 0
+    }
+
+    /* `range` is the chunk latency drawn fully green and the one drawn fully red, `pinned` if --latency-scale gave it */
+    pub fn color(&self, range: (f64, f64), pinned: bool, mode: ColorMode, scale: Scale) -> (u8, u8, u8) {
+        let palette = palette();
+
+        /* darker than anything the blend produces, so slow never looks dead */
+        if self.down() {
+            return mix_colors(self.tint_weight, self.tint, palette.down);
         }
 
+        let red = palette.bad;
+        let green = palette.good;
+
+        if let ColorMode::Game { .. } = mode {
+            let mix = 1.0 - self.late() as f64 / self.sent().max(1) as f64;
+            return mix_colors(self.tint_weight, self.tint, mix_colors(mix, green, red));
+        }
+
+        if mode == ColorMode::Mos {
+            /* 4.3 and up is as good as a call gets, 2.6 and below is unusable */
+            let mix = ((self.mos() - 2.6) / (4.3 - 2.6)).clamp(0.0, 1.0);
+            return mix_colors(self.tint_weight, self.tint, mix_colors(mix, green, red));
+        }
+
+        let loss = if mode == ColorMode::Burst { self.weighted_loss() } else { self.loss() };
+        let lat = scale.position(self.latency(), range.0, range.1, pinned);
+
         /* 100% = green */
         let mix = (1.0 - loss)*lat;
 
-        let red: (u8, u8, u8) = (224, 15, 71);
-        let green: (u8, u8, u8) = (14, 204, 80);
+        let mut color = mix_colors(mix, green, red);
+
+        /* slow replies pull toward amber, apart from the loss/latency blend */
+        let slow = self.slow();
+        if slow > 0 {
+            let weight = 0.3 + 0.5 * slow as f64 / self.sent() as f64;
+            color = mix_colors(weight, palette.slow, color);
+        }
 
-        let color = mix_colors(mix, green, red);
         mix_colors(self.tint_weight, self.tint, color)
     }
+
+    /* loss alone, for views that show latency some other way */
+    pub fn loss_color(&self) -> (u8, u8, u8) {
+        self.share_color(self.loss())
+    }
+
+    /* the same, by the loss weighted for bursts */
+    pub fn burst_color(&self) -> (u8, u8, u8) {
+        self.share_color(self.weighted_loss())
+    }
+
+    fn share_color(&self, loss: f64) -> (u8, u8, u8) {
+        let palette = palette();
+
+        if self.down() {
+            return mix_colors(self.tint_weight, self.tint, palette.down);
+        }
+
+        mix_colors(self.tint_weight, self.tint, mix_colors(1.0 - loss, palette.good, palette.bad))
+    }
+}
+
+/// What a tile's color is derived from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    Loss,
+    /* loss weighted by how bunched it is, see `PacketChunk::weighted_loss` */
+    Burst,
+    Mos,
+    /* probes sent at `hz`, anything lost or slow counts as a late tick */
+    Game { hz: f64 },
+}
+
+/// Which end of the history the tile flow starts from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Order {
+    NewestFirst,
+    /* a timeline, the newest tile last */
+    OldestFirst,
+}
+
+impl Order {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "newest-first" => Some(Order::NewestFirst),
+            "oldest-first" => Some(Order::OldestFirst),
+            _ => None,
+        }
+    }
+}
+
+/// How latency maps onto the green to red blend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scale {
+    Linear,
+    /* equal steps for equal ratios, so 5ms to 50ms spans as much as 50ms to 500ms */
+    Log,
+}
+
+impl Scale {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "linear" => Some(Scale::Linear),
+            "log" => Some(Scale::Log),
+            _ => None,
+        }
+    }
+
+    /*
+     * 1 at or below the minimum, falling to 0 as latency reaches the ceiling.
+     * unless the range is pinned a linear scale ignores the ceiling and is
+     * just min/latency, as it always was
+     */
+    pub fn position(self, latency: f64, min: f64, ceiling: f64, pinned: bool) -> f64 {
+        match self {
+            Scale::Linear if !pinned => (min / latency).min(1.0),
+            Scale::Linear => {
+                let floor = if ceiling > min { min / ceiling } else { 0.0 };
+                (((min / latency).min(1.0) - floor) / (1.0 - floor)).max(0.0)
+            },
+            Scale::Log => {
+                if latency <= min || min <= 0.0 {
+                    1.0
+                } else if ceiling <= min {
+                    0.0
+                } else {
+                    (1.0 - (latency / min).ln() / (ceiling / min).ln()).max(0.0)
+                }
+            },
+        }
+    }
+}
+
+/* "MIN:MAX" in milliseconds, e.g. 10:200 */
+pub fn parse_range(s: &str) -> Option<(f64, f64)> {
+    let mut parts = s.splitn(2, ':');
+    let min = parts.next()?.parse::<f64>().ok()?;
+    let max = parts.next()?.parse::<f64>().ok()?;
+
+    if min > 0.0 && max > min {
+        Some((min, max))
+    } else {
+        None
+    }
+}
+
+/// The colors tiles are blended from, see `set_palette`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    /// No loss at the lowest latency
+    pub good: (u8, u8, u8),
+    /// All lost, or at the top of the latency scale
+    pub bad: (u8, u8, u8),
+    /// Nothing came back at all
+    pub down: (u8, u8, u8),
+    /// Replies past the soft timeout pull toward this
+    pub slow: (u8, u8, u8),
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            good: (14, 204, 80),
+            bad: (224, 15, 71),
+            down: (96, 0, 24),
+            slow: (240, 150, 10),
+        }
+    }
 }
 
-fn mix_colors(mix: f64, a: (u8, u8, u8), b: (u8, u8, u8)) -> (u8, u8, u8) {
+/* good, bad, down and slow as 0xrrggbb, like the language in `locale` */
+static PALETTE: [AtomicU32; 4] = [
+    AtomicU32::new(0x0ecc50),
+    AtomicU32::new(0xe00f47),
+    AtomicU32::new(0x600018),
+    AtomicU32::new(0xf0960a),
+];
+
+/// Switch the colors of everything drawn from now on
+pub fn set_palette(palette: Palette) {
+    let colors = [palette.good, palette.bad, palette.down, palette.slow];
+
+    for (slot, (r, g, b)) in PALETTE.iter().zip(colors.iter()) {
+        slot.store((*r as u32) << 16 | (*g as u32) << 8 | *b as u32, Ordering::Relaxed);
+    }
+}
+
+pub fn palette() -> Palette {
+    let color = |i: usize| {
+        let x = PALETTE[i].load(Ordering::Relaxed);
+        ((x >> 16) as u8, (x >> 8) as u8, x as u8)
+    };
+
+    Palette {
+        good: color(0),
+        bad: color(1),
+        down: color(2),
+        slow: color(3),
+    }
+}
+
+/* "#rrggbb" */
+pub fn parse_rgb(s: &str) -> Option<(u8, u8, u8)> {
+    if !s.starts_with('#') || s.len() != 7 {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(s.get(i..i + 2)?, 16).ok();
+    Some((channel(1)?, channel(3)?, channel(5)?))
+}
+
+/* a reply without host details, as reflectors and decoded chunks give */
+pub fn reply(latency: f64, dropped: u32) -> Reply {
+    Reply {
+        hostname: String::new(),
+        address: String::new(),
+        family: AddrFamily::IPV4,
+        latency_ms: latency,
+        dropped,
+        seq: 0,
+        recv_ttl: 0,
+        recv_qos: 0,
+    }
+}
+
+pub(crate) fn mix_colors(mix: f64, a: (u8, u8, u8), b: (u8, u8, u8)) -> (u8, u8, u8) {
     if mix == 0.0 {
         b
     } else if mix == 1.0 {
@@ -155,52 +1110,139 @@ fn mix_colors(mix: f64, a: (u8, u8, u8), b: (u8, u8, u8)) -> (u8, u8, u8) {
     }
 }
 
-/* seperate struct for drawing - need min response time dynamically */
-pub struct DrawablePacket<'a> {
-    packet: &'a PacketChunk,
-    min_latency: f64,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl<'a> DrawablePacket<'a> {
-    pub fn new(packet: &'a PacketChunk, min: f64) -> Self {
-        DrawablePacket {
-            packet: packet,
-            min_latency: min,
+    /* a chunk of 10ms replies, one probe per character: '.' came back, 'x' was lost */
+    fn chunk(probes: &str) -> PacketChunk {
+        let mut chunk = PacketChunk::new(1000.0);
+        for probe in probes.chars() {
+            chunk.push(if probe == '.' { Some(reply(10.0, 0)) } else { None });
         }
+        chunk
     }
-}
 
-impl<'a> Widget for DrawablePacket<'a> {
-    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
-        let (r,g,b) = self.packet.color(self.min_latency);
-        let color = Color::Rgb(r,g,b);
+    #[test]
+    fn longest_run() {
+        assert_eq!(chunk("").longest_run(), 0);
+        assert_eq!(chunk("....").longest_run(), 0);
+        assert_eq!(chunk(".xx.x..xxx.").longest_run(), 3);
+        assert_eq!(chunk("xxxx").longest_run(), 4);
 
-        if area.width == 0 || area.height == 0 {
-            return;
+        /* a reply with the dropped flag is as lost as no reply */
+        let mut dropped = chunk(".x");
+        dropped.push(Some(reply(1000.0, 1)));
+        assert_eq!(dropped.longest_run(), 2);
+    }
+
+    #[test]
+    fn burst_ratio() {
+        /* nothing lost, or nothing to measure it against */
+        assert_eq!(chunk("....").burst_ratio(), None);
+        assert_eq!(chunk("xxxx").burst_ratio(), None);
+
+        /* 3 lost in 2 runs, at 30% loss: 1.5 / (1 / 0.7) */
+        let ratio = chunk(".xx..x....").burst_ratio().unwrap();
+        assert!((ratio - 1.05).abs() < 1e-9);
+
+        /* the same losses bunched together, and spread out */
+        assert!(chunk("xxx.......").burst_ratio().unwrap() > chunk("x..x..x...").burst_ratio().unwrap());
+        assert!(chunk("x..x..x...").burst_ratio().unwrap() < 1.0);
+    }
+
+    #[test]
+    fn jitter() {
+        /* sent 10ms apart, back in 10, 14 then 10ms */
+        let mut chunk = PacketChunk::new(1000.0);
+        for (send, latency) in [(0.0, 10.0), (10.0, 14.0), (20.0, 10.0)] {
+            chunk.push(Some(reply(latency, 0)));
+            chunk.stamp(send);
         }
 
-        self.background(&area, buf, color);
+        /* each pair is 4ms off, and moves the estimate a sixteenth of the way there */
+        let first = 4.0 / 16.0;
+        let second = first + (4.0 - first) / 16.0;
+        assert!((chunk.jitter().unwrap() - second).abs() < 1e-9);
 
-        let pct = (self.packet.loss()*100f64) as u32;
+        /* a lost probe between them is skipped, not counted as a pair */
+        chunk.push(None);
+        chunk.stamp(30.0);
+        assert!((chunk.jitter().unwrap() - second).abs() < 1e-9);
+    }
 
-        let time = self.packet.time.format("%b %d %H:%M:%S");
-        let long = format!(" {}: {}% packet loss, time {:.01}ms ", time, pct, self.packet.latency());
-        let short = format!(" {}% [{:.0}ms] ", pct, self.packet.latency());
+    #[test]
+    fn no_jitter() {
+        /* without send times, or a second reply to compare with */
+        assert_eq!(chunk("...").jitter(), None);
 
-        let info = if area.width >= long.len() as u16 {
-            long
-        } else if area.width >= short.len() as u16 {
-            short
-        } else {
-            return;
-        };
+        let mut single = chunk(".");
+        single.stamp(0.0);
+        assert_eq!(single.jitter(), None);
+    }
 
-        let x = area.x + (area.width / 2).saturating_sub(info.len() as u16 / 2);
-        let y = area.y + (area.height / 2);
+    #[test]
+    fn r_factor() {
+        /* 10ms replies and 10ms of codec delay, no jitter without send times */
+        assert!((chunk("..........").r_factor() - (93.2 - 20.0 / 40.0)).abs() < 1e-9);
 
-        let style = tui::style::Style::default()
-            .bg(color);
+        /* 2.5 points per percent lost */
+        assert!((chunk(".........x").r_factor() - (93.2 - 20.0 / 40.0 - 25.0)).abs() < 1e-9);
+
+        /* past 160ms of delay it falls faster, and never below 0 */
+        let mut slow = PacketChunk::new(1000.0);
+        slow.push(Some(reply(250.0, 0)));
+        assert!((slow.r_factor() - (93.2 - (260.0 - 120.0) / 10.0)).abs() < 1e-9);
+        assert_eq!(chunk(".xxxxxxxxx").r_factor(), 0.0);
+
+        assert_eq!(chunk("").r_factor(), 0.0);
+        assert_eq!(chunk("xxx").r_factor(), 0.0);
+    }
+
+    #[test]
+    fn mos() {
+        /* nothing back is unusable, a clean line is close to the 4.5 a call gets at best */
+        assert!((chunk("xxx").mos() - 1.0).abs() < 1e-9);
+        assert!((chunk("..........").mos() - 4.3994).abs() < 1e-4);
+        assert!((chunk(".........x").mos() - 3.4874).abs() < 1e-4);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut chunk = chunk(".x");
+        chunk.stamp(12.5);
+        chunk.push(Some(reply(1000.0, 1)));
+        chunk.set_oneway(1, 0);
+        chunk.annotate(94.5);
+        chunk.set_retrans(3, 120);
+        chunk.icmp = vec![IcmpError::HostUnreachable, IcmpError::Prohibited, IcmpError::HostUnreachable];
+        chunk.mark_manual();
+        chunk.set_params(Params { interval: 1.0, chunk_size: 3, spacing: 0.0 });
+        chunk.error = Some("no route | 100% lost".to_string());
+        chunk.attach(self::chunk("x."));
+
+        let line = chunk.encode();
+        let decoded = PacketChunk::decode(&line).unwrap();
+
+        assert_eq!(decoded.encode(), line);
+        assert_eq!(decoded.time(), chunk.time());
+        assert_eq!(decoded.sent(), 3);
+        assert_eq!(decoded.received(), 1);
+        assert_eq!(decoded.oneway(), Some((1, 0)));
+        assert_eq!(decoded.throughput(), Some(94.5));
+        assert_eq!(decoded.retrans_rate(), Some(3.0 / 120.0));
+        assert_eq!(decoded.dominant_error(), Some((IcmpError::HostUnreachable, 2)));
+        assert!(decoded.manual());
+        assert_eq!(decoded.params(), chunk.params());
+        assert_eq!(decoded.error(), Some("no route | 100% lost"));
+        assert_eq!(decoded.capture().map(|x| x.sent()), Some(2));
+    }
 
-        buf.set_stringn(x, y, info, area.width as usize, style);
+    #[test]
+    fn decode_garbage() {
+        assert!(PacketChunk::decode("").is_none());
+        assert!(PacketChunk::decode("yesterday 1000 10").is_none());
+        assert!(PacketChunk::decode("2024-01-01T00:00:00+00:00 1000 ^1").is_none());
+        assert!(PacketChunk::decode("2024-01-01T00:00:00+00:00 1000 !nope:1").is_none());
     }
 }
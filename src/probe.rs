@@ -0,0 +1,277 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error, info};
+use tokio::sync::Notify;
+use tokio::task;
+use tokio::time::{self, Instant};
+
+use crate::error::Error;
+use crate::alert::Policy;
+use crate::event::{Event, EventSender};
+use crate::icmp::Ping;
+use crate::ping::{self, PacketChunk, Params, Probe};
+use crate::reflect;
+use crate::tcp::Tcp;
+use crate::twamp;
+use crate::udp::Udp;
+use crate::http::Http;
+use crate::exec::Exec;
+
+/*
+ * probe worker
+ *
+ * chunks are probed in a task of their own, so keys and redraws carry on
+ * while a burst is out. each finished chunk comes back as an `Event::Chunk`
+ * on the same channel as input and ticks. bursts themselves block, and are
+ * run with `block_in_place` so they don't hold up the runtime's other tasks.
+ * once several bursts in a row have failed outright, the interface gone or
+ * the name not resolving, the wait before the next doubles each time up to
+ * BACKOFF_MAX, and the first burst that gets anywhere puts the schedule
+ * back as it was.
+ */
+
+/* failed bursts in a row before the schedule backs off */
+const BACKOFF_AFTER: u32 = 3;
+/* the longest wait backing off gets to, unless the interval is longer */
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/* the wait after `failures` failed bursts in a row */
+fn backoff(interval: Duration, failures: u32) -> Duration {
+    if failures < BACKOFF_AFTER {
+        return interval;
+    }
+
+    let doublings = (failures - BACKOFF_AFTER + 1).min(16);
+    interval.saturating_mul(1 << doublings).min(BACKOFF_MAX.max(interval))
+}
+
+/* where chunks come from */
+pub enum Prober {
+    Icmp(Ping),
+    Reflector(reflect::Client),
+    Twamp(twamp::Client),
+    Tcp(Tcp),
+    Udp(Udp),
+    Http(Http),
+    Exec(Exec),
+}
+
+impl Prober {
+    fn timeout(&self) -> Duration {
+        match self {
+            Prober::Icmp(ping) => ping.timeout(),
+            Prober::Reflector(client) => client.timeout(),
+            Prober::Twamp(client) => client.timeout(),
+            Prober::Tcp(tcp) => tcp.timeout(),
+            Prober::Udp(udp) => udp.timeout(),
+            Prober::Http(http) => http.timeout(),
+            Prober::Exec(exec) => exec.timeout(),
+        }
+    }
+
+    fn burst(&mut self, count: u64, spacing: Duration) -> Result<PacketChunk, Error> {
+        match self {
+            Prober::Icmp(ping) => Ok(ping.burst(count, spacing)?),
+            Prober::Reflector(client) => Ok(client.burst(count, spacing)?),
+            Prober::Twamp(client) => Ok(client.burst(count, spacing)?),
+            Prober::Tcp(tcp) => Ok(ping::burst(tcp, count, spacing)),
+            Prober::Udp(udp) => Ok(ping::burst(udp, count, spacing)),
+            Prober::Http(http) => Ok(ping::burst(http, count, spacing)),
+            Prober::Exec(exec) => Ok(ping::burst(exec, count, spacing)),
+        }
+    }
+}
+
+/// What goes into each chunk
+#[derive(Clone)]
+pub struct Schedule {
+    pub chunk_size: u64,
+    pub spacing: Duration,
+    pub interval: Duration,
+    /* packets to add to a lossy chunk, and their spacing */
+    pub capture: Option<(u64, Duration)>,
+    pub policy: Policy,
+    /* streams take turns through the interval rather than all starting at once, for a subnet's hosts */
+    pub turns: bool,
+}
+
+impl Schedule {
+    /// What a chunk taken on this schedule is stamped with
+    pub fn params(&self) -> Params {
+        Params {
+            interval: self.interval.as_secs_f64(),
+            chunk_size: self.chunk_size,
+            spacing: ping::ms(self.spacing),
+        }
+    }
+}
+
+/// Targets that share a probe stream
+///
+/// Targets given the same address are probed once, by the first of them,
+/// and every chunk goes to all of them. A stream lives as long as any
+/// target refers to it.
+pub struct Streams {
+    /* the targets fed by each stream, the first of them leading it */
+    targets: Vec<Vec<usize>>,
+}
+
+impl Streams {
+    /// One stream per distinct address, in the order they were first given
+    pub fn new(addresses: &[&str]) -> Self {
+        let mut targets: Vec<Vec<usize>> = vec![];
+
+        for (i, address) in addresses.iter().enumerate() {
+            match targets.iter_mut().find(|x| addresses[x[0]].eq_ignore_ascii_case(address)) {
+                Some(stream) => stream.push(i),
+                None => targets.push(vec![i]),
+            }
+        }
+
+        Streams { targets }
+    }
+
+    /// How many streams there are
+    pub fn count(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// The targets a stream's chunks go to
+    pub fn targets(&self, stream: usize) -> &[usize] {
+        self.targets.get(stream).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    /// The probers of the targets leading a stream, one per stream, with
+    /// the index of their target
+    pub fn leaders(&self, probers: Vec<Prober>) -> Vec<(usize, Prober)> {
+        probers.into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.targets.iter().any(|x| x[0] == *i))
+            .collect()
+    }
+}
+
+/// A finished chunk, with how long it took to get
+pub struct Probed {
+    /* index of the stream, see `Streams` */
+    pub target: usize,
+    pub chunk: PacketChunk,
+    pub took: Duration,
+    /* ms the chunk started past its interval, for scheduled chunks */
+    pub drift: Option<i64>,
+    /* failed bursts in a row and the wait before the next, while backing off */
+    pub backoff: Option<(u32, Duration)>,
+}
+
+pub struct Worker {
+    fire: Arc<Notify>,
+    task: task::JoinHandle<()>,
+}
+
+impl Worker {
+    /// Starts probing on the current runtime, which must be multi-threaded,
+    /// the first chunk after `start`. `name` is the target, for the log
+    pub fn spawn(mut prober: Prober, schedule: Schedule, target: usize, start: Duration, name: String, events: EventSender) -> Self {
+        let fire = Arc::new(Notify::new());
+        let manual = fire.clone();
+
+        info!("{}: {} pings per chunk, every {}s", name, schedule.chunk_size, schedule.interval.as_secs());
+
+        let task = tokio::spawn(async move {
+            let mut next = Instant::now() + start;
+            let mut last: Option<Instant> = None;
+            let mut failures: u32 = 0;
+
+            loop {
+                /* a manual chunk leaves the schedule alone */
+                let scheduled = tokio::select! {
+                    _ = time::sleep_until(next) => true,
+                    _ = manual.notified() => false,
+                };
+
+                let start = Instant::now();
+
+                /* the interval starts when the previous chunk ends */
+                let drift = last.filter(|_| scheduled).map(|last| {
+                    let gap = start.duration_since(last);
+                    (gap.as_secs() * 1000 + gap.subsec_millis() as u64) as i64
+                        - (schedule.interval.as_secs() * 1000 + schedule.interval.subsec_millis() as u64) as i64
+                });
+
+                if !scheduled {
+                    info!("{}: probing now, outside the schedule", name);
+                }
+
+                /* a burst that failed is a lost one, and the next may well get through */
+                let mut chunk = match task::block_in_place(|| probe(&mut prober, &schedule, &name)) {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        error!("{}: {}", name, e);
+                        PacketChunk::failed(ping::ms(prober.timeout()), schedule.chunk_size, &e.to_string())
+                    },
+                };
+
+                if !scheduled {
+                    chunk.mark_manual();
+                }
+                chunk.set_params(schedule.params());
+
+                failures = if chunk.error().is_some() { failures + 1 } else { 0 };
+                let wait = backoff(schedule.interval, failures);
+
+                if wait > schedule.interval {
+                    info!("{}: {} failed bursts in a row, next in {}s", name, failures, wait.as_secs());
+                }
+
+                let probed = Box::new(Probed {
+                    target,
+                    chunk,
+                    took: start.elapsed(),
+                    drift,
+                    backoff: Some((failures, wait)).filter(|_| wait > schedule.interval),
+                });
+
+                if scheduled {
+                    last = Some(Instant::now());
+                    next = Instant::now() + wait;
+                } else if failures == 0 && next > Instant::now() + schedule.interval {
+                    /* a manual burst that gets through ends a backoff too */
+                    next = Instant::now() + schedule.interval;
+                }
+
+                debug!("{}: {} sent, {} received in {}ms, {}ms late", name,
+                    probed.chunk.sent(), probed.chunk.received(), probed.took.as_millis(), probed.drift.unwrap_or(0));
+
+                if events.send(Event::Chunk(Ok(probed))).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Worker { fire, task }
+    }
+
+    /// Probe a chunk now, between scheduled ones
+    pub fn fire(&self) {
+        self.fire.notify_one();
+    }
+}
+
+/* a burst already out still comes in, the next is never started */
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn probe(prober: &mut Prober, schedule: &Schedule, name: &str) -> Result<PacketChunk, Error> {
+    let mut chunk = prober.burst(schedule.chunk_size, schedule.spacing)?;
+
+    if let Some((count, spacing)) = schedule.capture.filter(|_| schedule.policy.is_lossy(&chunk)) {
+        info!("{}: {:.0}% loss, capturing {} more pings", name, chunk.loss() * 100.0, count);
+        chunk.attach(prober.burst(count, spacing)?);
+    }
+
+    Ok(chunk)
+}
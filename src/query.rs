@@ -0,0 +1,269 @@
+use std::io::{self, Write};
+
+use chrono::prelude::*;
+
+use crate::ping::PacketChunk;
+use crate::report;
+
+/*
+ * `packetloss query`: the chunks of a recording that match a time range
+ * and conditions, one per row, for scripts. a condition is FIELD OP NUMBER,
+ * e.g. `loss>5` or `latency>=80`, and a chunk must meet all of them. loss
+ * is in percent, latency and jitter in ms. a chunk without a jitter
+ * doesn't match any condition on it.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Loss,
+    Latency,
+    Jitter,
+    Mos,
+    Sent,
+    Received,
+}
+
+/// Field names accepted in a condition
+pub const FIELDS: &[&str] = &["loss", "latency", "jitter", "mos", "sent", "received"];
+
+impl Field {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "loss" => Some(Field::Loss),
+            "latency" => Some(Field::Latency),
+            "jitter" => Some(Field::Jitter),
+            "mos" => Some(Field::Mos),
+            "sent" => Some(Field::Sent),
+            "received" => Some(Field::Received),
+            _ => None,
+        }
+    }
+
+    fn value(self, chunk: &PacketChunk) -> Option<f64> {
+        match self {
+            Field::Loss => Some(chunk.loss() * 100.0),
+            Field::Latency => Some(report::mean_latency(chunk)),
+            Field::Jitter => chunk.jitter(),
+            Field::Mos => Some(chunk.mos()),
+            Field::Sent => Some(chunk.sent() as f64),
+            Field::Received => Some(chunk.received() as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/* the first in the condition wins, the longer of two at the same place, so `>=` isn't read as `>` */
+const OPS: &[(&str, Op)] = &[
+    (">=", Op::Ge), ("<=", Op::Le), ("!=", Op::Ne), ("==", Op::Eq),
+    (">", Op::Gt), ("<", Op::Lt), ("=", Op::Eq),
+];
+
+#[derive(Debug, Clone)]
+pub struct Condition {
+    field: Field,
+    op: Op,
+    value: f64,
+}
+
+impl Condition {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (at, token, op) = OPS.iter()
+            .filter_map(|(token, op)| s.find(token).map(|at| (at, *token, *op)))
+            .min_by_key(|x| (x.0, std::cmp::Reverse(x.1.len())))
+            .ok_or_else(|| format!("'{}': expected FIELD OP NUMBER, e.g. loss>5", s))?;
+
+        let name = s[..at].trim();
+        let field = Field::parse(name)
+            .ok_or_else(|| format!("'{}': unknown field, expected one of {}", name, FIELDS.join(", ")))?;
+        let value = s[at + token.len()..].trim().parse::<f64>()
+            .map_err(|_| format!("'{}': expected a number after {}", s, token))?;
+
+        Ok(Condition {
+            field,
+            op,
+            value,
+        })
+    }
+
+    fn matches(&self, chunk: &PacketChunk) -> bool {
+        let value = match self.field.value(chunk) {
+            Some(value) => value,
+            None => return false,
+        };
+
+        match self.op {
+            Op::Lt => value < self.value,
+            Op::Le => value <= self.value,
+            Op::Gt => value > self.value,
+            Op::Ge => value >= self.value,
+            Op::Eq => value == self.value,
+            Op::Ne => value != self.value,
+        }
+    }
+}
+
+/// A local time as "2024-01-03 20:00", with or without seconds, a bare date, or RFC 3339
+pub fn parse_time(s: &str) -> Option<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time.with_timezone(&Local));
+    }
+
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"].iter()
+        .find_map(|x| NaiveDateTime::parse_from_str(s, x).ok())
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|x| x.and_hms_opt(0, 0, 0)))?;
+
+    Local.from_local_datetime(&naive).earliest()
+}
+
+pub fn is_time(v: String) -> Result<(), String> {
+    match parse_time(&v) {
+        Some(_) => Ok(()),
+        None => Err(String::from("Value must be a time such as \"2024-01-03 20:00\"")),
+    }
+}
+
+pub fn is_condition(v: String) -> Result<(), String> {
+    Condition::parse(&v).map(|_| ())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Table,
+    Csv,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "table" => Some(Format::Table),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Which chunks to print
+pub struct Query {
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+    pub conditions: Vec<Condition>,
+}
+
+impl Query {
+    fn matches(&self, chunk: &PacketChunk) -> bool {
+        self.since.is_none_or(|x| chunk.time() >= x)
+            && self.until.is_none_or(|x| chunk.time() < x)
+            && self.conditions.iter().all(|x| x.matches(chunk))
+    }
+
+    /// Write the matching chunks oldest first, returning how many there were
+    pub fn run<W: Write>(&self, chunks: &[PacketChunk], format: Format, out: &mut W) -> io::Result<usize> {
+        let mut chunks: Vec<&PacketChunk> = chunks.iter().filter(|x| self.matches(x)).collect();
+        chunks.sort_by_key(|x| x.time());
+
+        let jitter = |x: &PacketChunk| x.jitter().map(|x| format!("{:.2}", x)).unwrap_or_default();
+        /* interval, chunk size and spacing, empty for chunks recorded without them */
+        let params = |x: &PacketChunk| x.params()
+            .map(|x| format!("{},{},{}", x.interval, x.chunk_size, x.spacing))
+            .unwrap_or_else(|| String::from(",,"));
+
+        match format {
+            Format::Csv => {
+                writeln!(out, "time,sent,received,loss_pct,latency_ms,jitter_ms,mos,timeout_ms,interval_s,chunk_size,spacing_ms")?;
+                for chunk in chunks.iter() {
+                    writeln!(out, "{},{},{},{:.2},{:.3},{},{:.2},{},{}",
+                        chunk.time().to_rfc3339(), chunk.sent(), chunk.received(), chunk.loss() * 100.0,
+                        report::mean_latency(chunk), jitter(chunk), chunk.mos(), chunk.timeout(), params(chunk))?;
+                }
+            },
+            Format::Table => {
+                writeln!(out, "{:<19}  {:>5}  {:>8}  {:>7}  {:>10}  {:>9}  {:>4}",
+                    "time", "sent", "received", "loss", "latency", "jitter", "mos")?;
+                for chunk in chunks.iter() {
+                    let jitter = match chunk.jitter() {
+                        Some(_) => format!("{}ms", jitter(chunk)),
+                        None => String::from("-"),
+                    };
+
+                    writeln!(out, "{:<19}  {:>5}  {:>8}  {:>6.2}%  {:>8.1}ms  {:>9}  {:>4.2}",
+                        chunk.time().format("%Y-%m-%d %H:%M:%S"), chunk.sent(), chunk.received(),
+                        chunk.loss() * 100.0, report::mean_latency(chunk), jitter, chunk.mos())?;
+                }
+            },
+        }
+
+        Ok(chunks.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ping;
+
+    fn condition(s: &str) -> (Field, Op, f64) {
+        let condition = Condition::parse(s).unwrap();
+        (condition.field, condition.op, condition.value)
+    }
+
+    #[test]
+    fn parse_condition() {
+        assert_eq!(condition("loss>5"), (Field::Loss, Op::Gt, 5.0));
+        assert_eq!(condition("latency >= 80.5"), (Field::Latency, Op::Ge, 80.5));
+        assert_eq!(condition("mos<=3.6"), (Field::Mos, Op::Le, 3.6));
+        assert_eq!(condition("received!=0"), (Field::Received, Op::Ne, 0.0));
+        assert_eq!(condition("sent==10"), (Field::Sent, Op::Eq, 10.0));
+        assert_eq!(condition("jitter=2"), (Field::Jitter, Op::Eq, 2.0));
+        assert_eq!(condition("loss<-1"), (Field::Loss, Op::Lt, -1.0));
+    }
+
+    #[test]
+    fn parse_bad_condition() {
+        assert!(Condition::parse("loss").is_err());
+        assert!(Condition::parse("drops>5").is_err());
+        assert!(Condition::parse("loss>").is_err());
+        assert!(Condition::parse("loss>five").is_err());
+        assert!(Condition::parse(">5").is_err());
+    }
+
+    #[test]
+    fn matches() {
+        /* 1 of 4 lost, the rest back in 20ms */
+        let mut chunk = PacketChunk::new(1000.0);
+        for _ in 0..3 {
+            chunk.push(Some(ping::reply(20.0, 0)));
+        }
+        chunk.push(None);
+
+        assert!(Condition::parse("loss>=25").unwrap().matches(&chunk));
+        assert!(!Condition::parse("loss>25").unwrap().matches(&chunk));
+        assert!(Condition::parse("received==3").unwrap().matches(&chunk));
+
+        /* no send times, no jitter, and no match whatever the condition */
+        assert!(!Condition::parse("jitter>=0").unwrap().matches(&chunk));
+        assert!(!Condition::parse("jitter<1000").unwrap().matches(&chunk));
+    }
+
+    #[test]
+    fn parse_times() {
+        let expected = Local.with_ymd_and_hms(2024, 1, 3, 20, 0, 0).unwrap();
+
+        assert_eq!(parse_time("2024-01-03 20:00"), Some(expected));
+        assert_eq!(parse_time("2024-01-03 20:00:00"), Some(expected));
+        assert_eq!(parse_time("2024-01-03T20:00"), Some(expected));
+        assert_eq!(parse_time(&expected.to_rfc3339()), Some(expected));
+        assert_eq!(parse_time("2024-01-03"), Local.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).earliest());
+
+        assert_eq!(parse_time("yesterday"), None);
+        assert_eq!(parse_time("2024-13-01"), None);
+    }
+}
@@ -0,0 +1,426 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::time::{Duration, Instant};
+use std::thread;
+
+use chrono::{DateTime, Local};
+use tui::layout::Rect;
+
+use crate::event::{Event, Key};
+use crate::ping::PacketChunk;
+
+/*
+ * session recordings, one line per event:
+ *
+ *   <ms> target <address>
+ *   <ms> key <key>
+ *   <ms> tick
+ *   <ms> resize <width> <height>
+ *   <ms> chunk <encoded chunk>
+ *   <ms> marker <time> [label]
+ *
+ * where <ms> is the offset from the start of the recording. the target,
+ * when there is one, comes first and is only there to tell recordings apart.
+ */
+
+pub enum Recorded {
+    Event(Event<Key>),
+    Resize,
+    Chunk(Box<PacketChunk>),
+    /* placed after the newest chunk */
+    Marker(DateTime<Local>, String),
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    /// A new recording of `target`, none for a replay or an attached stream
+    pub fn create(path: &str, target: Option<&str>) -> io::Result<Self> {
+        let mut recorder = Recorder {
+            out: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        };
+
+        if let Some(target) = target {
+            recorder.line("target", target)?;
+        }
+
+        Ok(recorder)
+    }
+
+    fn line(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        let elapsed = self.start.elapsed();
+        self.at(elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64, kind, data)
+    }
+
+    fn at(&mut self, ms: u64, kind: &str, data: &str) -> io::Result<()> {
+        if data.is_empty() {
+            writeln!(self.out, "{} {}", ms, kind)?;
+        } else {
+            writeln!(self.out, "{} {} {}", ms, kind, data)?;
+        }
+
+        /* a recording is most useful after a crash, so never sit on lines */
+        self.out.flush()
+    }
+
+    pub fn event(&mut self, event: &Event<Key>) -> io::Result<()> {
+        match event {
+            Event::Input(key) => match encode_key(*key) {
+                Some(key) => self.line("key", &key),
+                None => Ok(()),
+            },
+            Event::Tick => self.line("tick", ""),
+            /* chunks are recorded once they are in the list */
+            Event::Chunk(_) => Ok(()),
+            /* a replay runs on the settings it is started with, and ends with the recording */
+            Event::Reload | Event::Stats | Event::Stop => Ok(()),
+        }
+    }
+
+    pub fn resize(&mut self, size: Rect) -> io::Result<()> {
+        self.line("resize", &format!("{} {}", size.width, size.height))
+    }
+
+    pub fn chunk(&mut self, chunk: &PacketChunk) -> io::Result<()> {
+        self.line("chunk", &chunk.encode())
+    }
+
+    pub fn marker(&mut self, time: DateTime<Local>, label: &str) -> io::Result<()> {
+        self.line("marker", format!("{} {}", time.to_rfc3339(), label).trim_end())
+    }
+}
+
+pub struct Replay {
+    lines: io::Lines<BufReader<File>>,
+    line: usize,
+    start: Instant,
+    size: Option<Rect>,
+}
+
+impl Replay {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Replay {
+            lines: BufReader::new(File::open(path)?).lines(),
+            line: 0,
+            start: Instant::now(),
+            size: None,
+        })
+    }
+
+    /// The terminal size as of the last replayed resize
+    pub fn size(&self) -> Option<Rect> {
+        self.size
+    }
+
+    /* next recorded event, paced to its original timing */
+    pub fn next(&mut self) -> io::Result<Option<Recorded>> {
+
+        let line = match self.lines.next() {
+            Some(line) => line?,
+            None => return Ok(None),
+        };
+
+        self.line += 1;
+
+        let invalid = |line: usize| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                format!("invalid event recording at line {}", line))
+        };
+
+        let mut fields = line.splitn(3, ' ');
+
+        let ms = fields.next()
+            .and_then(|x| x.parse::<u64>().ok())
+            .ok_or_else(|| invalid(self.line))?;
+        let kind = fields.next().ok_or_else(|| invalid(self.line))?;
+        let data = fields.next().unwrap_or("");
+
+        let due = Duration::from_millis(ms);
+        let elapsed = self.start.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+
+        let recorded = match kind {
+            "tick" => Some(Recorded::Event(Event::Tick)),
+            "key" => decode_key(data).map(|x| Recorded::Event(Event::Input(x))),
+            "resize" => {
+                let mut dims = data.split(' ').filter_map(|x| x.parse::<u16>().ok());
+                match (dims.next(), dims.next()) {
+                    (Some(w), Some(h)) => {
+                        let size = Rect::new(0, 0, w, h);
+                        self.size = Some(size);
+                        Some(Recorded::Resize)
+                    },
+                    _ => None,
+                }
+            },
+            "chunk" => PacketChunk::decode(data).map(|x| Recorded::Chunk(Box::new(x))),
+            "marker" => decode_marker(data).map(|(time, label)| Recorded::Marker(time, label)),
+            /* not an event, just which host this was */
+            "target" => return self.next(),
+            _ => None,
+        };
+
+        recorded.map(Some).ok_or_else(|| invalid(self.line))
+    }
+}
+
+fn encode_key(key: Key) -> Option<String> {
+    let key = match key {
+        Key::Char(c) => format!("char:{}", c as u32),
+        Key::Ctrl(c) => format!("ctrl:{}", c as u32),
+        Key::Alt(c) => format!("alt:{}", c as u32),
+        Key::F(n) => format!("f:{}", n),
+        Key::Up => String::from("up"),
+        Key::Down => String::from("down"),
+        Key::Left => String::from("left"),
+        Key::Right => String::from("right"),
+        Key::Home => String::from("home"),
+        Key::End => String::from("end"),
+        Key::PageUp => String::from("pageup"),
+        Key::PageDown => String::from("pagedown"),
+        Key::Backspace => String::from("backspace"),
+        Key::Delete => String::from("delete"),
+        Key::Insert => String::from("insert"),
+        Key::BackTab => String::from("backtab"),
+        Key::Esc => String::from("esc"),
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn decode_key(s: &str) -> Option<Key> {
+    let mut parts = s.splitn(2, ':');
+    let name = parts.next()?;
+    let arg = parts.next();
+
+    let c = || arg
+        .and_then(|x| x.parse::<u32>().ok())
+        .and_then(std::char::from_u32);
+
+    let key = match name {
+        "char" => Key::Char(c()?),
+        "ctrl" => Key::Ctrl(c()?),
+        "alt" => Key::Alt(c()?),
+        "f" => Key::F(arg?.parse::<u8>().ok()?),
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "backtab" => Key::BackTab,
+        "esc" => Key::Esc,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn decode_marker(s: &str) -> Option<(DateTime<Local>, String)> {
+    let mut parts = s.splitn(2, ' ');
+    let time = DateTime::parse_from_rfc3339(parts.next()?).ok()?;
+    Some((time.with_timezone(&Local), parts.next().unwrap_or("").to_string()))
+}
+
+/// Every chunk in a recording, in order, with markers on the chunk before them
+pub fn read_chunks(path: &str) -> io::Result<Vec<PacketChunk>> {
+    Ok(read(path)?.1)
+}
+
+/// The target a recording names, if any, and its chunks
+pub fn read(path: &str) -> io::Result<(Option<String>, Vec<PacketChunk>)> {
+    let mut target = None;
+    let mut chunks: Vec<PacketChunk> = vec![];
+
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let mut fields = line.splitn(3, ' ');
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData,
+            format!("invalid event recording at line {}", i + 1));
+
+        match fields.nth(1) {
+            Some("chunk") => {
+                let chunk = fields.next()
+                    .and_then(PacketChunk::decode)
+                    .ok_or_else(invalid)?;
+
+                chunks.push(chunk);
+            },
+            Some("marker") => {
+                let (time, label) = fields.next()
+                    .and_then(decode_marker)
+                    .ok_or_else(invalid)?;
+
+                if let Some(chunk) = chunks.last_mut() {
+                    chunk.mark(time, &label);
+                }
+            },
+            Some("target") => target = fields.next().map(String::from),
+            _ => {},
+        }
+    }
+
+    Ok((target, chunks))
+}
+
+/// What `merge` did
+pub struct Merged {
+    pub chunks: usize,
+    /* chunks found in more than one recording, kept once */
+    pub duplicates: usize,
+}
+
+/// Write the chunks and markers of several recordings of one target to
+/// `out` as a single recording, oldest chunk first. Keys and resizes are
+/// left out, they belong to the sessions that were merged
+pub fn merge(paths: &[&str], out: &str) -> io::Result<Merged> {
+    let mut target: Option<(String, &str)> = None;
+    let mut chunks: Vec<PacketChunk> = vec![];
+
+    for path in paths {
+        let (name, read) = read(path)?;
+
+        match (name, &target) {
+            (Some(name), Some((seen, first))) if name != *seen => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                    format!("{} records {}, but {} records {}", first, seen, path, name)));
+            },
+            (Some(name), None) => target = Some((name, path)),
+            _ => {},
+        }
+
+        chunks.extend(read);
+    }
+
+    /* a chunk in two files started at the same instant, a restart can't repeat that */
+    chunks.sort_by_key(|x| x.time());
+
+    let mut merged: Vec<PacketChunk> = vec![];
+    let mut duplicates = 0;
+
+    for chunk in chunks {
+        match merged.last_mut() {
+            Some(last) if last.time() == chunk.time() => {
+                for (time, label) in chunk.markers() {
+                    if !last.markers().iter().any(|x| x.0 == *time && x.1 == *label) {
+                        last.mark(*time, label);
+                    }
+                }
+                duplicates += 1;
+            },
+            _ => merged.push(chunk),
+        }
+    }
+
+    write(merged.iter(), target.as_ref().map(|x| x.0.as_str()), out)?;
+
+    Ok(Merged { chunks: merged.len(), duplicates })
+}
+
+/// An address as part of a file name
+pub fn file_name(address: &str) -> String {
+    address.chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Write `chunks`, oldest first, and their markers to `out` as a recording
+/// of `target`, paced as they happened
+pub fn write<'a, I: IntoIterator<Item = &'a PacketChunk>>(chunks: I, target: Option<&str>, out: &str) -> io::Result<()> {
+    let mut recorder = Recorder::create(out, target)?;
+    let mut start: Option<DateTime<Local>> = None;
+
+    for chunk in chunks {
+        let start = *start.get_or_insert(chunk.time());
+        let offset = |time: DateTime<Local>| (time - start).num_milliseconds().max(0) as u64;
+
+        recorder.at(offset(chunk.time()), "chunk", &chunk.encode())?;
+
+        for (time, label) in chunk.markers() {
+            recorder.at(offset(*time), "marker", format!("{} {}", time.to_rfc3339(), label).trim_end())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /* a path in the temp dir no other test run uses */
+    fn temp(name: &str) -> String {
+        env::temp_dir()
+            .join(format!("packetloss-{}-{}", std::process::id(), name))
+            .display().to_string()
+    }
+
+    fn chunk(second: i64) -> PacketChunk {
+        let mut chunk = PacketChunk::new(1000.0);
+        chunk.set_time(Local.timestamp_opt(1_700_000_000 + second, 0).unwrap());
+        chunk.push(Some(crate::ping::reply(10.0, 0)));
+        chunk
+    }
+
+    #[test]
+    fn merge_dedups() {
+        let (a, b, out) = (temp("merge-a"), temp("merge-b"), temp("merge-out"));
+
+        /* the chunk at 2s is in both, with a marker of its own in each */
+        let mut first = chunk(2);
+        first.mark(first.time(), "restart");
+        let mut second = chunk(2);
+        second.mark(second.time(), "restart");
+        second.mark(second.time(), "after");
+
+        write(&[chunk(3), first, chunk(1)], Some("10.0.0.1"), &a).unwrap();
+        write(&[second, chunk(4)], Some("10.0.0.1"), &b).unwrap();
+
+        let merged = merge(&[&a, &b], &out).unwrap();
+        let (target, chunks) = read(&out).unwrap();
+
+        assert_eq!((merged.chunks, merged.duplicates), (4, 1));
+        assert_eq!(target.as_deref(), Some("10.0.0.1"));
+        assert_eq!(chunks.iter().map(|x| x.time().timestamp() - 1_700_000_000).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+
+        let labels: Vec<&str> = chunks[1].markers().iter().map(|x| x.1.as_str()).collect();
+        assert_eq!(labels, vec!["restart", "after"]);
+
+        for path in [a, b, out] {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn merge_one_target() {
+        let (a, b, out) = (temp("other-a"), temp("other-b"), temp("other-out"));
+
+        write(&[chunk(1)], Some("10.0.0.1"), &a).unwrap();
+        write(&[chunk(2)], Some("10.0.0.2"), &b).unwrap();
+
+        let merged = merge(&[&a, &b], &out);
+        assert!(matches!(merged, Err(ref e) if e.kind() == io::ErrorKind::InvalidInput));
+
+        for path in [a, b, out] {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ping::{self, ms, PacketChunk};
+
+/*
+ * dual-ended measurement. a reflector echoes udp probes and counts the
+ * ones it saw per session, so after a chunk the client can tell probes
+ * lost on the way out from replies lost on the way back.
+ *
+ *   client:    probe <session> <seq>
+ *   reflector: echo <session> <seq>
+ *   client:    count <session>
+ *   reflector: total <session> <probes received>
+ */
+
+/* sessions not heard from in this long are forgotten */
+const SESSION_TTL: Duration = Duration::from_secs(600);
+
+/* attempts at fetching the reflector's count after a chunk */
+const COUNT_TRIES: usize = 3;
+
+/// `:9000` listens on every interface
+pub fn listen_addr(s: &str) -> String {
+    if s.starts_with(':') {
+        format!("0.0.0.0{}", s)
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn is_listen_addr(v: String) -> Result<(), String> {
+    listen_addr(&v).parse::<SocketAddr>()
+        .map(|_| ())
+        .map_err(|_| String::from("Value must be [ADDRESS]:PORT"))
+}
+
+/// Run a reflector until killed
+pub fn serve(addr: &str) -> io::Result<()> {
+    let socket = UdpSocket::bind(listen_addr(addr))?;
+    let mut sessions: HashMap<(SocketAddr, u64), (u64, Instant)> = HashMap::new();
+    let mut buf = [0u8; 512];
+
+    eprintln!("reflecting on {}", socket.local_addr()?);
+
+    loop {
+        let (n, peer) = socket.recv_from(&mut buf)?;
+        let text = String::from_utf8_lossy(&buf[..n]);
+        let words: Vec<&str> = text.split_whitespace().collect();
+
+        let session = match words.get(1).and_then(|x| x.parse::<u64>().ok()) {
+            Some(session) => session,
+            None => continue,
+        };
+
+        let reply = match words[0] {
+            "probe" if words.len() == 3 => {
+                let entry = sessions.entry((peer, session)).or_insert((0, Instant::now()));
+                entry.0 += 1;
+                entry.1 = Instant::now();
+
+                format!("echo {} {}", session, words[2])
+            },
+            "count" => {
+                let total = sessions.get(&(peer, session)).map(|x| x.0).unwrap_or(0);
+                format!("total {} {}", session, total)
+            },
+            _ => continue,
+        };
+
+        /* a reply that can't be sent is just more loss for the client to see */
+        let _ = socket.send_to(reply.as_bytes(), peer);
+
+        if sessions.len() > 1024 {
+            sessions.retain(|_, x| x.1.elapsed() < SESSION_TTL);
+        }
+    }
+}
+
+pub struct Client {
+    socket: UdpSocket,
+    session: u64,
+    timeout: Duration,
+    seq: u64,
+    /* the reflector's count after the previous chunk */
+    total: u64,
+}
+
+impl Client {
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn connect(peer: &str, timeout: Duration) -> io::Result<Self> {
+        let peer = peer.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}: no address", peer)))?;
+
+        let local = if peer.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(peer)?;
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.subsec_nanos() as u64)
+            .unwrap_or(0);
+
+        Ok(Client {
+            socket,
+            session: nanos << 20 ^ process::id() as u64,
+            timeout,
+            seq: 0,
+            total: 0,
+        })
+    }
+
+    /* wait for a reply whose leading words are `expect`, dropping stale ones */
+    fn receive(&self, expect: &[String], deadline: Instant) -> io::Result<Option<Vec<String>>> {
+        let mut buf = [0u8; 512];
+
+        loop {
+            let left = match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if left > Duration::from_millis(0) => left,
+                _ => return Ok(None),
+            };
+
+            self.socket.set_read_timeout(Some(left))?;
+
+            let n = match self.socket.recv(&mut buf) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+                /* an icmp unreachable from an earlier probe, the probe is just lost */
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => continue,
+                Err(e) => return Err(e),
+            };
+
+            let words: Vec<String> = String::from_utf8_lossy(&buf[..n])
+                .split_whitespace()
+                .map(String::from)
+                .collect();
+
+            if words.len() >= expect.len() && words[..expect.len()] == *expect {
+                return Ok(Some(words));
+            }
+        }
+    }
+
+    /// Like `Ping::burst`, with losses split by direction when the reflector answers
+    pub fn burst(&mut self, count: u64, spacing: Duration) -> io::Result<PacketChunk> {
+        let mut chunk = PacketChunk::new((self.timeout.as_secs() * 1000 + self.timeout.subsec_millis() as u64) as f64);
+        let origin = Instant::now();
+
+        for _ in 0..count {
+            let start = Instant::now();
+            self.seq += 1;
+
+            let expect = words(&format!("echo {} {}", self.session, self.seq));
+
+            /* a failed send is a lost probe, same as with icmp */
+            let sent = self.socket.send(format!("probe {} {}", self.session, self.seq).as_bytes()).is_ok();
+            let reply = if sent { self.receive(&expect, start + self.timeout)? } else { None };
+
+            let rtt = start.elapsed();
+            let send = ms(start.duration_since(origin));
+
+            match reply {
+                Some(_) => chunk.push(Some(ping::reply(ms(rtt), 0))),
+                None => chunk.push(None),
+            }
+            chunk.stamp(send);
+
+            if let Some(rest) = spacing.checked_sub(start.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+
+        if let Some(total) = self.count()? {
+            let arrived = (total.saturating_sub(self.total) as usize).min(chunk.sent());
+            let up = chunk.sent() - arrived;
+            let down = arrived.saturating_sub(chunk.received());
+
+            chunk.set_oneway(up, down);
+            self.total = total;
+        }
+
+        Ok(chunk)
+    }
+
+    /* probes the reflector has seen from this session */
+    fn count(&self) -> io::Result<Option<u64>> {
+        let expect = words(&format!("total {}", self.session));
+
+        for _ in 0..COUNT_TRIES {
+            if self.socket.send(format!("count {}", self.session).as_bytes()).is_err() {
+                continue;
+            }
+
+            if let Some(words) = self.receive(&expect, Instant::now() + self.timeout)? {
+                return Ok(words.get(2).and_then(|x| x.parse::<u64>().ok()));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn words(s: &str) -> Vec<String> {
+    s.split_whitespace().map(String::from).collect()
+}
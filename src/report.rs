@@ -0,0 +1,460 @@
+use std::fmt;
+
+use chrono::{DateTime, Duration, Local, NaiveDate};
+
+use crate::locale::{self, Text};
+use crate::ping::PacketChunk;
+
+const LABELS: &[Text] = &[
+    Text::From, Text::To, Text::Chunks, Text::Packets, Text::Latency, Text::Jitter,
+    Text::Oneway, Text::Speed, Text::Tcp, Text::Marker, Text::Mos, Text::Worst, Text::Day, Text::Runtime,
+];
+
+/* one line of the summary, labels padded to the widest in the current language */
+fn line(out: &mut String, label: Text, body: &str, args: &[&dyn fmt::Display]) {
+    let width = LABELS.iter().map(|x| locale::tr(*x).chars().count()).max().unwrap_or(0);
+    let label = locale::tr(label);
+
+    out.push_str(label);
+    for _ in label.chars().count()..width + 2 {
+        out.push(' ');
+    }
+    locale::fill(out, body, args);
+    out.push('\n');
+}
+
+/// Per-packet latency in ms, failed and dropped packets count as the timeout
+pub fn mean_latency(chunk: &PacketChunk) -> f64 {
+    if chunk.sent() == 0 {
+        0.0
+    } else {
+        chunk.latency() / chunk.sent() as f64
+    }
+}
+
+/// One chunk's figures, as the monitor engine hands them out
+#[derive(Clone)]
+pub struct ChunkReport {
+    pub target: String,
+    pub time: DateTime<Local>,
+    pub sent: usize,
+    pub received: usize,
+    /// From 0.0 to 1.0
+    pub loss: f64,
+    /// Per-packet mean in ms, see `mean_latency`
+    pub latency: f64,
+    pub jitter: Option<f64>,
+    pub mos: f64,
+    /// The chunk itself, for anything not summarized here
+    pub chunk: PacketChunk,
+}
+
+impl ChunkReport {
+    pub fn new(target: &str, chunk: PacketChunk) -> Self {
+        ChunkReport {
+            target: target.to_string(),
+            time: chunk.time(),
+            sent: chunk.sent(),
+            received: chunk.received(),
+            loss: chunk.loss(),
+            latency: mean_latency(&chunk),
+            jitter: chunk.jitter(),
+            mos: chunk.mos(),
+            chunk,
+        }
+    }
+}
+
+/// Plain text summary of a list of chunks
+pub fn summary(chunks: &[PacketChunk]) -> String {
+    let mut out = String::new();
+
+    if chunks.is_empty() {
+        out.push_str(locale::tr(Text::NoChunks));
+        out.push('\n');
+        return out;
+    }
+
+    let first = chunks.iter().map(|x| x.time()).min().unwrap();
+    let last = chunks.iter().map(|x| x.time()).max().unwrap();
+
+    let sent: usize = chunks.iter().map(|x| x.sent()).sum();
+    let received: usize = chunks.iter().map(|x| x.received()).sum();
+    let loss = if sent == 0 { 0.0 } else { 1.0 - received as f64 / sent as f64 };
+
+    let latencies: Vec<f64> = chunks.iter().map(mean_latency).collect();
+    let min = latencies.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = latencies.iter().cloned().fold(0.0, f64::max);
+    let avg = latencies.iter().sum::<f64>() / latencies.len() as f64;
+
+    let lossy = chunks.iter().filter(|x| x.loss() > 0.0).count();
+
+    let jitters: Vec<f64> = chunks.iter().filter_map(|x| x.jitter()).collect();
+
+    let worst = chunks.iter()
+        .max_by(|a, b| a.loss().partial_cmp(&b.loss()).unwrap())
+        .unwrap();
+
+    const TIME: &str = "%Y-%m-%d %H:%M:%S";
+
+    line(&mut out, Text::From, "{}", &[&first.format(TIME)]);
+    line(&mut out, Text::To, "{}", &[&last.format(TIME)]);
+    line(&mut out, Text::Chunks, locale::tr(Text::ChunksLine), &[&chunks.len(), &lossy]);
+    line(&mut out, Text::Packets, locale::tr(Text::PacketsLine),
+        &[&sent, &received, &format_args!("{:.2}", loss * 100.0)]);
+    line(&mut out, Text::Latency, locale::tr(Text::LatencyLine),
+        &[&format_args!("{:.01}", min), &format_args!("{:.01}", avg), &format_args!("{:.01}", max)]);
+    if !jitters.is_empty() {
+        line(&mut out, Text::Jitter, locale::tr(Text::JitterLine), &[
+            &format_args!("{:.02}", jitters.iter().sum::<f64>() / jitters.len() as f64),
+            &format_args!("{:.02}", jitters.iter().cloned().fold(0.0, f64::max)),
+        ]);
+    }
+    let oneway: Vec<(usize, usize)> = chunks.iter().filter_map(|x| x.oneway()).collect();
+    if !oneway.is_empty() {
+        line(&mut out, Text::Oneway, locale::tr(Text::OnewayLine), &[
+            &oneway.iter().map(|x| x.0).sum::<usize>(),
+            &oneway.iter().map(|x| x.1).sum::<usize>(),
+        ]);
+    }
+
+    let tests: Vec<&PacketChunk> = chunks.iter().filter(|x| x.throughput().is_some()).collect();
+    if !tests.is_empty() {
+        let mbps: Vec<f64> = tests.iter().filter_map(|x| x.throughput()).collect();
+        let loss = tests.iter().map(|x| x.loss()).sum::<f64>() / tests.len() as f64;
+
+        line(&mut out, Text::Speed, locale::tr(Text::SpeedLine), &[
+            &format_args!("{:.1}", mbps.iter().sum::<f64>() / mbps.len() as f64),
+            &format_args!("{:.1}", mbps.iter().cloned().fold(f64::INFINITY, f64::min)),
+            &format_args!("{:.1}", mbps.iter().cloned().fold(0.0, f64::max)),
+            &tests.len(),
+            &format_args!("{:.2}", loss * 100.0),
+        ]);
+    }
+
+    let retrans: Vec<f64> = chunks.iter().filter_map(|x| x.retrans_rate()).collect();
+    if !retrans.is_empty() {
+        line(&mut out, Text::Tcp, locale::tr(Text::TcpLine), &[
+            &format_args!("{:.2}", retrans.iter().sum::<f64>() / retrans.len() as f64 * 100.0),
+            &format_args!("{:.2}", retrans.iter().cloned().fold(0.0, f64::max) * 100.0),
+        ]);
+    }
+
+    for (time, label) in chunks.iter().flat_map(|x| x.markers()) {
+        line(&mut out, Text::Marker, "{} {}", &[&time.format(TIME), label]);
+    }
+
+    let mos: Vec<f64> = chunks.iter().map(|x| x.mos()).collect();
+    line(&mut out, Text::Mos, locale::tr(Text::MosLine), &[
+        &format_args!("{:.2}", mos.iter().sum::<f64>() / mos.len() as f64),
+        &format_args!("{:.2}", mos.iter().cloned().fold(5.0, f64::min)),
+    ]);
+    line(&mut out, Text::Worst, locale::tr(Text::WorstLine),
+        &[&worst.time().format(TIME), &format_args!("{:.0}", worst.loss() * 100.0)]);
+
+    for day in days(chunks) {
+        let uptime = format!("{:.2}", day.uptime * 100.0);
+
+        match day.worst {
+            Some(worst) => line(&mut out, Text::Day, locale::tr(Text::DayLine), &[
+                &day.date, &uptime, &worst.start.format("%H:%M:%S"),
+                &span(worst.duration()), &format_args!("{:.0}", worst.loss * 100.0),
+            ]),
+            None => line(&mut out, Text::Day, locale::tr(Text::DayClean), &[&day.date, &uptime]),
+        }
+    }
+
+    out
+}
+
+/// What the monitor leaves of a target on the way out: how long it ran,
+/// then the summary of the history it kept
+pub fn session(address: &str, chunks: &[PacketChunk], runtime: std::time::Duration) -> String {
+    let mut out = format!("{}\n", address);
+    let runtime = Duration::from_std(runtime).unwrap_or_else(|_| Duration::zero());
+
+    line(&mut out, Text::Runtime, "{}", &[&span(runtime)]);
+    out.push_str(&summary(chunks));
+    out
+}
+
+/// A run of chunks with loss, up to the next chunk without
+#[derive(Debug, Clone)]
+pub struct Incident {
+    pub start: DateTime<Local>,
+    /// The first chunk without loss, or the last lossy one when the history ends first
+    pub end: DateTime<Local>,
+    pub chunks: usize,
+    /// The highest loss of any of its chunks, from 0.0 to 1.0
+    pub loss: f64,
+}
+
+impl Incident {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// Every incident in `chunks`, which may be in any order, oldest first
+pub fn incidents<'a, I: IntoIterator<Item = &'a PacketChunk>>(chunks: I) -> Vec<Incident> {
+    let mut sorted: Vec<&PacketChunk> = chunks.into_iter().collect();
+    sorted.sort_by_key(|x| x.time());
+
+    let mut incidents: Vec<Incident> = vec![];
+    let mut open = false;
+
+    for chunk in sorted {
+        match incidents.last_mut() {
+            Some(incident) if open && chunk.loss() > 0.0 => {
+                incident.end = chunk.time();
+                incident.chunks += 1;
+                incident.loss = incident.loss.max(chunk.loss());
+            },
+            Some(incident) if open => {
+                incident.end = chunk.time();
+                open = false;
+            },
+            _ if chunk.loss() > 0.0 => {
+                incidents.push(Incident { start: chunk.time(), end: chunk.time(), chunks: 1, loss: chunk.loss() });
+                open = true;
+            },
+            _ => {},
+        }
+    }
+
+    incidents
+}
+
+/// One calendar day of a history
+#[derive(Debug, Clone)]
+pub struct Day {
+    pub date: NaiveDate,
+    pub chunks: usize,
+    /// The share of chunks that got any reply at all, from 0.0 to 1.0
+    pub uptime: f64,
+    /// The longest incident starting on this day, the lossiest of equally long ones
+    pub worst: Option<Incident>,
+}
+
+/// The days `chunks` cover, in local time, oldest first
+pub fn days<'a, I: IntoIterator<Item = &'a PacketChunk>>(chunks: I) -> Vec<Day> {
+    let chunks: Vec<&PacketChunk> = chunks.into_iter().collect();
+    let mut days: Vec<Day> = vec![];
+
+    for chunk in chunks.iter() {
+        let date = chunk.time().date_naive();
+        let up = if chunk.down() { 0.0 } else { 1.0 };
+
+        match days.iter_mut().find(|x| x.date == date) {
+            /* uptime holds the count of up chunks until the end */
+            Some(day) => {
+                day.chunks += 1;
+                day.uptime += up;
+            },
+            None => days.push(Day { date, chunks: 1, uptime: up, worst: None }),
+        }
+    }
+
+    for day in days.iter_mut() {
+        day.uptime /= day.chunks as f64;
+    }
+
+    for incident in incidents(chunks) {
+        let day = match days.iter_mut().find(|x| x.date == incident.start.date_naive()) {
+            Some(day) => day,
+            None => continue,
+        };
+
+        let worse = match day.worst {
+            Some(ref worst) => (incident.duration(), incident.chunks, incident.loss)
+                > (worst.duration(), worst.chunks, worst.loss),
+            None => true,
+        };
+        if worse {
+            day.worst = Some(incident);
+        }
+    }
+
+    days.sort_by_key(|x| x.date);
+    days
+}
+
+/// A duration as "45s", "2m30s" or "1h05m"
+pub fn span(d: Duration) -> String {
+    let s = d.num_seconds().max(0);
+
+    if s < 60 {
+        format!("{}s", s)
+    } else if s < 3600 {
+        format!("{}m{:02}s", s / 60, s % 60)
+    } else {
+        format!("{}h{:02}m", s / 3600, s % 3600 / 60)
+    }
+}
+
+/* the figures `compare` puts side by side */
+struct Figures {
+    first: DateTime<Local>,
+    last: DateTime<Local>,
+    chunks: usize,
+    loss: f64,
+    /* p50, p90 and p99 of every reply's latency */
+    percentiles: Option<[f64; 3]>,
+    jitter: Option<f64>,
+    incidents: usize,
+    mos: f64,
+}
+
+/* nearest rank, `sorted` must not be empty */
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1) - 1]
+}
+
+fn figures(chunks: &[PacketChunk]) -> Option<Figures> {
+    if chunks.is_empty() {
+        return None;
+    }
+
+    let sent: usize = chunks.iter().map(|x| x.sent()).sum();
+    let received: usize = chunks.iter().map(|x| x.received()).sum();
+
+    let mut latencies: Vec<f64> = chunks.iter()
+        .flat_map(|x| x.packets.iter())
+        .filter_map(|x| x.as_ref())
+        .filter(|x| x.dropped == 0)
+        .map(|x| x.latency_ms)
+        .collect();
+    latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let jitters: Vec<f64> = chunks.iter().filter_map(|x| x.jitter()).collect();
+
+    let first = chunks.iter().map(|x| x.time()).min().unwrap();
+    let last = chunks.iter().map(|x| x.time()).max().unwrap();
+
+    Some(Figures {
+        first,
+        last,
+        chunks: chunks.len(),
+        loss: if sent == 0 { 0.0 } else { 1.0 - received as f64 / sent as f64 },
+        percentiles: if latencies.is_empty() {
+            None
+        } else {
+            Some([percentile(&latencies, 50.0), percentile(&latencies, 90.0), percentile(&latencies, 99.0)])
+        },
+        jitter: if jitters.is_empty() { None } else { Some(jitters.iter().sum::<f64>() / jitters.len() as f64) },
+        incidents: incidents(chunks).len(),
+        mos: chunks.iter().map(|x| x.mos()).sum::<f64>() / chunks.len() as f64,
+    })
+}
+
+/* one row of the comparison, a change only for figures that can get better or worse */
+struct Row {
+    label: String,
+    before: String,
+    after: String,
+    change: String,
+    verdict: Option<Text>,
+}
+
+impl Row {
+    fn plain(label: String, before: String, after: String) -> Self {
+        Row { label, before, after, change: String::new(), verdict: None }
+    }
+
+    /* `lower` when a smaller value is the better one */
+    fn figure(label: String, before: Option<f64>, after: Option<f64>, decimals: usize, unit: &str, lower: bool) -> Self {
+        let show = |x: Option<f64>| match x {
+            Some(x) => format!("{:.*}{}", decimals, x, unit),
+            None => String::from("-"),
+        };
+
+        let mut row = Row::plain(label, show(before), show(after));
+
+        if let (Some(before), Some(after)) = (before, after) {
+            let change = format!("{:+.*}", decimals, after - before);
+
+            /* a change that doesn't show at this precision is no change */
+            if change.trim_start_matches(['+', '-']).chars().any(|x| x != '0' && x != '.') {
+                row.verdict = Some(if (after < before) == lower { Text::Better } else { Text::Worse });
+            }
+            row.change = change;
+        }
+
+        row
+    }
+}
+
+/// Plain text comparison of two sessions, such as before and after a change to the line
+pub fn compare(before: &[PacketChunk], after: &[PacketChunk]) -> String {
+    const TIME: &str = "%Y-%m-%d %H:%M:%S";
+
+    let before = figures(before);
+    let after = figures(after);
+
+    let time = |x: &Option<Figures>, f: fn(&Figures) -> DateTime<Local>| match x {
+        Some(x) => f(x).format(TIME).to_string(),
+        None => locale::tr(Text::NoChunks).to_string(),
+    };
+    let count = |x: &Option<Figures>, f: fn(&Figures) -> usize| x.as_ref().map(|x| f(x) as f64);
+    let value = |x: &Option<Figures>, f: fn(&Figures) -> Option<f64>| x.as_ref().and_then(f);
+
+    let latency = locale::tr(Text::Latency);
+    let mut rows = vec![
+        Row::plain(locale::tr(Text::From).to_string(), time(&before, |x| x.first), time(&after, |x| x.first)),
+        Row::plain(locale::tr(Text::To).to_string(), time(&before, |x| x.last), time(&after, |x| x.last)),
+        Row::plain(locale::tr(Text::Chunks).to_string(),
+            before.as_ref().map_or(String::from("-"), |x| x.chunks.to_string()),
+            after.as_ref().map_or(String::from("-"), |x| x.chunks.to_string())),
+        Row::figure(locale::tr(Text::Loss).to_string(),
+            value(&before, |x| Some(x.loss * 100.0)), value(&after, |x| Some(x.loss * 100.0)), 2, "%", true),
+    ];
+
+    for (i, p) in ["p50", "p90", "p99"].iter().enumerate() {
+        rows.push(Row::figure(format!("{} {}", latency, p),
+            before.as_ref().and_then(|x| x.percentiles).map(|x| x[i]),
+            after.as_ref().and_then(|x| x.percentiles).map(|x| x[i]), 1, "ms", true));
+    }
+
+    rows.push(Row::figure(locale::tr(Text::Jitter).to_string(),
+        value(&before, |x| x.jitter), value(&after, |x| x.jitter), 2, "ms", true));
+    rows.push(Row::figure(locale::tr(Text::Incidents).to_string(),
+        count(&before, |x| x.incidents), count(&after, |x| x.incidents), 0, "", true));
+    rows.push(Row::figure(locale::tr(Text::Mos).to_string(),
+        value(&before, |x| Some(x.mos)), value(&after, |x| Some(x.mos)), 2, "", false));
+
+    let mut header = Row::plain(String::new(),
+        locale::tr(Text::Before).to_string(), locale::tr(Text::After).to_string());
+    header.change = locale::tr(Text::Change).to_string();
+
+    let width = |f: fn(&Row) -> &String| rows.iter().chain(Some(&header))
+        .map(|x| f(x).chars().count())
+        .max()
+        .unwrap_or(0) + 2;
+    let widths = [width(|x| &x.label), width(|x| &x.before), width(|x| &x.after), width(|x| &x.change)];
+
+    let mut out = String::new();
+    let pad = |out: &mut String, cell: &str, width: usize| {
+        out.push_str(cell);
+        for _ in cell.chars().count()..width {
+            out.push(' ');
+        }
+    };
+
+    for row in Some(&header).into_iter().chain(rows.iter()) {
+        pad(&mut out, &row.label, widths[0]);
+        pad(&mut out, &row.before, widths[1]);
+        pad(&mut out, &row.after, widths[2]);
+        pad(&mut out, &row.change, widths[3]);
+
+        if let Some(verdict) = row.verdict {
+            out.push_str(locale::tr(verdict));
+        }
+
+        let end = out.trim_end_matches(' ').len();
+        out.truncate(end);
+        out.push('\n');
+    }
+
+    out
+}
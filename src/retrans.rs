@@ -0,0 +1,89 @@
+use std::fs;
+use std::process::Command;
+
+/*
+ * tcp retransmission counters, sampled once per chunk so the loss real
+ * traffic sees can be put next to icmp loss. linux only: the totals come
+ * from /proc/net/snmp, a single peer's from `ss -ti`.
+ */
+
+pub enum Scope {
+    All,
+    /* an ss destination filter, e.g. 192.0.2.1 or 192.0.2.1:443 */
+    Peer(String),
+}
+
+impl Scope {
+    pub fn new(s: &str) -> Self {
+        if s == "all" {
+            Scope::All
+        } else {
+            Scope::Peer(s.to_string())
+        }
+    }
+}
+
+pub struct Sampler {
+    scope: Scope,
+    /* (retransmitted, sent) segments at the last sample */
+    last: Option<(u64, u64)>,
+}
+
+impl Sampler {
+    pub fn new(scope: Scope) -> Self {
+        Sampler {
+            scope,
+            last: None,
+        }
+    }
+
+    /// Retransmitted and sent segments since the previous sample
+    pub fn sample(&mut self) -> Option<(u64, u64)> {
+        let now = match self.scope {
+            Scope::All => snmp(),
+            Scope::Peer(ref peer) => ss(peer),
+        }?;
+
+        let last = self.last.replace(now)?;
+
+        /* counters of closed connections drop out of the per-peer sum */
+        Some((now.0.saturating_sub(last.0), now.1.saturating_sub(last.1)))
+    }
+}
+
+/* "Tcp: RtoAlgorithm ... OutSegs RetransSegs ..." followed by a line of values */
+fn snmp() -> Option<(u64, u64)> {
+    let text = fs::read_to_string("/proc/net/snmp").ok()?;
+    let mut lines = text.lines().filter(|x| x.starts_with("Tcp:"));
+
+    let names: Vec<&str> = lines.next()?.split_whitespace().collect();
+    let values: Vec<&str> = lines.next()?.split_whitespace().collect();
+
+    let value = |name: &str| names.iter()
+        .position(|x| *x == name)
+        .and_then(|i| values.get(i))
+        .and_then(|x| x.parse::<u64>().ok());
+
+    Some((value("RetransSegs")?, value("OutSegs")?))
+}
+
+/* sums "retrans:current/total" and "segs_out:n" over the matching connections */
+fn ss(peer: &str) -> Option<(u64, u64)> {
+    let out = Command::new("ss").arg("-tin").arg("dst").arg(peer).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+
+    let mut retrans = 0;
+    let mut sent = 0;
+
+    for word in String::from_utf8_lossy(&out.stdout).split_whitespace() {
+        if word.starts_with("retrans:") {
+            retrans += word.rsplit('/').next().and_then(|x| x.parse::<u64>().ok()).unwrap_or(0);
+        } else if let Some(segs) = word.strip_prefix("segs_out:") {
+            sent += segs.parse::<u64>().unwrap_or(0);
+        }
+    }
+
+    Some((retrans, sent))
+}
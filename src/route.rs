@@ -0,0 +1,85 @@
+use crate::ping::PacketChunk;
+
+/*
+ * the local network going down, as distinct from the targets: every
+ * target's newest chunk got nothing back and there is no default route
+ * left, the interface gone or its lease lost. only then is the routing
+ * table looked at, so it is read at most once a chunk. where it can't be
+ * read (anywhere but linux for now) the network is never taken for down,
+ * and the targets are alerted on as ever.
+ */
+
+/// Whether there is a default route, for ipv4 or ipv6. `None` when the
+/// routes can't be read here
+#[cfg(target_os = "linux")]
+pub fn default_route() -> Option<bool> {
+    use std::fs;
+
+    let v4 = fs::read_to_string("/proc/net/route").ok()?;
+    let v6 = fs::read_to_string("/proc/net/ipv6_route").unwrap_or_default();
+
+    Some(v4.lines().skip(1).any(default_v4) || v6.lines().any(default_v6))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn default_route() -> Option<bool> {
+    None
+}
+
+/* RTF_UP, and RTF_REJECT for the unreachable routes ipv6 keeps on lo */
+#[cfg(target_os = "linux")]
+const UP: u32 = 0x0001;
+#[cfg(target_os = "linux")]
+const REJECT: u32 = 0x0200;
+
+/* Iface Destination Gateway Flags RefCnt Use Metric Mask ..., in hex */
+#[cfg(target_os = "linux")]
+fn default_v4(line: &str) -> bool {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let flags = fields.get(3).and_then(|x| u32::from_str_radix(x, 16).ok()).unwrap_or(0);
+
+    fields.get(1) == Some(&"00000000") && fields.get(7) == Some(&"00000000")
+        && flags & UP != 0 && flags & REJECT == 0
+}
+
+/* destination, its prefix length, source, its prefix length, next hop, metric, refcnt, use, flags, iface */
+#[cfg(target_os = "linux")]
+fn default_v6(line: &str) -> bool {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let flags = fields.get(8).and_then(|x| u32::from_str_radix(x, 16).ok()).unwrap_or(0);
+
+    fields.first().is_some_and(|x| x.bytes().all(|x| x == b'0')) && fields.get(1) == Some(&"00")
+        && fields.get(9) != Some(&"lo") && flags & UP != 0 && flags & REJECT == 0
+}
+
+/// Whether the local network is down, going by the newest chunk of every stream
+pub struct Watcher {
+    down: Vec<bool>,
+    offline: bool,
+}
+
+impl Watcher {
+    pub fn new(streams: usize) -> Self {
+        Watcher {
+            down: vec![false; streams],
+            offline: false,
+        }
+    }
+
+    /// Take in a stream's newest chunk, true when it changed whether the network is down
+    pub fn update(&mut self, stream: usize, chunk: &PacketChunk) -> bool {
+        if let Some(down) = self.down.get_mut(stream) {
+            *down = chunk.down();
+        }
+
+        let offline = self.down.iter().all(|x| *x) && default_route() == Some(false);
+        let changed = offline != self.offline;
+        self.offline = offline;
+
+        changed
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dirs;
+
+/*
+ * named sessions. whatever was toggled from the keyboard is kept per
+ * session name in the state directory, as sessions/<name>.toml, and put
+ * back the next time the same session is started. layouts saved before
+ * they moved there, to the config directory, are still read.
+ */
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Layout {
+    /// Metrics overlay shown
+    pub debug: bool,
+    /// Tiles colored by estimated call quality
+    pub mos: bool,
+    /// Tiles colored by loss weighted for bursts, see `--weight-bursts`
+    pub bursts: bool,
+    /// "linear" or "log", see `--latency-color`
+    pub latency_color: Option<String>,
+    /// "newest-first" or "oldest-first", see `--order`
+    pub order: Option<String>,
+    /// "tiles" or "timeline", see `--view`
+    pub view: Option<String>,
+    /// Every target side by side rather than one at a time
+    pub split: bool,
+    /// Newest loss and latency in huge digits instead of the history
+    pub billboard: bool,
+    /// A month of daily uptime instead of the history
+    pub calendar: bool,
+}
+
+pub fn is_name(v: String) -> Result<(), String> {
+    if v.is_empty() || v.starts_with('.') || v.contains('/') || v.contains('\\') {
+        Err(String::from("Value must be a name, not a path"))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn path(name: &str) -> Option<PathBuf> {
+    Some(dirs::state()?.join("sessions").join(format!("{}.toml", name)))
+}
+
+/* where layouts were saved before */
+fn legacy_path(name: &str) -> Option<PathBuf> {
+    Some(dirs::config()?.join("sessions").join(format!("{}.toml", name)))
+}
+
+impl Layout {
+    /// The saved layout of a session, `Ok(None)` for a new session
+    pub fn load(name: &str) -> Result<Option<Layout>, String> {
+        for path in path(name).into_iter().chain(legacy_path(name)) {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            };
+
+            return toml::from_str(&text)
+                .map(Some)
+                .map_err(|e| format!("{}: {}", path.display(), e));
+        }
+
+        Ok(None)
+    }
+
+    pub fn save(&self, name: &str) -> io::Result<()> {
+        let path = path(name).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+            "no state directory, set HOME, XDG_STATE_HOME or --state-dir"))?;
+
+        let text = toml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, text)
+    }
+}
@@ -0,0 +1,442 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::alert::{self, Escalator, Policy};
+use crate::config::Settings;
+use crate::discover;
+use crate::error::{Context, Error};
+use crate::event::Events;
+use crate::exec::Exec;
+use crate::http::{self, Http};
+use crate::icmp::Ping;
+use crate::lock::{self, Lock};
+use crate::notify::{Chat, ChatSink};
+use crate::pager::{Pager, PagerSink};
+use crate::probe::{self, Prober, Worker};
+use crate::pcap;
+use crate::reflect;
+use crate::shift::Shift;
+use crate::smtp::{Smtp, SmtpSink};
+use crate::tcp::Tcp;
+use crate::target::{self, Label};
+use crate::term;
+use crate::twamp;
+use crate::udp::{self, Udp};
+
+/*
+ * what the settings make of a monitor, for the ui and the daemon alike:
+ * the targets and how each is labelled, the probers and workers that
+ * probe them, and the alerting on their chunks.
+ */
+
+/* hosts of a subnet given as a target, at most a /24 */
+const MAX_SUBNET: usize = 254;
+
+/* pings to each host of a subnet, to tell whether it answers */
+const SWEEP_PINGS: u64 = 3;
+
+/* the targets to show, the first as the options say and the others pinged alongside it */
+pub fn targets_of(settings: &Settings, replay: Option<&str>, attach: bool) -> Vec<String> {
+    let probing = replay.is_none();
+
+    let discovered = if settings.flag("discover-lan") && probing && !attach {
+        lan_hosts()
+    } else {
+        vec![]
+    };
+
+    let listed = match settings.value_of("targets").map(|x| target::load(Path::new(x))) {
+        Some(Ok(listed)) => listed,
+        Some(Err(e)) => clap::Error::with_description(&e, clap::ErrorKind::InvalidValue).exit(),
+        None => vec![],
+    };
+
+    let address = match (settings.value_of("address"), replay) {
+        (Some(address), _) => address,
+        (None, Some(path)) => path,
+        (None, None) if settings.value_of("reflector").is_some() => settings.value_of("reflector").unwrap(),
+        (None, None) if settings.value_of("twamp").is_some() => settings.value_of("twamp").unwrap(),
+        (None, None) if settings.value_of("tcp").is_some() => settings.value_of("tcp").unwrap(),
+        (None, None) if settings.value_of("http").is_some() => settings.value_of("http").unwrap(),
+        (None, None) if settings.value_of("probe-cmd").is_some() => settings.value_of("probe-cmd").unwrap(),
+        (None, None) if attach => "stdin",
+        (None, None) if !listed.is_empty() => &listed[0].address,
+        (None, None) if !discovered.is_empty() => &discovered[0],
+        (None, None) if settings.flag("discover-lan") => {
+            clap::Error::with_description(
+                "--discover-lan found no live hosts in the neighbor table",
+                clap::ErrorKind::InvalidValue).exit();
+        },
+        (None, None) => {
+            clap::Error::with_description(
+                "The following required arguments were not provided:\n    <address>",
+                clap::ErrorKind::MissingRequiredArgument).exit();
+        },
+    };
+
+    /* more hosts, all probed on the same schedule */
+    let mut addresses = vec![address];
+    addresses.extend(settings.values_of("address").into_iter().skip(1));
+    addresses.extend(settings.values_of("host"));
+
+    /* a host listed or a neighbor already given is only shown once */
+    for host in listed.iter().map(|x| &x.address).chain(discovered.iter()) {
+        if !addresses.iter().any(|x| x.eq_ignore_ascii_case(host)) {
+            addresses.push(host);
+        }
+    }
+
+    let single = ["via", "reflector", "twamp", "tcp", "http", "probe-cmd"].iter().all(|x| settings.value_of(x).is_none());
+    let subnets = probing && !attach && addresses.iter().any(|x| is_subnet(x));
+    if (addresses.len() > 1 || subnets) && (!probing || attach || !single) {
+        clap::Error::with_description(
+            "several hosts can only be pinged directly, not replayed, attached to \
+             or probed with --via, --reflector, --twamp, --tcp, --http or --probe-cmd",
+            clap::ErrorKind::ArgumentConflict).exit();
+    }
+
+    if !subnets {
+        return addresses.into_iter().map(String::from).collect();
+    }
+
+    /* a small subnet is every host in it that answers, in its place */
+    let timeout = Duration::from_millis(settings.value_of("timeout").unwrap().parse::<u64>().unwrap());
+    let mut expanded = vec![];
+
+    for address in addresses {
+        if !is_subnet(address) {
+            expanded.push(address.to_string());
+            continue;
+        }
+
+        let hosts = match discover::sweep(address) {
+            Ok(hosts) if hosts.len() <= MAX_SUBNET => hosts,
+            _ => {
+                clap::Error::with_description(
+                    &format!("'{}' is not a subnet of at most {} hosts, such as a /24\n\n\
+                              use 'packetloss discover --sweep {}' to find the responders of a larger one",
+                             address, MAX_SUBNET, address),
+                    clap::ErrorKind::InvalidValue).exit();
+            },
+        };
+
+        eprintln!("sweeping {} hosts of {}", hosts.len(), address);
+        let mut responders = discover::probe(hosts, SWEEP_PINGS, timeout);
+        responders.retain(|x| !x.1.down());
+
+        if responders.is_empty() {
+            clap::Error::with_description(
+                &format!("no host of {} answered", address),
+                clap::ErrorKind::InvalidValue).exit();
+        }
+        expanded.extend(responders.into_iter().map(|(x, _)| x.address));
+    }
+
+    expanded
+}
+
+/* the live hosts of the neighbor table, but for ipv6 link-local ones, which need their interface to be pinged */
+fn lan_hosts() -> Vec<String> {
+    let neighbors = match discover::neighbors() {
+        Ok(neighbors) => neighbors,
+        Err(e) => {
+            clap::Error::with_description(
+                &format!("--discover-lan can't read the neighbor table: {}", e),
+                clap::ErrorKind::Io).exit();
+        },
+    };
+
+    neighbors.into_iter()
+        .map(|x| x.address)
+        .filter(|x| !x.to_ascii_lowercase().starts_with("fe80:"))
+        .collect()
+}
+
+/* an address such as 192.168.1.0/28, rather than a single host */
+fn is_subnet(address: &str) -> bool {
+    matches!(address.split_once('/'), Some((base, _)) if base.parse::<Ipv4Addr>().is_ok())
+}
+
+/* whether a subnet is among the targets, its hosts then take turns */
+pub fn has_subnet(settings: &Settings) -> bool {
+    settings.values_of("address").into_iter().chain(settings.values_of("host")).any(is_subnet)
+}
+
+/* each target's tags and border color: as the targets file gives them, else --tag and --accent */
+pub fn labels(settings: &Settings, addresses: &[&str]) -> Result<Vec<Label>, String> {
+    let listed = match settings.value_of("targets") {
+        Some(path) => target::load(Path::new(path))?,
+        None => vec![],
+    };
+    let tags: Vec<String> = settings.values_of("tag").into_iter().map(String::from).collect();
+    let accent = settings.value_of("accent").and_then(term::parse_color);
+
+    Ok(addresses.iter()
+        .map(|address| match listed.iter().find(|x| x.address.eq_ignore_ascii_case(address)) {
+            Some(entry) => (
+                entry.tags.clone().unwrap_or_else(|| tags.clone()),
+                entry.accent.as_deref().and_then(term::parse_color).or(accent),
+            ),
+            None => (tags.clone(), accent),
+        })
+        .collect())
+}
+
+/* each target's alert policy: `policy`, with the recovery count the targets file gives it */
+pub fn policies(settings: &Settings, policy: &Policy, addresses: &[&str]) -> Result<Vec<Policy>, String> {
+    let listed = match settings.value_of("targets") {
+        Some(path) => target::load(Path::new(path))?,
+        None => vec![],
+    };
+
+    Ok(target::policies(&listed, addresses, policy))
+}
+
+/*
+ * another monitor writing the same recording would interleave its lines
+ * with ours, and is refused. another probing the same target doubles the
+ * packets both of them see, which is only warned of, and asked about when
+ * there is someone to ask. `None` when they said no.
+ */
+pub fn lock_instance(settings: &Settings, addresses: &[&str], ask: bool) -> Result<Option<Vec<Lock>>, Error> {
+    let mut locks = vec![];
+
+    if let Some(path) = settings.value_of("record-events") {
+        /* the same file by any name, whether or not it exists yet */
+        let file = Path::new(path);
+        let dir = file.parent().filter(|x| !x.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+        let name = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+            .join(file.file_name().unwrap_or_default());
+
+        match lock::take("recording", &name.to_string_lossy()).file(path)? {
+            Ok(lock) => locks.push(lock),
+            Err(held) => return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                format!("already being recorded by {}", held.holder()))).file(path),
+        }
+    }
+
+    let mut others = vec![];
+
+    for (i, address) in addresses.iter().enumerate() {
+        if addresses[..i].iter().any(|x| x.eq_ignore_ascii_case(address)) {
+            continue;
+        }
+
+        match lock::take("target", &address.to_lowercase())? {
+            Ok(lock) => locks.push(lock),
+            Err(held) => others.push((address, held)),
+        }
+    }
+
+    if others.is_empty() {
+        return Ok(Some(locks));
+    }
+
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+
+    for (address, held) in others.iter() {
+        writeln!(stderr, "warning: {} is already monitored by {}, each will count the other's probes",
+            address, held.holder())?;
+    }
+
+    if ask {
+        write!(stderr, "continue anyway? [y/N] ")?;
+        stderr.flush()?;
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(locks))
+}
+
+/* one prober per target: the first as the options say, the others pinged or probed over udp */
+pub fn probers_for(settings: &Settings, addresses: &[&str], timeout: Duration, probing: bool) -> Result<Vec<Prober>, Error> {
+    /* hosts probed from here, without a reflector */
+    let port = settings.value_of("port").map(|x| x.parse::<u16>().unwrap()).unwrap_or(udp::PORT);
+    let direct = |address: &str| match settings.value_of("proto") {
+        Some("udp") => Prober::Udp(Udp::new(address, port, timeout)),
+        _ => Prober::Icmp(Ping::new(address, timeout)),
+    };
+
+    let prober = match (settings.value_of("reflector"), settings.value_of("twamp"), settings.value_of("tcp")) {
+        (Some(peer), _, _) if probing => Prober::Reflector(reflect::Client::connect(peer, timeout).target(peer)?),
+        (_, Some(peer), _) if probing => Prober::Twamp(twamp::Client::connect(peer, timeout).target(peer)?),
+        (_, _, Some(peer)) if probing => Prober::Tcp(Tcp::new(peer, timeout)),
+        _ if probing && settings.value_of("http").is_some() => {
+            let method = settings.value_of("http-method").and_then(http::Method::parse).unwrap_or(http::Method::Head);
+            Prober::Http(Http::new(settings.value_of("http").unwrap(), method, timeout))
+        },
+        _ if probing && settings.value_of("probe-cmd").is_some() => {
+            Prober::Exec(Exec::new(settings.value_of("probe-cmd").unwrap(), timeout))
+        },
+        _ => direct(addresses[0]),
+    };
+
+    let mut probers = vec![prober];
+    probers.extend(addresses[1..].iter().map(|x| direct(x)));
+
+    Ok(probers)
+}
+
+/* the probers again from settings read anew, which may give another timeout */
+pub fn reprobe(settings: &Settings, addresses: &[&str], probing: bool) -> Result<Vec<Prober>, Error> {
+    let timeout = Duration::from_millis(settings.value_of("timeout").unwrap().parse::<u64>().unwrap());
+    probers_for(settings, addresses, timeout, probing)
+}
+
+/* a worker probing each stream, in the order they were given, and in turns if the schedule says */
+pub fn spawn(leaders: Vec<(usize, Prober)>, addresses: &[&str], schedule: &probe::Schedule, events: &Events) -> Vec<Worker> {
+    let n = leaders.len().max(1) as u32;
+
+    leaders.into_iter()
+        .enumerate()
+        .map(|(i, (leader, prober))| {
+            let start = if schedule.turns { schedule.interval * i as u32 / n } else { Duration::ZERO };
+            Worker::spawn(prober, schedule.clone(), i, start, addresses[leader].to_string(), events.sender())
+        })
+        .collect()
+}
+
+/* when a chunk counts as lossy, and the stages that fire on a run of them */
+pub fn alert_policy(settings: &Settings, probing: bool) -> Policy {
+    let alert_loss = settings.value_of("alert-loss").unwrap()
+        .parse::<u64>().unwrap();
+    let alert_cooldown = settings.value_of("alert-cooldown").unwrap()
+        .parse::<u64>().unwrap();
+    let alert_rearm = settings.value_of("alert-rearm").unwrap()
+        .parse::<u32>().unwrap();
+
+    let mut policy = Policy::new(alert_loss as f64 / 100.0,
+        Duration::from_secs(alert_cooldown), alert_rearm);
+    policy.mos = settings.value_of("alert-mos").map(|x| x.parse::<f64>().unwrap());
+    policy.burst = settings.value_of("alert-burst")
+        .map(|x| x.parse::<usize>().unwrap())
+        .filter(|x| *x > 0);
+
+    for stage in settings.values_of("escalate").into_iter().filter(|_| probing) {
+        /* already checked by the validator */
+        policy.stage(stage).unwrap();
+    }
+
+    policy
+}
+
+/* path shift detection for one target, when asked for and probing */
+pub fn shift(settings: &Settings, address: &str, probing: bool) -> Option<Shift> {
+    let factor = settings.value_of("alert-shift")
+        .map(|x| x.parse::<f64>().unwrap())
+        .filter(|_| probing)?;
+    let sustain = settings.value_of("alert-shift-for").unwrap()
+        .parse::<u64>().unwrap();
+    /* already checked by the validator */
+    let actions = settings.values_of("shift-action").into_iter()
+        .map(|x| alert::parse_action(x).unwrap())
+        .collect();
+
+    Some(Shift::new(address, factor, Duration::from_secs(sustain), actions))
+}
+
+/* alerting for one target, with its sinks and capture when probing */
+pub fn escalator(settings: &Settings, policy: &Policy, address: &str, probing: bool) -> Escalator {
+    let mut escalator = Escalator::new(policy.clone(), address);
+
+    if probing {
+        add_sinks(settings, &mut escalator);
+    }
+
+    if let Some(dir) = settings.value_of("pcap-dir").filter(|_| probing) {
+        let linger = settings.value_of("pcap-linger").unwrap()
+            .parse::<u64>().unwrap();
+        let max = settings.value_of("pcap-max").unwrap()
+            .parse::<u64>().unwrap();
+
+        escalator.pcap(pcap::Pcap::new(dir, settings.value_of("pcap-command").unwrap(),
+            Duration::from_secs(linger), max));
+    }
+
+    escalator
+}
+
+/* incident sinks configured by the settings */
+fn add_sinks(settings: &Settings, escalator: &mut Escalator) {
+    if let Some(server) = settings.value_of("smtp") {
+        let from = settings.value_of("smtp-from").unwrap();
+        let to = settings.values_of("smtp-to").into_iter()
+            .map(String::from)
+            .collect();
+
+        escalator.sink(Box::new(SmtpSink::new(Smtp::new(server, from, to))));
+    }
+
+    let mut chats = vec![];
+
+    if let Some(url) = settings.value_of("slack") {
+        chats.push(Chat::Slack(url.to_string()));
+    }
+
+    if let Some(url) = settings.value_of("discord") {
+        chats.push(Chat::Discord(url.to_string()));
+    }
+
+    if let Some(token) = settings.value_of("telegram-token") {
+        chats.push(Chat::Telegram {
+            token: token.to_string(),
+            chat: settings.value_of("telegram-chat").unwrap().to_string(),
+        });
+    }
+
+    let open = settings.value_of("open-template").unwrap();
+    let close = settings.value_of("close-template").unwrap();
+
+    for chat in chats {
+        escalator.sink(Box::new(ChatSink::new(chat, open, close)));
+    }
+
+    if let Some(key) = settings.value_of("pagerduty") {
+        escalator.sink(Box::new(PagerSink::new(Pager::PagerDuty(key.to_string()))));
+    }
+
+    if let Some(key) = settings.value_of("opsgenie") {
+        escalator.sink(Box::new(PagerSink::new(Pager::Opsgenie(key.to_string()))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::ping::Probe;
+
+    #[test]
+    fn reload_timeout() {
+        let path = env::temp_dir().join(format!("packetloss-{}-reload.toml", std::process::id()));
+        fs::write(&path, "timeout = 500\n").unwrap();
+
+        let matches = crate::app().get_matches_from(vec!["packetloss", "127.0.0.1"]);
+        let config = Config::load(&path).unwrap();
+        let settings = Settings::new(&matches, config.as_ref()).path(Some(&path));
+
+        fs::write(&path, "timeout = 2000\n").unwrap();
+        let fresh = settings.reload().unwrap();
+        let _ = fs::remove_file(&path);
+
+        let timeouts: Vec<Duration> = [&settings, &fresh].iter()
+            .map(|settings| match reprobe(settings, &["127.0.0.1"], false).unwrap().remove(0) {
+                Prober::Icmp(ping) => ping.timeout(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(timeouts, vec![Duration::from_millis(500), Duration::from_millis(2000)]);
+    }
+}
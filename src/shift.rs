@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+
+use chrono::prelude::*;
+
+use crate::alert::{self, Action, Alert};
+use crate::locale::{self, Text};
+use crate::ping::PacketChunk;
+
+/*
+ * path shifts: a reroute onto another transit moves the latency of every
+ * reply at once and leaves it there, with or without loss. the baseline is
+ * the median of each chunk's median rtt over the last BASELINE chunks, the
+ * current level the same over the last RECENT. once the current level has
+ * stayed a factor away from the baseline for long enough the path counts
+ * as changed: the change is marked in the history, a move up runs the
+ * shift actions, and the new level becomes the baseline. chunks with no
+ * replies say nothing about the path and are skipped.
+ */
+
+const BASELINE: usize = 60;
+const RECENT: usize = 5;
+
+/* a baseline of fewer chunks than this isn't judged against yet */
+const WARMUP: usize = 2 * RECENT;
+
+/* moves smaller than this (ms) are jitter on any path, however large the factor */
+const FLOOR: f64 = 5.0;
+
+fn median(values: &VecDeque<f64>) -> f64 {
+    let mut values: Vec<f64> = values.iter().cloned().collect();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    values[values.len() / 2]
+}
+
+/// Validator for `--alert-shift`, a factor above 1
+pub fn is_factor(v: String) -> Result<(), String> {
+    match v.parse::<f64>() {
+        Ok(factor) if factor > 1.0 => Ok(()),
+        _ => Err(String::from("Value must be a factor above 1, e.g. 1.5")),
+    }
+}
+
+/// Watches the latency of one target for a sustained move
+pub struct Shift {
+    target: String,
+    factor: f64,
+    sustain: chrono::Duration,
+    actions: Vec<Action>,
+    baseline: VecDeque<f64>,
+    recent: VecDeque<f64>,
+    /* when the current level left the baseline, chunks since, and whether it went up */
+    since: Option<(DateTime<Local>, u32, bool)>,
+}
+
+impl Shift {
+    pub fn new(target: &str, factor: f64, sustain: std::time::Duration, actions: Vec<Action>) -> Self {
+        Shift {
+            target: target.to_string(),
+            factor,
+            sustain: chrono::Duration::from_std(sustain).unwrap_or_else(|_| chrono::Duration::zero()),
+            actions,
+            baseline: VecDeque::with_capacity(BASELINE),
+            recent: VecDeque::with_capacity(RECENT),
+            since: None,
+        }
+    }
+
+    /// Take the factor, sustain time and actions of `other`, keeping the baseline
+    pub fn configure(&mut self, other: Shift) {
+        self.factor = other.factor;
+        self.sustain = other.sustain;
+        self.actions = other.actions;
+    }
+
+    /// Feed one chunk, returning a label for the history once the path has shifted
+    pub fn update(&mut self, chunk: &PacketChunk) -> Option<String> {
+        let rtt = chunk.median_rtt()?;
+
+        self.recent.push_back(rtt);
+        if self.recent.len() > RECENT {
+            let old = self.recent.pop_front().unwrap();
+
+            /* a level still in question stays out of the baseline */
+            if self.since.is_none() {
+                self.baseline.push_back(old);
+                if self.baseline.len() > BASELINE {
+                    self.baseline.pop_front();
+                }
+            }
+        }
+
+        if self.baseline.len() < WARMUP || self.recent.len() < RECENT {
+            return None;
+        }
+
+        let baseline = median(&self.baseline);
+        let current = median(&self.recent);
+        let up = current > baseline;
+
+        let moved = (current - baseline).abs() >= FLOOR
+            && (current >= baseline * self.factor || current * self.factor <= baseline);
+
+        self.since = match self.since {
+            _ if !moved => None,
+            Some((start, chunks, was)) if was == up => Some((start, chunks + 1, up)),
+            _ => Some((chunk.time(), 1, up)),
+        };
+
+        let (start, chunks, _) = self.since?;
+        if chunk.time() - start < self.sustain {
+            return None;
+        }
+
+        if up {
+            let alert = Alert {
+                target: self.target.clone(),
+                stage: 0,
+                consecutive: chunks,
+                loss: chunk.loss(),
+                latency: current,
+                time: chunk.time().to_rfc3339(),
+                baseline: Some(baseline),
+            };
+
+            for action in self.actions.iter() {
+                alert::run(action.clone(), alert.clone());
+            }
+        }
+
+        /* the new path is the one to compare against from here */
+        self.baseline = self.recent.clone();
+        self.since = None;
+
+        Some(locale::format(Text::PathShift, &[&format!("{:.0}", baseline), &format!("{:.0}", current)]))
+    }
+}
@@ -0,0 +1,51 @@
+use log::info;
+
+use crate::event::{Event, EventSender};
+
+/*
+ * signals, for a monitor run by a supervisor with no keyboard attached:
+ *
+ *   SIGHUP   read the config file again, as R does
+ *   SIGUSR1  write the statistics so far and carry on, to the log in the
+ *            ui and to stdout headless
+ *   SIGTERM  stop as q does, the history summed up on the way out
+ *
+ * SIGINT is left alone, in the ui ctrl-c is a key like any other. where
+ * there are no unix signals nothing is listened for.
+ */
+
+#[cfg(unix)]
+pub fn listen(events: EventSender) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let (mut hangups, mut stats, mut stops) = match (
+            signal(SignalKind::hangup()),
+            signal(SignalKind::user_defined1()),
+            signal(SignalKind::terminate()),
+        ) {
+            (Ok(hangups), Ok(stats), Ok(stops)) => (hangups, stats, stops),
+            _ => return,
+        };
+
+        loop {
+            let event = tokio::select! {
+                Some(_) = hangups.recv() => Event::Reload,
+                Some(_) = stats.recv() => Event::Stats,
+                Some(_) = stops.recv() => {
+                    info!("SIGTERM, stopping");
+                    Event::Stop
+                },
+                else => return,
+            };
+
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn listen(_events: EventSender) {
+}
@@ -0,0 +1,140 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use std::thread;
+
+use chrono::prelude::*;
+use log::warn;
+
+use crate::alert::{Incident, Sink};
+
+/*
+ * minimal smtp client for a plain (no tls, no auth) relay such as a
+ * local mta or an internal smarthost.
+ */
+
+#[derive(Debug, Clone)]
+pub struct Smtp {
+    server: String,
+    from: String,
+    to: Vec<String>,
+}
+
+impl Smtp {
+    pub fn new(server: &str, from: &str, to: Vec<String>) -> Self {
+
+        /* default to the standard relay port */
+        let server = if server.contains(':') {
+            server.to_string()
+        } else {
+            format!("{}:25", server)
+        };
+
+        Smtp {
+            server,
+            from: from.to_string(),
+            to,
+        }
+    }
+
+    pub fn send(&self, subject: &str, body: &str) -> io::Result<()> {
+        let addr = self.server.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for smtp server"))?;
+
+        let timeout = Duration::from_secs(30);
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        expect(&mut reader, 220)?;
+
+        command(&mut writer, &mut reader, "EHLO packetloss", 250)?;
+        command(&mut writer, &mut reader, &format!("MAIL FROM:<{}>", self.from), 250)?;
+
+        for to in self.to.iter() {
+            command(&mut writer, &mut reader, &format!("RCPT TO:<{}>", to), 250)?;
+        }
+
+        command(&mut writer, &mut reader, "DATA", 354)?;
+
+        write!(writer, "From: {}\r\n", self.from)?;
+        write!(writer, "To: {}\r\n", self.to.join(", "))?;
+        write!(writer, "Subject: {}\r\n", subject)?;
+        write!(writer, "Date: {}\r\n", Local::now().to_rfc2822())?;
+        write!(writer, "Content-Type: text/plain; charset=utf-8\r\n\r\n")?;
+
+        for line in body.lines() {
+            /* dot-stuffing, rfc 5321 4.5.2 */
+            if line.starts_with('.') {
+                write!(writer, ".")?;
+            }
+            write!(writer, "{}\r\n", line)?;
+        }
+
+        command(&mut writer, &mut reader, ".", 250)?;
+        command(&mut writer, &mut reader, "QUIT", 221)?;
+
+        Ok(())
+    }
+}
+
+fn command<R: BufRead, W: Write>(writer: &mut W, reader: &mut R, line: &str, code: u16) -> io::Result<()> {
+    write!(writer, "{}\r\n", line)?;
+    writer.flush()?;
+    expect(reader, code)
+}
+
+/* read a (possibly multi-line) reply and check its status code */
+fn expect<R: BufRead>(reader: &mut R, code: u16) -> io::Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "smtp server closed the connection"));
+        }
+
+        let status = line.get(0..3)
+            .and_then(|x| x.parse::<u16>().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("bad smtp reply: {}", line.trim())))?;
+
+        /* "250-..." continues, "250 ..." ends */
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+
+        if status != code {
+            return Err(io::Error::other(format!("smtp error: {}", line.trim())));
+        }
+
+        return Ok(());
+    }
+}
+
+/// Emails a summary of every incident once it closes
+pub struct SmtpSink {
+    smtp: Smtp,
+}
+
+impl SmtpSink {
+    pub fn new(smtp: Smtp) -> Self {
+        SmtpSink { smtp }
+    }
+}
+
+impl Sink for SmtpSink {
+    fn close(&mut self, incident: &Incident) {
+        let smtp = self.smtp.clone();
+        let subject = format!("[packetloss] {} recovered after {:.0}% peak loss",
+            incident.target, incident.worst_loss() * 100.0);
+        let body = incident.summary();
+
+        thread::spawn(move || {
+            if let Err(e) = smtp.send(&subject, &body) {
+                warn!("incident email: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::vec_deque::{self, VecDeque};
+use std::ops::{Index, IndexMut};
+
+use crate::ping::PacketChunk;
+
+/// A bounded history of chunks, newest first
+///
+/// Once `max` chunks are held the oldest is dropped for each new one, so a
+/// long running monitor doesn't grow without limit.
+pub struct ChunkStore {
+    items: VecDeque<PacketChunk>,
+    min_latency: f64,
+    max: usize,
+}
+
+impl ChunkStore {
+    pub fn new(max: usize) -> Self {
+        ChunkStore {
+            items: VecDeque::new(),
+            min_latency: f64::INFINITY,
+            max,
+        }
+    }
+
+    /// Add the newest chunk
+    pub fn insert(&mut self, item: PacketChunk) {
+        if item.latency() < self.min_latency {
+            self.min_latency = item.latency();
+        }
+
+        self.items.push_front(item);
+
+        /* prevent oom */
+        if self.items.len() >= self.max {
+            self.items.pop_back();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Lowest chunk latency seen, counting chunks that have since been dropped
+    pub fn min_latency(&self) -> f64 {
+        self.min_latency
+    }
+
+    /// Approximate bytes held, see `PacketChunk::memory`
+    pub fn memory(&self) -> usize {
+        self.items.iter().map(|x| x.memory()).sum()
+    }
+
+    /// The `i`th newest chunk
+    pub fn get(&self, i: usize) -> Option<&PacketChunk> {
+        self.items.get(i)
+    }
+
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut PacketChunk> {
+        self.items.get_mut(i)
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut PacketChunk> {
+        self.items.front_mut()
+    }
+
+    /// Chunks from newest to oldest
+    pub fn iter(&self) -> vec_deque::Iter<'_, PacketChunk> {
+        self.items.iter()
+    }
+}
+
+impl Index<usize> for ChunkStore {
+    type Output = PacketChunk;
+
+    fn index(&self, i: usize) -> &PacketChunk {
+        &self.items[i]
+    }
+}
+
+impl IndexMut<usize> for ChunkStore {
+    fn index_mut(&mut self, i: usize) -> &mut PacketChunk {
+        &mut self.items[i]
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::ping::PacketChunk;
+
+/*
+ * chunk streams, so probing and display can run in different places.
+ * `packetloss serve` writes one line per chunk, and with --heartbeat-every
+ * a line now and then to say it is still alive. --no-tui adds the totals
+ * so far on SIGUSR1 and on the way out:
+ *
+ *   chunk <encoded chunk>
+ *   heartbeat <chunks written so far>
+ *   stats <key>=<value> ...
+ *
+ * and a stream reads them back on a thread, from a pipe or a remote
+ * `serve` started over ssh.
+ */
+
+pub fn write(out: &mut impl Write, chunk: &PacketChunk) -> io::Result<()> {
+    writeln!(out, "chunk {}", chunk.encode())?;
+    out.flush()
+}
+
+/* `Some(None)` for lines that carry no chunk */
+fn parse(line: &str) -> Option<Option<PacketChunk>> {
+    let mut fields = line.splitn(2, ' ');
+
+    match fields.next()? {
+        "chunk" => PacketChunk::decode(fields.next()?).map(Some),
+        "heartbeat" | "stats" => Some(None),
+        _ => None,
+    }
+}
+
+pub struct Stream {
+    chunks: Receiver<io::Result<PacketChunk>>,
+    /* kept so the remote end is killed along with us */
+    child: Option<Child>,
+    /* last line the remote end wrote to stderr, to explain why it stopped */
+    stderr: Arc<Mutex<String>>,
+}
+
+impl Stream {
+    /// Read chunks from anything, in the background
+    pub fn read<R: Read + Send + 'static>(input: R, name: String) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (i, line) in BufReader::new(input).lines().enumerate() {
+                let chunk = line.and_then(|x| parse(&x).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData,
+                        format!("{}: invalid chunk at line {}", name, i + 1))
+                }));
+
+                let chunk = match chunk {
+                    Ok(None) => continue,
+                    Ok(Some(chunk)) => Ok(chunk),
+                    Err(e) => Err(e),
+                };
+
+                let failed = chunk.is_err();
+                if tx.send(chunk).is_err() || failed {
+                    return;
+                }
+            }
+
+            let _ = tx.send(Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                format!("{}: stream ended", name))));
+        });
+
+        Stream {
+            chunks: rx,
+            child: None,
+            stderr: Arc::new(Mutex::new(String::new())),
+        }
+    }
+
+    /// Run `packetloss serve ARGS` on the host of `ssh://[user@]host[:port][/path/to/packetloss]`
+    pub fn ssh(via: &str, args: &[String]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput,
+            format!("{}: expected ssh://[user@]host[:port][/path/to/packetloss]", via));
+
+        if !via.starts_with("ssh://") {
+            return Err(invalid());
+        }
+
+        let rest = &via["ssh://".len()..];
+        let (target, program) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "packetloss"),
+        };
+
+        let (target, port) = match target.rfind(':') {
+            Some(i) => (&target[..i], Some(&target[i + 1..])),
+            None => (target, None),
+        };
+
+        if target.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut ssh = Command::new("ssh");
+        ssh.arg("-T");
+        if let Some(port) = port {
+            ssh.arg("-p").arg(port);
+        }
+
+        let mut child = ssh.arg(target)
+            .arg(program)
+            .arg("serve")
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            /* ssh would scribble over the ui, keep its complaints for later */
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+
+        let mut stream = Stream::read(stdout, via.to_string());
+        stream.child = Some(child);
+
+        let last = stream.stderr.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if !line.trim().is_empty() {
+                    *last.lock().unwrap() = line;
+                }
+            }
+        });
+
+        Ok(stream)
+    }
+
+    /// The next chunk if one has arrived, an error once the stream is over
+    pub fn next(&self) -> io::Result<Option<PacketChunk>> {
+        let result = match self.chunks.try_recv() {
+            Ok(chunk) => chunk.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                "stream ended")),
+        };
+
+        result.map_err(|e| {
+            let stderr = self.stderr.lock().unwrap();
+            if stderr.is_empty() {
+                e
+            } else {
+                io::Error::new(e.kind(), format!("{} ({})", e, stderr))
+            }
+        })
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if let Some(ref mut child) = self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! A one-row strip of chunks for embedding in other terminal UIs
+//!
+//! Each cell is one chunk, colored the way packetloss colors its tiles, the
+//! newest on the right. [`StripState`] holds the history and how it is
+//! colored, and is drawn by [`Strip`] with the `tui` feature or by
+//! [`ratatui::Strip`] with the `ratatui` feature.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use packetloss::icmp::Ping;
+//! use packetloss::strip::StripState;
+//!
+//! let ping = Ping::new("example.com", Duration::from_secs(1));
+//! let mut state = StripState::new(500);
+//!
+//! state.push(ping.ping(10).unwrap());
+//! /* then draw it each frame, e.g. frame.render_stateful_widget(Strip, area, &mut state) */
+//! ```
+
+use crate::ping::{ColorMode, PacketChunk, Scale};
+use crate::store::ChunkStore;
+
+/// The chunks a strip shows and how they are colored
+pub struct StripState {
+    history: ChunkStore,
+    mode: ColorMode,
+    scale: Scale,
+    range: Option<(f64, f64)>,
+}
+
+impl StripState {
+    /// An empty strip keeping at most `max` chunks
+    pub fn new(max: usize) -> Self {
+        StripState {
+            history: ChunkStore::new(max),
+            mode: ColorMode::Loss,
+            scale: Scale::Linear,
+            range: None,
+        }
+    }
+
+    /// Add the newest chunk
+    pub fn push(&mut self, chunk: PacketChunk) {
+        self.history.insert(chunk);
+    }
+
+    pub fn history(&self) -> &ChunkStore {
+        &self.history
+    }
+
+    pub fn mode(&mut self, mode: ColorMode) {
+        self.mode = mode;
+    }
+
+    pub fn scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    /// Per-packet latency (ms) drawn fully green and fully red, instead of
+    /// the minimum seen and the timeout
+    pub fn range(&mut self, range: Option<(f64, f64)>) {
+        self.range = range;
+    }
+
+    /* each visible chunk as (column, color, down), newest in the last column */
+    #[cfg(any(feature = "tui", feature = "ratatui"))]
+    fn cells(&self, width: u16) -> impl Iterator<Item = (u16, (u8, u8, u8), bool)> + '_ {
+        self.history.iter()
+            .take(width as usize)
+            .enumerate()
+            .map(move |(i, chunk)| {
+                /* chunk latency is the sum over its packets */
+                let sent = chunk.sent() as f64;
+                let range = match self.range {
+                    Some((min, max)) => (min * sent, max * sent),
+                    None => (self.history.min_latency(), chunk.timeout * sent),
+                };
+
+                (width - 1 - i as u16, chunk.color(range, self.range.is_some(), self.mode, self.scale), chunk.down())
+            })
+    }
+}
+
+/* dead chunks get the tiles' hatch mark */
+#[cfg(any(feature = "tui", feature = "ratatui"))]
+const DOWN: &str = "✖";
+
+/// Draws a [`StripState`] into the first row of a tui area
+#[cfg(feature = "tui")]
+pub struct Strip<'a> {
+    state: &'a StripState,
+}
+
+#[cfg(feature = "tui")]
+impl<'a> Strip<'a> {
+    pub fn new(state: &'a StripState) -> Self {
+        Strip { state }
+    }
+}
+
+#[cfg(feature = "tui")]
+impl<'a> tui::widgets::Widget for Strip<'a> {
+    fn draw(&mut self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+        use tui::style::Color;
+
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        let hatch = crate::ping::palette().bad;
+
+        for (x, (r, g, b), down) in self.state.cells(area.width) {
+            let cell = buf.get_mut(area.x + x, area.y);
+            cell.set_symbol(if down { DOWN } else { " " });
+            cell.set_fg(Color::Rgb(hatch.0, hatch.1, hatch.2));
+            cell.set_bg(Color::Rgb(r, g, b));
+        }
+    }
+}
+
+/// The same strip as a ratatui `StatefulWidget`, for apps built on ratatui
+#[cfg(feature = "ratatui")]
+pub mod ratatui {
+    use ::ratatui::buffer::Buffer;
+    use ::ratatui::layout::Rect;
+    use ::ratatui::style::Color;
+    use ::ratatui::widgets::StatefulWidget;
+
+    use super::{StripState, DOWN};
+
+    /// Render with `frame.render_stateful_widget(Strip, area, &mut state)`
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct Strip;
+
+    impl StatefulWidget for Strip {
+        type State = StripState;
+
+        fn render(self, area: Rect, buf: &mut Buffer, state: &mut StripState) {
+            if area.width == 0 || area.height == 0 {
+                return;
+            }
+
+            let hatch = crate::ping::palette().bad;
+
+            for (x, (r, g, b), down) in state.cells(area.width) {
+                if let Some(cell) = buf.cell_mut((area.x + x, area.y)) {
+                    cell.set_symbol(if down { DOWN } else { " " });
+                    cell.set_fg(Color::Rgb(hatch.0, hatch.1, hatch.2));
+                    cell.set_bg(Color::Rgb(r, g, b));
+                }
+            }
+        }
+    }
+}
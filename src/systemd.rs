@@ -0,0 +1,111 @@
+use std::env;
+use std::io;
+use std::time::Duration;
+
+use log::warn;
+
+/*
+ * the sd_notify(3) protocol, for --no-tui run as a Type=notify service
+ * with WatchdogSec=. systemd passes a datagram socket in $NOTIFY_SOCKET
+ * and the watchdog period in $WATCHDOG_USEC; the monitor says READY=1
+ * once the first chunk is in, WATCHDOG=1 with every chunk after, and
+ * STOPPING=1 on the way out. a name starting with @ is in the abstract
+ * namespace. with no socket, or where there are no unix sockets,
+ * nothing is sent.
+ */
+
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: Option<(std::os::unix::net::UnixDatagram, String)>,
+    watchdog: Option<Duration>,
+    ready: bool,
+}
+
+impl Notifier {
+    pub fn from_env() -> Self {
+        /* a watchdog meant for another process, e.g. a wrapper script, isn't ours to feed */
+        let ours = env::var("WATCHDOG_PID").ok()
+            .and_then(|x| x.parse::<u32>().ok())
+            .is_none_or(|x| x == std::process::id());
+        let watchdog = env::var("WATCHDOG_USEC").ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .filter(|x| *x > 0 && ours)
+            .map(Duration::from_micros);
+
+        Notifier {
+            #[cfg(unix)]
+            socket: env::var("NOTIFY_SOCKET").ok()
+                .filter(|x| !x.is_empty())
+                .and_then(|path| match std::os::unix::net::UnixDatagram::unbound() {
+                    Ok(socket) => Some((socket, path)),
+                    Err(e) => {
+                        warn!("NOTIFY_SOCKET: {}", e);
+                        None
+                    },
+                }),
+            watchdog,
+            ready: false,
+        }
+    }
+
+    /// How often systemd expects to hear from us, when it watches
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    /// A chunk is in: ready the first time, alive every time
+    pub fn chunk(&mut self) {
+        if !self.ready {
+            self.ready = true;
+            self.send("READY=1");
+        }
+
+        if self.watchdog.is_some() {
+            self.send("WATCHDOG=1");
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+
+    /* a lost notification is only logged, the monitor carries on either way */
+    fn send(&self, state: &str) {
+        if let Err(e) = self.write(state) {
+            warn!("notifying systemd of {}: {}", state, e);
+        }
+    }
+
+    #[cfg(unix)]
+    fn write(&self, state: &str) -> io::Result<()> {
+        let (socket, path) = match self.socket {
+            Some((ref socket, ref path)) => (socket, path),
+            None => return Ok(()),
+        };
+
+        if let Some(name) = path.strip_prefix('@') {
+            return abstract_send(socket, name, state);
+        }
+
+        socket.send_to(state.as_bytes(), path).map(|_| ())
+    }
+
+    #[cfg(not(unix))]
+    fn write(&self, _state: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_send(socket: &std::os::unix::net::UnixDatagram, name: &str, state: &str) -> io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let addr = SocketAddr::from_abstract_name(name.as_bytes())?;
+    socket.send_to_addr(state.as_bytes(), &addr).map(|_| ())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn abstract_send(_socket: &std::os::unix::net::UnixDatagram, _name: &str, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "abstract sockets are linux only"))
+}
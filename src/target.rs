@@ -0,0 +1,205 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tui::style::Color;
+
+use crate::alert::{Escalator, Policy};
+use crate::health::Tracker;
+use crate::ping::PacketChunk;
+use crate::shift::Shift;
+use crate::term::{self, SelectableLogList};
+
+/* how long the newest tile flashes after a lossy chunk */
+const BLINK_DURATION: Duration = Duration::from_millis(2000);
+
+/*
+ * one monitored host: its history, health and alerting. every target is
+ * probed on the same schedule, and the keys act on the one in view.
+ */
+pub struct Target<'a> {
+    pub address: String,
+    /* labels for grouping, in the window title and the output */
+    pub tags: Vec<String>,
+    pub list: SelectableLogList<'a>,
+    pub health: Tracker,
+    pub escalator: Escalator,
+    pub shift: Option<Shift>,
+    /* when the newest tile started flashing, toggled every tick */
+    flash: Option<Instant>,
+}
+
+impl<'a> Target<'a> {
+    pub fn new(address: &str, list: SelectableLogList<'a>, health: Tracker, escalator: Escalator, shift: Option<Shift>) -> Self {
+        Target {
+            address: address.to_string(),
+            tags: vec![],
+            list,
+            health,
+            escalator,
+            shift,
+            flash: None,
+        }
+    }
+
+    /// Take the thresholds of `policy` and the settings of `shift`, keeping
+    /// the history and any baseline already learnt
+    pub fn configure(&mut self, policy: &Policy, shift: Option<Shift>) {
+        self.health.configure(policy.threshold, policy.rearm);
+        self.escalator.set_policy(policy.clone());
+        self.shift = match (self.shift.take(), shift) {
+            (Some(mut old), Some(new)) => {
+                old.configure(new);
+                Some(old)
+            },
+            (_, new) => new,
+        };
+    }
+
+    /// Take the tags and border color the target was given, `None` for the default border
+    pub fn label(&mut self, tags: Vec<String>, accent: Option<Color>) {
+        self.tags = tags;
+        self.list.accent(accent);
+    }
+
+    /// Take in a finished chunk, true when the target's health changed
+    pub fn insert(&mut self, chunk: PacketChunk, blink: bool) -> bool {
+        if blink && self.escalator.is_lossy(&chunk) {
+            self.flash = Some(Instant::now());
+        }
+
+        let changed = self.health.update(&chunk);
+        if changed {
+            self.list.health(self.health.health());
+        }
+
+        self.escalator.update(&chunk);
+        self.list.insert(chunk);
+
+        changed
+    }
+
+    /// Step the flash of the newest tile, true while it is flashing
+    pub fn blink(&mut self, tick_rate: Duration) -> bool {
+        let start = match self.flash {
+            Some(start) => start,
+            None => return false,
+        };
+
+        let elapsed = start.elapsed();
+        let phase = (elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64)
+            / (tick_rate.as_secs() * 1000 + tick_rate.subsec_millis() as u64).max(1);
+
+        /* a few blinks, then leave the tile alone */
+        if elapsed >= BLINK_DURATION {
+            self.flash = None;
+            self.list.flash(false);
+        } else {
+            self.list.flash(phase.is_multiple_of(2));
+        }
+
+        true
+    }
+}
+
+/*
+ * the targets file of --targets, for targets that each carry their own
+ * tags and border color:
+ *
+ *   [[target]]
+ *   address = "192.168.1.1"
+ *   tags = ["LAN"]
+ *   accent = "green"
+ *   rearm = 5
+ *
+ * what an entry leaves out, --tag, --accent and --alert-rearm give.
+ */
+
+/// A target's tags and border color
+pub type Label = (Vec<String>, Option<Color>);
+
+/// One target of a targets file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub address: String,
+    pub tags: Option<Vec<String>>,
+    /* a name or #rrggbb */
+    pub accent: Option<String>,
+    /* good chunks in a row before the target counts as recovered */
+    pub rearm: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    target: Vec<Entry>,
+}
+
+/// The targets of a targets file, in order
+pub fn load(path: &Path) -> Result<Vec<Entry>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file: File = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    for entry in file.target.iter() {
+        if let Some(Err(e)) = entry.accent.clone().map(term::is_color) {
+            return Err(format!("{}: {}: accent '{}': {}", path.display(), entry.address, entry.accent.as_ref().unwrap(), e));
+        }
+    }
+
+    Ok(file.target)
+}
+
+/// The alert policy of each of `addresses`: `policy`, with the recovery
+/// count of its entry in `listed` when the entry gives one
+pub fn policies(listed: &[Entry], addresses: &[&str], policy: &Policy) -> Vec<Policy> {
+    addresses.iter()
+        .map(|address| {
+            let mut policy = policy.clone();
+            if let Some(rearm) = listed.iter().find(|x| x.address.eq_ignore_ascii_case(address)).and_then(|x| x.rearm) {
+                policy.rearm = rearm;
+            }
+            policy
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::ping;
+
+    /* 10 probes, `lost` of them lost */
+    fn chunk(lost: usize) -> PacketChunk {
+        let mut chunk = PacketChunk::new(1000.0);
+        for i in 0..10 {
+            chunk.push(if i < lost { None } else { Some(ping::reply(10.0, 0)) });
+        }
+        chunk
+    }
+
+    #[test]
+    fn recover_per_target() {
+        let path = env::temp_dir().join(format!("packetloss-{}-targets.toml", std::process::id()));
+        fs::write(&path, "[[target]]\naddress = \"10.0.0.1\"\nrearm = 1\n\n\
+                          [[target]]\naddress = \"10.0.0.2\"\nrearm = 4\n").unwrap();
+        let listed = load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        /* the third is not listed, so it recovers as --alert-rearm says */
+        let policy = Policy::new(0.2, Duration::from_secs(0), 2);
+        let recovered: Vec<usize> = policies(&listed, &["10.0.0.1", "10.0.0.2", "10.0.0.3"], &policy).iter()
+            .map(|policy| {
+                let mut tracker = Tracker::new(policy.threshold, policy.rearm);
+                tracker.update(&chunk(10));
+                (1..).find(|_| tracker.update(&chunk(0))).unwrap()
+            })
+            .collect();
+
+        assert_eq!(recovered, vec![1, 4, 2]);
+    }
+}
@@ -0,0 +1,76 @@
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::ping::{ms, AddrFamily, PingError, Probe, ProbeResult, Reply};
+
+/*
+ * tcp connect probes, for networks that drop icmp. each probe is one
+ * handshake to HOST:PORT, timed up to the connection, then closed again.
+ * a refusal is the host answering with a reset, as good a round trip as
+ * any, so only a connect that times out counts as dropped.
+ */
+
+/// TCP connect probes to one `HOST:PORT`
+pub struct Tcp {
+    addr: String,
+    timeout: Duration,
+    seq: AtomicU16,
+}
+
+impl Tcp {
+    /// Probes to `addr`, e.g. `example.com:443` or `[::1]:22`, counting
+    /// connects slower than `timeout` as lost
+    pub fn new(addr: &str, timeout: Duration) -> Self {
+        Tcp {
+            addr: addr.to_string(),
+            timeout,
+            seq: AtomicU16::new(0),
+        }
+    }
+}
+
+impl Probe for Tcp {
+    fn probe(&self) -> ProbeResult {
+        let target = self.addr.to_socket_addrs()
+            .map_err(|_| PingError::Resolve(self.addr.clone()))?
+            .next()
+            .ok_or_else(|| PingError::Resolve(self.addr.clone()))?;
+
+        let mut reply = Reply {
+            hostname: self.addr.clone(),
+            address: target.ip().to_string(),
+            family: if target.is_ipv6() { AddrFamily::IPV6 } else { AddrFamily::IPV4 },
+            latency_ms: -1.0,
+            dropped: 1,
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) as i32,
+            recv_ttl: -1,
+            recv_qos: 0,
+        };
+
+        let start = Instant::now();
+        match TcpStream::connect_timeout(&target, self.timeout) {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => (),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock => return Ok(reply),
+            Err(e) => return Err(e.into()),
+        }
+
+        reply.latency_ms = ms(start.elapsed());
+        reply.dropped = 0;
+        Ok(reply)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// Validator for `HOST:PORT`
+pub fn is_host_port(v: String) -> Result<(), String> {
+    match v.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+        _ => Err(String::from("Value must be HOST:PORT")),
+    }
+}
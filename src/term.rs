@@ -1,181 +1,81 @@
 use std::iter::Iterator;
-use std::cmp::min;
-use std::f64::INFINITY;
-use std::collections::vec_deque::VecDeque;
+
+use chrono::{DateTime, Local};
 
 use tui::layout::Rect;
 use tui::buffer::Buffer;
 use tui::widgets::{Block, Widget, Borders};
 use tui::style::{Style, Color};
 
-use crate::ping::{DrawablePacket, PacketChunk};
+use crate::draw::{LogList, View};
+use crate::ping::{ColorMode, Order, PacketChunk, Scale};
+use crate::health::Health;
+use crate::locale::{self, Text};
 
-pub struct LogList<'b> {
+pub struct SelectableLogList<'b> {
+    selection: Option<usize>,
     block: Option<Block<'b>>,
-    items: VecDeque<PacketChunk>,
-    min_latency: f64,
-    max: usize,
+    list: LogList,
+    min_height: u16,
+    /* newest tile is currently lit up */
+    flash: bool,
+    health: Health,
+    /* border color, set per target, white by default */
+    accent: Option<Color>,
+    /* moving past either end goes round to the other */
+    wrap: bool,
 }
 
-
-impl<'b> LogList<'b> {
+impl<'b> SelectableLogList<'b> {
     pub fn new(max: usize) -> Self {
-        LogList {
+        SelectableLogList {
+            list: LogList::new(max),
+            selection: None,
             block: None,
-            items: VecDeque::new(),
-            min_latency: INFINITY,
-            max: max,
+            min_height: 5,
+            flash: false,
+            health: Health::Unknown,
+            accent: None,
+            wrap: false,
         }
     }
-}
-
 
-impl<'b> LogList<'b> {
-    pub fn insert(&mut self, item: PacketChunk) {
-        if item.latency() < self.min_latency {
-            self.min_latency = item.latency();
-        }
-
-        self.items.push_front(item);
-
-        /* prevent oom */
-        if self.items.len() >= self.max {
-            self.items.pop_back();
-        }
+    pub fn mode(&mut self, mode: ColorMode) {
+        self.list.set_mode(mode);
     }
 
-    pub fn len(&self) -> usize {
-        self.items.len()
+    pub fn scale(&mut self, scale: Scale) {
+        self.list.set_scale(scale);
     }
 
-    pub fn block(&mut self, block: Block<'b>) {
-        self.block = Some(block);
+    /// Pin the latency drawn fully green and fully red, or None for the lowest seen and the timeout
+    pub fn range(&mut self, range: Option<(f64, f64)>) {
+        self.list.set_range(range);
     }
 
-    pub fn partition(&mut self, size: Rect) -> LogListPartitioner {
-        LogListPartitioner {
-            x: 0,
-            y: 0,
-            offset_x: size.x,
-            offset_y: size.y,
-            width: size.width,
-            max_width: size.width,
-            height: size.height,
-            length: self.len() as u16,
-        }
+    pub fn order(&mut self, order: Order) {
+        self.list.set_order(order);
     }
-}
 
-#[derive(Debug)]
-pub struct LogListPartitioner {
-    x: u16,
-    y: u16,
-    offset_x: u16,
-    offset_y: u16,
-    width: u16,
-    max_width: u16,
-    height: u16,
-    length: u16,
-}
-
-fn ceil(a: u16, b: u16) -> u16 {
-    if a == 0 {
-        0
-    } else {
-        1 + ((a - 1) / b)
+    pub fn view(&mut self, view: View) {
+        self.list.set_view(view);
     }
-}
 
-impl Iterator for LogListPartitioner {
-    type Item = Rect;
-
-    /*
-     * we want to use up all of size
-     * dont exceed size
-     */
-    fn next(&mut self) -> Option<Self::Item> {
-
-        if self.height == 0 || self.length == 0 {
-            return None;
-        }
-
-        let x = self.x;
-        let y = self.y;
-
-        let after = min(self.length, (self.height - 1) * self.max_width);
-
-        let mut wdiv = (self.length - after) + 1;
-        let hdiv = min(self.height, self.length);
-
-        if self.height == 1 && wdiv > 0 {
-            wdiv = wdiv - 1;
-        }
-
-        let width = ceil(self.width, wdiv);
-        let height = ceil(self.height, hdiv);
-
-        self.width -= width;
-        self.height -= height - 1;
-
-        /* if the line's width was consumed consume one more line and reset width */
-        if self.width == 0 && self.height > 1 {
-            self.width = self.max_width;
-            self.height -= 1;
-            self.y += 1;
-        }
-
-        self.x += width;
-        self.y += height - 1;
-
-        if self.x == self.max_width {
-            self.x = 0;
-        }
-
-        self.length -= 1;
-
-        Some(Rect::new(x+self.offset_x, y+self.offset_y, width, height))
+    pub fn accent(&mut self, color: Option<Color>) {
+        self.accent = color;
     }
-}
-
-impl<'b> Widget for LogList<'b> {
-    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
-
-        let area = self.block.map(|ref mut x| {
-            x.draw(area, buf);
-            x.inner(area)
-        }).unwrap_or(area);
-
-        if area.width == 0 || area.height == 0 {
-            return;
-        }
-
-        let partitions = self.partition(area);
-        for (item, area) in self.items.iter_mut().zip(partitions) {
-            let mut drawable = DrawablePacket::new(item, self.min_latency);
-            drawable.draw(area, buf);
-        }
 
+    pub fn wrap(&mut self, on: bool) {
+        self.wrap = on;
     }
-}
-
-pub struct SelectableLogList<'b> {
-    selection: Option<usize>,
-    block: Option<Block<'b>>,
-    list: LogList<'b>,
-    min_height: u16,
-}
 
-impl<'b> SelectableLogList<'b> {
-    pub fn new(max: usize) -> Self {
-        SelectableLogList {
-            list: LogList::new(max),
-            selection: None,
-            block: None,
-            min_height: 5,
-        }
+    pub fn health(&mut self, health: Health) {
+        self.health = health;
     }
 
     pub fn insert(&mut self, item: PacketChunk) {
+        /* the flash belongs to the tile that is about to stop being newest */
+        self.flash(false);
         self.list.insert(item);
 
         if let Some(i) = self.selection {
@@ -183,7 +83,7 @@ impl<'b> SelectableLogList<'b> {
             if i > 0 {
                 self.select(i+1);
             } else {
-                self.list.items[1].tint_weight(0.0);
+                self.list.items_mut()[1].tint_weight(0.0);
             }
         }
     }
@@ -192,20 +92,72 @@ impl<'b> SelectableLogList<'b> {
         self.list.len()
     }
 
+    pub fn newest(&self) -> Option<&PacketChunk> {
+        self.list.items().get(0)
+    }
+
+    /// The whole history, newest first
+    pub fn chunks(&self) -> impl Iterator<Item = &PacketChunk> {
+        self.list.items().iter()
+    }
+
+    pub fn memory(&self) -> usize {
+        self.list.memory()
+    }
+
+    /// Light up the newest tile, or put it back the way it was
+    pub fn flash(&mut self, on: bool) {
+        if self.flash == on || self.list.is_empty() {
+            return;
+        }
+
+        self.flash = on;
+
+        let selected = self.selection == Some(0);
+        let item = &mut self.list.items_mut()[0];
+
+        if on {
+            item.tint((255, 255, 255));
+            item.tint_weight(0.6);
+        } else {
+            item.tint((0, 0, 0));
+            item.tint_weight(if selected { 0.5 } else { 0.0 });
+        }
+    }
+
+    /// Place a marker after the newest chunk, false when there is none yet
+    pub fn mark(&mut self, time: DateTime<Local>, label: &str) -> bool {
+        match self.list.items_mut().front_mut() {
+            Some(item) => {
+                item.mark(time, label);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /* an index past the end, e.g. from before the history was trimmed, is ignored */
     pub fn select(&mut self, i: usize) {
+        if i >= self.len() {
+            return;
+        }
 
-        if let Some(i) = self.selection {
-            self.list.items[i].tint_weight(0.0);
+        if self.flash {
+            self.flash(false);
+        }
+
+        if let Some(item) = self.selection.and_then(|x| self.list.items_mut().get_mut(x)) {
+            item.tint_weight(0.0);
         }
 
         self.selection = Some(i);
-        self.list.items[i].tint_weight(0.5);
+        self.list.items_mut()[i].tint_weight(0.5);
     }
 
     pub fn clear(&mut self) {
 
-        if let Some(i) = self.selection {
-            self.list.items[i].tint_weight(0.0);
+        if let Some(item) = self.selection.and_then(|x| self.list.items_mut().get_mut(x)) {
+            item.tint_weight(0.0);
         }
 
         self.selection = None;
@@ -215,32 +167,73 @@ impl<'b> SelectableLogList<'b> {
         self.selection.is_some() && self.selection.unwrap() < self.len()
     }
 
-    pub fn select_next(&mut self) {
+    fn select_older(&mut self) {
+        if self.len() == 0 {
+            return;
+        }
+
         if let Some(i) = self.selection {
             if i < self.len() - 1 {
                 self.select(i+1);
+            } else if self.wrap {
+                self.select(0);
             }
         } else {
             self.select(0);
         }
     }
 
-    pub fn select_prev(&mut self) {
+    fn select_newer(&mut self) {
+        if self.len() == 0 {
+            return;
+        }
+
         if let Some(i) = self.selection {
             if i > 0 {
                 self.select(i-1);
+            } else if self.wrap {
+                self.select(self.len() - 1);
             }
         } else {
             self.select(0);
         }
     }
 
+    /* next, previous, first and last follow the tile flow, whichever its order */
+    pub fn select_next(&mut self) {
+        match self.list.order() {
+            Order::NewestFirst => self.select_older(),
+            Order::OldestFirst => self.select_newer(),
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        match self.list.order() {
+            Order::NewestFirst => self.select_newer(),
+            Order::OldestFirst => self.select_older(),
+        }
+    }
+
     pub fn select_last(&mut self) {
-        self.select(self.len() - 1);
+        if self.len() == 0 {
+            return;
+        }
+
+        match self.list.order() {
+            Order::NewestFirst => self.select(self.len() - 1),
+            Order::OldestFirst => self.select(0),
+        }
     }
 
     pub fn select_first(&mut self) {
-        self.select(0);
+        if self.len() == 0 {
+            return;
+        }
+
+        match self.list.order() {
+            Order::NewestFirst => self.select(0),
+            Order::OldestFirst => self.select(self.len() - 1),
+        }
     }
 }
 
@@ -261,11 +254,17 @@ impl<'b> Widget for SelectableLogList<'b> {
         let block = Block::default()
             .borders(Borders::ALL)
             .border_style(Style::default()
-                .fg(Color::White))
+                .fg(self.accent.unwrap_or(Color::White)))
             .style(Style::default()
                 .bg(Color::Black));
 
-        let mut inspect_block = block.clone().title(" Inspect packet ");
+        /* the schedule the chunk was taken on goes in the title, when it was kept */
+        let inspect_title = match self.list.items()[i].params() {
+            Some(params) => format!(" {} · {} ", locale::tr(Text::InspectPacket), locale::format(Text::InspectParams,
+                &[&params.chunk_size, &params.interval, &params.spacing, &self.list.items()[i].timeout()])),
+            None => format!(" {} ", locale::tr(Text::InspectPacket)),
+        };
+        let mut inspect_block = block.title(&inspect_title);
 
         let mut rect = self.list.partition(area).next().unwrap();
 
@@ -274,7 +273,7 @@ impl<'b> Widget for SelectableLogList<'b> {
         }
 
         /* keep it centered */
-        if rect.height % 2 == 0 {
+        if rect.height.is_multiple_of(2) {
             rect.height += 1;
         }
 
@@ -286,18 +285,199 @@ impl<'b> Widget for SelectableLogList<'b> {
         inspect_block.draw(rect, buf);
         let inner = inspect_block.inner(rect);
 
-        self.list.items[i].tint_weight(0.0);
-        let mut drawable = DrawablePacket::new(&self.list.items[i], self.list.min_latency);
-        drawable.draw(inner, buf);
-        self.list.items[i].tint_weight(0.5);
+        self.list.items_mut()[i].tint_weight(0.0);
+
+        self.list.inspect(i, inner, buf);
+        self.list.items_mut()[i].tint_weight(0.5);
 
         self.block = None;
 
         area.height -= rect.height;
         area.y += rect.height;
 
-        self.list.block(block.clone().title(" Packet list "));
-        self.list.draw(area, buf);
-        self.list.block = None;
+        let title = format!(" {} {} ", self.health.glyph(), locale::tr(Text::PacketList));
+
+        /* the title is built each frame, too short-lived for the list to hold */
+        let mut list_block = block
+            .title(&title)
+            .title_style(Style::default().fg(self.health.color()));
+        list_block.draw(area, buf);
+        self.list.draw(list_block.inner(area), buf);
+    }
+}
+
+/// A color name such as `cyan`, or `#rrggbb`
+pub fn parse_color(s: &str) -> Option<Color> {
+    if s.starts_with('#') && s.len() == 7 {
+        let channel = |i: usize| u8::from_str_radix(s.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb(channel(1)?, channel(3)?, channel(5)?));
+    }
+
+    let color = match s.to_lowercase().as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "white" => Color::White,
+        _ => return None,
+    };
+
+    Some(color)
+}
+
+pub fn is_color(v: String) -> Result<(), String> {
+    parse_color(&v)
+        .map(|_| ())
+        .ok_or_else(|| String::from("Value must be a color name or #rrggbb"))
+}
+
+/* tile colors are blended, so they need the channels */
+pub fn is_rgb(v: String) -> Result<(), String> {
+    crate::ping::parse_rgb(&v)
+        .map(|_| ())
+        .ok_or_else(|| String::from("Value must be #rrggbb"))
+}
+
+/// One row naming targets with their health glyph, the one in view highlighted
+pub struct TargetBar<'a> {
+    targets: Vec<(&'a str, Health)>,
+    current: Option<usize>,
+}
+
+impl<'a> TargetBar<'a> {
+    pub fn new(targets: Vec<(&'a str, Health)>, current: Option<usize>) -> Self {
+        TargetBar { targets, current }
+    }
+}
+
+impl<'a> Widget for TargetBar<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        let mut x = area.left();
+
+        for (i, (name, health)) in self.targets.iter().enumerate() {
+            if x >= area.right() {
+                break;
+            }
+
+            let style = if Some(i) == self.current {
+                Style::default().fg(Color::Black).bg(Color::Gray)
+            } else {
+                Style::default().fg(health.color())
+            };
+
+            let text = format!(" {} {} ", health.glyph(), name);
+            buf.set_stringn(x, area.y, &text, (area.right() - x) as usize, style);
+            x = x.saturating_add(text.chars().count() as u16);
+        }
+    }
+}
+
+/* a cell of the overview is a name over its newest loss */
+const CELL_HEIGHT: u16 = 2;
+const CELL_WIDTH: u16 = 24;
+
+/// Every target as a small cell colored by its health, for more targets
+/// than fit a bar. the chosen one is filled in
+pub struct Overview<'a> {
+    targets: Vec<(&'a str, Health, Option<f64>)>,
+    current: usize,
+}
+
+impl<'a> Overview<'a> {
+    /// `targets` as their name, health and newest loss, if any chunk is in
+    pub fn new(targets: Vec<(&'a str, Health, Option<f64>)>, current: usize) -> Self {
+        Overview { targets, current }
+    }
+}
+
+impl<'a> Widget for Overview<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        if area.width == 0 || area.height < CELL_HEIGHT {
+            return;
+        }
+
+        /* as wide as the longest name needs, and as many across as fit */
+        let longest = self.targets.iter().map(|x| x.0.chars().count() as u16).max().unwrap_or(0);
+        let width = (longest + 4).clamp(12, CELL_WIDTH).min(area.width);
+        let across = (area.width / width).max(1) as usize;
+        let rows = (area.height / CELL_HEIGHT) as usize;
+
+        /* rows scroll off the top to keep the chosen cell in view */
+        let skip = (self.current / across + 1).saturating_sub(rows);
+
+        for (i, (name, health, loss)) in self.targets.iter().enumerate().skip(skip * across) {
+            let row = (i / across - skip) as u16;
+            if row as usize >= rows {
+                break;
+            }
+
+            let x = area.x + (i % across) as u16 * width;
+            let y = area.y + row * CELL_HEIGHT;
+            /* a column apart, so neighbours of the same color don't run together */
+            let cell = Rect::new(x, y, width - 1, CELL_HEIGHT);
+
+            let style = if i == self.current {
+                buf.set_background(&cell, health.color());
+                Style::default().fg(Color::Black).bg(health.color())
+            } else {
+                Style::default().fg(health.color())
+            };
+
+            let loss = loss.map(|x| format!(" {:.1}%", x * 100.0)).unwrap_or_default();
+            buf.set_stringn(x, y, format!(" {} {}", health.glyph(), name), cell.width as usize, style);
+            buf.set_stringn(x, y + 1, loss, cell.width as usize, style);
+        }
+    }
+}
+
+/// The one-line input for a marker label, along the bottom of the screen
+pub struct Prompt<'a> {
+    text: &'a str,
+}
+
+impl<'a> Prompt<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Prompt { text }
+    }
+}
+
+impl<'a> Widget for Prompt<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        let style = Style::default()
+            .fg(Color::Black)
+            .bg(Color::Cyan);
+
+        buf.set_background(&area, Color::Cyan);
+        buf.set_stringn(area.x, area.y,
+            format!(" {}", locale::format(Text::MarkerPrompt, &[&self.text])),
+            area.width as usize, style);
+    }
+}
+
+/// A passing message along the bottom of the screen, e.g. after a reload
+pub struct Notice<'a> {
+    text: &'a str,
+    error: bool,
+}
+
+impl<'a> Notice<'a> {
+    pub fn new(text: &'a str, error: bool) -> Self {
+        Notice { text, error }
+    }
+}
+
+impl<'a> Widget for Notice<'a> {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        let background = if self.error { Color::Red } else { Color::Cyan };
+        let style = Style::default()
+            .fg(Color::Black)
+            .bg(background);
+
+        buf.set_background(&area, background);
+        buf.set_stringn(area.x, area.y, format!(" {}", self.text), area.width as usize, style);
     }
 }
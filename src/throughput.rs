@@ -0,0 +1,100 @@
+use std::io::Read;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/*
+ * periodic throughput tests, run beside probing so loss can be lined up
+ * with how busy the link was. a test is either an external command
+ * printing Mbit/s (iperf3 piped through jq, a speedtest cli, ...) or a
+ * timed http download.
+ */
+
+/* a built-in download stops after this long */
+const DOWNLOAD_TIME: Duration = Duration::from_secs(5);
+
+pub enum Test {
+    Command(String),
+    Download(String),
+}
+
+pub struct Throughput {
+    test: Test,
+    running: Option<Receiver<Option<f64>>>,
+}
+
+impl Throughput {
+    pub fn new(test: Test) -> Self {
+        Throughput {
+            test,
+            running: None,
+        }
+    }
+
+    /// Start a test unless one is still running
+    pub fn start(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let test = match self.test {
+            Test::Command(ref cmd) => Test::Command(cmd.clone()),
+            Test::Download(ref url) => Test::Download(url.clone()),
+        };
+
+        thread::spawn(move || {
+            let _ = tx.send(match test {
+                Test::Command(cmd) => command(&cmd),
+                Test::Download(url) => download(&url),
+            });
+        });
+
+        self.running = Some(rx);
+    }
+
+    /// Mbit/s of a finished test, `Some(None)` when it failed
+    pub fn result(&mut self) -> Option<Option<f64>> {
+        let result = self.running.as_ref()?.try_recv().ok()?;
+        self.running = None;
+        Some(result)
+    }
+}
+
+/* the first number the command prints is taken as Mbit/s */
+fn command(cmd: &str) -> Option<f64> {
+    let out = Command::new("sh").arg("-c").arg(cmd).output().ok()?;
+
+    String::from_utf8_lossy(&out.stdout)
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .filter_map(|x| x.parse::<f64>().ok())
+        .next()
+}
+
+fn download(url: &str) -> Option<f64> {
+    let response = ureq::get(url)
+        .timeout(DOWNLOAD_TIME * 2)
+        .call()
+        .ok()?;
+
+    let mut reader = response.into_reader();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut bytes = 0u64;
+    let start = Instant::now();
+
+    while start.elapsed() < DOWNLOAD_TIME {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => bytes += n as u64,
+            Err(_) => return None,
+        }
+    }
+
+    let secs = start.elapsed().as_secs() as f64 + start.elapsed().subsec_nanos() as f64 / 1e9;
+    if secs == 0.0 {
+        return None;
+    }
+
+    Some(bytes as f64 * 8.0 / secs / 1_000_000.0)
+}
@@ -0,0 +1,187 @@
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ping::{self, ms, PacketChunk};
+
+/*
+ * TWAMP-light sender (RFC 5357 appendix I), unauthenticated mode. test
+ * packets go straight to a reflector, usually on port 862, with no
+ * control session. the reply carries the reflector's own sequence
+ * number, which shows how many probes made it there, and its receive
+ * and send times, which take its processing delay out of the rtt.
+ */
+
+pub const PORT: u16 = 862;
+
+/* the reflected packet is 41 bytes, padding the probe to match keeps it symmetric */
+const SIZE: usize = 41;
+
+/* unsynchronized clock, error estimate multiplier 1, scale 0 */
+const ERROR_ESTIMATE: u16 = 0x0001;
+
+/* seconds between the ntp epoch (1900) and the unix epoch */
+const NTP_OFFSET: u64 = 2_208_988_800;
+
+fn ntp_now() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let fraction = ((now.subsec_nanos() as u64) << 32) / 1_000_000_000;
+
+    ((now.as_secs() + NTP_OFFSET) << 32) | fraction
+}
+
+/* ntp timestamp difference in ms */
+fn ntp_ms(later: u64, earlier: u64) -> f64 {
+    later.wrapping_sub(earlier) as i64 as f64 * 1000.0 / (1u64 << 32) as f64
+}
+
+fn u32_at(buf: &[u8], i: usize) -> u32 {
+    let mut x = [0u8; 4];
+    x.copy_from_slice(&buf[i..i + 4]);
+    u32::from_be_bytes(x)
+}
+
+fn u64_at(buf: &[u8], i: usize) -> u64 {
+    let mut x = [0u8; 8];
+    x.copy_from_slice(&buf[i..i + 8]);
+    u64::from_be_bytes(x)
+}
+
+struct Reflected {
+    /* the reflector's sequence number, one per probe it answered */
+    seq: u32,
+    /* time spent inside the reflector, ms */
+    dwell: f64,
+}
+
+pub struct Client {
+    socket: UdpSocket,
+    timeout: Duration,
+    seq: u32,
+    /* last (sender, reflector) sequence pair seen */
+    last: Option<(u32, u32)>,
+}
+
+impl Client {
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Connect to `HOST` or `HOST:PORT`, the port defaulting to 862
+    pub fn connect(peer: &str, timeout: Duration) -> io::Result<Self> {
+        let peer = match peer.to_socket_addrs() {
+            Ok(addrs) => addrs,
+            Err(_) => (peer, PORT).to_socket_addrs()?,
+        }
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}: no address", peer)))?;
+
+        let local = if peer.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(peer)?;
+
+        Ok(Client {
+            socket,
+            timeout,
+            seq: 0,
+            last: None,
+        })
+    }
+
+    fn probe(&self, seq: u32) -> io::Result<()> {
+        let mut buf = [0u8; SIZE];
+        buf[0..4].copy_from_slice(&seq.to_be_bytes());
+        buf[4..12].copy_from_slice(&ntp_now().to_be_bytes());
+        buf[12..14].copy_from_slice(&ERROR_ESTIMATE.to_be_bytes());
+
+        self.socket.send(&buf).map(|_| ())
+    }
+
+    /* wait for the reflection of `seq`, dropping late replies to earlier probes */
+    fn receive(&self, seq: u32, deadline: Instant) -> io::Result<Option<Reflected>> {
+        let mut buf = [0u8; 1500];
+
+        loop {
+            let left = match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if left > Duration::from_millis(0) => left,
+                _ => return Ok(None),
+            };
+
+            self.socket.set_read_timeout(Some(left))?;
+
+            let n = match self.socket.recv(&mut buf) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => continue,
+                Err(e) => return Err(e),
+            };
+
+            if n < SIZE - 1 || u32_at(&buf, 24) != seq {
+                continue;
+            }
+
+            return Ok(Some(Reflected {
+                seq: u32_at(&buf, 0),
+                dwell: ntp_ms(u64_at(&buf, 4), u64_at(&buf, 16)).max(0.0),
+            }));
+        }
+    }
+
+    /// Like `Ping::burst`, with losses split by direction from the reflector's sequence numbers
+    pub fn burst(&mut self, count: u64, spacing: Duration) -> io::Result<PacketChunk> {
+        let mut chunk = PacketChunk::new((self.timeout.as_secs() * 1000 + self.timeout.subsec_millis() as u64) as f64);
+        let origin = Instant::now();
+
+        let mut up = 0;
+        /* probes since the last reply of this chunk */
+        let mut trailing = 0;
+
+        for _ in 0..count {
+            let start = Instant::now();
+            let seq = self.seq;
+            self.seq = self.seq.wrapping_add(1);
+
+            let reply = match self.probe(seq) {
+                Ok(()) => self.receive(seq, start + self.timeout)?,
+                Err(_) => None,
+            };
+
+            let rtt = ms(start.elapsed());
+
+            match reply {
+                Some(reflected) => {
+                    /* a gap in the reflector's numbering is probes that never got there */
+                    if let Some((sent, seen)) = self.last {
+                        let probes = seq.wrapping_sub(sent);
+                        let answered = reflected.seq.wrapping_sub(seen);
+                        up += probes.saturating_sub(answered) as usize;
+                    }
+
+                    self.last = Some((seq, reflected.seq));
+                    trailing = 0;
+                    chunk.push(Some(ping::reply((rtt - reflected.dwell).max(0.0), 0)));
+                },
+                None => {
+                    trailing += 1;
+                    chunk.push(None);
+                },
+            }
+
+            chunk.stamp(ms(start.duration_since(origin)));
+
+            if let Some(rest) = spacing.checked_sub(start.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+
+        /* losses after the last reply can't be placed yet, count them as outbound */
+        let lost = chunk.sent() - chunk.received();
+        let up = (up + trailing).min(lost);
+
+        chunk.set_oneway(up, lost - up);
+
+        Ok(chunk)
+    }
+}
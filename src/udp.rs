@@ -0,0 +1,105 @@
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+
+use crate::ping::{ms, AddrFamily, PingError, Probe, ProbeResult, Reply};
+
+/*
+ * udp probes, for telling whether udp in particular is being dropped. each
+ * probe is one datagram from a fresh socket. a closed port answers with an
+ * icmp port unreachable, which a connected socket sees as a refusal, and a
+ * cooperating responder such as an echo service sends the datagram back.
+ * either counts as a reply, silence until the timeout as a drop.
+ *
+ * hosts rate limit their port unreachables, so bursts to a closed port can
+ * show loss that isn't there. space the probes out, or use a responder.
+ */
+
+/// The first port traceroute uses, closed almost everywhere
+pub const PORT: u16 = 33434;
+
+/// UDP probes to one host and port
+pub struct Udp {
+    addr: String,
+    port: u16,
+    timeout: Duration,
+    seq: AtomicU16,
+}
+
+impl Udp {
+    /// Probes to `port` on `addr`, a host name or address, counting replies
+    /// slower than `timeout` as lost
+    pub fn new(addr: &str, port: u16, timeout: Duration) -> Self {
+        Udp {
+            addr: addr.to_string(),
+            port,
+            timeout,
+            seq: AtomicU16::new(0),
+        }
+    }
+}
+
+impl Probe for Udp {
+    fn probe(&self) -> ProbeResult {
+        let target = (self.addr.as_str(), self.port).to_socket_addrs()
+            .map_err(|_| PingError::Resolve(self.addr.clone()))?
+            .next()
+            .ok_or_else(|| PingError::Resolve(self.addr.clone()))?;
+
+        /* a socket per probe, so an unreachable meant for an earlier one can't show up here */
+        let socket = UdpSocket::bind(if target.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" })?;
+        socket.connect(target)?;
+
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let token = Local::now().timestamp_nanos_opt().unwrap_or(0) as u64 ^ seq as u64;
+        let payload = format!("packetloss {} {}", seq, token);
+
+        let mut reply = Reply {
+            hostname: self.addr.clone(),
+            address: target.ip().to_string(),
+            family: if target.is_ipv6() { AddrFamily::IPV6 } else { AddrFamily::IPV4 },
+            latency_ms: -1.0,
+            dropped: 1,
+            seq: seq as i32,
+            recv_ttl: -1,
+            recv_qos: 0,
+        };
+
+        let start = Instant::now();
+        let deadline = start + self.timeout;
+        socket.send(payload.as_bytes())?;
+
+        let mut buf = [0u8; 512];
+        loop {
+            let left = match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if left > Duration::from_millis(0) => left,
+                _ => return Ok(reply),
+            };
+            socket.set_read_timeout(Some(left))?;
+
+            match socket.recv(&mut buf) {
+                /* anything else is a stray datagram, keep waiting */
+                Ok(n) if buf[..n] == *payload.as_bytes() => break,
+                Ok(_) => continue,
+                /* windows reports the unreachable as a reset */
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused
+                    || e.kind() == io::ErrorKind::ConnectionReset => break,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut => return Ok(reply),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        reply.latency_ms = ms(start.elapsed());
+        reply.dropped = 0;
+        Ok(reply)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
@@ -0,0 +1,1146 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDate};
+use log::{debug, info, warn};
+use tui::Terminal;
+use tui::layout::Rect;
+use tui::terminal::Frame;
+use tui::widgets::Widget;
+
+use crate::backend::{self, Backend};
+use crate::billboard::Billboard;
+use crate::calendar::{self, Calendar};
+use crate::checkpoint::{Checkpoints, Left};
+use crate::command::{self, Command};
+use crate::config::{Settings, Source};
+use crate::crash;
+use crate::draw::View;
+use crate::error::{Context, Error};
+use crate::event::{self, Event, Events, Key};
+use crate::health::Tracker;
+use crate::heartbeat::Heartbeat;
+use crate::locale::{self, Text};
+use crate::metrics::{self, Metrics, MetricsPane};
+use crate::netns;
+use crate::ping::{self, ColorMode, Order, PacketChunk, Scale};
+use crate::probe::{self, Prober, Streams, Worker};
+use crate::record::{Recorded, Recorder, Replay};
+use crate::report;
+use crate::retrans;
+use crate::route;
+use crate::session::Layout;
+use crate::setup::{alert_policy, escalator, has_subnet, labels, lock_instance, policies, probers_for, reprobe, shift, spawn, targets_of};
+use crate::stream::Stream;
+use crate::target::Target;
+use crate::term::{Notice, Overview, Prompt, SelectableLogList, TargetBar};
+use crate::throughput::{self, Throughput};
+
+/*
+ * the monitor in a terminal. `run` sets it up from the settings and
+ * drives it: it draws when something changed, at most once a frame, and
+ * hands each chunk and event to a method of `Monitor`, which holds what
+ * is shown and what is probed.
+ */
+
+/* history size above which we ask before starting */
+const MEMORY_WARNING: usize = 1 << 30;
+
+/* how long a notice stays along the bottom */
+const NOTICE: Duration = Duration::from_secs(5);
+
+/* targets past which they open as a grid rather than a bar */
+const OVERVIEW: usize = 10;
+
+/* `attach` shows chunks piped in from a `serve` instead of probing */
+pub async fn run(settings: &Settings<'_>, replay: Option<&str>, attach: bool) -> Result<(), Error> {
+
+    /* a replayed session never probes or alerts, even once the recording runs out */
+    let probing = replay.is_none();
+
+    let addresses = targets_of(settings, replay, attach);
+    let addresses: Vec<&str> = addresses.iter().map(String::as_str).collect();
+    let address = addresses[0];
+
+    let chunk_size = settings.value_of("chunk-size").unwrap()
+        .parse::<u64>().unwrap();
+    let interval = settings.value_of("interval").unwrap()
+        .parse::<u64>().unwrap();
+    let timeout = settings.value_of("timeout").unwrap()
+        .parse::<u64>().unwrap();
+    let max = settings.value_of("max").unwrap()
+        .parse::<usize>().unwrap();
+    let fps = settings.value_of("fps").unwrap()
+        .parse::<u64>().unwrap()
+        .max(1);
+    ping::set_palette(palette(settings));
+    let slow = settings.value_of("slow")
+        .map(|x| x.parse::<u64>().unwrap());
+    let game = settings.value_of("game")
+        .map(|x| x.parse::<u64>().unwrap().max(1));
+    /* in game mode the frame budget takes the place of the soft timeout */
+    let slow = match game {
+        Some(hz) => Some(settings.value_of("frame-budget")
+            .map(|x| x.parse::<u64>().unwrap())
+            .unwrap_or(1000 / hz)),
+        None => slow,
+    };
+    let spacing = game
+        .map(|hz| Duration::from_micros(1_000_000 / hz))
+        .unwrap_or_default();
+    let capture = settings.value_of("capture")
+        .map(|x| x.parse::<u64>().unwrap())
+        .filter(|x| *x > 0);
+    let capture_spacing = settings.value_of("capture-spacing").unwrap()
+        .parse::<u64>().unwrap();
+
+    let policy = alert_policy(settings, probing);
+
+    let throughput = match (settings.value_of("throughput-cmd"), settings.value_of("throughput-url")) {
+        (Some(cmd), _) => Some(Throughput::new(throughput::Test::Command(cmd.to_string()))),
+        (_, Some(url)) => Some(Throughput::new(throughput::Test::Download(url.to_string()))),
+        _ => None,
+    }.filter(|_| probing);
+    let throughput_every = settings.value_of("throughput-every").unwrap()
+        .parse::<u64>().unwrap()
+        .max(1);
+
+    /* keys bound to a run of commands, over whatever they did before */
+    let macros: HashMap<Key, Vec<Command>> = settings.values_of("bind").into_iter()
+        /* already checked by the validator */
+        .map(|x| command::parse_macro(x).unwrap())
+        .collect();
+
+    let mut retransmits = settings.value_of("retransmits")
+        .filter(|_| probing)
+        .map(|x| retrans::Sampler::new(retrans::Scope::new(x)));
+
+    /* the first sample is only a baseline */
+    if let Some(ref mut sampler) = retransmits {
+        sampler.sample();
+    }
+
+    let heartbeat = settings.value_of("heartbeat-url")
+        .filter(|_| probing)
+        .map(Heartbeat::new);
+
+    /* an attached stream's targets are probed by whoever serves it */
+    let probed: &[&str] = if probing && !attach { &addresses } else { &[] };
+    let _locks = match lock_instance(settings, probed, io::stdin().is_terminal())? {
+        Some(locks) => locks,
+        None => return Ok(()),
+    };
+
+    if !fits(address, chunk_size, max, addresses.len())? {
+        return Ok(());
+    }
+
+    /* a history left by a monitor that never exited, offered back before the terminal is taken */
+    let every = settings.value_of("checkpoint").unwrap()
+        .parse::<u64>().unwrap();
+    let checkpoints = Some(every)
+        .filter(|x| *x > 0 && probing && !attach)
+        .map(|x| Checkpoints::new(&addresses, settings.value_of("session"), Duration::from_secs(x)));
+    let left = match checkpoints {
+        Some(ref checkpoints) => restore(checkpoints, &addresses)?,
+        None => vec![],
+    };
+
+    let timeout = Duration::from_millis(timeout);
+
+    /* before any socket or thread exists, with --via it is the remote end's */
+    if let Some(name) = settings.value_of("netns").filter(|_| probing && !attach && settings.value_of("via").is_none()) {
+        netns::enter(name).during(&format!("entering network namespace {}", name))?;
+    }
+
+    let remote = match settings.value_of("via").filter(|_| probing && !attach) {
+        Some(via) => Some(ssh(settings, via, address, chunk_size, interval, timeout)?),
+        None if attach => Some(Stream::read(io::stdin(), String::from("stdin"))),
+        None => None,
+    };
+
+    let probers = probers_for(settings, &addresses, timeout, probing)?;
+
+    /* no point drawing a screen of loss when no icmp socket may be opened */
+    let socket = match probers[0] {
+        Prober::Icmp(ref ping) if probing && remote.is_none() => Some(ping.mode().target(address)?),
+        _ => None,
+    };
+
+    let recorded = Some(address).filter(|_| probing && !attach);
+    let recorder = settings.value_of("record-events").map(|x| Recorder::create(x, recorded).file(x)).transpose()?;
+    let replay = replay.map(|x| Replay::open(x).file(x)).transpose()?;
+
+    let (mut terminal, raw) = backend::terminal().during("setting up the terminal")?;
+    terminal.hide_cursor()?;
+
+    let events = Events::new();
+
+    let schedule = probe::Schedule {
+        chunk_size,
+        spacing,
+        interval: Duration::from_secs(interval),
+        capture: capture.map(|x| (x, Duration::from_millis(capture_spacing))),
+        policy: policy.clone(),
+        turns: has_subnet(settings),
+    };
+
+    /* a target given twice is probed once, and shown in both places */
+    let streams = Streams::new(&addresses);
+
+    let workers: Vec<Worker> = if probing && remote.is_none() {
+        spawn(streams.leaders(probers), &addresses, &schedule, &events)
+    } else {
+        vec![]
+    };
+    let watcher = route::Watcher::new(streams.count());
+
+    let mut targets: Vec<Target> = addresses.iter()
+        .zip(policies(settings, &policy, &addresses).map_err(Error::Config)?)
+        .map(|(address, policy)| Target::new(address, SelectableLogList::new(max),
+            Tracker::new(policy.threshold, policy.rearm),
+            escalator(settings, &policy, address, probing),
+            shift(settings, address, probing)))
+        .collect();
+    for x in left {
+        for chunk in x.chunks {
+            targets[x.target].list.insert(chunk);
+        }
+    }
+
+    let session = settings.value_of("session");
+    let layout = layout(settings, session)?;
+
+    /* already read once by targets_of */
+    let labelled = labels(settings, &addresses).map_err(Error::Config)?;
+
+    for (target, (tags, accent)) in targets.iter_mut().zip(labelled) {
+        apply_layout(&mut target.list, &layout, game);
+
+        target.list.range(settings.value_of("latency-scale").and_then(ping::parse_range));
+        target.label(tags, accent);
+        target.list.wrap(settings.flag("wrap"));
+    }
+
+    let mut monitor = Monitor {
+        settings,
+        probing,
+        addresses: &addresses,
+        session,
+        started: Instant::now(),
+        blink: !settings.flag("no-blink"),
+        slow,
+        game,
+        throughput,
+        throughput_every,
+        chunks: 0,
+        retransmits,
+        heartbeat,
+        checkpoints,
+        remote,
+        recorder,
+        replay,
+        events,
+        schedule,
+        pending: None,
+        backoff: vec![None; addresses.len()],
+        workers,
+        streams,
+        watcher,
+        /* with many targets, every one as a cell until one is drilled into */
+        overview: targets.len() > OVERVIEW,
+        targets,
+        current: 0,
+        macros,
+        marking: None,
+        debug: layout.debug,
+        layout,
+        month: None,
+        metrics: Metrics {
+            socket,
+            ..Metrics::default()
+        },
+        notice: None,
+        redraw: true,
+        reloading: false,
+        tick_rate: event::Config::default().tick_rate,
+    };
+
+    title(&monitor.targets[0], session)?;
+    monitor.drive(&mut terminal, Duration::from_millis(1000 / fps)).await?;
+
+    terminal.clear()?;
+    monitor.close()?;
+
+    /* the shell gets the screen back first, the history is left on it */
+    drop(terminal);
+    drop(raw);
+
+    monitor.report();
+
+    Ok(())
+}
+
+/*
+ * what the monitor holds between events: its targets and how they are
+ * shown, the workers probing them, and where their chunks go besides.
+ */
+struct Monitor<'a> {
+    settings: &'a Settings<'a>,
+    /* false when replaying, which never probes or alerts */
+    probing: bool,
+    addresses: &'a [&'a str],
+    session: Option<&'a str>,
+    started: Instant,
+    blink: bool,
+    slow: Option<u64>,
+    game: Option<u64>,
+
+    throughput: Option<Throughput>,
+    throughput_every: u64,
+    /* chunks of the first stream so far, for when to test throughput */
+    chunks: u64,
+    retransmits: Option<retrans::Sampler>,
+    heartbeat: Option<Heartbeat>,
+    checkpoints: Option<Checkpoints>,
+    /* chunks probed elsewhere, over ssh or piped in */
+    remote: Option<Stream>,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+
+    events: Events,
+    schedule: probe::Schedule,
+    workers: Vec<Worker>,
+    streams: Streams,
+    /* a chunk from a worker and its stream, put in the lists on the next pass */
+    pending: Option<(usize, PacketChunk)>,
+    /* per stream, failed bursts in a row and when the next is due, while it backs off */
+    backoff: Vec<Option<(u32, Instant)>>,
+    watcher: route::Watcher,
+
+    targets: Vec<Target<'a>>,
+    /* the target the keys act on, and the one shown unless split */
+    current: usize,
+    overview: bool,
+    macros: HashMap<Key, Vec<Command>>,
+    /* label of a marker being typed */
+    marking: Option<String>,
+    layout: Layout,
+    debug: bool,
+    /* the calendar's month, the newest chunk's until moved */
+    month: Option<NaiveDate>,
+    metrics: Metrics,
+    /* a message along the bottom, since when, and whether it is an error */
+    notice: Option<(String, Instant, bool)>,
+    redraw: bool,
+    /* set by the reload key or SIGHUP, done once the event is handled */
+    reloading: bool,
+    tick_rate: Duration,
+}
+
+impl<'a> Monitor<'a> {
+    /* draw when something changed, at most once a `frame`, and hand everything that comes in to its method until quit */
+    async fn drive(&mut self, terminal: &mut Terminal<Backend>, frame: Duration) -> Result<(), Error> {
+        let mut internal_size = terminal.size()?;
+
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.resize(internal_size)?;
+        }
+
+        let mut last_draw: Option<Instant> = None;
+
+        loop {
+
+            let size = match self.replay.as_ref().and_then(|x| x.size()) {
+                Some(size) => size,
+                None => terminal.size()?,
+            };
+
+
+            if size != internal_size {
+
+                info!("terminal resized from {}x{} to {}x{}", internal_size.width, internal_size.height, size.width, size.height);
+
+                if let Some(ref mut recorder) = self.recorder {
+                    recorder.resize(size)?;
+                }
+
+                terminal.resize(size)?;
+                internal_size = size;
+                terminal.clear()?;
+
+                self.redraw = true;
+
+                continue;
+
+            }
+
+            let due = last_draw
+                .map(|x| x.elapsed() >= frame)
+                .unwrap_or(true);
+
+            /* only redraw after size is verified, and at most once per frame */
+            if self.redraw && due {
+                self.redraw = false;
+                self.draw(terminal, size)?;
+                last_draw = Some(Instant::now());
+            }
+
+            self.probed()?;
+
+            /* a pending redraw only waits out the rest of the frame */
+            let event = match self.replay {
+                Some(ref mut replay) => {
+                    let recorded = replay.next()?;
+                    self.replayed(recorded)?
+                },
+                None if self.redraw => {
+                    let wait = last_draw
+                        .map(|x| frame.checked_sub(x.elapsed()).unwrap_or_default())
+                        .unwrap_or_default();
+
+                    self.events.next_timeout(wait).await?
+                },
+                None => Some(self.events.next().await?),
+            };
+
+            if self.event(event)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /* the screen as it stands, or a crash report when a widget panics */
+    fn draw(&mut self, terminal: &mut Terminal<Backend>, size: Rect) -> Result<(), Error> {
+        if self.debug {
+            self.metrics.queue = self.events.depth();
+            self.metrics.chunks = self.targets[self.current].list.len();
+            self.metrics.memory = self.targets.iter().map(|x| x.list.memory()).sum();
+        }
+
+        let start = Instant::now();
+
+        /* a target backing off is shown along the bottom when nothing else is */
+        let (addresses, streams) = (self.addresses, &self.streams);
+        let backing_off = self.backoff.iter().enumerate().find_map(|(stream, x)| x.map(|(failures, next)| {
+            let secs = next.saturating_duration_since(Instant::now()).as_secs();
+            locale::format(Text::Backoff, &[&addresses[streams.targets(stream)[0]], &failures, &secs])
+        }));
+
+        let (targets, current, layout, month) = (&mut self.targets, self.current, &self.layout, self.month);
+        let (watcher, marking, notice, debug, metrics, overview) =
+            (&self.watcher, &self.marking, &self.notice, self.debug, &self.metrics, self.overview);
+
+        /* a panic in the widgets ends the monitor, but not before the history is safe */
+        let drawn = crash::guard(|| terminal.draw(|mut f| {
+            let mut size = size;
+
+            /* along the top, apart from anything about a single target */
+            if watcher.offline() && size.height > 1 {
+                Notice::new(locale::tr(Text::NetworkDown), true).render(&mut f, Rect::new(size.x, size.y, size.width, 1));
+                size.y += 1;
+                size.height -= 1;
+            }
+
+            if let Some(label) = marking.as_ref().filter(|_| size.height > 1) {
+                size.height -= 1;
+                Prompt::new(label).render(&mut f, Rect::new(size.x, size.y + size.height, size.width, 1));
+            } else if let Some((ref text, _, error)) = notice.as_ref().filter(|_| size.height > 1) {
+                size.height -= 1;
+                Notice::new(text, *error).render(&mut f, Rect::new(size.x, size.y + size.height, size.width, 1));
+            } else if let Some(text) = backing_off.as_ref().filter(|_| size.height > 1) {
+                size.height -= 1;
+                Notice::new(text, true).render(&mut f, Rect::new(size.x, size.y + size.height, size.width, 1));
+            }
+
+            if debug && size.height > metrics::HEIGHT {
+                size.height -= metrics::HEIGHT;
+                let pane = Rect::new(size.x, size.y + size.height, size.width, metrics::HEIGHT);
+
+                MetricsPane::new(metrics).render(&mut f, pane);
+            }
+
+            /* with several targets, each gets a bar naming it above its list */
+            if overview {
+                let cells = targets.iter()
+                    .map(|x| (x.address.as_str(), x.health.health(), x.list.newest().map(|x| x.loss())))
+                    .collect();
+                Overview::new(cells, current).render(&mut f, size);
+            } else if targets.len() == 1 || size.height < 2 {
+                show(&mut f, &mut targets[current], layout, month, size);
+            } else if layout.split {
+                let n = targets.len() as u16;
+
+                for (i, target) in targets.iter_mut().enumerate() {
+                    let x = size.x + i as u16 * (size.width / n);
+                    /* the last column takes what the division leaves over */
+                    let width = if i as u16 == n - 1 { size.right() - x } else { size.width / n };
+
+                    TargetBar::new(vec![(target.address.as_str(), target.health.health())], Some(i).filter(|x| *x == current))
+                        .render(&mut f, Rect::new(x, size.y, width, 1));
+                    show(&mut f, target, layout, month, Rect::new(x, size.y + 1, width, size.height - 1));
+                }
+            } else {
+                TargetBar::new(targets.iter().map(|x| (x.address.as_str(), x.health.health())).collect(), Some(current))
+                    .render(&mut f, Rect::new(size.x, size.y, size.width, 1));
+                show(&mut f, &mut targets[current], layout, month, Rect::new(size.x, size.y + 1, size.width, size.height - 1));
+            }
+        }));
+
+        match drawn {
+            Ok(drawn) => drawn?,
+            /* the terminal is given back as the guard drops, before the report is printed */
+            Err(panic) => {
+                return Err(Error::Crash(crash::Report {
+                    panic,
+                    size,
+                    saved: crash::save(&self.targets),
+                }));
+            },
+        }
+
+        self.metrics.draw = start.elapsed();
+        self.metrics.frames += 1;
+        if self.metrics.draw > self.tick_rate {
+            self.metrics.dropped += 1;
+            debug!("drawing took {}ms, longer than a tick", self.metrics.draw.as_millis());
+        }
+
+        Ok(())
+    }
+
+    /* a chunk in from the remote end or a worker, into the lists of the targets of its stream */
+    fn probed(&mut self) -> Result<(), Error> {
+        let probed = match self.remote {
+            Some(ref remote) => remote.next().target(self.addresses[0])?.map(|x| (0, x)),
+            None => self.pending.take(),
+        };
+
+        let (stream, mut chunk) = match probed {
+            Some(probed) => probed,
+            None => return Ok(()),
+        };
+
+        if let Some(slow) = self.slow {
+            chunk.slow_after(slow as f64);
+        }
+
+        /* figures about the host itself, and the recording, go with the first target, which leads the first stream */
+        if stream == 0 {
+            if let Some((retransmitted, sent)) = self.retransmits.as_mut().and_then(|x| x.sample()) {
+                chunk.set_retrans(retransmitted, sent);
+            }
+
+            /* a test that finished during this chunk belongs to it */
+            if let Some(ref mut throughput) = self.throughput {
+                if let Some(Some(mbps)) = throughput.result() {
+                    chunk.annotate(mbps);
+                }
+
+                if self.chunks.is_multiple_of(self.throughput_every) {
+                    throughput.start();
+                }
+            }
+            self.chunks += 1;
+
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.chunk(&chunk)?;
+            }
+        }
+
+        /* drawn as lost like any other, the banner says why */
+        if let Some(error) = chunk.error() {
+            self.notice(format!("{}: {}", self.addresses[self.streams.targets(stream)[0]], error), true);
+        }
+
+        /* the local network going down fails every target alike, and isn't theirs to alert on */
+        if self.probing && self.remote.is_none() && self.watcher.update(stream, &chunk) {
+            for target in self.targets.iter_mut() {
+                target.escalator.hold(self.watcher.offline());
+            }
+
+            if self.watcher.offline() {
+                warn!("{}", locale::tr(Text::NetworkDown));
+            } else {
+                info!("{}", locale::tr(Text::NetworkBack));
+                self.notice(locale::tr(Text::NetworkBack).to_string(), false);
+            }
+        }
+
+        for &i in self.streams.targets(stream) {
+            let chunk = chunk.clone();
+            let target = &mut self.targets[i];
+
+            /* a path shift is marked after the chunk that confirmed it */
+            let shifted = target.shift.as_mut().and_then(|x| x.update(&chunk));
+            let time = chunk.time();
+
+            if target.insert(chunk, self.blink) && i == self.current {
+                title(target, self.session)?;
+            }
+
+            if let Some(label) = shifted {
+                info!("{}: {}", target.address, label);
+
+                if target.list.mark(time, &label) && i == 0 {
+                    if let Some(ref mut recorder) = self.recorder {
+                        recorder.marker(time, &label)?;
+                    }
+                }
+            }
+        }
+
+        if let Some(ref mut heartbeat) = self.heartbeat {
+            heartbeat.beat(&chunk, self.schedule.interval);
+        }
+
+        if let Some(checkpoints) = self.checkpoints.as_mut().filter(|x| x.due()) {
+            if let Err(e) = checkpoints.save(&self.targets) {
+                warn!("checkpoint: {}", e);
+                self.notice(format!("checkpoint: {}", e), true);
+            }
+        }
+
+        self.redraw = true;
+
+        Ok(())
+    }
+
+    /* the next line of a recording being replayed, the event if it was one */
+    fn replayed(&mut self, recorded: Option<Recorded>) -> Result<Option<Event<Key>>, Error> {
+        match recorded {
+            Some(Recorded::Event(event)) => return Ok(Some(event)),
+            Some(Recorded::Resize) => {},
+            Some(Recorded::Chunk(mut chunk)) => {
+                if let Some(slow) = self.slow {
+                    chunk.slow_after(slow as f64);
+                }
+
+                if self.targets[0].insert(*chunk, self.blink) {
+                    title(&self.targets[0], self.session)?;
+                }
+                self.redraw = true;
+            },
+            Some(Recorded::Marker(time, label)) => {
+                self.targets[0].list.mark(time, &label);
+                self.redraw = true;
+            },
+            /* end of the recording, hand over to the keyboard */
+            None => {
+                self.replay = None;
+            },
+        }
+
+        Ok(None)
+    }
+
+    /* whatever woke the loop, true when the monitor should stop */
+    fn event(&mut self, event: Option<Event<Key>>) -> Result<bool, Error> {
+        if let Some(ref mut recorder) = self.recorder {
+            if let Some(ref event) = event {
+                recorder.event(event)?;
+            }
+        }
+
+        for target in self.targets.iter_mut() {
+            if target.blink(self.tick_rate) {
+                self.redraw = true;
+            }
+        }
+
+        if self.notice.as_ref().is_some_and(|x| x.1.elapsed() >= NOTICE) {
+            self.notice = None;
+            self.redraw = true;
+        }
+
+        /* the countdown on the status line */
+        if matches!(event, Some(Event::Tick)) && self.backoff.iter().any(|x| x.is_some()) {
+            self.redraw = true;
+        }
+
+        match event {
+            Some(Event::Chunk(probed)) => {
+                let probed = probed?;
+
+                self.metrics.probe = probed.took;
+                if let Some(drift) = probed.drift {
+                    self.metrics.drift = drift;
+                }
+
+                self.backoff[probed.target] = probed.backoff.map(|(failures, wait)| (failures, Instant::now() + wait));
+                self.pending = Some((probed.target, probed.chunk));
+            },
+            /* everything goes to the label while one is being typed */
+            Some(Event::Input(input)) if self.marking.is_some() => {
+                self.label(input)?;
+                self.redraw = true;
+            },
+            Some(Event::Input(input)) => {
+                let commands = self.macros.get(&input).cloned()
+                    .or_else(|| command::builtin(input).map(|x| vec![x]))
+                    .unwrap_or_default();
+                let mut quit = false;
+
+                /* a macro's commands all land before the next redraw */
+                for command in commands {
+                    quit |= self.command(command)?;
+                    self.redraw = true;
+                }
+
+                if quit {
+                    return Ok(true);
+                }
+            },
+            Some(Event::Reload) => {
+                self.reloading = true;
+            },
+            Some(Event::Stats) => self.stats(),
+            Some(Event::Stop) => return Ok(true),
+            _ => {},
+        }
+
+        if self.reloading {
+            self.reloading = false;
+            self.redraw = true;
+            self.reload();
+        }
+
+        Ok(false)
+    }
+
+    /* a key while a marker's label is typed */
+    fn label(&mut self, input: Key) -> Result<(), Error> {
+        match input {
+            Key::Char('\n') => {
+                let label = self.marking.take().unwrap();
+                self.mark(label.trim())?;
+            },
+            Key::Esc => {
+                self.marking = None;
+            },
+            Key::Backspace => {
+                self.marking.as_mut().unwrap().pop();
+            },
+            Key::Char(c) if !c.is_control() => {
+                self.marking.as_mut().unwrap().push(c);
+            },
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    /* one command of a key or macro, true for quit */
+    fn command(&mut self, command: Command) -> Result<bool, Error> {
+        let (targets, current) = (&mut self.targets, self.current);
+
+        match command {
+            Command::Quit => return Ok(true),
+            Command::Marker => {
+                self.marking = Some(String::new());
+            },
+            Command::Mark(label) => self.mark(&label)?,
+            /* in the overview the selection is the target, in order */
+            Command::SelectNext | Command::SelectPrev | Command::SelectFirst | Command::SelectLast if self.overview => {
+                let n = targets.len();
+                self.current = match command {
+                    Command::SelectNext => (current + 1) % n,
+                    Command::SelectPrev => (current + n - 1) % n,
+                    Command::SelectFirst => 0,
+                    _ => n - 1,
+                };
+                title(&targets[self.current], self.session)?;
+            },
+            Command::SelectNext => targets[current].list.select_next(),
+            Command::SelectPrev => targets[current].list.select_prev(),
+            Command::SelectFirst => targets[current].list.select_first(),
+            Command::SelectLast => targets[current].list.select_last(),
+            Command::Clear => targets[current].list.clear(),
+            Command::ToggleDebug => {
+                self.debug = !self.debug;
+            },
+            Command::ToggleMos => {
+                self.layout.mos = !self.layout.mos;
+                self.apply_layout();
+            },
+            Command::Probe => {
+                for worker in self.workers.iter() {
+                    worker.fire();
+                }
+            },
+            Command::Reload => {
+                self.reloading = true;
+            },
+            Command::ToggleSplit => {
+                self.layout.split = !self.layout.split;
+            },
+            Command::ToggleBillboard => {
+                self.layout.billboard = !self.layout.billboard;
+                self.layout.calendar = false;
+            },
+            Command::ToggleCalendar => {
+                self.layout.calendar = !self.layout.calendar;
+                self.layout.billboard = false;
+            },
+            Command::PrevMonth | Command::NextMonth => {
+                let shown = self.month.unwrap_or_else(|| newest_month(&targets[current]));
+                self.month = Some(calendar::shift(shown, match command {
+                    Command::NextMonth => 1,
+                    _ => -1,
+                }));
+            },
+            Command::NextTarget | Command::PrevTarget => {
+                let n = targets.len();
+                self.current = match command {
+                    Command::NextTarget => (current + 1) % n,
+                    _ => (current + n - 1) % n,
+                };
+                title(&targets[self.current], self.session)?;
+            },
+            Command::ToggleOverview => {
+                self.overview = !self.overview && targets.len() > OVERVIEW;
+            },
+            Command::ToggleOrder => {
+                let oldest = self.layout.order.as_deref() == Some("oldest-first");
+                self.layout.order = Some(String::from(if oldest { "newest-first" } else { "oldest-first" }));
+                self.apply_layout();
+            },
+            Command::ToggleView => {
+                let timeline = self.layout.view.as_deref() == Some("timeline");
+                self.layout.view = Some(String::from(if timeline { "tiles" } else { "timeline" }));
+                self.apply_layout();
+            },
+            Command::ToggleLog => {
+                let log = self.layout.latency_color.as_deref() == Some("log");
+                self.layout.latency_color = Some(String::from(if log { "linear" } else { "log" }));
+                self.apply_layout();
+            },
+        }
+
+        Ok(false)
+    }
+
+    /* a marker on the target in view, now */
+    fn mark(&mut self, label: &str) -> Result<(), Error> {
+        let time = Local::now();
+
+        /* a replayed marker comes back as its own line */
+        if self.replay.is_none() && self.targets[self.current].list.mark(time, label) && self.current == 0 {
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.marker(time, label)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_layout(&mut self) {
+        for target in self.targets.iter_mut() {
+            apply_layout(&mut target.list, &self.layout, self.game);
+        }
+    }
+
+    /* the screen is the ui's, so the statistics go to the log */
+    fn stats(&mut self) {
+        match self.settings.value_of("log-file") {
+            Some(path) => {
+                for target in self.targets.iter() {
+                    let chunks: Vec<PacketChunk> = target.list.chunks().cloned().collect();
+
+                    for line in report::session(&target.address, &chunks, self.started.elapsed()).lines() {
+                        info!("{}", line);
+                    }
+                }
+                self.notice(locale::format(Text::StatsLogged, &[&path]), false);
+            },
+            None => self.notice(locale::tr(Text::StatsNoLog).to_string(), true),
+        }
+        self.redraw = true;
+    }
+
+    /* the file's values in place of the ones it had, the history stays */
+    fn reload(&mut self) {
+        let fresh = match self.settings.reload() {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                warn!("reloading: {}", e);
+                self.notice(e, true);
+                return;
+            },
+        };
+
+        let policy = alert_policy(&fresh, self.probing);
+
+        if self.game.is_none() {
+            self.slow = fresh.value_of("slow").map(|x| x.parse::<u64>().unwrap());
+        }
+
+        ping::set_palette(palette(&fresh));
+
+        /* the targets file is read again too, for its tags, colors and recovery counts; the targets stay */
+        let listed = labels(&fresh, self.addresses)
+            .and_then(|labels| Ok((labels, policies(&fresh, &policy, self.addresses)?)));
+        let (labels, policies) = match listed {
+            Ok(listed) => listed,
+            Err(e) => {
+                warn!("reloading: {}", e);
+                self.notice(e, true);
+                return;
+            },
+        };
+
+        for ((target, (tags, accent)), policy) in self.targets.iter_mut().zip(labels).zip(policies) {
+            let shift = shift(&fresh, &target.address, self.probing);
+            target.configure(&policy, shift);
+
+            target.list.range(fresh.value_of("latency-scale").and_then(ping::parse_range));
+            target.label(tags, accent);
+        }
+        if let Err(e) = title(&self.targets[self.current], self.session) {
+            warn!("reloading: {}", e);
+        }
+
+        self.schedule.interval = Duration::from_secs(fresh.value_of("interval").unwrap().parse::<u64>().unwrap());
+        self.schedule.policy = policy;
+
+        /* the targets stay as they started, the probers come from the file as it is now */
+        if !self.workers.is_empty() {
+            match reprobe(&fresh, self.addresses, self.probing) {
+                Ok(probers) => {
+                    self.workers.clear();
+                    self.workers = spawn(self.streams.leaders(probers), self.addresses, &self.schedule, &self.events);
+                    /* fresh workers start on the schedule */
+                    self.backoff.iter_mut().for_each(|x| *x = None);
+                },
+                Err(e) => {
+                    warn!("reloading: {}", e);
+                    self.notice(e.to_string(), true);
+                    return;
+                },
+            }
+        }
+
+        let path = self.settings.config_path().map(|x| x.display().to_string()).unwrap_or_default();
+        info!("reloaded {}", path);
+        self.notice(locale::format(Text::Reloaded, &[&path]), false);
+    }
+
+    /* a message along the bottom from now, `error` for a red one */
+    fn notice(&mut self, text: String, error: bool) {
+        self.notice = Some((text, Instant::now(), error));
+    }
+
+    /* the checkpoints go once the monitor exits as it should, and the layout is kept for the session */
+    fn close(&mut self) -> Result<(), Error> {
+        if let Some(ref checkpoints) = self.checkpoints {
+            checkpoints.remove();
+        }
+
+        if let Some(name) = self.session {
+            self.layout.debug = self.debug;
+            self.layout.save(name).during(&format!("saving session {}", name))?;
+        }
+
+        Ok(())
+    }
+
+    /* each target's totals, left on the screen the shell gets back */
+    fn report(&self) {
+        for (i, target) in self.targets.iter().enumerate() {
+            let chunks: Vec<PacketChunk> = target.list.chunks().cloned().collect();
+
+            if i > 0 {
+                println!();
+            }
+            print!("{}", report::session(&target.address, &chunks, self.started.elapsed()));
+        }
+    }
+}
+
+/* whether `max` chunks of every target fit in memory, or whoever started the monitor says to go on anyway */
+fn fits(address: &str, chunk_size: u64, max: usize, targets: usize) -> io::Result<bool> {
+    let per_chunk = PacketChunk::estimate(address, chunk_size);
+    let memory = per_chunk.saturating_mul(max).saturating_mul(targets);
+
+    if memory < MEMORY_WARNING {
+        return Ok(true);
+    }
+
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+
+    writeln!(stderr, "warning: storing {} chunks of {} pings may use up to {} of memory",
+        max, chunk_size, metrics::bytes(memory))?;
+    writeln!(stderr, "         consider --max {} (about {}) or a smaller --chunk-size",
+        MEMORY_WARNING / 2 / per_chunk, metrics::bytes(MEMORY_WARNING / 2))?;
+
+    if io::stdin().is_terminal() {
+        write!(stderr, "continue anyway? [y/N] ")?;
+        stderr.flush()?;
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+
+        return Ok(answer.trim().eq_ignore_ascii_case("y"));
+    }
+
+    Ok(true)
+}
+
+/* the chunks a monitor that never exited checkpointed, when asked for them back */
+fn restore(checkpoints: &Checkpoints, addresses: &[&str]) -> io::Result<Vec<Left>> {
+    let left = checkpoints.left();
+
+    /* nobody to ask, the next checkpoint takes its place */
+    if left.is_empty() || !io::stdin().is_terminal() {
+        return Ok(vec![]);
+    }
+
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+
+    for x in left.iter() {
+        let newest = x.chunks.last().map(|x| x.time().format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+        writeln!(stderr, "{}: {} chunks up to {} were checkpointed by a monitor that didn't exit",
+            addresses[x.target], x.chunks.len(), newest)?;
+    }
+    write!(stderr, "restore them? [Y/n] ")?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    if answer.trim().eq_ignore_ascii_case("n") {
+        return Ok(vec![]);
+    }
+
+    Ok(left)
+}
+
+/* a remote end does the probing, and its own pacing */
+fn ssh(settings: &Settings, via: &str, address: &str, chunk_size: u64, interval: u64, timeout: Duration) -> Result<Stream, Error> {
+    let mut args = vec![
+        String::from("--chunk-size"), chunk_size.to_string(),
+        String::from("--interval"), interval.to_string(),
+        String::from("--timeout"), (timeout.as_secs() * 1000 + timeout.subsec_millis() as u64).to_string(),
+    ];
+    if let Some(name) = settings.value_of("netns") {
+        args.push(String::from("--netns"));
+        args.push(name.to_string());
+    }
+    args.push(address.to_string());
+
+    Stream::ssh(via, &args).during(&format!("connecting to {}", via))
+}
+
+/* a saved layout wins over the defaults, options given now win over it */
+fn layout(settings: &Settings, session: Option<&str>) -> Result<Layout, Error> {
+    let saved = match session {
+        Some(name) => Layout::load(name).map_err(Error::Config)?,
+        None => None,
+    };
+    let mut layout = saved.clone().unwrap_or_default();
+
+    if saved.is_none() || settings.source("mos") == Source::Cli {
+        layout.mos = settings.flag("mos");
+    }
+    if saved.is_none() || settings.source("weight-bursts") == Source::Cli {
+        layout.bursts = settings.flag("weight-bursts");
+    }
+    if layout.latency_color.is_none() || settings.source("latency-color") == Source::Cli {
+        layout.latency_color = settings.value_of("latency-color").map(String::from);
+    }
+    if layout.order.is_none() || settings.source("order") == Source::Cli {
+        layout.order = settings.value_of("order").map(String::from);
+    }
+    if layout.view.is_none() || settings.source("view") == Source::Cli {
+        layout.view = settings.value_of("view").map(String::from);
+    }
+    if saved.is_none() || settings.source("billboard") == Source::Cli {
+        layout.billboard = settings.flag("billboard");
+    }
+
+    Ok(layout)
+}
+
+/* the month the calendar opens on, that of the newest chunk */
+fn newest_month(target: &Target) -> NaiveDate {
+    let newest = target.list.newest().map(|x| x.time()).unwrap_or_else(Local::now);
+    calendar::month_of(newest.date_naive())
+}
+
+/* a target's history, its newest chunk as a billboard, or its days as a calendar */
+fn show(f: &mut Frame<backend::Backend>, target: &mut Target, layout: &Layout, month: Option<NaiveDate>, area: Rect) {
+    if layout.calendar {
+        let days = report::days(target.list.chunks());
+        Calendar::new(&days, month.unwrap_or_else(|| newest_month(target))).render(f, area);
+    } else if layout.billboard {
+        Billboard::new(target.list.newest()).render(f, area);
+    } else {
+        target.list.render(f, area);
+    }
+}
+
+/* the default tile colors, with any given in place */
+fn palette(settings: &Settings) -> ping::Palette {
+    let mut palette = ping::Palette::default();
+
+    for (key, color) in [
+        ("color-good", &mut palette.good),
+        ("color-bad", &mut palette.bad),
+        ("color-down", &mut palette.down),
+        ("color-slow", &mut palette.slow),
+    ] {
+        if let Some(rgb) = settings.value_of(key).and_then(ping::parse_rgb) {
+            *color = rgb;
+        }
+    }
+
+    palette
+}
+
+fn apply_layout(list: &mut SelectableLogList, layout: &Layout, game: Option<u64>) {
+    /* game mode is chosen at start and is not part of the layout */
+    if let Some(hz) = game {
+        list.mode(ColorMode::Game { hz: hz as f64 });
+    } else if layout.mos {
+        list.mode(ColorMode::Mos);
+    } else if layout.bursts {
+        list.mode(ColorMode::Burst);
+    } else {
+        list.mode(ColorMode::Loss);
+    }
+
+    let scale = layout.latency_color.as_deref()
+        .and_then(Scale::parse)
+        .unwrap_or(Scale::Linear);
+    list.scale(scale);
+
+    let order = layout.order.as_deref()
+        .and_then(Order::parse)
+        .unwrap_or(Order::NewestFirst);
+    list.order(order);
+
+    let view = layout.view.as_deref()
+        .and_then(View::parse)
+        .unwrap_or(View::Tiles);
+    list.view(view);
+}
+
+/* the window (or tmux pane) title carries the health glyph, for triage from outside */
+fn title(target: &Target, session: Option<&str>) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let glyph = target.health.health().glyph();
+
+    match session {
+        Some(name) => write!(stdout, "\x1b]2;{} {} - packetloss {}", glyph, name, target.address)?,
+        None => write!(stdout, "\x1b]2;{} packetloss {}", glyph, target.address)?,
+    }
+    if !target.tags.is_empty() {
+        write!(stdout, " [{}]", target.tags.join(", "))?;
+    }
+    write!(stdout, "\x07")?;
+    stdout.flush()
+}